@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use fift::core::env::EmptyEnvironment;
+use fift::core::SourceBlock;
+
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "stack_words",
+        "0 { 1 + dup 2dup drop swap over nip rot -rot 2drop } 10000 times drop",
+    ),
+    (
+        "defined_words",
+        "{ 1 + } : inc { inc inc } : inc2 0 { inc2 } 10000 times drop",
+    ),
+    ("prefix_words", "0 { x{abcd} drop 1 + } 1000 times drop"),
+    (
+        "cells",
+        "<b { 1 8 u, dup bbits 1000 > { b> <b swap ref, } if } 1000 times b> drop",
+    ),
+    (
+        "asm",
+        "\"Asm.fif\" include <{ { NOP } 1000 times ONE INC DROP }>c drop",
+    ),
+];
+
+fn run_script(source: &str) {
+    let mut env = EmptyEnvironment;
+    let mut stdout = std::io::sink();
+    let mut ctx = fift::Context::new(&mut env, &mut stdout)
+        .with_basic_modules()
+        .unwrap();
+    for (name, content) in fift_libs::all() {
+        ctx.add_virtual_file(*name, *content);
+    }
+
+    let base_lib = fift_libs::base_lib();
+    ctx.add_source_str("<bench>", source);
+    ctx.add_source_block(SourceBlock::new(
+        base_lib.name,
+        std::io::Cursor::new(base_lib.content),
+    ));
+    ctx.run().unwrap();
+}
+
+fn interp(c: &mut Criterion) {
+    c.bench_function("startup", |b| b.iter(|| run_script(black_box(""))));
+    for (name, source) in SCRIPTS {
+        c.bench_function(name, |b| b.iter(|| run_script(black_box(source))));
+    }
+}
+
+criterion_group!(benches, interp);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+use argh::FromArgs;
+
+use fift::core::env::EmptyEnvironment;
+use fift::core::WordInfo;
+
+use crate::modules::*;
+
+/// Generate Markdown documentation for all registered words
+#[derive(FromArgs)]
+#[argh(subcommand, name = "docgen")]
+pub struct DocGen {
+    /// write the documentation to the specified file instead of stdout
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+}
+
+impl DocGen {
+    pub fn run(self) -> Result<()> {
+        let mut env = EmptyEnvironment;
+        let mut stdout = std::io::sink();
+        let ctx = fift::Context::new(&mut env, &mut stdout)
+            .with_basic_modules()?
+            .with_cmd_args(Vec::new())?
+            .with_module(ShellUtils)?;
+
+        let mut modules = BTreeMap::<&str, Vec<(String, Option<&WordInfo>)>>::new();
+        for (name, _) in ctx.dicts.current.iter()? {
+            let info = ctx.word_info(&name);
+            let module = info.map(|info| info.module).unwrap_or("Other");
+            modules
+                .entry(module)
+                .or_default()
+                .push((name.trim_end().to_owned(), info));
+        }
+
+        let mut output = Vec::new();
+        writeln!(output, "# Fift words")?;
+        for (module, mut words) in modules {
+            words.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            writeln!(output, "\n## {module}\n")?;
+            writeln!(output, "| Word | Stack effect | Description |")?;
+            writeln!(output, "| --- | --- | --- |")?;
+            for (name, info) in words {
+                let effect = match info.and_then(|info| info.stack_effect) {
+                    Some(effect) => code_span(&format!("({effect})")),
+                    None => String::new(),
+                };
+                let doc = info.map(|info| info.doc).unwrap_or_default();
+                writeln!(
+                    output,
+                    "| {} | {} | {} |",
+                    code_span(&name),
+                    effect,
+                    escape_cell(doc).replace('\n', " "),
+                )?;
+            }
+        }
+
+        match &self.output {
+            Some(path) => std::fs::write(path, output)?,
+            None => std::io::stdout().write_all(&output)?,
+        }
+        Ok(())
+    }
+}
+
+fn code_span(s: &str) -> String {
+    let s = escape_cell(s);
+    if s.contains('`') {
+        format!("`` {s} ``")
+    } else {
+        format!("`{s}`")
+    }
+}
+
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
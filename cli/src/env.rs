@@ -1,35 +1,156 @@
-use std::fs::File;
-use std::io::{BufReader, Read, Result, Seek, SeekFrom};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Result, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-use fift::core::{Environment, SourceBlock};
+use anyhow::Context as _;
+use fift::core::{Environment, SourceBlock, WriteMode};
+use fift::prelude::Cell;
 
+use crate::func::FuncCompiler;
+
+pub const MANIFEST_NAME: &str = "fiftpath.toml";
+
+/// Resolves files in the following order:
+/// - `pkg:name` is searched only in the `pkg` package;
+/// - a path relative to the current directory (or an absolute path);
+/// - include directories (it is an error if several of them contain the file);
+/// - embedded libraries.
+#[derive(Clone)]
 pub struct SystemEnvironment {
     include_dirs: Vec<PathBuf>,
+    packages: BTreeMap<String, Package>,
+    func: Option<FuncCompiler>,
+    kv_store: Option<String>,
 }
 
 impl SystemEnvironment {
-    pub fn with_include_dirs(dirs: &str) -> Self {
-        let dirs = dirs.trim();
-        let include_dirs = if dirs.is_empty() {
-            Vec::new()
-        } else {
-            dirs.split(':')
-                .map(|item| PathBuf::from(item.trim()))
-                .collect()
+    /// Parses a colon-separated list of include directories.
+    /// Items in the form `name=dir` define packages.
+    pub fn with_include_dirs(dirs: &str) -> anyhow::Result<Self> {
+        let mut res = Self {
+            include_dirs: Vec::new(),
+            packages: BTreeMap::new(),
+            func: None,
+            kv_store: None,
         };
-        Self { include_dirs }
+
+        for item in dirs.split(':') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+
+            match item.split_once('=') {
+                Some((name, dir)) => {
+                    res.add_package(name.trim(), Package::Dir(PathBuf::from(dir.trim())))?
+                }
+                None => res.include_dirs.push(PathBuf::from(item)),
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Sets the compiler used by `func>cell`.
+    pub fn set_func_compiler(&mut self, compiler: FuncCompiler) {
+        self.func = Some(compiler);
+    }
+
+    /// Sets the JSON file used by `kv-get` and `kv-put`.
+    pub fn set_kv_store(&mut self, path: String) {
+        self.kv_store = Some(path);
+    }
+
+    /// Adds packages from the manifest file.
+    ///
+    /// ```toml
+    /// [packages]
+    /// ton = "../ton/crypto/fift/lib" # relative to the manifest directory
+    /// std = { embedded = true }      # libraries built into the binary
+    /// ```
+    pub fn load_manifest(&mut self, path: &Path) -> anyhow::Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read `{}`", path.display()))?;
+        let root = path.parent().unwrap_or(Path::new(""));
+
+        for (name, package) in parse_manifest(&contents, root)
+            .with_context(|| format!("Invalid manifest `{}`", path.display()))?
+        {
+            self.add_package(&name, package)
+                .with_context(|| format!("Invalid manifest `{}`", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn add_package(&mut self, name: &str, package: Package) -> anyhow::Result<()> {
+        anyhow::ensure!(is_package_name(name), "Invalid package name `{name}`");
+        anyhow::ensure!(
+            !self.packages.contains_key(name),
+            "Package `{name}` is defined more than once"
+        );
+        self.packages.insert(name.to_owned(), package);
+        Ok(())
+    }
+
+    /// Returns the path of the file, or `None` for embedded libraries.
+    pub fn resolve_path(&self, name: &str) -> Option<PathBuf> {
+        match self.resolve_file(name).ok()? {
+            Resolved::File(path) => Some(path),
+            Resolved::Lib(_) => None,
+        }
     }
 
     fn resolve_file(&self, name: &str) -> Result<Resolved> {
+        if let Some((package, file)) = split_package(name) {
+            let Some(package) = self.packages.get(package) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Unknown package `{package}` of `{name}`"),
+                ));
+            };
+
+            match package {
+                Package::Dir(dir) => {
+                    let path = dir.join(file);
+                    if path.is_file() {
+                        return Ok(Resolved::File(path));
+                    }
+                }
+                Package::Embedded => {
+                    if let Some(lib) = fift_libs::all().get(file) {
+                        return Ok(Resolved::Lib(lib));
+                    }
+                }
+            }
+            return Err(not_found(name));
+        }
+
         if Path::new(name).is_file() {
             return Ok(Resolved::File(PathBuf::from(name)));
         }
 
+        let mut found = Vec::<PathBuf>::new();
         for dir in &self.include_dirs {
             let path = dir.join(name);
-            if path.is_file() {
-                return Ok(Resolved::File(path));
+            if path.is_file() && !found.iter().any(|item| same_file(item, &path)) {
+                found.push(path);
+            }
+        }
+
+        match found.len() {
+            0 => {}
+            1 => return Ok(Resolved::File(found.remove(0))),
+            _ => {
+                let paths = found
+                    .iter()
+                    .map(|path| format!("`{}`", path.display()))
+                    .collect::<Vec<_>>();
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("`{name}` is ambiguous, found in {}", paths.join(", ")),
+                ));
             }
         }
 
@@ -37,10 +158,7 @@ impl SystemEnvironment {
             return Ok(Resolved::Lib(lib));
         }
 
-        Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("`{name}` file not found"),
-        ))
+        Err(not_found(name))
     }
 }
 
@@ -57,12 +175,24 @@ impl Environment for SystemEnvironment {
     }
 
     fn file_exists(&self, name: &str) -> bool {
+        // NOTE: ambiguous names can't be opened, so they don't exist either
         self.resolve_file(name).is_ok()
     }
 
-    fn write_file(&mut self, name: &str, contents: &[u8]) -> std::io::Result<()> {
-        std::fs::write(name, contents)?;
-        Ok(())
+    fn write_file(&mut self, name: &str, contents: &[u8], mode: WriteMode) -> std::io::Result<()> {
+        match mode {
+            WriteMode::Create => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(name)?
+                .write_all(contents),
+            WriteMode::Append => OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(name)?
+                .write_all(contents),
+            WriteMode::Truncate => write_atomic(Path::new(name), contents),
+        }
     }
 
     fn read_file(&mut self, name: &str) -> std::io::Result<Vec<u8>> {
@@ -102,9 +232,228 @@ impl Environment for SystemEnvironment {
             Resolved::Lib(lib) => fift::core::SourceBlock::new(name, std::io::Cursor::new(lib)),
         })
     }
+
+    fn create_dir(&mut self, name: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(name)
+    }
+
+    fn remove_file(&mut self, name: &str) -> std::io::Result<()> {
+        std::fs::remove_file(name)
+    }
+
+    fn compile_func(&mut self, files: &[String]) -> Result<Cell> {
+        let Some(compiler) = &self.func else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "FunC compiler is not configured",
+            ));
+        };
+        compiler
+            .compile(self, files)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{e:?}")))
+    }
+
+    fn canonical_name(&self, name: &str) -> String {
+        match self.resolve_file(name) {
+            Ok(Resolved::File(path)) => match path.canonicalize() {
+                Ok(path) => path.display().to_string(),
+                Err(_) => name.to_owned(),
+            },
+            // NOTE: embedded libraries are the same for all packages
+            Ok(Resolved::Lib(_)) => {
+                let file = split_package(name).map_or(name, |(_, file)| file);
+                format!("<lib>/{file}")
+            }
+            Err(_) => name.to_owned(),
+        }
+    }
+
+    fn kv_store_path(&self) -> Option<String> {
+        self.kv_store.clone()
+    }
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let Some(file_name) = path.file_name() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("`{}` is not a file path", path.display()),
+        ));
+    };
+
+    // NOTE: temp file is created in the same directory to make `rename` atomic
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let res = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if res.is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+    res
 }
 
 enum Resolved {
     File(PathBuf),
     Lib(&'static str),
 }
+
+#[derive(Clone)]
+enum Package {
+    Dir(PathBuf),
+    Embedded,
+}
+
+fn split_package(name: &str) -> Option<(&str, &str)> {
+    let (package, file) = name.split_once(':')?;
+    // NOTE: single letters are left for Windows drive prefixes
+    (package.len() > 1 && is_package_name(package)).then_some((package, file))
+}
+
+fn is_package_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn same_file(left: &Path, right: &Path) -> bool {
+    match (left.canonicalize(), right.canonicalize()) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn not_found(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("`{name}` file not found"),
+    )
+}
+
+/// Parses the `[packages]` table of the manifest.
+fn parse_manifest(contents: &str, root: &Path) -> anyhow::Result<Vec<(String, Package)>> {
+    let mut manifest = contents.parse::<toml::Table>()?;
+    let packages = match manifest.remove("packages") {
+        Some(toml::Value::Table(packages)) => packages,
+        Some(_) => anyhow::bail!("`packages` must be a table"),
+        None => Default::default(),
+    };
+    if let Some(key) = manifest.keys().next() {
+        anyhow::bail!("Unexpected `{key}` outside of the `[packages]` section");
+    }
+
+    packages
+        .into_iter()
+        .map(|(name, value)| {
+            let package = match value {
+                toml::Value::String(path) => Package::Dir(root.join(path)),
+                toml::Value::Table(table)
+                    if table.len() == 1
+                        && table.get("embedded") == Some(&toml::Value::Boolean(true)) =>
+                {
+                    Package::Embedded
+                }
+                _ => anyhow::bail!(
+                    "Package `{name}` must be a directory string or `{{ embedded = true }}`"
+                ),
+            };
+            Ok((name, package))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_packages() {
+        let root = Path::new("root");
+        let packages = parse_manifest(
+            r#"
+            # Comment
+            [packages] # Comment after the header
+            ton = "ton/lib" # Comment with "quotes"
+            "quoted-key" = 'literal\path'
+            escaped = "dir\twith \"quotes\" # not a comment"
+            std = { embedded = true }
+            "#,
+            root,
+        )
+        .unwrap();
+
+        let packages = packages
+            .into_iter()
+            .map(|(name, package)| match package {
+                Package::Dir(path) => (name, Some(path)),
+                Package::Embedded => (name, None),
+            })
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(packages.len(), 4);
+        assert_eq!(packages["ton"], Some(root.join("ton/lib")));
+        assert_eq!(packages["quoted-key"], Some(root.join("literal\\path")));
+        assert_eq!(
+            packages["escaped"],
+            Some(root.join("dir\twith \"quotes\" # not a comment"))
+        );
+        assert_eq!(packages["std"], None);
+
+        for invalid in [
+            "[packages]\nton = \"a\"\nton = \"b\"",
+            "[packages]\nton = { embedded = false }",
+            "ton = \"a\"",
+            "[packages]\nton = \"unterminated",
+        ] {
+            assert!(parse_manifest(invalid, root).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn ambiguous_files_do_not_exist() {
+        let root = std::env::temp_dir().join(format!("fift-env-test-{}", std::process::id()));
+        for dir in ["a", "b"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("lib.fif"), "").unwrap();
+        }
+        let dirs = format!("{}:{}", root.join("a").display(), root.join("b").display());
+
+        let mut env = SystemEnvironment::with_include_dirs(&dirs).unwrap();
+        assert!(!env.file_exists("lib.fif"));
+        assert!(env.read_file("lib.fif").is_err());
+
+        std::fs::remove_file(root.join("b").join("lib.fif")).unwrap();
+        assert!(env.file_exists("lib.fif"));
+        assert!(env.read_file("lib.fif").is_ok());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn canonical_names() {
+        let root = std::env::temp_dir().join(format!("fift-env-names-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(root.join("lib").join("a.fif"), "").unwrap();
+
+        let mut env =
+            SystemEnvironment::with_include_dirs(&root.join("lib").display().to_string()).unwrap();
+        env.add_package("std", Package::Embedded).unwrap();
+
+        let other_name = root.join("lib").join("..").join("lib").join("a.fif");
+        assert_eq!(
+            env.canonical_name("a.fif"),
+            env.canonical_name(&other_name.display().to_string())
+        );
+        assert_eq!(env.canonical_name("Asm.fif"), "<lib>/Asm.fif");
+        assert_eq!(env.canonical_name("std:Asm.fif"), "<lib>/Asm.fif");
+        assert_eq!(env.canonical_name("missing.fif"), "missing.fif");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
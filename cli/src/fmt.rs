@@ -0,0 +1,259 @@
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use anyhow::{Context as _, Result};
+use argh::FromArgs;
+
+use fift::core::lexer::{Token, Tokenizer};
+
+/// Reformat scripts with consistent indentation and spacing
+#[derive(FromArgs)]
+#[argh(subcommand, name = "fmt")]
+pub struct Fmt {
+    /// source files to format (stdin will be used if empty)
+    #[argh(positional)]
+    inputs: Vec<String>,
+
+    /// do not write anything, fail if some file is not formatted
+    #[argh(switch)]
+    check: bool,
+
+    /// overwrite the files instead of printing them to stdout
+    #[argh(switch, short = 'w')]
+    write: bool,
+
+    /// number of spaces per indentation level. Defaults to 2
+    #[argh(option, default = "2")]
+    indent: usize,
+}
+
+// NOTE: the next word after these (active) words is a name or a text
+const NAME_READING_WORDS: &[&str] = &[":", "::", ":_", "::_", "'", "@'", "char"];
+// NOTE: the next word after these words is a name when they are used outside blocks
+const DEFINING_WORDS: &[&str] = &["constant", "2constant", "variable", "create", "forget"];
+// NOTE: the rest of the line after these words is kept as is
+const LINE_READING_WORDS: &[&str] = &["word"];
+
+impl Fmt {
+    pub fn run(self) -> Result<ExitCode> {
+        if self.inputs.is_empty() {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source)?;
+            let formatted = format_source("<stdin>", &source, self.indent)?;
+            if self.check {
+                return Ok(check_result(formatted == source, "<stdin>"));
+            }
+            std::io::stdout().write_all(formatted.as_bytes())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let mut all_formatted = true;
+        for path in &self.inputs {
+            let source = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read `{path}`"))?;
+            let formatted = format_source(path, &source, self.indent)?;
+            if self.check {
+                if check_result(formatted == source, path) != ExitCode::SUCCESS {
+                    all_formatted = false;
+                }
+            } else if self.write {
+                if formatted != source {
+                    std::fs::write(path, formatted)
+                        .with_context(|| format!("Failed to write `{path}`"))?;
+                }
+            } else {
+                std::io::stdout().write_all(formatted.as_bytes())?;
+            }
+        }
+
+        Ok(if all_formatted {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+}
+
+fn check_result(formatted: bool, path: &str) -> ExitCode {
+    if formatted {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("`{path}` is not formatted");
+        ExitCode::FAILURE
+    }
+}
+
+/// Re-prints the source with words of each line separated by a single space
+/// and lines indented by the nesting level of `{ }`, `[ ]` and `<{ }>` blocks.
+///
+/// Line breaks are kept (except for repeated empty lines) since some words
+/// read the rest of the line. Literals and comments are kept as is.
+fn format_source(name: &str, source: &str, indent: usize) -> Result<String> {
+    let items = classify_tokens(name, source)?;
+
+    let mut output = String::with_capacity(source.len());
+    // NOTE: contents of all blocks opened on the same line share the indentation level
+    let mut levels = Vec::<usize>::new();
+    let mut line_level = 0;
+    let mut prev_line = None::<usize>;
+    for (i, item) in items.iter().enumerate() {
+        match prev_line {
+            Some(line) if line == item.line_number => output.push(' '),
+            _ => {
+                if let Some(line) = prev_line {
+                    output.push('\n');
+                    if item.line_number > line + 1 {
+                        output.push('\n');
+                    }
+                }
+                // NOTE: closing words at the beginning of the line are not indented
+                let leading_closes = items[i..]
+                    .iter()
+                    .take_while(|next| {
+                        next.line_number == item.line_number && next.closes && !next.opens
+                    })
+                    .count();
+                let depth = levels.len().saturating_sub(leading_closes);
+                line_level = depth.checked_sub(1).map_or(0, |depth| levels[depth]);
+                output.push_str(&" ".repeat(line_level * indent));
+            }
+        }
+        output.push_str(item.text);
+
+        if item.closes {
+            levels.pop();
+        }
+        if item.opens {
+            levels.push(line_level + 1);
+        }
+        prev_line = Some(item.end_line_number);
+    }
+
+    if prev_line.is_some() {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+struct Item<'a> {
+    text: &'a str,
+    line_number: usize,
+    end_line_number: usize,
+    closes: bool,
+    opens: bool,
+}
+
+impl<'a> Item<'a> {
+    fn new(text: &'a str, line_number: usize) -> Self {
+        Self {
+            text,
+            line_number,
+            end_line_number: line_number + text.matches('\n').count(),
+            closes: false,
+            opens: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Block {
+    Brace,
+    Bracket,
+    Asm,
+}
+
+fn classify_tokens<'a>(name: &str, source: &'a str) -> Result<Vec<Item<'a>>> {
+    let mut items = Vec::new();
+    let mut prev_word = None;
+    // Open blocks with indices of their opening items
+    let mut blocks = Vec::<(Block, usize)>::new();
+
+    // NOTE: tokenizer is restarted after words which read the input themselves
+    let mut offset = 0;
+    let mut line_offset = 0;
+    'restart: loop {
+        for (token, span) in Tokenizer::new(&source[offset..]) {
+            let start = offset + span.start;
+            let line_number = line_offset + span.line_number;
+            // NOTE: words inside blocks are executed later
+            let interpreted = matches!(
+                blocks.iter().rev().find(|(block, _)| *block != Block::Asm),
+                None | Some((Block::Bracket, _))
+            );
+            let is_name = matches!(prev_word, Some(prev) if NAME_READING_WORDS.contains(&prev)
+                || interpreted && DEFINING_WORDS.contains(&prev));
+
+            let restart_at = match token {
+                // NOTE: names (e.g. after `char`) can look like the beginning of a literal
+                Token::String { .. } | Token::Data { .. } | Token::Unterminated(_) if is_name => {
+                    let rest = &source[start..];
+                    Some(&rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())])
+                }
+                Token::Word(word)
+                    if !is_name && interpreted && LINE_READING_WORDS.contains(&word) =>
+                {
+                    let rest = &source[start..];
+                    Some(rest[..rest.find('\n').unwrap_or(rest.len())].trim_end())
+                }
+                _ => None,
+            };
+            if let Some(text) = restart_at {
+                items.push(Item::new(text, line_number));
+                prev_word = None;
+                offset = start + text.len();
+                line_offset = line_number - 1;
+                continue 'restart;
+            }
+
+            let mut item = Item::new(&source[start..offset + span.end], line_number);
+            match token {
+                Token::Unterminated(_) => anyhow::bail!(
+                    "Unterminated literal at {name}:{line_number}:{}",
+                    source[..start]
+                        .rfind('\n')
+                        .map_or(start, |pos| start - pos - 1)
+                        + 1
+                ),
+                Token::Word(word) if !is_name => {
+                    let innermost = blocks.last().map(|(block, _)| *block);
+                    item.closes = match word {
+                        "}" => {
+                            // NOTE: unclosed asm blocks are not blocks (e.g. `@doafter<{ }`)
+                            while let Some((Block::Asm, opened)) = blocks.last() {
+                                items[*opened].opens = false;
+                                blocks.pop();
+                            }
+                            blocks.pop().is_some()
+                        }
+                        "]" => innermost == Some(Block::Bracket) && blocks.pop().is_some(),
+                        _ if word.starts_with("}>") => {
+                            innermost == Some(Block::Asm) && blocks.pop().is_some()
+                        }
+                        _ => false,
+                    };
+                    let block = match word {
+                        "{" => Some(Block::Brace),
+                        "[" => Some(Block::Bracket),
+                        // NOTE: `}>ELSE<{`-like words are closing and opening at once
+                        _ if word.ends_with("<{") && (item.closes || !word.starts_with("}>")) => {
+                            Some(Block::Asm)
+                        }
+                        _ => None,
+                    };
+                    if let Some(block) = block {
+                        item.opens = true;
+                        blocks.push((block, items.len()));
+                    }
+                }
+                _ => {}
+            }
+            prev_word = match token {
+                Token::Word(word) => Some(word),
+                Token::Comment(_) => prev_word,
+                _ => None,
+            };
+            items.push(item);
+        }
+        return Ok(items);
+    }
+}
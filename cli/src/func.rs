@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context as _, Result};
+use sha2::Digest;
+
+use fift::core::{Environment, SourceBlock};
+use fift::prelude::{Boc, Cell};
+
+use crate::env::SystemEnvironment;
+
+pub const DEFAULT_FUNC_COMMAND: &str = "func -SPA";
+
+/// An external compiler which produces Fift assembler code.
+#[derive(Clone)]
+pub struct FuncCompiler {
+    program: String,
+    args: Vec<String>,
+}
+
+impl FuncCompiler {
+    /// Parses a whitespace-separated command line, source files are appended to it.
+    pub fn new(command: &str) -> Result<Self> {
+        let mut parts = command.split_whitespace().map(str::to_owned);
+        let program = parts.next().context("Empty FunC compiler command")?;
+        Ok(Self {
+            program,
+            args: parts.collect(),
+        })
+    }
+
+    pub fn compile(&self, env: &SystemEnvironment, files: &[String]) -> Result<Cell> {
+        let cache_path = self.cache_path(env, files)?;
+        if let Some(Ok(data)) = cache_path.as_ref().map(std::fs::read) {
+            if let Ok(cell) = Boc::decode(data) {
+                return Ok(cell);
+            }
+        }
+
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .args(files)
+            .output()
+            .with_context(|| format!("Failed to run FunC compiler `{}`", self.program))?;
+        anyhow::ensure!(
+            output.status.success(),
+            "FunC compiler failed ({}):\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+        let asm = String::from_utf8(output.stdout).context("Invalid compiler output")?;
+
+        let cell = assemble(env, asm)?;
+
+        // NOTE: cache is best effort
+        if let Some(path) = &cache_path {
+            if let Some(dir) = path.parent() {
+                if std::fs::create_dir_all(dir).is_ok() {
+                    std::fs::write(path, Boc::encode(&cell)).ok();
+                }
+            }
+        }
+        Ok(cell)
+    }
+
+    /// Returns the cache file path for the compiler command, the contents
+    /// of the sources with all their (transitive) includes and the assembler.
+    ///
+    /// Sources with includes which can't be read (e.g. resolved by the compiler
+    /// itself, like `@stdlib/...`) are not cached.
+    fn cache_path(&self, env: &SystemEnvironment, files: &[String]) -> Result<Option<PathBuf>> {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(crate::util::VERSION.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.program.as_bytes());
+        for arg in &self.args {
+            hasher.update([0]);
+            hasher.update(arg.as_bytes());
+        }
+
+        // NOTE: the output is assembled with the libraries which can be overridden
+        for name in [fift_libs::base_lib().name, "Asm.fif"] {
+            let mut block = env.include(name)?;
+            let mut contents = Vec::new();
+            block.buffer_mut().read_to_end(&mut contents)?;
+            hasher.update([2]);
+            hasher.update(&contents);
+        }
+
+        let mut visited = Vec::new();
+        let mut queue = files.iter().map(PathBuf::from).collect::<Vec<_>>();
+        while let Some(path) = queue.pop() {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if visited.contains(&canonical) {
+                continue;
+            }
+            let Ok(contents) = std::fs::read(&path) else {
+                return Ok(None);
+            };
+
+            hasher.update([1]);
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(&contents);
+
+            let dir = path.parent().unwrap_or(Path::new(""));
+            for include in find_includes(&String::from_utf8_lossy(&contents)) {
+                queue.push(dir.join(include));
+            }
+            visited.push(canonical);
+        }
+
+        let dir = match env.get_env("FIFT_ARTIFACTS") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(".fift-artifacts"),
+        };
+        let hash = hex::encode(hasher.finalize());
+        Ok(Some(dir.join("func").join(format!("{hash}.boc"))))
+    }
+}
+
+/// Finds `#include "..."` (FunC) and `import "..."` (Tolk) paths.
+fn find_includes(source: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    for line in source.lines() {
+        let line = line.trim_start();
+        let Some(rest) = line
+            .strip_prefix("#include")
+            .or_else(|| line.strip_prefix("import"))
+        else {
+            continue;
+        };
+        let mut parts = rest.trim_start().split('"');
+        if let (Some(""), Some(path)) = (parts.next(), parts.next()) {
+            result.push(path);
+        }
+    }
+    result
+}
+
+/// Runs the generated Fift assembler code and returns the resulting cell.
+fn assemble(env: &SystemEnvironment, asm: String) -> Result<Cell> {
+    let mut env = env.clone();
+    let mut stdout = std::io::sink();
+
+    let base_lib = env.include(fift_libs::base_lib().name)?;
+    let mut ctx = fift::Context::new(&mut env, &mut stdout).with_basic_modules()?;
+
+    // NOTE: `Asm.fif` is included only once even if the code includes it too
+    let asm = format!("\"Asm.fif\" include\n{asm}");
+    ctx.add_source_block(SourceBlock::from_string("<func>", asm));
+    ctx.add_source_block(base_lib);
+    ctx.run()
+        .context("Failed to assemble the compiler output")?;
+
+    let cell = ctx
+        .stack
+        .pop_cell()
+        .context("Compiler output must produce a code cell")?;
+    Ok(cell.as_ref().clone())
+}
@@ -3,11 +3,13 @@ use std::io::{BufRead, Write};
 use std::rc::Rc;
 
 use anyhow::Result;
+use fift::core::lexer::{Token, Tokenizer};
 use rustyline::{DefaultEditor, ExternalPrinter};
 
 pub struct LineReader {
     editor: DefaultEditor,
     line: String,
+    pending: String,
     offset: usize,
     add_newline: Rc<Cell<bool>>,
     finished: bool,
@@ -19,6 +21,7 @@ impl LineReader {
         Ok(Self {
             editor,
             line: String::default(),
+            pending: String::default(),
             offset: 0,
             add_newline: Default::default(),
             finished: false,
@@ -79,21 +82,36 @@ impl std::io::BufRead for LineReader {
                     println!();
                 }
 
-                match self.editor.readline("> ") {
-                    Ok(line) if line.is_empty() => continue,
-                    Ok(mut line) => {
+                let prompt = if self.pending.is_empty() {
+                    "> "
+                } else {
+                    "... "
+                };
+                match self.editor.readline(prompt) {
+                    Ok(line) if line.is_empty() && self.pending.is_empty() => continue,
+                    Ok(line) => {
+                        self.pending.push_str(&line);
+                        self.pending.push('\n');
+                        if is_incomplete(&self.pending) {
+                            continue;
+                        }
+
+                        let input = std::mem::take(&mut self.pending);
                         {
-                            let line = line.trim();
-                            if !line.is_empty() {
-                                self.editor.add_history_entry(line.to_owned()).ok();
+                            let input = input.trim();
+                            if !input.is_empty() {
+                                self.editor.add_history_entry(input.to_owned()).ok();
                             }
                         }
 
-                        line.push('\n');
-                        self.line = line;
+                        self.line = input;
                         self.offset = 0;
                         break;
                     }
+                    // NOTE: interrupt only discards an unfinished multiline input
+                    Err(ReadlineError::Interrupted) if !self.pending.is_empty() => {
+                        self.pending.clear();
+                    }
                     Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
                         self.line = Default::default();
                         self.offset = 0;
@@ -105,10 +123,66 @@ impl std::io::BufRead for LineReader {
                 }
             }
         }
-        Ok(self.line[self.offset..].as_bytes())
+        Ok(&self.line.as_bytes()[self.offset..])
     }
 
     fn consume(&mut self, amt: usize) {
         self.offset += amt;
     }
 }
+
+/// Returns whether the input has unbalanced `{`/`(` or an unterminated
+/// multiline comment, so that more lines are required.
+///
+/// NOTE: string and data literals can't span multiple lines in Fift,
+/// so an unterminated literal is left for the interpreter to report.
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0isize;
+    let mut prev_word = None;
+    for (token, _) in Tokenizer::new(input) {
+        match token {
+            Token::Unterminated(text) if text.starts_with("/*") => return true,
+            // NOTE: the next token after `char` is used as a text
+            Token::Word(_) if prev_word == Some("char") => {}
+            Token::Word("{" | "(") => depth += 1,
+            Token::Word("}" | ")") => depth -= 1,
+            _ => {}
+        }
+        prev_word = match token {
+            Token::Word(word) => Some(word),
+            _ => None,
+        };
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incomplete_input() {
+        for input in [
+            "{ 1 2",
+            "{ { } ",
+            "( 1",
+            "/* comment",
+            "1 /* a */ /* b",
+            "{ char } ",
+        ] {
+            assert!(is_incomplete(input), "{input}");
+        }
+        for input in [
+            "",
+            "1 2 +",
+            "{ 1 } : a",
+            "/* a */",
+            "char {",
+            "\"{\" ",
+            "// {",
+            "\"open",
+        ] {
+            assert!(!is_incomplete(input), "{input}");
+        }
+    }
+}
@@ -1,22 +1,37 @@
 use std::io::IsTerminal;
+use std::path::Path;
 use std::process::ExitCode;
+use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use argh::FromArgs;
 use console::style;
-use unicode_width::UnicodeWidthStr;
 
-use fift::core::lexer::LexerPosition;
-use fift::core::{Environment, SourceBlock};
+use fift::core::{
+    cont, Cont, DisplayLimits, Environment, ExecutionLimits, RngSource, SourceBlock, StrictMode,
+};
+use fift::render::{Diagnostic, Report, Theme};
+use fift::util::decode_boc;
 
+use self::docgen::DocGen;
 use self::env::SystemEnvironment;
+use self::fmt::Fmt;
+use self::func::FuncCompiler;
 use self::input::LineReader;
+use self::minify::Minify;
 use self::modules::*;
 use self::util::{ArgsOrVersion, RestArgs, RestArgsDelimiter};
+use self::vectors::GenVectors;
 
+mod docgen;
 mod env;
+mod fmt;
+mod func;
 mod input;
+mod minify;
+mod signals;
 mod util;
+mod vectors;
 
 mod modules;
 
@@ -33,84 +48,348 @@ struct App {
     interactive: bool,
 
     /// sets color-separated library source include path.
+    /// Items in the form `name=dir` define packages for `name:file` includes.
     /// If not indicated, $FIFTPATH is used instead
     #[argh(option, short = 'I')]
     include: Option<String>,
 
+    /// a manifest with package directories.
+    /// If not indicated, `fiftpath.toml` from the current directory is used (if exists)
+    #[argh(option)]
+    manifest: Option<String>,
+
     /// sets an explicit path to the library source file.
     /// If not indicated, a default one will be used
     #[argh(option, short = 'L')]
     lib: Option<String>,
 
+    /// max number of bytes written to stdout per run.
+    /// Defaults to 1 GiB in non-interactive mode
+    #[argh(option)]
+    max_output: Option<u64>,
+
+    /// max number of nested continuations (e.g. non-tail recursive calls), 0 means unlimited.
+    /// Defaults to 1048576
+    #[argh(option)]
+    max_cont_depth: Option<usize>,
+
+    /// global TVM version which the scripts target.
+    /// If not indicated, the latest supported version is used
+    #[argh(option)]
+    target_version: Option<u32>,
+
+    /// write a Make-compatible dependency file with all included and read files.
+    /// The target is the file path without the `.d` extension
+    #[argh(option)]
+    depfile: Option<String>,
+
+    /// use a fixed time and seeded RNG for reproducible runs.
+    /// The time is taken from $SOURCE_DATE_EPOCH (or zero).
+    /// Keys and mnemonics are still random
+    #[argh(option)]
+    deterministic: Option<u64>,
+
+    /// print per-word call counts and timings to stderr at exit
+    #[argh(switch)]
+    profile: bool,
+
+    /// record source positions of compiled words to show them in backtraces
+    #[argh(switch)]
+    source_maps: bool,
+
+    /// write per-file line hits and per-word call counts as JSON to the file at exit
+    #[argh(option)]
+    coverage: Option<String>,
+
+    /// print the final stack as JSON to stdout at exit
+    #[argh(switch)]
+    dump_stack_json: bool,
+
+    /// a BOC file with library cells (or a directory of such `.boc` files)
+    /// to register in `vmlibs` before execution. Can be repeated
+    #[argh(option)]
+    vmlib: Vec<String>,
+
+    /// a BOC file to load before execution. In the form `name=path` its root cell
+    /// is bound to a constant word `name`, otherwise it is pushed onto the stack.
+    /// Can be repeated
+    #[argh(option)]
+    boc: Vec<String>,
+
+    /// a FunC (or Tolk) compiler command used by `func>cell`, which prints Fift assembler code.
+    /// If not indicated, $FIFT_FUNC is used instead (or `func -SPA`)
+    #[argh(option)]
+    func: Option<String>,
+
+    /// a JSON file used by `kv-get` and `kv-put`. If not indicated, $FIFT_KV
+    /// is used instead (or `<script>.kv.json` next to the first source file)
+    #[argh(option)]
+    kv_store: Option<String>,
+
+    /// fail on words which are missing in the reference implementation
+    #[argh(switch)]
+    strict: bool,
+
+    /// print usages of words which are missing in the reference implementation
+    /// to stderr at exit
+    #[argh(switch)]
+    warn_nonstandard: bool,
+
+    /// format of error messages: `human` (default) or `json` (one object per line)
+    #[argh(option, default = "ErrorFormat::Human")]
+    error_format: ErrorFormat,
+
+    /// a Fift snippet to execute before source files. Can be repeated,
+    /// stdin is not used if only snippets are indicated
+    #[argh(option, short = 'e')]
+    eval: Vec<String>,
+
     /// a list of source files to execute (stdin will be used if empty)
     #[argh(positional)]
     source_files: Vec<String>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    DocGen(DocGen),
+    GenVectors(GenVectors),
+    Minify(Minify),
+    Fmt(Fmt),
 }
 
+impl Command {
+    fn run(self) -> Result<ExitCode> {
+        match self {
+            Self::DocGen(docgen) => docgen.run().map(|_| ExitCode::SUCCESS),
+            Self::GenVectors(gen_vectors) => gen_vectors.run().map(|_| ExitCode::SUCCESS),
+            Self::Minify(minify) => minify.run().map(|_| ExitCode::SUCCESS),
+            Self::Fmt(fmt) => fmt.run(),
+        }
+    }
+}
+
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("Unknown error format `{s}`, expected `human` or `json`"),
+        }
+    }
+}
+
+const DEFAULT_MAX_OUTPUT_BYTES: u64 = 1 << 30;
+
+const STDIN_BLOCK_NAME: &str = "<stdin>";
+const STDIN_SCRIPT_PATH: &str = "-";
+const EVAL_BLOCK_NAME: &str = "<eval>";
+
 #[derive(Default)]
 struct ScriptModeDelim;
 
 impl RestArgsDelimiter for ScriptModeDelim {
     const DELIM: &'static str = "-s";
-    const DESCR: &'static str = r"script mode: use first argument as a fift source file and
-                    import remaining arguments as $n";
+    const DESCR: &'static str = r"script mode: use first argument as a fift source file
+                    (`-` for stdin) and import remaining arguments as $n";
 }
 
 fn main() -> Result<ExitCode> {
-    let RestArgs(ArgsOrVersion::<App>(app), rest, ScriptModeDelim) = argh::from_env();
+    let RestArgs(ArgsOrVersion::<App>(mut app), rest, ScriptModeDelim) = argh::from_env();
+    if let Some(command) = app.command.take() {
+        return command.run();
+    }
 
     // Prepare system environment
     let mut env = SystemEnvironment::with_include_dirs(
         &app.include
+            .clone()
             .unwrap_or_else(|| std::env::var("FIFTPATH").unwrap_or_default()),
-    );
+    )?;
+    match &app.manifest {
+        Some(path) => env.load_manifest(Path::new(path))?,
+        None => {
+            let path = Path::new(env::MANIFEST_NAME);
+            if path.is_file() {
+                env.load_manifest(path)?;
+            }
+        }
+    }
+
+    let func = match &app.func {
+        Some(command) => command.clone(),
+        None => std::env::var("FIFT_FUNC")
+            .ok()
+            .filter(|command| !command.trim().is_empty())
+            .unwrap_or_else(|| func::DEFAULT_FUNC_COMMAND.to_owned()),
+    };
+    env.set_func_compiler(FuncCompiler::new(&func)?);
+
+    let kv_store = app
+        .kv_store
+        .clone()
+        .or_else(|| {
+            std::env::var("FIFT_KV")
+                .ok()
+                .filter(|path| !path.is_empty())
+        })
+        .or_else(|| {
+            let script = rest
+                .first()
+                .filter(|script| *script != STDIN_SCRIPT_PATH)
+                .or(app.source_files.first())?;
+            Some(format!("{script}.kv.json"))
+        });
+    if let Some(path) = kv_store {
+        env.set_kv_store(path);
+    }
 
-    let interactive = app.interactive || rest.is_empty() && app.source_files.is_empty();
+    let interactive =
+        app.interactive || rest.is_empty() && app.source_files.is_empty() && app.eval.is_empty();
+    anyhow::ensure!(
+        !(interactive && rest.first().map(String::as_str) == Some(STDIN_SCRIPT_PATH)),
+        "Script can't be read from stdin in interactive mode"
+    );
 
     // Prepare the source block which will be executed
     let mut stdout: Box<dyn std::io::Write> = Box::new(std::io::stdout());
 
     let mut source_blocks = Vec::new();
 
+    // NOTE: words defined before the interactive input are not saved in sessions
+    let session = SessionUtils::default();
     if interactive {
         if std::io::stdin().is_terminal() {
             let mut line_reader = LineReader::new()?;
             stdout = line_reader.create_external_printer()?;
-            source_blocks.push(SourceBlock::new("<stdin>", line_reader));
+            let line_reader = session.mark_on_read(line_reader);
+            source_blocks.push(SourceBlock::new(STDIN_BLOCK_NAME, line_reader));
         } else {
-            source_blocks.push(SourceBlock::new("<stdin>", std::io::stdin().lock()));
+            let stdin = session.mark_on_read(std::io::stdin().lock());
+            source_blocks.push(SourceBlock::new(STDIN_BLOCK_NAME, stdin));
         }
     }
+    source_blocks.extend(script_source_blocks(&app, rest.first(), &env)?);
+
+    // NOTE: resolver is cloned since the environment is borrowed by the context
+    let depfile = app.depfile.map(|path| {
+        let deps = source_blocks
+            .iter()
+            .filter(|block| is_file_block(block))
+            .map(|block| block.name().to_owned())
+            .collect::<Vec<_>>();
+        (path, deps, env.clone())
+    });
+
+    let rng = match app.deterministic {
+        Some(seed) => {
+            let now = std::env::var("SOURCE_DATE_EPOCH")
+                .ok()
+                .and_then(|epoch| epoch.parse::<u64>().ok())
+                .unwrap_or_default();
+            RngSource::deterministic(seed, now * 1000)
+        }
+        None => RngSource::System,
+    };
 
-    if let Some(path) = rest.first() {
-        source_blocks.push(env.include(path)?);
-    }
+    // Prepare Fift context
+    let mut ctx = fift::Context::new(&mut env, &mut stdout)
+        .with_rng(rng)
+        .with_basic_modules()?
+        .with_cmd_args(rest)?
+        .with_module(ShellUtils)?
+        .with_module(session)?;
 
-    for path in app.source_files.into_iter().rev() {
-        source_blocks.push(env.include(&path)?);
-    }
+    signals::install_handlers(ctx.interrupts.clone());
 
-    // Prepare preamble block
-    if let Some(lib) = &app.lib {
-        source_blocks.push(env.include(lib)?);
-    } else if !app.bare {
-        source_blocks.push(env.include(fift_libs::base_lib().name)?);
+    for path in &app.vmlib {
+        load_vm_libraries(&ctx, Path::new(path))
+            .with_context(|| format!("Failed to load VM libraries from `{path}`"))?;
     }
 
-    // Prepare Fift context
-    let mut ctx = fift::Context::new(&mut env, &mut stdout)
-        .with_basic_modules()?
-        .with_module(CmdArgsUtils::new(rest))?
-        .with_module(ShellUtils)?;
+    for arg in &app.boc {
+        load_boc(&mut ctx, arg).with_context(|| format!("Failed to load BOC `{arg}`"))?;
+    }
 
     for source_block in source_blocks {
+        if is_file_block(&source_block) {
+            ctx.mark_included(source_block.name());
+        }
         ctx.add_source_block(source_block);
     }
 
+    if app.profile {
+        ctx.enable_profiling();
+    }
+
+    ctx.source_maps = app.source_maps;
+
+    // NOTE: bundled libraries are allowed to use extensions
+    for name in fift_libs::all().keys() {
+        ctx.allow_nonstandard_in(name);
+    }
+    ctx.strict = if app.strict {
+        StrictMode::Deny
+    } else if app.warn_nonstandard {
+        StrictMode::Warn
+    } else {
+        StrictMode::Off
+    };
+
+    if app.coverage.is_some() {
+        ctx.enable_coverage();
+    }
+
+    let max_output_bytes = match app.max_output {
+        Some(limit) => Some(limit),
+        None if !interactive => Some(DEFAULT_MAX_OUTPUT_BYTES),
+        None => None,
+    };
+    let max_cont_depth = match app.max_cont_depth {
+        Some(0) => None,
+        Some(limit) => Some(limit),
+        None => Some(ExecutionLimits::DEFAULT_MAX_CONT_DEPTH),
+    };
+    ctx.set_limits(ExecutionLimits {
+        max_output_bytes,
+        max_cont_depth,
+        ..Default::default()
+    });
+
+    ctx.target_version = app.target_version;
+
+    if interactive {
+        ctx.display_limits = DisplayLimits {
+            max_items: Some(64),
+            max_len: Some(256),
+            max_depth: Some(8),
+        };
+    }
+
     // Execute
     loop {
         let error = match ctx.run() {
-            Ok(exit_code) => return Ok(ExitCode::from(!exit_code)),
+            Ok(outcome) => {
+                if let Some((path, mut deps, resolver)) = depfile {
+                    deps.extend_from_slice(ctx.accessed_files());
+                    write_depfile(&path, &deps, &resolver)?;
+                }
+                print_profile(&mut ctx);
+                print_nonstandard_usages(&ctx);
+                write_coverage(&ctx, app.coverage.as_deref())?;
+                dump_stack_json(&ctx, app.dump_stack_json)?;
+                return Ok(ExitCode::from(!outcome.exit_code()));
+            }
             Err(e) => e,
         };
 
@@ -118,19 +397,16 @@ fn main() -> Result<ExitCode> {
             eprintln!("{}", style("!!!").dim())
         }
 
-        if let Some(pos) = ctx.input.get_position() {
-            eprintln!("{}", Report { pos, error });
-        };
-
-        if let Some(next) = ctx.next.take() {
-            eprintln!(
-                "{}\n{}",
-                style("backtrace:").red(),
-                style(next.display_backtrace(&ctx.dicts.current)).dim()
-            );
+        match app.error_format {
+            ErrorFormat::Human => print_error_report(&mut ctx, error),
+            ErrorFormat::Json => print_error_json(&mut ctx, error),
         }
 
         if !interactive {
+            print_profile(&mut ctx);
+            print_nonstandard_usages(&ctx);
+            write_coverage(&ctx, app.coverage.as_deref())?;
+            dump_stack_json(&ctx, app.dump_stack_json)?;
             return Ok(ExitCode::FAILURE);
         }
 
@@ -140,51 +416,188 @@ fn main() -> Result<ExitCode> {
     }
 }
 
-struct Report<'a, E> {
-    pos: LexerPosition<'a>,
-    error: E,
-}
-
-impl<E> std::fmt::Display for Report<'_, E>
-where
-    E: std::fmt::Debug,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let line_number = self.pos.line_number.to_string();
-        let offset_len = line_number.len();
-        let offset = format!("{:offset_len$}", "");
-
-        let arrow = style("-->").blue().bold();
-        let block = style("|").blue().bold();
-        let line_number = style(line_number).blue().bold();
-
-        let line = self.pos.line.trim_end();
-        let word_start = std::cmp::min(self.pos.word_start, line.len());
-        let word_end = std::cmp::min(self.pos.word_end, line.len());
-        let (line_start, rest) = line.split_at(word_start);
-        let (underlined, line_end) = rest.split_at(word_end - word_start);
-
-        let line_start_len = UnicodeWidthStr::width(line_start);
-        let underlined_len = UnicodeWidthStr::width(underlined);
-
-        write!(
-            f,
-            "{}{:?}\n\
-            {offset}{arrow} {}:{}:{}\n\
-            {offset} {block}\n\
-            {line_number} {block} {}{}{}\n\
-            {offset} {block} {:line_start_len$}{}\n\
-            {offset} {block}",
-            style("error: ").red(),
-            style(&self.error).bold(),
-            self.pos.source_block_name,
-            self.pos.line_number,
-            self.pos.word_start + 1,
-            line_start,
-            style(underlined).red(),
-            line_end,
-            "",
-            style(format!("{:->1$}", "", underlined_len)).red(),
-        )
+fn print_error_report(ctx: &mut fift::Context<'_>, error: anyhow::Error) {
+    if let Some(pos) = ctx.input.get_position() {
+        let origin = ctx
+            .next
+            .as_ref()
+            .and_then(|next| next.find_source_position());
+        let theme = Theme::ascii().with_color(console::colors_enabled());
+        let report = Report::new(pos, error)
+            .with_origin(origin)
+            .with_theme(theme);
+        eprintln!("{report}");
+    };
+
+    if let Some(next) = ctx.next.take() {
+        eprintln!(
+            "{}\n{}",
+            style("backtrace:").red(),
+            style(next.display_backtrace(&ctx.dicts.current)).dim()
+        );
+    }
+}
+
+fn print_error_json(ctx: &mut fift::Context<'_>, error: anyhow::Error) {
+    let message = format!("{error:#}");
+    let diagnostic = match ctx.input.get_position() {
+        Some(pos) => Diagnostic::new(pos, message),
+        None => Diagnostic {
+            message,
+            ..Default::default()
+        },
+    };
+
+    let next = ctx.next.take();
+    let diagnostic = diagnostic
+        .with_origin(next.as_ref().and_then(|next| next.find_source_position()))
+        .with_backtrace(next.map(|next| next.backtrace(&ctx.dicts.current)));
+    eprintln!("{}", diagnostic.to_json());
+}
+
+/// Returns non-interactive source blocks in the reversed order of execution.
+fn script_source_blocks(
+    app: &App,
+    script: Option<&String>,
+    env: &SystemEnvironment,
+) -> Result<Vec<SourceBlock>> {
+    let mut source_blocks = Vec::new();
+
+    match script {
+        Some(path) if path == STDIN_SCRIPT_PATH => {
+            let stdin = std::io::stdin().lock();
+            source_blocks.push(SourceBlock::new(STDIN_BLOCK_NAME, stdin));
+        }
+        Some(path) => source_blocks.push(env.include(path)?),
+        None => {}
+    }
+
+    for path in app.source_files.iter().rev() {
+        source_blocks.push(env.include(path)?);
+    }
+
+    for expr in app.eval.iter().rev() {
+        source_blocks.push(SourceBlock::from_string(EVAL_BLOCK_NAME, expr.as_str()));
+    }
+
+    // Prepare preamble block
+    if let Some(lib) = &app.lib {
+        source_blocks.push(env.include(lib)?);
+    } else if !app.bare {
+        source_blocks.push(env.include(fift_libs::base_lib().name)?);
+    }
+
+    Ok(source_blocks)
+}
+
+fn is_file_block(block: &SourceBlock) -> bool {
+    !matches!(block.name(), STDIN_BLOCK_NAME | EVAL_BLOCK_NAME)
+}
+
+fn write_depfile(path: &str, deps: &[String], resolver: &SystemEnvironment) -> Result<()> {
+    fn escape(path: &str) -> String {
+        path.replace('$', "$$").replace(' ', "\\ ")
+    }
+
+    let target = path.strip_suffix(".d").unwrap_or(path);
+    let mut paths = Vec::new();
+    for name in deps {
+        // NOTE: embedded libraries have no path
+        if let Some(path) = resolver.resolve_path(name) {
+            let path = escape(&path.to_string_lossy());
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    let mut contents = format!("{}:", escape(target));
+    for path in &paths {
+        contents.push_str(" \\\n  ");
+        contents.push_str(path);
+    }
+    contents.push('\n');
+
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+fn print_profile(ctx: &mut fift::Context<'_>) {
+    if let Some(profiler) = &mut ctx.profiler {
+        profiler.finish();
+        eprintln!("{}", profiler.display_report());
+    }
+}
+
+fn load_vm_libraries(ctx: &fift::Context<'_>, path: &Path) -> Result<()> {
+    if !path.is_dir() {
+        let data = std::fs::read(path)?;
+        ctx.vm_libraries.add_boc(&data)?;
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some("boc".as_ref()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    for file in files {
+        let data = std::fs::read(&file)?;
+        ctx.vm_libraries
+            .add_boc(&data)
+            .with_context(|| format!("Invalid BOC `{}`", file.display()))?;
+    }
+    Ok(())
+}
+
+fn load_boc(ctx: &mut fift::Context<'_>, arg: &str) -> Result<()> {
+    let (name, path) = match arg.split_once('=') {
+        Some((name, path)) => (Some(name), path),
+        None => (None, arg),
+    };
+
+    let data = std::fs::read(path)?;
+    let cell = decode_boc(&data)?;
+    match name {
+        Some(name) => {
+            anyhow::ensure!(
+                !name.is_empty() && !name.contains(char::is_whitespace),
+                "Invalid word name `{name}`"
+            );
+            let cont = Rc::new(cont::LitCont(Rc::new(cell))) as Cont;
+            ctx.dicts.current.define_word(format!("{name} "), cont)
+        }
+        None => ctx.stack.push(cell),
+    }
+}
+
+fn print_nonstandard_usages(ctx: &fift::Context<'_>) {
+    for usage in ctx.nonstandard_usages() {
+        let mut message = format!("nonstandard word `{}`", usage.word.name.trim_end());
+        if let Some(position) = &usage.position {
+            message.push_str(&format!(" at {position}"));
+        }
+        if let Some(suggestion) = usage.word.suggestion {
+            message.push_str(&format!(", use {suggestion} instead"));
+        }
+        eprintln!("{} {message}", style("warning:").yellow());
+    }
+}
+
+fn dump_stack_json(ctx: &fift::Context<'_>, enabled: bool) -> Result<()> {
+    if enabled {
+        println!("{}", ctx.stack_to_json()?);
+    }
+    Ok(())
+}
+
+fn write_coverage(ctx: &fift::Context<'_>, path: Option<&str>) -> Result<()> {
+    if let (Some(coverage), Some(path)) = (&ctx.coverage, path) {
+        std::fs::write(path, coverage.to_json())?;
     }
+    Ok(())
 }
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use anyhow::{Context as _, Result};
+use argh::FromArgs;
+use sha2::Digest;
+
+use fift::core::env::EmptyEnvironment;
+use fift::core::lexer::{Span, Token, Tokenizer};
+use fift::core::SourceBlock;
+
+use crate::modules::*;
+
+/// Strip comments and whitespace from the script and shorten names of its words
+#[derive(FromArgs)]
+#[argh(subcommand, name = "minify")]
+pub struct Minify {
+    /// a source file to minify
+    #[argh(positional)]
+    input: String,
+
+    /// write the minified script to the specified file instead of stdout
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// do not shorten names of the words defined in the script
+    #[argh(switch)]
+    keep_names: bool,
+
+    /// verify the checksum of an already minified script instead
+    #[argh(switch)]
+    check: bool,
+}
+
+// NOTE: prefix words (`:_`, `::_`) are not renamed since they are not separate tokens
+const DEFINING_WORDS: &[&str] = &[":", "::", "constant", "2constant", "variable", "create"];
+// NOTE: the next token after these words is used as a text
+const TEXT_READING_WORDS: &[&str] = &["char", "word"];
+// NOTE: included files can use words defined before the inclusion by name
+const INCLUDE_WORDS: &[&str] = &["include", "include-once"];
+
+const CHECKSUM_PREFIX: &str = "// sha256: ";
+
+impl Minify {
+    pub fn run(self) -> Result<()> {
+        let source = std::fs::read_to_string(&self.input)
+            .with_context(|| format!("Failed to read `{}`", self.input))?;
+
+        if self.check {
+            return verify_checksum(&source)
+                .with_context(|| format!("Failed to verify `{}`", self.input));
+        }
+
+        let output = minify(&source, self.keep_names)
+            .with_context(|| format!("Failed to minify `{}`", self.input))?;
+        match &self.output {
+            Some(path) => std::fs::write(path, output)?,
+            None => std::io::stdout().write_all(output.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+/// Returns the minified script with a checksum comment on the last line.
+fn minify(source: &str, keep_names: bool) -> Result<String> {
+    let tokens = Tokenizer::new(source).collect::<Vec<_>>();
+    if let Some((_, span)) = tokens
+        .iter()
+        .find(|(token, _)| matches!(token, Token::Unterminated(_)))
+    {
+        anyhow::bail!(
+            "Unterminated literal at {}:{}",
+            span.line_number,
+            span.column
+        );
+    }
+
+    let renames = if keep_names {
+        HashMap::new()
+    } else {
+        find_renames(&tokens)?
+    };
+
+    let mut output = String::with_capacity(source.len());
+    let mut prev_line = None;
+    for (token, span) in &tokens {
+        let text = match token {
+            // NOTE: the shebang line is required to run the script directly
+            Token::Comment(text) if span.start == 0 && text.starts_with("#!") => *text,
+            Token::Comment(_) => continue,
+            Token::Word(word) => renames.get(word).map_or(*word, String::as_str),
+            _ => &source[span.start..span.end],
+        };
+
+        // NOTE: line breaks are kept for words which read the rest of the line
+        match prev_line {
+            Some(line) if line == span.line_number => output.push(' '),
+            Some(_) => output.push('\n'),
+            None => {}
+        }
+        output.push_str(text);
+        prev_line = Some(span.line_number);
+    }
+    output.push('\n');
+
+    let checksum = hex::encode(sha2::Sha256::digest(&output));
+    output.push_str(CHECKSUM_PREFIX);
+    output.push_str(&checksum);
+    output.push('\n');
+    Ok(output)
+}
+
+/// Checks that the minified script was not modified after minification.
+fn verify_checksum(source: &str) -> Result<()> {
+    let body_len = source
+        .trim_end()
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or_default();
+    let (body, last_line) = source.split_at(body_len);
+    let checksum = last_line
+        .trim_end()
+        .strip_prefix(CHECKSUM_PREFIX)
+        .context("No checksum found")?;
+    anyhow::ensure!(
+        checksum == hex::encode(sha2::Sha256::digest(body)),
+        "Checksum mismatch"
+    );
+    Ok(())
+}
+
+/// Assigns short names to the words defined in the script.
+///
+/// Only words which are not predefined, are not mentioned in string literals,
+/// are not used before their first definition and are not defined before
+/// an include are renamed.
+fn find_renames<'a>(tokens: &[(Token<'a>, Span)]) -> Result<HashMap<&'a str, String>> {
+    let predefined = predefined_words()?;
+
+    let mut used = HashSet::new();
+    let mut counts = HashMap::<&str, (usize, usize)>::new();
+    let mut rejected = HashSet::new();
+    let mut in_strings = String::new();
+    let mut last_include = None;
+
+    let mut prev_word = None;
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::Word(word) => {
+                used.insert(*word);
+                if INCLUDE_WORDS.contains(word) {
+                    last_include = Some(i);
+                }
+                let is_definition =
+                    matches!(prev_word, Some(prev) if DEFINING_WORDS.contains(&prev));
+                if matches!(prev_word, Some(prev) if TEXT_READING_WORDS.contains(&prev)) {
+                    rejected.insert(*word);
+                }
+                match counts.get_mut(word) {
+                    Some((count, _)) => *count += 1,
+                    None if is_definition && !predefined.contains(*word) => {
+                        counts.insert(word, (1, i));
+                    }
+                    None => {
+                        rejected.insert(*word);
+                    }
+                }
+                prev_word = Some(*word);
+            }
+            Token::String { value, .. } => {
+                in_strings.push_str(value);
+                in_strings.push('\n');
+                prev_word = None;
+            }
+            Token::Comment(_) => {}
+            _ => prev_word = None,
+        }
+    }
+
+    let mut candidates = counts
+        .into_iter()
+        .filter(|(word, (_, pos))| {
+            !rejected.contains(word)
+                && !in_strings.contains(*word)
+                && !matches!(last_include, Some(include) if include > *pos)
+        })
+        .collect::<Vec<_>>();
+    // NOTE: the most used words get the shortest names
+    candidates.sort_by(|(_, (a_count, a_pos)), (_, (b_count, b_pos))| {
+        b_count.cmp(a_count).then(a_pos.cmp(b_pos))
+    });
+
+    let mut names = ShortNames::default();
+    let mut renames = HashMap::with_capacity(candidates.len());
+    for (word, _) in candidates {
+        let name = loop {
+            let name = names.next();
+            if !predefined.contains(&name) && !used.contains(name.as_str()) {
+                break name;
+            }
+        };
+        if name.len() < word.len() {
+            renames.insert(word, name);
+        }
+    }
+    Ok(renames)
+}
+
+fn predefined_words() -> Result<HashSet<String>> {
+    let mut env = EmptyEnvironment;
+    let mut stdout = std::io::sink();
+    let mut ctx = fift::Context::new(&mut env, &mut stdout)
+        .with_basic_modules()?
+        .with_cmd_args(Vec::new())?
+        .with_module(ShellUtils)?;
+
+    // NOTE: words of the bundled libraries are also reserved since scripts can include them
+    let mut includes = String::new();
+    for (name, content) in fift_libs::all() {
+        ctx.add_virtual_file(*name, *content);
+        if *name != fift_libs::base_lib().name {
+            includes.push_str(&format!("\"{name}\" include\n"));
+        }
+    }
+
+    let base_lib = fift_libs::base_lib();
+    ctx.add_source_block(SourceBlock::from_string("<libs>", includes));
+    ctx.add_source_block(SourceBlock::new(
+        base_lib.name,
+        std::io::Cursor::new(base_lib.content),
+    ));
+    ctx.run()?;
+
+    let mut words = HashSet::new();
+    for (name, _) in ctx.dicts.current.iter()? {
+        words.insert(name.trim_end().to_owned());
+    }
+    Ok(words)
+}
+
+/// Generates `a`, `b`, ..., `z`, `aa`, `ab`, ...
+#[derive(Default)]
+struct ShortNames {
+    index: usize,
+}
+
+impl ShortNames {
+    fn next(&mut self) -> String {
+        let mut index = self.index;
+        self.index += 1;
+
+        let mut name = Vec::new();
+        loop {
+            name.push(b'a' + (index % 26) as u8);
+            index /= 26;
+            if index == 0 {
+                break;
+            }
+            index -= 1;
+        }
+        name.reverse();
+        String::from_utf8(name).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIB: &str = "{ shared-word 1 + } : lib-result\n";
+
+    const SCRIPT: &str = r#"#!/usr/bin/env -S fift -s
+{ 42 } : shared-word
+"lib.fif" include
+// Squares the value
+{ dup * } : square-it
+{ square-it 1 + } : next-value
+/* Multiline
+   comment */
+5 next-value . cr
+variable counter
+10 counter !  counter @ square-it .
+."done" cr
+lib-result .
+"#;
+
+    fn run_script(source: &str) -> (String, String) {
+        let mut env = EmptyEnvironment;
+        let mut stdout = Vec::new();
+        let stack = {
+            let mut ctx = fift::Context::new(&mut env, &mut stdout)
+                .with_basic_modules()
+                .unwrap();
+            ctx.add_virtual_file("lib.fif", LIB);
+
+            let base_lib = fift_libs::base_lib();
+            ctx.add_source_str("<script>", source);
+            ctx.add_source_block(SourceBlock::new(
+                base_lib.name,
+                std::io::Cursor::new(base_lib.content),
+            ));
+            ctx.run().unwrap();
+            ctx.stack_to_json().unwrap()
+        };
+        (stack, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn minified_script_behaves_the_same() {
+        let minified = minify(SCRIPT, false).unwrap();
+        assert!(minified.starts_with("#!/usr/bin/env -S fift -s\n"));
+        assert!(!minified.contains("next-value"));
+        // Used by the included file
+        assert!(minified.contains("shared-word"));
+
+        assert_eq!(run_script(&minified), run_script(SCRIPT));
+
+        verify_checksum(&minified).unwrap();
+        let tampered = minified.replace("10", "11");
+        assert!(verify_checksum(&tampered).is_err());
+    }
+}
@@ -1,5 +1,5 @@
-pub use self::args::CmdArgsUtils;
+pub use self::session::SessionUtils;
 pub use self::shell::ShellUtils;
 
-mod args;
+mod session;
 mod shell;
@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{BufRead, Read};
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use fift::core::*;
+
+/// Words to save and restore interpreter sessions.
+///
+/// Only words which were defined or redefined after the session start are saved.
+/// The session starts when the module is added, and is restarted by the first
+/// read from the input wrapped with [`SessionUtils::mark_on_read`].
+#[derive(Default, Clone)]
+pub struct SessionUtils {
+    state: Rc<SessionState>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    dict: RefCell<Option<Dictionary>>,
+    baseline: RefCell<HashSet<(Rc<String>, *const ())>>,
+}
+
+impl SessionState {
+    fn mark(&self) -> Result<()> {
+        let mut baseline = HashSet::new();
+        if let Some(d) = &*self.dict.borrow() {
+            for (name, entry) in d.iter()? {
+                baseline.insert((name, Rc::as_ptr(&entry.definition) as *const ()));
+            }
+        }
+        *self.baseline.borrow_mut() = baseline;
+        Ok(())
+    }
+
+    fn session_words(&self, d: &Dictionary) -> Result<Vec<(Rc<String>, DictionaryEntry)>> {
+        let baseline = self.baseline.borrow();
+        let mut words = d
+            .iter()?
+            .filter(|(name, entry)| {
+                let ptr = Rc::as_ptr(&entry.definition) as *const ();
+                !baseline.contains(&(name.clone(), ptr))
+            })
+            .collect::<Vec<_>>();
+        words.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(words)
+    }
+}
+
+impl SessionUtils {
+    /// Wraps the input to start the session when it is read for the first time.
+    pub fn mark_on_read<R: BufRead>(&self, reader: R) -> SessionStart<R> {
+        SessionStart {
+            reader,
+            state: Some(self.state.clone()),
+        }
+    }
+}
+
+#[fift_module]
+impl SessionUtils {
+    #[init]
+    fn init(&self, d: &mut Dictionary) -> Result<()> {
+        // save-session (S -- )
+        let state = self.state.clone();
+        d.define_closure_word("save-session ", move |ctx| {
+            let path = ctx.stack.pop_string()?;
+            let d = &ctx.dicts.current;
+            let words = state.session_words(d)?;
+            let (data, skipped) = suspend::serialize_session(&words, ctx.stack.items(), d)?;
+            ctx.env.write_file(&path, &data, WriteMode::Truncate)?;
+
+            if !skipped.is_empty() {
+                let names = skipped
+                    .iter()
+                    .map(|name| name.trim_end())
+                    .collect::<Vec<_>>();
+                eprintln!(
+                    "warning: skipped unserializable words: {}",
+                    names.join(", ")
+                );
+            }
+            Ok(())
+        })?;
+
+        // load-session (S -- )
+        d.define_closure_word("load-session ", |ctx| {
+            let path = ctx.stack.pop_string()?;
+            let data = ctx.env.read_file(&path)?;
+            let session =
+                suspend::deserialize_session(&data, &ctx.dicts.current, ctx.stack.atoms_mut())?;
+            for (name, entry) in session.words {
+                ctx.dicts.current.define_word(name, entry)?;
+            }
+            for item in session.stack {
+                ctx.stack.push_raw(item)?;
+            }
+            Ok(())
+        })?;
+
+        *self.state.dict.borrow_mut() = Some(d.clone());
+        self.state.mark()
+    }
+}
+
+pub struct SessionStart<R> {
+    reader: R,
+    state: Option<Rc<SessionState>>,
+}
+
+impl<R> SessionStart<R> {
+    fn start(&mut self) -> std::io::Result<()> {
+        if let Some(state) = self.state.take() {
+            state
+                .mark()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SessionStart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.start()?;
+        self.reader.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for SessionStart<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.start()?;
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
@@ -12,8 +12,8 @@ pub struct ShellUtils;
 impl ShellUtils {
     // runshell (cmd:string args:tuple(string...) -- exit_code:int)
     // runshellx (cmd:string args:tuple(string...) [stdin:string] mode:int -- [stdout:string/bytes] [stderr:string] exit_code:int)
-    #[cmd(name = "runshell", stack, args(mode = Some(ShellMode::DEFAULT)))]
-    #[cmd(name = "runshellx", stack, args(mode = None))]
+    #[cmd(name = "runshell", stack, args(mode = Some(ShellMode::DEFAULT)), nonstandard)]
+    #[cmd(name = "runshellx", stack, args(mode = None), nonstandard)]
     fn interpret_run_shell(stack: &mut Stack, mode: Option<ShellMode>) -> Result<()> {
         let mode = match mode {
             Some(m) => m,
@@ -0,0 +1,45 @@
+use fift::core::Interrupts;
+
+/// Triggers `on-interrupt` handlers on SIGINT and SIGTERM.
+///
+/// The process is terminated as usual if the script has no handler
+/// or the previous signal was not handled yet.
+#[cfg(unix)]
+pub fn install_handlers(interrupts: Interrupts) {
+    use std::sync::OnceLock;
+
+    static INTERRUPTS: OnceLock<Interrupts> = OnceLock::new();
+
+    extern "C" fn handle(signal: libc::c_int) {
+        if !matches!(INTERRUPTS.get(), Some(interrupts) if interrupts.trigger()) {
+            // SAFETY: both functions are async-signal-safe
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    if INTERRUPTS.set(interrupts).is_err() {
+        return;
+    }
+    let handler = handle as extern "C" fn(libc::c_int);
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only uses atomics and async-signal-safe functions
+        unsafe { libc::signal(signal, handler as libc::sighandler_t) };
+    }
+}
+
+/// Triggers `on-interrupt` handlers on Ctrl+C and Ctrl+Break.
+///
+/// The process exits with the code 130 if the script has no handler
+/// or the previous event was not handled yet.
+#[cfg(not(unix))]
+pub fn install_handlers(interrupts: Interrupts) {
+    // NOTE: a handler can only be installed once per process
+    _ = ctrlc::try_set_handler(move || {
+        if !interrupts.trigger() {
+            std::process::exit(130);
+        }
+    });
+}
@@ -28,14 +28,19 @@ impl<T: argh::FromArgs> argh::FromArgs for ArgsOrVersion<T> {
                 })
             }
             Err(exit) if exit.status.is_ok() => {
-                let help = match T::from_args(command_name, &["--help"]) {
-                    Ok(_) => unreachable!(),
-                    Err(exit) => exit.output,
-                };
-                Err(argh::EarlyExit {
-                    output: format!("{help}  -v, --version     print version information and exit"),
-                    status: Ok(()),
-                })
+                let help = top_level_help::<T>(command_name);
+                match T::from_args(command_name, args) {
+                    // NOTE: help of subcommands is printed as is
+                    Err(exit) if exit.status.is_ok() && !is_same_usage(&exit.output, &help) => {
+                        Err(exit)
+                    }
+                    _ => Err(argh::EarlyExit {
+                        output: format!(
+                            "{help}  -v, --version     print version information and exit"
+                        ),
+                        status: Ok(()),
+                    }),
+                }
             }
             _ => T::from_args(command_name, args).map(|app| Self(app)),
         }
@@ -62,10 +67,10 @@ impl<T: argh::FromArgs, D: RestArgsDelimiter> argh::FromArgs for RestArgs<T, D>
                 D::default(),
             )),
             Err(exit) if exit.status.is_ok() && FALLBACK_TO_HELP.load(Ordering::Acquire) => {
-                let help = match T::from_args(command_name, &["--help"]) {
-                    Ok(_) => unreachable!(),
-                    Err(exit) => exit.output,
-                };
+                let help = top_level_help::<T>(command_name);
+                if !is_same_usage(&exit.output, &help) {
+                    return Err(exit);
+                }
                 Err(argh::EarlyExit {
                     output: format!("{help}\n  {:<16}  {}", D::DELIM, D::DESCR),
                     status: Ok(()),
@@ -80,3 +85,15 @@ pub trait RestArgsDelimiter: Default {
     const DELIM: &'static str;
     const DESCR: &'static str;
 }
+
+fn top_level_help<T: argh::FromArgs>(command_name: &[&str]) -> String {
+    match T::from_args(command_name, &["--help"]) {
+        Ok(_) => unreachable!(),
+        Err(exit) => exit.output,
+    }
+}
+
+/// Returns whether both help messages are for the same (sub)command.
+fn is_same_usage(a: &str, b: &str) -> bool {
+    a.lines().next() == b.lines().next()
+}
@@ -0,0 +1,120 @@
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::{Context as _, Result};
+use argh::FromArgs;
+
+use fift::core::env::EmptyEnvironment;
+use fift::core::SourceBlock;
+use fift::util::write_json_string;
+
+/// Generate a JSON corpus of inputs and outputs for cell, hashing, address and crypto words
+#[derive(FromArgs)]
+#[argh(subcommand, name = "gen-vectors")]
+pub struct GenVectors {
+    /// write the vectors to the specified file instead of stdout
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+}
+
+const VERSION: u32 = 1;
+
+// NOTE: all inputs must be deterministic, outputs are final stacks
+const VECTORS: &[(&str, &str)] = &[
+    // Cell serialization
+    ("cells", "<b b> boc>B"),
+    ("cells", "<b 123 8 u, -1 16 i, b> hashu"),
+    ("cells", "<b 1 1 u, <b 2 8 u, b> ref, b> dup hashu swap totalcsize"),
+    ("cells", "<b x{deadbeef} s, b> boc>B"),
+    ("cells", "<b x{deadbeef} s, <b b> ref, b> 31 boc+>B"),
+    ("cells", "<b x{deadbeef} s, b> boc>base64"),
+    (
+        "cells",
+        "B{b5ee9c72010101010006000008deadbeef} B>boc <s 32 u@",
+    ),
+    ("cells", "<b 1 4 u, b> <s s>c hashu"),
+    // Hashing
+    ("hashing", "\"\" $>B Bhashu"),
+    ("hashing", "\"abc\" $>B Bhashu"),
+    ("hashing", "\"The quick brown fox\" $>B crc16"),
+    ("hashing", "\"The quick brown fox\" $>B crc32"),
+    ("hashing", "\"The quick brown fox\" $>B crc32c"),
+    // Address packing
+    ("addresses", "0 0 addr>slice s>c boc>B"),
+    ("addresses", "-1 0x123456789abcdef addr>slice slice>addr"),
+    ("addresses", "0 0x7777 3 smca>$"),
+    (
+        "addresses",
+        "\"0QAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAB3d7at\" $>smca",
+    ),
+    ("addresses", "-1 255 raw>$"),
+    // Crypto
+    ("crypto", "B{0000000000000000000000000000000000000000000000000000000000000001} priv>pub"),
+    (
+        "crypto",
+        "\"message\" $>B B{0000000000000000000000000000000000000000000000000000000000000001} ed25519_sign",
+    ),
+    (
+        "crypto",
+        "\"message\" $>B B{0000000000000000000000000000000000000000000000000000000000000001} \
+         2dup ed25519_sign swap priv>pub ed25519_chksign",
+    ),
+    (
+        "crypto",
+        "12345 B{0000000000000000000000000000000000000000000000000000000000000002} ed25519_sign_uint",
+    ),
+];
+
+impl GenVectors {
+    pub fn run(self) -> Result<()> {
+        let output = generate()?;
+        match &self.output {
+            Some(path) => std::fs::write(path, output)?,
+            None => std::io::stdout().write_all(output.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+fn generate() -> Result<String> {
+    let mut output = String::new();
+    writeln!(output, "{{\n  \"version\": {VERSION},\n  \"vectors\": [")?;
+    for (i, (group, input)) in VECTORS.iter().enumerate() {
+        let stack = run_vector(input).with_context(|| format!("Vector `{input}` failed"))?;
+        output.push_str("    {\"group\": ");
+        write_json_string(&mut output, group)?;
+        output.push_str(", \"input\": ");
+        write_json_string(&mut output, input)?;
+        let delim = if i + 1 == VECTORS.len() { "" } else { "," };
+        writeln!(output, ", \"output\": {stack}}}{delim}")?;
+    }
+    writeln!(output, "  ]\n}}")?;
+    Ok(output)
+}
+
+fn run_vector(input: &str) -> Result<String> {
+    let mut env = EmptyEnvironment;
+    let mut stdout = std::io::sink();
+    let mut ctx = fift::Context::new(&mut env, &mut stdout).with_basic_modules()?;
+
+    let base_lib = fift_libs::base_lib();
+    ctx.add_source_str("<vector>", input);
+    ctx.add_source_block(SourceBlock::new(
+        base_lib.name,
+        std::io::Cursor::new(base_lib.content),
+    ));
+
+    ctx.run()?;
+    ctx.stack_to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_are_up_to_date() {
+        // NOTE: regenerate with `fift gen-vectors -o cli/vectors.json`
+        assert_eq!(generate().unwrap(), include_str!("../vectors.json"));
+    }
+}
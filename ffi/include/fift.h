@@ -0,0 +1,114 @@
+#ifndef FIFT_H
+#define FIFT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// An interpreter context with all basic modules and the base library loaded.
+typedef struct FiftContext FiftContext;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a new context with all basic modules and the base library (`Fift.fif`) loaded.
+//
+// Bundled libraries (e.g. `Asm.fif`) can be included by scripts, no other files
+// are accessible. Returns `NULL` on error.
+FiftContext *fift_context_new(void);
+
+// Frees the context. Does nothing for `NULL`.
+//
+// # Safety
+// `ctx` must be a pointer returned by `fift_context_new` which was not freed yet.
+void fift_context_free(FiftContext *ctx);
+
+// Interprets the NUL-terminated source code.
+//
+// Returns the exit code (`0` when all input was consumed, the code passed to
+// `halt`, or `255` for `bye`) or `-1` on error. The stack and definitions are
+// kept between calls, the rest of the source is skipped on error.
+//
+// # Safety
+// `ctx` must be a valid context, `source` must be a valid C string.
+int32_t fift_eval(FiftContext *ctx, const char *source);
+
+// Returns the number of items on the stack (`0` for `NULL`).
+//
+// # Safety
+// `ctx` must be a valid context or `NULL`.
+size_t fift_stack_depth(const FiftContext *ctx);
+
+// Pops an integer which fits into 64 bits.
+//
+// Returns `0` on success or `-1` on error. The integer stays
+// on the stack if it doesn't fit.
+//
+// # Safety
+// `ctx` must be a valid context, `out` must be a valid pointer.
+int32_t fift_stack_pop_int(FiftContext *ctx, int64_t *out);
+
+// Pops an integer as a decimal string.
+//
+// Returns `NULL` on error, the result must be freed with `fift_string_free`.
+//
+// # Safety
+// `ctx` must be a valid context.
+char *fift_stack_pop_int_str(FiftContext *ctx);
+
+// Pops a string.
+//
+// Returns `NULL` on error, the result must be freed with `fift_string_free`.
+//
+// # Safety
+// `ctx` must be a valid context.
+char *fift_stack_pop_string(FiftContext *ctx);
+
+// Pops a byte string and writes its length into `len`.
+//
+// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+//
+// # Safety
+// `ctx` must be a valid context, `len` must be a valid pointer.
+uint8_t *fift_stack_pop_bytes(FiftContext *ctx, size_t *len);
+
+// Pops a cell serialized as a BOC and writes its length into `len`.
+//
+// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+//
+// # Safety
+// `ctx` must be a valid context, `len` must be a valid pointer.
+uint8_t *fift_stack_pop_boc(FiftContext *ctx, size_t *len);
+
+// Takes everything printed since the previous call and writes its length into `len`.
+//
+// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+//
+// # Safety
+// `ctx` must be a valid context, `len` must be a valid pointer.
+uint8_t *fift_take_output(FiftContext *ctx, size_t *len);
+
+// Returns the error message of the last failed call on the current thread
+// or `NULL` if there were no errors.
+//
+// The message is valid until the next failed call on the same thread.
+const char *fift_last_error(void);
+
+// Frees a string returned by the library. Does nothing for `NULL`.
+//
+// # Safety
+// `s` must be a string returned by the library which was not freed yet.
+void fift_string_free(char *s);
+
+// Frees bytes returned by the library. Does nothing for `NULL`.
+//
+// # Safety
+// `bytes` must be a pointer returned by the library with the same `len`,
+// which was not freed yet.
+void fift_bytes_free(uint8_t *bytes, size_t len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif // FIFT_H
@@ -0,0 +1,452 @@
+//! C ABI bindings for embedding the interpreter into non-Rust hosts.
+//!
+//! See `include/fift.h` for the generated header. Rust crates which
+//! provide bindings for other languages can use [`FiftContext`] directly.
+//!
+//! NOTE: a context is not thread-safe, it must only be used
+//! from the thread which created it.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use fift::core::env::EmptyEnvironment;
+use fift::core::SourceBlock;
+use fift::prelude::*;
+
+/// An interpreter context with all basic modules and the base library loaded.
+pub struct FiftContext {
+    // NOTE: the context borrows the host, so it must be dropped first
+    ctx: ManuallyDrop<Context<'static>>,
+    host: *mut Host,
+    output: OutputBuffer,
+}
+
+impl FiftContext {
+    pub fn new() -> Result<Self> {
+        let output = OutputBuffer::default();
+        let host = Box::into_raw(Box::new(Host {
+            env: EmptyEnvironment,
+            output: output.clone(),
+        }));
+
+        // SAFETY: the host is freed only after the context is dropped
+        let (env, stdout) = unsafe { (&mut (*host).env, &mut (*host).output) };
+        let ctx = match Context::new(env, stdout).with_basic_modules() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                // SAFETY: the host was allocated above and is no longer borrowed
+                drop(unsafe { Box::from_raw(host) });
+                return Err(e);
+            }
+        };
+
+        let mut this = Self {
+            ctx: ManuallyDrop::new(ctx),
+            host,
+            output,
+        };
+        for (name, content) in fift_libs::all() {
+            this.ctx.add_virtual_file(*name, *content);
+        }
+
+        let base_lib = fift_libs::base_lib();
+        this.ctx.add_source_block(SourceBlock::new(
+            base_lib.name,
+            std::io::Cursor::new(base_lib.content),
+        ));
+        this.run()?;
+        Ok(this)
+    }
+
+    /// Interprets the source code and returns the exit code.
+    ///
+    /// The stack and definitions are kept between calls, the rest
+    /// of the source is skipped on error or when the interpreter stops.
+    pub fn eval(&mut self, source: &str) -> Result<u8> {
+        self.ctx.add_source_str("<ffi>", source);
+        self.run().map(RunOutcome::exit_code)
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.ctx.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.ctx.stack
+    }
+
+    /// Takes everything printed since the previous call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.0.borrow_mut())
+    }
+
+    fn run(&mut self) -> Result<RunOutcome> {
+        let ctx = &mut *self.ctx;
+        let res = ctx.run();
+
+        // NOTE: the rest of the source is skipped (after an error, `halt` or `bye`)
+        // so that it is not interpreted on the next call
+        while ctx.input.pop_source_block() {}
+        if res.is_err() {
+            ctx.state = Default::default();
+            ctx.next = None;
+        }
+        res
+    }
+}
+
+impl Drop for FiftContext {
+    fn drop(&mut self) {
+        // SAFETY: the context is dropped only once, before the host it borrows
+        unsafe {
+            ManuallyDrop::drop(&mut self.ctx);
+            drop(Box::from_raw(self.host));
+        }
+    }
+}
+
+struct Host {
+    env: EmptyEnvironment,
+    output: OutputBuffer,
+}
+
+#[derive(Default, Clone)]
+struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs the function, converting errors and panics into
+/// the default value and the last error message.
+fn ffi_call<T>(default: T, f: impl FnOnce() -> Result<T>) -> T {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(format!("{e:#}"));
+            default
+        }
+        Err(_) => {
+            set_last_error("Interpreter panicked".to_owned());
+            default
+        }
+    }
+}
+
+unsafe fn context_mut<'a>(ctx: *mut FiftContext) -> Result<&'a mut FiftContext> {
+    ctx.as_mut().context("Null context")
+}
+
+fn into_c_string(s: String) -> Result<*mut c_char> {
+    let s = CString::new(s).context("String contains a NUL byte")?;
+    Ok(s.into_raw())
+}
+
+unsafe fn into_c_bytes(bytes: Vec<u8>, len: *mut usize) -> Result<*mut u8> {
+    let len = len.as_mut().context("Null length pointer")?;
+    let bytes = bytes.into_boxed_slice();
+    *len = bytes.len();
+    Ok(Box::into_raw(bytes).cast())
+}
+
+/// Creates a new context with all basic modules and the base library (`Fift.fif`) loaded.
+///
+/// Bundled libraries (e.g. `Asm.fif`) can be included by scripts, no other files
+/// are accessible. Returns `NULL` on error.
+#[no_mangle]
+pub extern "C" fn fift_context_new() -> *mut FiftContext {
+    ffi_call(std::ptr::null_mut(), || {
+        FiftContext::new().map(|ctx| Box::into_raw(Box::new(ctx)))
+    })
+}
+
+/// Frees the context. Does nothing for `NULL`.
+///
+/// # Safety
+/// `ctx` must be a pointer returned by `fift_context_new` which was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fift_context_free(ctx: *mut FiftContext) {
+    if !ctx.is_null() {
+        ffi_call((), || {
+            drop(Box::from_raw(ctx));
+            Ok(())
+        })
+    }
+}
+
+/// Interprets the NUL-terminated source code.
+///
+/// Returns the exit code (`0` when all input was consumed, the code passed to
+/// `halt`, or `255` for `bye`) or `-1` on error. The stack and definitions are
+/// kept between calls, the rest of the source is skipped on error or when
+/// the interpreter stops.
+///
+/// # Safety
+/// `ctx` must be a valid context, `source` must be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn fift_eval(ctx: *mut FiftContext, source: *const c_char) -> i32 {
+    ffi_call(-1, || {
+        let ctx = context_mut(ctx)?;
+        anyhow::ensure!(!source.is_null(), "Null source");
+        let source = CStr::from_ptr(source)
+            .to_str()
+            .context("Source is not a valid UTF-8 string")?;
+        ctx.eval(source).map(i32::from)
+    })
+}
+
+/// Returns the number of items on the stack (`0` for `NULL`).
+///
+/// # Safety
+/// `ctx` must be a valid context or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_depth(ctx: *const FiftContext) -> usize {
+    match ctx.as_ref() {
+        Some(ctx) => ctx.stack().depth(),
+        None => 0,
+    }
+}
+
+/// Pops an integer which fits into 64 bits.
+///
+/// Returns `0` on success or `-1` on error. The integer stays
+/// on the stack if it doesn't fit.
+///
+/// # Safety
+/// `ctx` must be a valid context, `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_pop_int(ctx: *mut FiftContext, out: *mut i64) -> i32 {
+    ffi_call(-1, || {
+        let ctx = context_mut(ctx)?;
+        let out = out.as_mut().context("Null output pointer")?;
+        let int = ctx.stack_mut().pop_int()?;
+        match i64::try_from(int.as_ref()) {
+            Ok(value) => {
+                *out = value;
+                Ok(0)
+            }
+            Err(_) => {
+                ctx.stack_mut().push_raw(int)?;
+                anyhow::bail!("Integer does not fit into 64 bits")
+            }
+        }
+    })
+}
+
+/// Pops an integer as a decimal string.
+///
+/// Returns `NULL` on error, the result must be freed with `fift_string_free`.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_pop_int_str(ctx: *mut FiftContext) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let int = context_mut(ctx)?.stack_mut().pop_int()?;
+        into_c_string(int.to_string())
+    })
+}
+
+/// Pops a string.
+///
+/// Returns `NULL` on error, the result must be freed with `fift_string_free`.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_pop_string(ctx: *mut FiftContext) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let string = context_mut(ctx)?.stack_mut().pop_string_owned()?;
+        into_c_string(string)
+    })
+}
+
+/// Pops a byte string and writes its length into `len`.
+///
+/// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+///
+/// # Safety
+/// `ctx` must be a valid context, `len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_pop_bytes(ctx: *mut FiftContext, len: *mut usize) -> *mut u8 {
+    ffi_call(std::ptr::null_mut(), || {
+        let bytes = context_mut(ctx)?.stack_mut().pop_bytes_owned()?;
+        into_c_bytes(bytes, len)
+    })
+}
+
+/// Pops a cell serialized as a BOC and writes its length into `len`.
+///
+/// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+///
+/// # Safety
+/// `ctx` must be a valid context, `len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fift_stack_pop_boc(ctx: *mut FiftContext, len: *mut usize) -> *mut u8 {
+    ffi_call(std::ptr::null_mut(), || {
+        let cell = context_mut(ctx)?.stack_mut().pop_cell()?;
+        into_c_bytes(Boc::encode(cell.as_ref()), len)
+    })
+}
+
+/// Takes everything printed since the previous call and writes its length into `len`.
+///
+/// Returns `NULL` on error, the result must be freed with `fift_bytes_free`.
+///
+/// # Safety
+/// `ctx` must be a valid context, `len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fift_take_output(ctx: *mut FiftContext, len: *mut usize) -> *mut u8 {
+    ffi_call(std::ptr::null_mut(), || {
+        let output = context_mut(ctx)?.take_output();
+        into_c_bytes(output, len)
+    })
+}
+
+/// Returns the error message of the last failed call on the current thread
+/// or `NULL` if there were no errors.
+///
+/// The message is valid until the next failed call on the same thread.
+#[no_mangle]
+pub extern "C" fn fift_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// Frees a string returned by the library. Does nothing for `NULL`.
+///
+/// # Safety
+/// `s` must be a string returned by the library which was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fift_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Frees bytes returned by the library. Does nothing for `NULL`.
+///
+/// # Safety
+/// `bytes` must be a pointer returned by the library with the same `len`,
+/// which was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fift_bytes_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            bytes, len,
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(ctx: *mut FiftContext, source: &str) -> i32 {
+        let source = CString::new(source).unwrap();
+        unsafe { fift_eval(ctx, source.as_ptr()) }
+    }
+
+    fn output(ctx: &mut FiftContext) -> String {
+        String::from_utf8(ctx.take_output()).unwrap()
+    }
+
+    #[test]
+    fn eval_and_recover() {
+        let mut ctx = FiftContext::new().unwrap();
+        assert_eq!(ctx.eval("{ 2 * } : double 21 double .").unwrap(), 0);
+        assert_eq!(output(&mut ctx), "42 ");
+
+        // NOTE: definitions are kept, the rest of the failed source is skipped
+        assert!(ctx.eval("1 . undefined-word 2 .").is_err());
+        assert_eq!(ctx.eval("3 double .").unwrap(), 0);
+        assert_eq!(output(&mut ctx), "1 6 ");
+
+        // Unfinished blocks are dropped after an error
+        assert!(ctx.eval("{ 1 undefined-word").is_err());
+        assert_eq!(ctx.eval("5 6 + .").unwrap(), 0);
+        assert_eq!(output(&mut ctx), "11 ");
+    }
+
+    #[test]
+    fn eval_after_halt() {
+        let mut ctx = FiftContext::new().unwrap();
+        assert_eq!(ctx.eval("1 . 7 halt 3 . 4 .").unwrap(), 7);
+        assert_eq!(ctx.eval("5 .").unwrap(), 0);
+        assert_eq!(output(&mut ctx), "1 5 ");
+
+        assert_eq!(ctx.eval("bye 6 .").unwrap(), 255);
+        assert_eq!(ctx.eval("\"Asm.fif\" include 8 .").unwrap(), 0);
+        assert_eq!(output(&mut ctx), "8 ");
+    }
+
+    #[test]
+    fn c_api() {
+        let ctx = fift_context_new();
+        assert!(!ctx.is_null());
+
+        assert_eq!(eval(ctx, "123 \"abc\" 1 100 <<"), 0);
+        unsafe {
+            assert_eq!(fift_stack_depth(ctx), 3);
+
+            let mut value = 0;
+            assert_eq!(fift_stack_pop_int(ctx, &mut value), -1);
+            assert!(!fift_last_error().is_null());
+
+            let s = fift_stack_pop_int_str(ctx);
+            assert_eq!(
+                CStr::from_ptr(s).to_str().unwrap(),
+                "1267650600228229401496703205376"
+            );
+            fift_string_free(s);
+
+            let s = fift_stack_pop_string(ctx);
+            assert_eq!(CStr::from_ptr(s).to_str().unwrap(), "abc");
+            fift_string_free(s);
+
+            assert_eq!(fift_stack_pop_int(ctx, &mut value), 0);
+            assert_eq!(value, 123);
+            assert_eq!(fift_stack_depth(ctx), 0);
+        }
+
+        assert_eq!(eval(ctx, "1 . -1 abort\"oops\""), -1);
+        unsafe {
+            let error = CStr::from_ptr(fift_last_error()).to_str().unwrap();
+            assert!(error.contains("oops"), "{error}");
+
+            let mut len = 0;
+            let bytes = fift_take_output(ctx, &mut len);
+            assert_eq!(std::slice::from_raw_parts(bytes, len), b"1 ");
+            fift_bytes_free(bytes, len);
+
+            assert_eq!(fift_stack_depth(std::ptr::null()), 0);
+            assert_eq!(eval(std::ptr::null_mut(), "1"), -1);
+
+            fift_context_free(ctx);
+            fift_context_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -1,5 +1,8 @@
+#![allow(clippy::manual_unwrap_or_default)]
+
 use std::collections::HashMap;
 
+use darling::util::Override;
 use darling::{Error, FromMeta};
 use proc_macro::TokenStream;
 use quote::quote;
@@ -19,6 +22,14 @@ struct FiftCmdArgs {
 
     name: String,
 
+    #[darling(default)]
+    effect: Option<String>,
+
+    /// Marks an extension which is missing in the reference implementation,
+    /// optionally with a suggested replacement.
+    #[darling(default)]
+    nonstandard: Option<Override<String>>,
+
     #[darling(default)]
     args: Option<HashMap<String, syn::Expr>>,
 }
@@ -29,7 +40,11 @@ pub fn fift_module(_: TokenStream, input: TokenStream) -> TokenStream {
 
     let dict_arg = quote::format_ident!("__dict");
 
+    let ty = input.self_ty.clone();
+    let module_name = quote!(#ty).to_string();
+
     let mut definitions = Vec::new();
+    let mut word_infos = Vec::new();
     let mut errors = Vec::new();
 
     let mut init_function_names = Vec::new();
@@ -66,9 +81,29 @@ pub fn fift_module(_: TokenStream, input: TokenStream) -> TokenStream {
             init_function_names.push(fun.sig.ident.clone());
             init_functions.push(fun);
         } else {
+            let doc = collect_doc(&fun.attrs);
             for attr in cmd_attrs {
                 match process_cmd_definition(&fun, &dict_arg, attr) {
-                    Ok(definition) => definitions.push(definition),
+                    Ok((definition, info)) => {
+                        definitions.push(definition);
+                        let name = info.name;
+                        let symbol = fun.sig.ident.to_string();
+                        let effect = quote_option(info.effect.as_deref());
+                        let nonstandard = info.nonstandard.is_some();
+                        let suggestion = info.nonstandard.and_then(Override::explicit);
+                        let suggestion = quote_option(suggestion.as_deref());
+                        word_infos.push(quote! {
+                            ::fift::core::WordInfo {
+                                name: #name,
+                                module: #module_name,
+                                symbol: #symbol,
+                                doc: #doc,
+                                stack_effect: #effect,
+                                nonstandard: #nonstandard,
+                                suggestion: #suggestion,
+                            }
+                        });
+                    }
                     Err(e) => errors.push(e),
                 }
             }
@@ -81,7 +116,6 @@ pub fn fift_module(_: TokenStream, input: TokenStream) -> TokenStream {
         return TokenStream::from(Error::multiple(errors).write_errors());
     }
 
-    let ty = input.self_ty;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     quote! {
@@ -99,6 +133,11 @@ pub fn fift_module(_: TokenStream, input: TokenStream) -> TokenStream {
                 #(#definitions?;)*
                 Ok(())
             }
+
+            fn words(&self) -> &'static [::fift::core::WordInfo] {
+                const WORDS: &[::fift::core::WordInfo] = &[#(#word_infos),*];
+                WORDS
+            }
         }
 
         #(#other_functions)*
@@ -106,11 +145,45 @@ pub fn fift_module(_: TokenStream, input: TokenStream) -> TokenStream {
     .into()
 }
 
+fn quote_option(value: Option<&str>) -> impl quote::ToTokens {
+    match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    }
+}
+
+struct CmdWordInfo {
+    name: String,
+    effect: Option<String>,
+    nonstandard: Option<Override<String>>,
+}
+
+fn collect_doc(attrs: &[syn::Attribute]) -> String {
+    let mut lines = Vec::new();
+    for attr in attrs {
+        if !attr.path().is_ident("doc") {
+            continue;
+        }
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(line),
+                    ..
+                }),
+            ..
+        }) = &attr.meta
+        {
+            lines.push(line.value().trim().to_owned());
+        }
+    }
+    lines.join("\n")
+}
+
 fn process_cmd_definition(
     function: &syn::ImplItemFn,
     dict_arg: &syn::Ident,
     attr: syn::Attribute,
-) -> Result<syn::Expr, Error> {
+) -> Result<(syn::Expr, CmdWordInfo), Error> {
     let cmd = FiftCmdArgs::from_meta(&attr.meta)?;
 
     let reg_fn = match (cmd.tail, cmd.active, cmd.stack) {
@@ -163,7 +236,13 @@ fn process_cmd_definition(
         }
     };
 
-    Ok(syn::parse_quote! { #dict_arg.#reg_fn(#cmd_name, #expr) })
+    let definition = syn::parse_quote! { #dict_arg.#reg_fn(#cmd_name, #expr) };
+    let info = CmdWordInfo {
+        name: cmd_name,
+        effect: cmd.effect,
+        nonstandard: cmd.nonstandard,
+    };
+    Ok((definition, info))
 }
 
 fn find_command_args(function: &syn::ImplItemFn) -> Result<Vec<String>, Error> {
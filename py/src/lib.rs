@@ -0,0 +1,126 @@
+//! Python bindings for the interpreter.
+//!
+//! ```python
+//! import fift
+//!
+//! interpreter = fift.Fift()
+//! interpreter.eval('"Asm.fif" include 2 3 + <{ ONE }>c')
+//! code = interpreter.pop()  # fift.Cell
+//! assert interpreter.pop() == 5
+//! ```
+
+use fift::core::{StackValue, StackValueType};
+use fift::prelude::{Boc, Cell};
+use fift_ffi::FiftContext;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+
+create_exception!(fift, FiftError, PyException);
+
+/// An interpreter context with all basic modules and the base library loaded.
+#[pyclass(unsendable, name = "Fift", module = "fift")]
+struct Fift(FiftContext);
+
+#[pymethods]
+impl Fift {
+    #[new]
+    fn new() -> PyResult<Self> {
+        FiftContext::new().map(Self).map_err(to_py_err)
+    }
+
+    /// Interprets the source code and returns the exit code.
+    ///
+    /// The stack and definitions are kept between calls.
+    fn eval(&mut self, source: &str) -> PyResult<u8> {
+        self.0.eval(source).map_err(to_py_err)
+    }
+
+    /// Pops the top stack value.
+    fn pop(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let value = self.0.stack_mut().pop().map_err(to_py_err)?;
+        to_py_value(py, value.as_ref())
+    }
+
+    /// Returns all stack values (from the bottom) without removing them.
+    fn stack(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let items = self.0.stack().items();
+        items
+            .iter()
+            .map(|item| to_py_value(py, item.as_ref()))
+            .collect()
+    }
+
+    /// Takes everything printed since the previous call.
+    fn output(&mut self) -> String {
+        String::from_utf8_lossy(&self.0.take_output()).into_owned()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.stack().depth()
+    }
+}
+
+/// A cell from the stack.
+#[pyclass(frozen, name = "Cell", module = "fift")]
+struct PyCell(Cell);
+
+#[pymethods]
+impl PyCell {
+    /// Serializes the cell into a BOC.
+    fn to_boc<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &Boc::encode(&self.0))
+    }
+
+    #[getter]
+    fn hash<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.0.repr_hash().as_slice())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Cell({})", self.0.repr_hash())
+    }
+}
+
+/// Converts integers, strings, bytes, cells and tuples (as lists)
+/// into Python objects, `null` is `None`.
+fn to_py_value(py: Python<'_>, value: &dyn StackValue) -> PyResult<PyObject> {
+    Ok(match value.ty() {
+        StackValueType::Null => py.None(),
+        StackValueType::Int => value.as_int().map_err(to_py_err)?.to_object(py),
+        StackValueType::String => value.as_string().map_err(to_py_err)?.to_object(py),
+        StackValueType::Bytes => PyBytes::new(py, value.as_bytes().map_err(to_py_err)?).into(),
+        StackValueType::Cell => {
+            let cell = value.as_cell().map_err(to_py_err)?.clone();
+            Py::new(py, PyCell(cell))?.into_py(py)
+        }
+        StackValueType::Tuple => {
+            let items = value
+                .as_tuple()
+                .map_err(to_py_err)?
+                .iter()
+                .map(|item| to_py_value(py, item.as_ref()))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items).into()
+        }
+        ty => {
+            return Err(PyTypeError::new_err(format!(
+                "Unsupported stack value type: {ty:?}"
+            )))
+        }
+    })
+}
+
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    FiftError::new_err(format!("{e:#}"))
+}
+
+#[pymodule]
+#[pyo3(name = "fift")]
+fn init(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Fift>()?;
+    m.add_class::<PyCell>()?;
+    m.add("FiftError", py.get_type::<FiftError>())?;
+    Ok(())
+}
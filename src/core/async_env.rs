@@ -0,0 +1,195 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+use everscale_types::cell::{Cell, HashBytes};
+
+use super::env::{Environment, SourceBlock, WriteMode};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// An environment which can supply files lazily (e.g. over network).
+pub trait AsyncEnvironment {
+    fn now_ms(&self) -> u64;
+
+    fn get_env(&self, name: &str) -> Option<String>;
+
+    fn file_exists<'a>(&'a self, name: &'a str) -> BoxFuture<'a, bool>;
+
+    fn write_file<'a>(
+        &'a mut self,
+        name: &'a str,
+        contents: &'a [u8],
+        mode: WriteMode,
+    ) -> BoxFuture<'a, std::io::Result<()>>;
+
+    fn read_file<'a>(&'a mut self, name: &'a str) -> BoxFuture<'a, std::io::Result<Vec<u8>>>;
+
+    fn read_file_part<'a>(
+        &'a mut self,
+        name: &'a str,
+        offset: u64,
+        len: u64,
+    ) -> BoxFuture<'a, std::io::Result<Vec<u8>>>;
+
+    fn include<'a>(&'a self, name: &'a str) -> BoxFuture<'a, std::io::Result<SourceBlock>>;
+
+    fn canonical_name(&self, name: &str) -> String {
+        name.to_owned()
+    }
+
+    fn create_dir<'a>(&'a mut self, name: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        _ = name;
+        Box::pin(std::future::ready(Ok(())))
+    }
+
+    fn remove_file<'a>(&'a mut self, name: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        _ = name;
+        Box::pin(std::future::ready(Ok(())))
+    }
+
+    fn compile_func<'a>(&'a mut self, files: &'a [String]) -> BoxFuture<'a, std::io::Result<Cell>> {
+        _ = files;
+        Box::pin(std::future::ready(Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "FunC compiler is not configured",
+        ))))
+    }
+
+    fn load_library<'a>(&'a mut self, hash: &'a HashBytes) -> BoxFuture<'a, Option<Cell>> {
+        _ = hash;
+        Box::pin(std::future::ready(None))
+    }
+
+    fn kv_store_path(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A way to drive environment futures to completion from the interpreter.
+pub trait BlockOn {
+    fn block_on<T>(&self, future: BoxFuture<'_, T>) -> T;
+}
+
+/// Polls futures on the current thread, parking it while they are pending.
+///
+/// NOTE: Futures which require a runtime context (e.g. tokio I/O) must be
+/// driven by a runtime-aware [`BlockOn`] implementation instead.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ThreadBlockOn;
+
+impl BlockOn for ThreadBlockOn {
+    fn block_on<T>(&self, mut future: BoxFuture<'_, T>) -> T {
+        struct ThreadWaker(Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(res) => return res,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+}
+
+/// Adapter which exposes an [`AsyncEnvironment`] as a blocking [`Environment`].
+pub struct BlockingEnvironment<E, B = ThreadBlockOn> {
+    env: E,
+    executor: B,
+}
+
+impl<E: AsyncEnvironment> BlockingEnvironment<E> {
+    pub fn new(env: E) -> Self {
+        Self {
+            env,
+            executor: ThreadBlockOn,
+        }
+    }
+}
+
+impl<E: AsyncEnvironment, B: BlockOn> BlockingEnvironment<E, B> {
+    pub fn with_executor(env: E, executor: B) -> Self {
+        Self { env, executor }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.env
+    }
+
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.env
+    }
+
+    pub fn into_inner(self) -> E {
+        self.env
+    }
+}
+
+impl<E: AsyncEnvironment, B: BlockOn> Environment for BlockingEnvironment<E, B> {
+    fn now_ms(&self) -> u64 {
+        self.env.now_ms()
+    }
+
+    fn get_env(&self, name: &str) -> Option<String> {
+        self.env.get_env(name)
+    }
+
+    fn file_exists(&self, name: &str) -> bool {
+        self.executor.block_on(self.env.file_exists(name))
+    }
+
+    fn write_file(&mut self, name: &str, contents: &[u8], mode: WriteMode) -> std::io::Result<()> {
+        self.executor
+            .block_on(self.env.write_file(name, contents, mode))
+    }
+
+    fn read_file(&mut self, name: &str) -> std::io::Result<Vec<u8>> {
+        self.executor.block_on(self.env.read_file(name))
+    }
+
+    fn read_file_part(&mut self, name: &str, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        self.executor
+            .block_on(self.env.read_file_part(name, offset, len))
+    }
+
+    fn include(&self, name: &str) -> std::io::Result<SourceBlock> {
+        self.executor.block_on(self.env.include(name))
+    }
+
+    fn canonical_name(&self, name: &str) -> String {
+        self.env.canonical_name(name)
+    }
+
+    fn create_dir(&mut self, name: &str) -> std::io::Result<()> {
+        self.executor.block_on(self.env.create_dir(name))
+    }
+
+    fn remove_file(&mut self, name: &str) -> std::io::Result<()> {
+        self.executor.block_on(self.env.remove_file(name))
+    }
+
+    fn compile_func(&mut self, files: &[String]) -> std::io::Result<Cell> {
+        self.executor.block_on(self.env.compile_func(files))
+    }
+
+    fn load_library(&mut self, hash: &HashBytes) -> Option<Cell> {
+        self.executor.block_on(self.env.load_library(hash))
+    }
+
+    fn kv_store_path(&self) -> Option<String> {
+        self.env.kv_store_path()
+    }
+}
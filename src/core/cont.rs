@@ -1,10 +1,15 @@
-use std::cell::RefCell;
+use std::io::Write as _;
 use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use num_bigint::BigInt;
 
-use super::{Context, Dictionary, Stack, StackValue, StackValueType, WordList};
+use super::stack::Atom;
+use super::suspend::{ContTag, NotSuspendable, Suspender};
+use super::{
+    Context, Dictionaries, Dictionary, SourcePosition, Stack, StackValue, StackValueType, WordList,
+};
+use crate::render::Backtrace;
 use crate::util::*;
 
 pub type Cont = Rc<dyn ContImpl>;
@@ -16,6 +21,58 @@ pub trait ContImpl {
         None
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.up().into_iter().collect()
+    }
+
+    /// Returns the number of nested continuations which are executed after this one.
+    fn depth(&self) -> usize {
+        0
+    }
+
+    /// Returns the continuation which this one only delegates to (e.g. the first
+    /// continuation of a sequence), used to walk long chains without recursion.
+    fn head(&self) -> Option<&Cont> {
+        None
+    }
+
+    /// Returns the word list if the continuation executes it
+    /// from the beginning (e.g. a `{ }` block).
+    fn word_list(&self) -> Option<&WordList> {
+        None
+    }
+
+    /// Returns the values which the continuation pushes (e.g. compiled literals).
+    fn literals(&self) -> Option<&[Rc<dyn StackValue>]> {
+        None
+    }
+
+    /// Whether the continuation is implemented in Rust.
+    fn is_native(&self) -> bool {
+        false
+    }
+
+    /// Moves out the continuations which are executed after this one,
+    /// so that long chains can be dropped without recursion.
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (None, None)
+    }
+
+    /// Returns the source position of the word which is currently executed.
+    fn source_position(&self) -> Option<&SourcePosition> {
+        None
+    }
+
+    /// Returns the source position of the word which will be executed next.
+    fn next_source_position(&self) -> Option<&SourcePosition> {
+        None
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        _ = s;
+        Err(NotSuspendable.into())
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
 
     fn fmt_dump(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -24,29 +81,43 @@ pub trait ContImpl {
 }
 
 impl dyn ContImpl + '_ {
-    pub fn display_backtrace<'a>(&'a self, d: &'a Dictionary) -> impl std::fmt::Display + 'a {
-        struct ContinuationBacktrace<'a> {
-            d: &'a Dictionary,
-            cont: &'a dyn ContImpl,
+    /// Returns the innermost known source position in the backtrace.
+    pub fn find_source_position(&self) -> Option<&SourcePosition> {
+        let mut cont = self;
+        for _ in 0..16 {
+            if let Some(position) = cont.source_position() {
+                return Some(position);
+            }
+            cont = cont.up()?.as_ref();
         }
+        None
+    }
 
-        impl std::fmt::Display for ContinuationBacktrace<'_> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                let mut cont = self.cont;
-                let mut newline = "";
-                for i in 1..=16 {
-                    write!(f, "{newline}{i:>4}: {}", cont.display_dump(self.d))?;
-                    newline = "\n";
-                    match cont.up() {
-                        Some(next) => cont = next.as_ref(),
-                        None => return Ok(()),
-                    }
-                }
-                write!(f, "{newline}... more levels ...")
+    pub fn display_backtrace(&self, d: &Dictionary) -> impl std::fmt::Display {
+        self.backtrace(d)
+    }
+
+    /// Collects dumps of the continuation and the ones it returns to.
+    pub fn backtrace(&self, d: &Dictionary) -> Backtrace {
+        const MAX_FRAMES: usize = 16;
+
+        let mut frames = Vec::new();
+        let mut cont = Some(self);
+        while let Some(current) = cont {
+            if frames.len() == MAX_FRAMES {
+                break;
             }
+            frames.push(current.display_dump(d).to_string());
+            cont = current.up().map(AsRef::as_ref);
+        }
+
+        let mut omitted = 0;
+        while let Some(current) = cont {
+            omitted += 1;
+            cont = current.up().map(AsRef::as_ref);
         }
 
-        ContinuationBacktrace { d, cont: self }
+        Backtrace { frames, omitted }
     }
 
     pub fn display_name<'a>(&'a self, d: &'a Dictionary) -> impl std::fmt::Display + 'a {
@@ -78,6 +149,114 @@ impl dyn ContImpl + '_ {
 
         ContinuationDump { d, cont: self }
     }
+
+    /// Displays the word list (or literals) of the definition with nested
+    /// blocks on separate lines, named words are not expanded.
+    pub fn display_definition<'a>(&'a self, d: &'a Dictionaries) -> impl std::fmt::Display + 'a {
+        struct ContinuationDefinition<'a> {
+            d: &'a Dictionaries,
+            cont: &'a dyn ContImpl,
+        }
+
+        impl std::fmt::Display for ContinuationDefinition<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                if let Some(list) = self.cont.word_list() {
+                    return write_word_list(list, self.d, 0, f);
+                }
+
+                f.write_str("{ ")?;
+                match self.cont.literals() {
+                    Some(values) => {
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                f.write_str(" ")?;
+                            }
+                            let part = DefinitionPart::Literal(value.as_ref());
+                            write_definition_part(&part, self.d, 0, f)?;
+                        }
+                    }
+                    None => self.cont.fmt_name(&self.d.current, f)?,
+                }
+                f.write_str(" }")
+            }
+        }
+
+        ContinuationDefinition { d, cont: self }
+    }
+
+    pub fn display_dot<'a>(&'a self, d: &'a Dictionary) -> impl std::fmt::Display + 'a {
+        struct ContinuationDot<'a> {
+            d: &'a Dictionary,
+            cont: &'a dyn ContImpl,
+        }
+
+        impl std::fmt::Display for ContinuationDot<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                const MAX_NODES: usize = 1000;
+                const MAX_LABEL_LEN: usize = 48;
+
+                let mut ids = ahash::HashMap::<*const (), usize>::default();
+                let mut stack = vec![self.cont];
+                ids.insert(cont_ptr(self.cont), 0);
+
+                f.write_str("digraph cont {\n  node [shape=box];\n")?;
+                while let Some(cont) = stack.pop() {
+                    let id = ids[&cont_ptr(cont)];
+
+                    let name = self.d.resolve_name(cont);
+                    let mut label = match &name {
+                        Some(name) => name.trim_end().to_owned(),
+                        None => cont.display_dump(self.d).to_string(),
+                    };
+                    if let Some((i, _)) = label.char_indices().nth(MAX_LABEL_LEN) {
+                        label.truncate(i);
+                        label.push_str("...");
+                    }
+                    writeln!(f, "  n{id} [label=\"{}\"];", escape_dot_label(&label))?;
+
+                    // Named words are not expanded to keep the graph readable
+                    if id != 0 && name.is_some() {
+                        continue;
+                    }
+
+                    let up = cont.up().map(|up| cont_ptr(up.as_ref()));
+                    for (i, child) in cont.children().into_iter().enumerate() {
+                        let ptr = cont_ptr(child.as_ref());
+                        let child_id = match ids.get(&ptr) {
+                            Some(child_id) => *child_id,
+                            None if ids.len() < MAX_NODES => {
+                                let child_id = ids.len();
+                                ids.insert(ptr, child_id);
+                                stack.push(child.as_ref());
+                                child_id
+                            }
+                            None => {
+                                writeln!(f, "  n{id} -> more;")?;
+                                continue;
+                            }
+                        };
+
+                        if Some(ptr) == up {
+                            writeln!(f, "  n{id} -> n{child_id} [style=dashed];")?;
+                        } else {
+                            writeln!(f, "  n{id} -> n{child_id} [label=\"{i}\"];")?;
+                        }
+                    }
+                }
+
+                if ids.len() >= MAX_NODES {
+                    f.write_str("  more [label=\"...\", shape=none];\n")?;
+                }
+                f.write_str("}")
+            }
+        }
+
+        ContinuationDot { d, cont: self }
+    }
+}
+
+fn cont_ptr(cont: &dyn ContImpl) -> *const () {
+    cont as *const dyn ContImpl as *const ()
 }
 
 pub struct InterpreterCont;
@@ -86,7 +265,6 @@ impl ContImpl for InterpreterCont {
     fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
         thread_local! {
             static COMPILE_EXECUTE: Cont = Rc::new(CompileExecuteCont);
-            static WORD: RefCell<String> = RefCell::new(String::with_capacity(128));
         };
 
         ctx.stdout.flush()?;
@@ -98,35 +276,23 @@ impl ContImpl for InterpreterCont {
                 let mut rewind = None;
                 let entry = 'entry: {
                     let Some(token) = ctx.input.scan_word()? else {
+                        if ctx.input.is_nested_source_block() {
+                            return Ok(None);
+                        }
                         if ctx.input.pop_source_block() {
                             continue 'source_block;
                         }
                         return Ok(None);
                     };
 
-                    // Find in predefined entries
-                    if let Some(entry) = WORD.with(|word| {
-                        let mut word = word.borrow_mut();
-                        word.clear();
-                        word.push_str(token);
-                        word.push(' ');
-
-                        // Search parsed token as a separate word first
-                        if let Some(entry) = ctx.dicts.lookup(&word, false)? {
-                            return Ok::<_, anyhow::Error>(Some(entry));
-                        }
-
-                        // Then find the largest possible prefix
-                        while !word.is_empty() {
-                            word.pop();
-                            if let Some(entry) = ctx.dicts.lookup(&word, false)? {
-                                rewind = Some(word.len());
-                                return Ok(Some(entry));
-                            }
-                        }
+                    // Search parsed token as a separate word first
+                    if let Some(entry) = ctx.dicts.lookup_token(token)? {
+                        break 'entry entry;
+                    }
 
-                        Ok(None)
-                    })? {
+                    // Then find the largest possible prefix
+                    if let Some((len, entry)) = ctx.dicts.lookup_prefix(token)? {
+                        rewind = Some(len);
                         break 'entry entry;
                     }
 
@@ -145,6 +311,8 @@ impl ContImpl for InterpreterCont {
                     anyhow::bail!("Undefined word `{token}`");
                 };
 
+                ctx.check_standard_word(&entry.definition)?;
+
                 if let Some(rewind) = rewind {
                     ctx.input.rewind(rewind);
                 } else {
@@ -173,6 +341,11 @@ impl ContImpl for InterpreterCont {
         }
     }
 
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.stop();
+        Ok(())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("<text interpreter continuation>")
     }
@@ -199,6 +372,24 @@ pub struct ListCont {
     pub list: Rc<WordList>,
     pub after: Option<Cont>,
     pub pos: usize,
+    depth: usize,
+}
+
+impl ListCont {
+    pub fn new(list: Rc<WordList>, after: Option<Cont>, pos: usize) -> Self {
+        Self {
+            depth: chain_depth(after.as_ref()) + 1,
+            list,
+            after,
+            pos,
+        }
+    }
+}
+
+impl Drop for ListCont {
+    fn drop(&mut self) {
+        drop_chain(self.after.take());
+    }
 }
 
 impl ContImpl for ListCont {
@@ -210,7 +401,7 @@ impl ContImpl for ListCont {
 
         match Rc::get_mut(&mut self) {
             Some(this) => {
-                ctx.insert_before_next(&mut this.after);
+                ctx.insert_before_next(&mut this.after, &mut this.depth);
                 this.pos += 1;
                 ctx.next = if is_last {
                     this.after.take()
@@ -220,19 +411,19 @@ impl ContImpl for ListCont {
             }
             None => {
                 if let Some(next) = ctx.next.take() {
-                    ctx.next = Some(Rc::new(ListCont {
-                        after: SeqCont::make(self.after.clone(), Some(next)),
-                        list: self.list.clone(),
-                        pos: self.pos + 1,
-                    }))
+                    ctx.next = Some(Rc::new(ListCont::new(
+                        self.list.clone(),
+                        SeqCont::make(self.after.clone(), Some(next)),
+                        self.pos + 1,
+                    )))
                 } else if is_last {
                     ctx.next = self.after.clone()
                 } else {
-                    ctx.next = Some(Rc::new(ListCont {
-                        after: self.after.clone(),
-                        list: self.list.clone(),
-                        pos: self.pos + 1,
-                    }))
+                    ctx.next = Some(Rc::new(ListCont::new(
+                        self.list.clone(),
+                        self.after.clone(),
+                        self.pos + 1,
+                    )))
                 }
             }
         }
@@ -244,6 +435,38 @@ impl ContImpl for ListCont {
         self.after.as_ref()
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        let items = self.list.items.iter().skip(self.pos);
+        items.chain(self.after.as_ref()).collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn word_list(&self) -> Option<&WordList> {
+        (self.pos == 0 && self.after.is_none()).then_some(self.list.as_ref())
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.after.take(), None)
+    }
+
+    fn source_position(&self) -> Option<&SourcePosition> {
+        self.list.position(self.pos.checked_sub(1)?)
+    }
+
+    fn next_source_position(&self) -> Option<&SourcePosition> {
+        self.list.position(self.pos)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::List);
+        s.write_word_list(&self.list)?;
+        s.write_varuint(self.pos as u64);
+        s.write_opt_cont(self.after.as_ref())
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write_cont_name(self, d, f)
     }
@@ -263,7 +486,7 @@ impl ContImpl for ListCont {
             }
 
             let len = self.list.items.len();
-            let start = if self.pos >= N { self.pos - N } else { 0 };
+            let start = self.pos.saturating_sub(N);
             let items = self.list.items.iter();
 
             if start > 0 {
@@ -275,9 +498,14 @@ impl ContImpl for ListCont {
                 }
                 write!(f, "{} ", item.display_name(d))?;
             }
-            if self.pos + N < len {
+            let truncated = self.pos + N < len;
+            if truncated {
                 f.write_str("...")?;
             }
+            if let Some(position) = self.source_position() {
+                let delim = if truncated { " " } else { "" };
+                write!(f, "{delim}(at {position})")?;
+            }
             Ok(())
         }
     }
@@ -314,6 +542,11 @@ impl ContImpl for NopCont {
         Ok(None)
     }
 
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Nop);
+        Ok(())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("<nop>")
     }
@@ -322,21 +555,43 @@ impl ContImpl for NopCont {
 pub struct SeqCont {
     pub first: Option<Cont>,
     pub second: Option<Cont>,
+    depth: usize,
 }
 
 impl SeqCont {
+    pub fn new(first: Option<Cont>, second: Option<Cont>) -> Self {
+        Self {
+            depth: chain_depth(first.as_ref()) + chain_depth(second.as_ref()),
+            first,
+            second,
+        }
+    }
+
     pub fn make(first: Option<Cont>, second: Option<Cont>) -> Option<Cont> {
         if second.is_none() {
             first
-        } else if let Some(first) = first {
-            Some(Rc::new(Self {
-                first: Some(first),
-                second,
-            }))
+        } else if first.is_some() {
+            Some(Rc::new(Self::new(first, second)))
         } else {
             second
         }
     }
+
+    /// Returns the innermost continuation which is executed first.
+    fn innermost_first(&self) -> Option<&dyn ContImpl> {
+        let mut cont = self.first.as_deref()?;
+        while let Some(head) = cont.head() {
+            cont = head.as_ref();
+        }
+        Some(cont)
+    }
+}
+
+impl Drop for SeqCont {
+    fn drop(&mut self) {
+        drop_chain(self.first.take());
+        drop_chain(self.second.take());
+    }
 }
 
 impl ContImpl for SeqCont {
@@ -349,6 +604,8 @@ impl ContImpl for SeqCont {
                 } else {
                     let result = std::mem::replace(&mut this.first, this.second.take());
                     this.second = ctx.next.take();
+                    this.depth =
+                        chain_depth(this.first.as_ref()) + chain_depth(this.second.as_ref());
                     ctx.next = Some(self);
                     result
                 }
@@ -364,17 +621,39 @@ impl ContImpl for SeqCont {
         self.second.as_ref()
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.first.iter().chain(self.second.as_ref()).collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn head(&self) -> Option<&Cont> {
+        self.first.as_ref()
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.second.take(), self.first.take())
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Seq);
+        s.write_opt_cont(self.first.as_ref())?;
+        s.write_opt_cont(self.second.as_ref())
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(first) = &self.first {
-            first.as_ref().fmt_name(d, f)
+        if let Some(first) = self.innermost_first() {
+            first.fmt_name(d, f)
         } else {
             Ok(())
         }
     }
 
     fn fmt_dump(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(first) = &self.first {
-            first.as_ref().fmt_dump(d, f)?;
+        if let Some(first) = self.innermost_first() {
+            first.fmt_dump(d, f)?;
         }
         Ok(())
     }
@@ -384,13 +663,31 @@ pub struct TimesCont {
     pub body: Option<Cont>,
     pub after: Option<Cont>,
     pub count: usize,
+    depth: usize,
+}
+
+impl TimesCont {
+    pub fn new(body: Option<Cont>, after: Option<Cont>, count: usize) -> Self {
+        Self {
+            depth: chain_depth(after.as_ref()) + 1,
+            body,
+            after,
+            count,
+        }
+    }
+}
+
+impl Drop for TimesCont {
+    fn drop(&mut self) {
+        drop_chain(self.after.take());
+    }
 }
 
 impl ContImpl for TimesCont {
     fn run(mut self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
         Ok(match Rc::get_mut(&mut self) {
             Some(this) => {
-                ctx.insert_before_next(&mut this.after);
+                ctx.insert_before_next(&mut this.after, &mut this.depth);
 
                 if this.count > 1 {
                     this.count -= 1;
@@ -406,11 +703,7 @@ impl ContImpl for TimesCont {
                 let next = SeqCont::make(self.after.clone(), ctx.next.take());
 
                 ctx.next = if self.count > 1 {
-                    Some(Rc::new(Self {
-                        body: self.body.clone(),
-                        after: next,
-                        count: self.count - 1,
-                    }))
+                    Some(Rc::new(Self::new(self.body.clone(), next, self.count - 1)))
                 } else {
                     next
                 };
@@ -424,6 +717,26 @@ impl ContImpl for TimesCont {
         self.after.as_ref()
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.body.iter().chain(self.after.as_ref()).collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.after.take(), None)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Times);
+        s.write_opt_cont(self.body.as_ref())?;
+        s.write_opt_cont(self.after.as_ref())?;
+        s.write_varuint(self.count as u64);
+        Ok(())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<repeat {} times>", self.count)
     }
@@ -440,6 +753,23 @@ impl ContImpl for TimesCont {
 pub struct UntilCont {
     pub body: Option<Cont>,
     pub after: Option<Cont>,
+    depth: usize,
+}
+
+impl UntilCont {
+    pub fn new(body: Option<Cont>, after: Option<Cont>) -> Self {
+        Self {
+            depth: chain_depth(after.as_ref()) + 1,
+            body,
+            after,
+        }
+    }
+}
+
+impl Drop for UntilCont {
+    fn drop(&mut self) {
+        drop_chain(self.after.take());
+    }
 }
 
 impl ContImpl for UntilCont {
@@ -454,15 +784,15 @@ impl ContImpl for UntilCont {
         let body = self.body.clone();
         let next = match Rc::get_mut(&mut self) {
             Some(this) => {
-                ctx.insert_before_next(&mut this.after);
+                ctx.insert_before_next(&mut this.after, &mut this.depth);
                 self
             }
             None => {
                 if let Some(next) = ctx.next.take() {
-                    Rc::new(UntilCont {
-                        body: self.body.clone(),
-                        after: SeqCont::make(self.after.clone(), Some(next)),
-                    })
+                    Rc::new(UntilCont::new(
+                        self.body.clone(),
+                        SeqCont::make(self.after.clone(), Some(next)),
+                    ))
                 } else {
                     self
                 }
@@ -476,6 +806,24 @@ impl ContImpl for UntilCont {
         self.after.as_ref()
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.body.iter().chain(self.after.as_ref()).collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.after.take(), None)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Until);
+        s.write_opt_cont(self.body.as_ref())?;
+        s.write_opt_cont(self.after.as_ref())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("<until loop continuation>")
     }
@@ -494,9 +842,25 @@ pub struct WhileCont {
     pub body: Option<Cont>,
     pub after: Option<Cont>,
     pub running_body: bool,
+    depth: usize,
 }
 
 impl WhileCont {
+    pub fn new(
+        condition: Option<Cont>,
+        body: Option<Cont>,
+        after: Option<Cont>,
+        running_body: bool,
+    ) -> Self {
+        Self {
+            depth: chain_depth(after.as_ref()) + 1,
+            condition,
+            body,
+            after,
+            running_body,
+        }
+    }
+
     fn stage_name(&self) -> &'static str {
         if self.running_body {
             "body"
@@ -506,6 +870,12 @@ impl WhileCont {
     }
 }
 
+impl Drop for WhileCont {
+    fn drop(&mut self) {
+        drop_chain(self.after.take());
+    }
+}
+
 impl ContImpl for WhileCont {
     fn run(mut self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
         let cont = if self.running_body {
@@ -523,16 +893,16 @@ impl ContImpl for WhileCont {
 
         let next = match Rc::get_mut(&mut self) {
             Some(this) => {
-                ctx.insert_before_next(&mut this.after);
+                ctx.insert_before_next(&mut this.after, &mut this.depth);
                 this.running_body = !this.running_body;
                 self
             }
-            None => Rc::new(Self {
-                condition: self.condition.clone(),
-                body: self.body.clone(),
-                after: SeqCont::make(self.after.clone(), ctx.next.take()),
-                running_body: !self.running_body,
-            }),
+            None => Rc::new(Self::new(
+                self.condition.clone(),
+                self.body.clone(),
+                SeqCont::make(self.after.clone(), ctx.next.take()),
+                !self.running_body,
+            )),
         };
 
         ctx.next = Some(next);
@@ -543,6 +913,28 @@ impl ContImpl for WhileCont {
         self.after.as_ref()
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        let stages = self.condition.iter().chain(self.body.as_ref());
+        stages.chain(self.after.as_ref()).collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.after.take(), None)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::While);
+        s.write_opt_cont(self.condition.as_ref())?;
+        s.write_opt_cont(self.body.as_ref())?;
+        s.write_opt_cont(self.after.as_ref())?;
+        s.write_bool(self.running_body);
+        Ok(())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<while loop {}>", self.stage_name())
     }
@@ -566,6 +958,7 @@ pub struct LoopCont<T> {
     state: LoopContState,
     func: Cont,
     after: Option<Cont>,
+    depth: usize,
 }
 
 impl<T> LoopCont<T> {
@@ -574,11 +967,18 @@ impl<T> LoopCont<T> {
             inner,
             state: LoopContState::Init,
             func,
+            depth: chain_depth(after.as_ref()) + 1,
             after,
         }
     }
 }
 
+impl<T> Drop for LoopCont<T> {
+    fn drop(&mut self) {
+        drop_chain(self.after.take());
+    }
+}
+
 impl<T: LoopContImpl + 'static> ContImpl for LoopCont<T> {
     fn run(mut self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
         let Some(this) = Rc::get_mut(&mut self) else {
@@ -587,10 +987,11 @@ impl<T: LoopContImpl + 'static> ContImpl for LoopCont<T> {
                 state: self.state,
                 func: self.func.clone(),
                 after: self.after.clone(),
+                depth: self.depth,
             })));
         };
 
-        ctx.insert_before_next(&mut this.after);
+        ctx.insert_before_next(&mut this.after, &mut this.depth);
         Ok(loop {
             match this.state {
                 LoopContState::Init => {
@@ -628,6 +1029,24 @@ impl<T: LoopContImpl + 'static> ContImpl for LoopCont<T> {
         })
     }
 
+    fn up(&self) -> Option<&Cont> {
+        self.after.as_ref()
+    }
+
+    fn children(&self) -> Vec<&Cont> {
+        std::iter::once(&self.func)
+            .chain(self.after.as_ref())
+            .collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn take_links(&mut self) -> (Option<Cont>, Option<Cont>) {
+        (self.after.take(), None)
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<generic loop continuation state {:?}>", self.state)
     }
@@ -681,6 +1100,12 @@ impl ContImpl for IntLitCont {
         Ok(None)
     }
 
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::IntLit);
+        s.write_int(&self.0);
+        Ok(())
+    }
+
     fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
@@ -698,6 +1123,19 @@ impl ContImpl for LitCont {
         Ok(None)
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.0.as_cont().into_iter().collect()
+    }
+
+    fn literals(&self) -> Option<&[Rc<dyn StackValue>]> {
+        Some(std::slice::from_ref(&self.0))
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Lit);
+        s.write_value(self.0.as_ref())
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write_lit_cont_name(self.0.as_ref(), d, f)
     }
@@ -722,6 +1160,26 @@ impl ContImpl for MultiLitCont {
         Ok(None)
     }
 
+    fn children(&self) -> Vec<&Cont> {
+        self.0
+            .iter()
+            .filter_map(|item| item.as_cont().ok())
+            .collect()
+    }
+
+    fn literals(&self) -> Option<&[Rc<dyn StackValue>]> {
+        Some(&self.0)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::MultiLit);
+        s.write_varuint(self.0.len() as u64);
+        for item in &self.0 {
+            s.write_value(item.as_ref())?;
+        }
+        Ok(())
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut first = true;
         for item in &self.0 {
@@ -734,6 +1192,80 @@ impl ContImpl for MultiLitCont {
     }
 }
 
+/// Stack permutation described by a pattern like `abc-cab`.
+///
+/// Names before `-` are the top items (the last one is the top),
+/// names after it are the new top items (names can be repeated or omitted).
+pub struct ShuffleCont {
+    pattern: String,
+    depth: usize,
+    order: Vec<u8>,
+}
+
+impl ShuffleCont {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let (input, output) = pattern
+            .split_once('-')
+            .with_context(|| format!("Expected `-` in the shuffle pattern `{pattern}`"))?;
+
+        let mut names = Vec::new();
+        for c in input.chars().filter(|c| !c.is_whitespace()) {
+            anyhow::ensure!(
+                !names.contains(&c),
+                "Duplicate name `{c}` in the shuffle pattern `{pattern}`"
+            );
+            names.push(c);
+        }
+        anyhow::ensure!(names.len() <= 255, "Too many names in the shuffle pattern");
+
+        let mut order = Vec::new();
+        for c in output.chars().filter(|c| !c.is_whitespace()) {
+            let i = names.iter().position(|name| *name == c).with_context(|| {
+                format!("Unknown name `{c}` in the shuffle pattern `{pattern}`")
+            })?;
+            order.push(i as u8);
+        }
+
+        Ok(Self {
+            pattern: pattern.trim().to_owned(),
+            depth: names.len(),
+            order,
+        })
+    }
+
+    pub fn apply(&self, stack: &mut Stack) -> Result<()> {
+        if self.depth > 0 {
+            stack.check_underflow(self.depth)?;
+        }
+        let mut items = Vec::with_capacity(self.depth);
+        for _ in 0..self.depth {
+            items.push(stack.pop()?);
+        }
+        items.reverse();
+        for i in &self.order {
+            stack.push_raw(items[*i as usize].clone())?;
+        }
+        Ok(())
+    }
+}
+
+impl ContImpl for ShuffleCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        self.apply(&mut ctx.stack)?;
+        Ok(None)
+    }
+
+    fn suspend(&self, s: &mut Suspender<'_>) -> Result<()> {
+        s.write_tag(ContTag::Shuffle);
+        s.write_str(&self.pattern);
+        Ok(())
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "shuffle\"{}\"", self.pattern)
+    }
+}
+
 pub type ContextWordFunc = fn(&mut Context) -> Result<()>;
 
 impl ContImpl for ContextWordFunc {
@@ -742,6 +1274,10 @@ impl ContImpl for ContextWordFunc {
         Ok(None)
     }
 
+    fn is_native(&self) -> bool {
+        true
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write_cont_name(self, d, f)
     }
@@ -754,6 +1290,10 @@ impl ContImpl for ContextTailWordFunc {
         (self)(ctx)
     }
 
+    fn is_native(&self) -> bool {
+        true
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write_cont_name(self, d, f)
     }
@@ -767,23 +1307,71 @@ impl ContImpl for StackWordFunc {
         Ok(None)
     }
 
+    fn is_native(&self) -> bool {
+        true
+    }
+
     fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write_cont_name(self, d, f)
     }
 }
 
-/// === impl Context ===
+/// Word defined by a closure, which can capture the host state.
+pub struct ClosureWord(Box<ClosureWordFunc>);
+
+type ClosureWordFunc = dyn Fn(&mut Context) -> Result<Option<Cont>>;
+
+impl ClosureWord {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&mut Context) -> Result<Option<Cont>> + 'static,
+    {
+        Self(Box::new(f))
+    }
+}
+
+impl ContImpl for ClosureWord {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        (self.0)(ctx)
+    }
+
+    fn is_native(&self) -> bool {
+        true
+    }
+
+    fn fmt_name(&self, d: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_cont_name(self, d, f)
+    }
+}
+
+// === impl Context ===
 
 impl Context<'_> {
-    fn insert_before_next(&mut self, cont: &mut Option<Cont>) {
+    fn insert_before_next(&mut self, cont: &mut Option<Cont>, depth: &mut usize) {
         if let Some(next) = self.next.take() {
             *cont = match cont.take() {
-                Some(prev) => Some(Rc::new(SeqCont {
-                    first: Some(prev),
-                    second: Some(next),
-                })),
+                Some(prev) => Some(Rc::new(SeqCont::new(Some(prev), Some(next)))),
                 None => Some(next),
             };
+            *depth = chain_depth(cont.as_ref()) + 1;
+        }
+    }
+}
+
+fn chain_depth(cont: Option<&Cont>) -> usize {
+    cont.map(|cont| cont.depth()).unwrap_or_default()
+}
+
+/// Drops a continuation chain without recursion (see [`ContImpl::take_links`]).
+fn drop_chain(cont: Option<Cont>) {
+    let mut pending = Vec::new();
+    let mut current = cont;
+    while let Some(mut cont) = current.take().or_else(|| pending.pop()) {
+        // NOTE: shared continuations are not dropped here
+        if let Some(cont) = Rc::get_mut(&mut cont) {
+            let (next, other) = cont.take_links();
+            current = next;
+            pending.extend(other);
         }
     }
 }
@@ -795,9 +1383,14 @@ fn write_lit_cont_name(
 ) -> std::fmt::Result {
     let ty = stack_entry.ty();
     match ty {
-        StackValueType::Int | StackValueType::String | StackValueType::Builder => {
-            stack_entry.fmt_dump(f)
-        }
+        StackValueType::Int
+        | StackValueType::String
+        | StackValueType::Builder
+        | StackValueType::Slice => stack_entry.fmt_dump(f),
+        StackValueType::Atom => match stack_entry.as_atom() {
+            Ok(Atom::Named(name)) => write!(f, "`{name}"),
+            _ => stack_entry.fmt_dump(f),
+        },
         _ => {
             if let Ok(cont) = stack_entry.as_cont() {
                 write!(f, "{{ {} }}", cont.display_dump(d))
@@ -808,6 +1401,116 @@ fn write_lit_cont_name(
     }
 }
 
+enum DefinitionPart<'a> {
+    Word(&'a dyn ContImpl),
+    Literal(&'a dyn StackValue),
+    Block(&'a WordList),
+}
+
+impl<'a> DefinitionPart<'a> {
+    fn from_literal(value: &'a dyn StackValue, d: &Dictionaries) -> Self {
+        if let Ok(cont) = value.as_cont() {
+            if let (None, Some(list)) = (d.resolve_name(cont.as_ref()), cont.word_list()) {
+                return Self::Block(list);
+            }
+        }
+        Self::Literal(value)
+    }
+}
+
+fn definition_parts<'a>(item: &'a dyn ContImpl, d: &Dictionaries) -> Vec<DefinitionPart<'a>> {
+    if d.resolve_name(item).is_some() {
+        return vec![DefinitionPart::Word(item)];
+    }
+    if let Some(list) = item.word_list() {
+        return vec![DefinitionPart::Block(list)];
+    }
+    match item.literals() {
+        Some(values) => values
+            .iter()
+            .map(|value| DefinitionPart::from_literal(value.as_ref(), d))
+            .collect(),
+        None => vec![DefinitionPart::Word(item)],
+    }
+}
+
+/// Writes the word list inline or, if it has nested blocks, with runs
+/// of other words and each nested block on separate lines.
+fn write_word_list(
+    list: &WordList,
+    d: &Dictionaries,
+    indent: usize,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    const INDENT: usize = 2;
+
+    let parts = list
+        .items
+        .iter()
+        .flat_map(|item| definition_parts(item.as_ref(), d))
+        .collect::<Vec<_>>();
+
+    if !parts
+        .iter()
+        .any(|part| matches!(part, DefinitionPart::Block(_)))
+    {
+        f.write_str("{")?;
+        for part in &parts {
+            f.write_str(" ")?;
+            write_definition_part(part, d, indent, f)?;
+        }
+        return f.write_str(" }");
+    }
+
+    let inner = indent + INDENT;
+    f.write_str("{\n")?;
+    let mut line_started = false;
+    for part in &parts {
+        if let DefinitionPart::Block(_) = part {
+            if std::mem::take(&mut line_started) {
+                f.write_str("\n")?;
+            }
+            write!(f, "{:inner$}", "")?;
+            write_definition_part(part, d, inner, f)?;
+            f.write_str("\n")?;
+        } else {
+            if std::mem::replace(&mut line_started, true) {
+                f.write_str(" ")?;
+            } else {
+                write!(f, "{:inner$}", "")?;
+            }
+            write_definition_part(part, d, inner, f)?;
+        }
+    }
+    if line_started {
+        f.write_str("\n")?;
+    }
+    write!(f, "{:indent$}}}", "")
+}
+
+fn write_definition_part(
+    part: &DefinitionPart<'_>,
+    d: &Dictionaries,
+    indent: usize,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    match part {
+        DefinitionPart::Word(cont) => match d.resolve_name(*cont) {
+            Some(name) => f.write_str(name.trim_end()),
+            None => cont.fmt_name(&d.current, f),
+        },
+        DefinitionPart::Literal(value) => match value.as_cont() {
+            Ok(cont) => {
+                f.write_str("{ ")?;
+                write_definition_part(&DefinitionPart::Word(cont.as_ref()), d, indent, f)?;
+                f.write_str(" }")
+            }
+            Err(_) => write_lit_cont_name(*value, &d.current, f),
+        },
+        DefinitionPart::Block(list) => write_word_list(list, d, indent, f),
+    }
+}
+
 fn write_cont_name(
     cont: &dyn ContImpl,
     d: &Dictionary,
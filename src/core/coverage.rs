@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use ahash::HashMap;
+use anyhow::Result;
+
+use super::cont::Cont;
+use super::dictionary::{Dictionary, DictionaryEntry};
+use super::lexer::{LexerPosition, SourcePosition};
+use super::stack::SharedBox;
+use crate::util::write_json_string;
+
+/// Collects executed dictionary words and source lines.
+///
+/// Lines of compiled word lists are known from their source maps,
+/// so lines which were compiled but never executed have zero hits.
+#[derive(Default)]
+pub struct Coverage {
+    words: HashMap<*const (), Rc<str>>,
+    words_version: Option<(*const SharedBox, u64)>,
+    word_calls: BTreeMap<Rc<str>, u64>,
+    lines: BTreeMap<Rc<str>, BTreeMap<usize, u64>>,
+    last_input_position: Option<(usize, usize, usize)>,
+}
+
+impl Coverage {
+    /// Returns the number of calls of each dictionary word (including unused ones).
+    pub fn word_calls(&self) -> &BTreeMap<Rc<str>, u64> {
+        &self.word_calls
+    }
+
+    /// Returns the number of hits of each known line per source block.
+    pub fn lines(&self) -> &BTreeMap<Rc<str>, BTreeMap<usize, u64>> {
+        &self.lines
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\n  \"files\": {");
+        for (i, (name, lines)) in self.lines.iter().enumerate() {
+            out.push_str(if i == 0 { "\n    " } else { ",\n    " });
+            write_json_string(&mut out, name).unwrap();
+            out.push_str(": {");
+            for (i, (line, hits)) in lines.iter().enumerate() {
+                let delim = if i == 0 { "" } else { ", " };
+                write!(out, "{delim}\"{line}\": {hits}").unwrap();
+            }
+            out.push('}');
+        }
+        out.push_str("\n  },\n  \"words\": {");
+        for (i, (name, calls)) in self.word_calls.iter().enumerate() {
+            out.push_str(if i == 0 { "\n    " } else { ",\n    " });
+            write_json_string(&mut out, name).unwrap();
+            write!(out, ": {calls}").unwrap();
+        }
+        out.push_str("\n  }\n}\n");
+        out
+    }
+
+    /// Registers a line of a compiled word.
+    pub(crate) fn add_line(&mut self, position: &SourcePosition) {
+        self.file_lines(&position.source_block_name)
+            .entry(position.line_number)
+            .or_default();
+    }
+
+    pub(crate) fn enter(
+        &mut self,
+        cont: &Cont,
+        d: &Dictionary,
+        input: Option<LexerPosition<'_>>,
+    ) -> Result<()> {
+        self.refresh_words(d)?;
+
+        let ptr = Rc::as_ptr(cont) as *const ();
+        if let Some(name) = self.words.get(&ptr) {
+            *self.word_calls.entry(name.clone()).or_default() += 1;
+        }
+
+        if let Some(position) = cont.next_source_position() {
+            self.hit(&position.source_block_name, position.line_number);
+        }
+
+        // NOTE: words of the interpreted source are counted once per word
+        if let Some(input) = input {
+            let key = (input.offset, input.line_number, input.word_start);
+            if input.word_end > input.word_start && self.last_input_position != Some(key) {
+                self.last_input_position = Some(key);
+                self.hit(input.source_block_name, input.line_number);
+            }
+        }
+        Ok(())
+    }
+
+    fn hit(&mut self, name: &str, line: usize) {
+        *self.file_lines(name).entry(line).or_default() += 1;
+    }
+
+    fn file_lines(&mut self, name: &str) -> &mut BTreeMap<usize, u64> {
+        if !self.lines.contains_key(name) {
+            self.lines.insert(Rc::from(name), BTreeMap::new());
+        }
+        self.lines.get_mut(name).unwrap()
+    }
+
+    fn refresh_words(&mut self, d: &Dictionary) -> Result<()> {
+        let words_box = d.get_words_box();
+        let version = Some((Rc::as_ptr(words_box), words_box.version()));
+        if version == self.words_version {
+            return Ok(());
+        }
+
+        self.words.clear();
+        if let Some(map) = &d.clone_words_map()? {
+            for entry in map.as_ref() {
+                let Some((cont, _)) = DictionaryEntry::cont_from_value(entry.value.as_ref()) else {
+                    continue;
+                };
+                let name: Rc<str> = Rc::from(entry.key.stack_value.as_string()?.trim_end());
+                self.word_calls.entry(name.clone()).or_default();
+                self.words.insert(Rc::as_ptr(cont) as *const (), name);
+            }
+        }
+        self.words_version = version;
+        Ok(())
+    }
+}
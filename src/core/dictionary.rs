@@ -1,10 +1,16 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
+use ahash::HashMap;
 use anyhow::Result;
 
-use super::cont::{Cont, ContImpl, ContextTailWordFunc, ContextWordFunc, StackWordFunc};
-use super::stack::{HashMapTreeKey, HashMapTreeKeyRef, HashMapTreeNode, SharedBox, StackValue};
-use super::StackValueType;
+use super::cont::{
+    ClosureWord, Cont, ContImpl, ContextTailWordFunc, ContextWordFunc, StackWordFunc,
+};
+use super::stack::{
+    HashMapTreeKey, HashMapTreeKeyRef, HashMapTreeNode, HashMapTreeOwnedIter, SharedBox, StackValue,
+};
+use super::{Context, Module, StackValueType, WordInfo};
 
 pub struct Dictionaries {
     pub current: Dictionary,
@@ -24,34 +30,85 @@ impl Default for Dictionaries {
 }
 
 impl Dictionaries {
-    pub fn lookup(&self, word: &String, allow_space: bool) -> Result<Option<DictionaryEntry>> {
-        if allow_space {
-            let mut entry = self.lookup(word, false)?;
-
-            if entry.is_none() {
-                entry = self.lookup(&format!("{word} "), false)?;
-            }
-
-            return Ok(entry);
+    pub fn lookup(&self, word: &str, allow_space: bool) -> Result<Option<DictionaryEntry>> {
+        let mut entry = self.lookup_in(|d| d.lookup(word))?;
+        if entry.is_none() && allow_space {
+            entry = self.lookup_token(word)?;
         }
+        Ok(entry)
+    }
 
-        let mut entry = self.context.lookup(word)?;
+    /// Finds a word by the token, i.e. by the name without a trailing space.
+    pub fn lookup_token(&self, token: &str) -> Result<Option<DictionaryEntry>> {
+        self.lookup_in(|d| d.lookup_token(token))
+    }
+
+    fn lookup_in<F>(&self, f: F) -> Result<Option<DictionaryEntry>>
+    where
+        F: Fn(&Dictionary) -> Result<Option<DictionaryEntry>>,
+    {
+        let mut entry = f(&self.context)?;
 
         if entry.is_none() && self.current != self.context {
-            entry = self.current.lookup(word)?;
+            entry = f(&self.current)?;
         }
 
         if entry.is_none() && self.original != self.context && self.original != self.current {
-            entry = self.original.lookup(word)?;
+            entry = f(&self.original)?;
         }
 
         Ok(entry)
     }
+
+    /// Finds a name of the definition in the same order as words are looked up.
+    pub fn resolve_name(&self, definition: &dyn ContImpl) -> Option<Rc<String>> {
+        let mut name = self.context.resolve_name(definition);
+
+        if name.is_none() && self.current != self.context {
+            name = self.current.resolve_name(definition);
+        }
+
+        if name.is_none() && self.original != self.context && self.original != self.current {
+            name = self.original.resolve_name(definition);
+        }
+
+        name
+    }
+
+    /// Finds a word with the longest name which is a prefix of the token.
+    pub fn lookup_prefix(&self, token: &str) -> Result<Option<(usize, DictionaryEntry)>> {
+        let mut len = self.context.find_prefix_len(token)?;
+
+        if self.current != self.context {
+            len = len.max(self.current.find_prefix_len(token)?);
+        }
+
+        if self.original != self.context && self.original != self.current {
+            len = len.max(self.original.find_prefix_len(token)?);
+        }
+
+        let Some(len) = len else {
+            return Ok(None);
+        };
+        let entry = self.lookup_in(|d| d.lookup_name(&token[..len], false))?;
+        Ok(entry.map(|entry| (len, entry)))
+    }
 }
 
-#[derive(Default, Clone, Eq, PartialEq)]
+#[derive(Default, Clone)]
 pub struct Dictionary {
     words: Rc<SharedBox>,
+    index: Rc<RefCell<WordIndexCache>>,
+    lazy_modules: Rc<RefCell<Vec<LazyModule>>>,
+    constants: Rc<RefCell<ConstantPool>>,
+    nonstandard: Rc<RefCell<NonstandardDefinitions>>,
+}
+
+impl Eq for Dictionary {}
+impl PartialEq for Dictionary {
+    fn eq(&self, other: &Self) -> bool {
+        self.words == other.words
+    }
 }
 
 impl Dictionary {
@@ -84,33 +141,88 @@ impl Dictionary {
         })
     }
 
-    pub fn lookup(&self, name: &String) -> Result<Option<DictionaryEntry>> {
-        let map = self.clone_words_map()?;
-        let key = HashMapTreeKeyRef::from(name);
-        let Some(node) = HashMapTreeNode::lookup(&map, key) else {
-            return Ok(None);
-        };
-        Ok(DictionaryEntry::try_from_value(node.value.as_ref()))
+    pub fn iter(&self) -> Result<DictionaryIter> {
+        Ok(DictionaryIter {
+            inner: self.clone_words_map()?.map(HashMapTreeNode::owned_iter),
+        })
     }
 
-    pub fn resolve_name(&self, definition: &dyn ContImpl) -> Option<Rc<String>> {
-        let map = self.words.borrow();
-        if let Ok(map) = map.as_hashmap() {
-            for entry in map {
-                let Some((cont, _)) = DictionaryEntry::cont_from_value(entry.value.as_ref()) else {
-                    continue;
-                };
-
-                // NOTE: erase trait data from fat pointers
-                let left = Rc::as_ptr(cont) as *const ();
-                let right = definition as *const _ as *const ();
-                // Compare only the address part
-                if std::ptr::eq(left, right) {
-                    return entry.key.stack_value.clone().into_string().ok();
-                }
+    pub fn lookup(&self, name: &str) -> Result<Option<DictionaryEntry>> {
+        self.lookup_name(name, false)
+    }
+
+    /// Finds a word by the token, i.e. by the name without a trailing space.
+    pub fn lookup_token(&self, token: &str) -> Result<Option<DictionaryEntry>> {
+        self.lookup_name(token, true)
+    }
+
+    fn lookup_name(&self, name: &str, add_space: bool) -> Result<Option<DictionaryEntry>> {
+        let entry = self.lookup_entry(name, add_space)?;
+        if let Some(entry) = &entry {
+            if self.init_lazy_module(&entry.definition)? {
+                return self.lookup_entry(name, add_space);
             }
         }
-        None
+        Ok(entry)
+    }
+
+    fn lookup_entry(&self, name: &str, add_space: bool) -> Result<Option<DictionaryEntry>> {
+        let mut cache = self.index.borrow_mut();
+        let index = cache.get(&self.words);
+        if !index.is_valid() {
+            index.rebuild()?;
+        }
+        Ok(index
+            .get(name, add_space)
+            .and_then(|value| DictionaryEntry::try_from_value(value.as_ref())))
+    }
+
+    /// Registers a named constant which is shared by all dictionaries
+    /// of the context, returns the previous value.
+    pub fn define_constant<T, V>(&self, name: T, value: V) -> Option<Rc<dyn StackValue>>
+    where
+        T: AsRef<str>,
+        V: Into<Rc<dyn StackValue>>,
+    {
+        self.constants
+            .borrow_mut()
+            .insert(name.as_ref(), value.into())
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<Rc<dyn StackValue>> {
+        self.constants.borrow().values.get(name).cloned()
+    }
+
+    /// Returns all registered constants sorted by name.
+    pub fn constants(&self) -> Vec<(Rc<str>, Rc<dyn StackValue>)> {
+        let pool = self.constants.borrow();
+        let mut constants = pool
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        constants.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        constants
+    }
+
+    /// Returns the length of the longest word name which is a prefix of the token.
+    pub fn find_prefix_len(&self, token: &str) -> Result<Option<usize>> {
+        let mut cache = self.index.borrow_mut();
+        let index = cache.get(&self.words);
+        if !index.is_valid() {
+            index.rebuild()?;
+        }
+        Ok(index.find(token))
+    }
+
+    pub fn resolve_name(&self, definition: &dyn ContImpl) -> Option<Rc<String>> {
+        let mut cache = self.index.borrow_mut();
+        let index = cache.get(&self.words);
+        if !index.is_valid() {
+            index.rebuild().ok()?;
+        }
+        // NOTE: erase trait data from fat pointers
+        index.resolve(definition as *const _ as *const ())
     }
 
     pub fn define_context_word<T: Into<String>>(
@@ -165,27 +277,515 @@ impl Dictionary {
         )
     }
 
+    /// Defines a word from a closure, which can capture the host state.
+    pub fn define_closure_word<T, F>(&mut self, name: T, f: F) -> Result<()>
+    where
+        T: Into<String>,
+        F: Fn(&mut Context) -> Result<()> + 'static,
+    {
+        self.define_closure_tail_word(name, move |ctx| {
+            f(ctx)?;
+            Ok(None)
+        })
+    }
+
+    pub fn define_closure_tail_word<T, F>(&mut self, name: T, f: F) -> Result<()>
+    where
+        T: Into<String>,
+        F: Fn(&mut Context) -> Result<Option<Cont>> + 'static,
+    {
+        self.define_word(
+            name,
+            DictionaryEntry {
+                definition: Rc::new(ClosureWord::new(f)),
+                active: false,
+            },
+        )
+    }
+
     pub fn define_word<T, E>(&mut self, name: T, word: E) -> Result<()>
     where
         T: Into<String>,
         E: Into<DictionaryEntry>,
     {
         fn define_word_impl(d: &mut Dictionary, name: String, word: DictionaryEntry) -> Result<()> {
-            let mut map = d.use_words_map()?;
+            let index_valid = d.index.borrow_mut().get(&d.words).is_valid();
+
+            let name = Rc::new(name);
+            let value: Rc<dyn StackValue> = word.into();
+            {
+                let mut map = d.use_words_map()?;
+                let key = HashMapTreeKey::from(name.clone());
+                HashMapTreeNode::set(&mut map, &key, &value);
+            }
 
-            let key = HashMapTreeKey::from(name);
-            let value = &word.into();
-            HashMapTreeNode::set(&mut map, &key, value);
+            if index_valid {
+                let mut cache = d.index.borrow_mut();
+                let index = cache.get(&d.words);
+                index.insert(name, value);
+                index.version = Some(d.words.version());
+            }
             Ok(())
         }
         define_word_impl(self, name.into(), word.into())
     }
 
+    /// Defines multiple words at once, the words map is updated only once.
+    pub fn define_many<I, T, E>(&mut self, words: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (T, E)>,
+        T: Into<String>,
+        E: Into<DictionaryEntry>,
+    {
+        let index_valid = self.index.borrow_mut().get(&self.words).is_valid();
+
+        let words = words.into_iter();
+        let mut items = Vec::with_capacity(words.size_hint().0);
+        {
+            let mut map = self.use_words_map()?;
+            for (name, word) in words {
+                let name = Rc::new(name.into());
+                let key = HashMapTreeKey::from(name.clone());
+                let word: DictionaryEntry = word.into();
+                let value: Rc<dyn StackValue> = word.into();
+                HashMapTreeNode::set(&mut map, &key, &value);
+                items.push((name, value));
+            }
+        }
+
+        if index_valid {
+            let mut cache = self.index.borrow_mut();
+            let index = cache.get(&self.words);
+            index.extend(items);
+            index.version = Some(self.words.version());
+        }
+        Ok(())
+    }
+
+    /// Remembers definitions of the nonstandard words of the module,
+    /// so that they are recognized by the definition rather than by the name.
+    pub(crate) fn mark_nonstandard(&self, words: &'static [WordInfo]) -> Result<()> {
+        for info in words.iter().filter(|info| info.nonstandard) {
+            if let Some(entry) = self.lookup_entry(info.name, false)? {
+                let mut nonstandard = self.nonstandard.borrow_mut();
+                nonstandard.insert(
+                    Rc::as_ptr(&entry.definition) as *const (),
+                    (entry.definition, info),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns info of the nonstandard word with the specified definition.
+    pub fn nonstandard_info(&self, definition: &Cont) -> Option<&'static WordInfo> {
+        let nonstandard = self.nonstandard.borrow();
+        let (_, info) = nonstandard.get(&(Rc::as_ptr(definition) as *const ()))?;
+        Some(*info)
+    }
+
+    /// Defines stubs for all words with `WordInfo` of the module.
+    /// The module is initialized on the first lookup of any of them.
+    ///
+    /// NOTE: words which are defined without `WordInfo` (e.g. in `#[init]`)
+    /// are available only after the module is initialized.
+    pub fn define_lazy_module<T: Module + 'static>(&mut self, module: T) -> Result<()> {
+        let stub: Cont = Rc::new(LazyModuleStub);
+        self.define_many(module.words().iter().map(|info| (info.name, stub.clone())))?;
+        self.lazy_modules.borrow_mut().push(LazyModule {
+            stub,
+            module: Box::new(module),
+        });
+        Ok(())
+    }
+
+    /// Initializes the lazy module if the definition is its stub.
+    fn init_lazy_module(&self, definition: &Cont) -> Result<bool> {
+        let LazyModule { stub, module } = {
+            let mut modules = self.lazy_modules.borrow_mut();
+            match modules
+                .iter()
+                .position(|m| is_same_cont(&m.stub, definition))
+            {
+                Some(i) => modules.swap_remove(i),
+                None => return Ok(false),
+            }
+        };
+
+        let mut words = Dictionary {
+            constants: self.constants.clone(),
+            nonstandard: self.nonstandard.clone(),
+            ..Default::default()
+        };
+        module.init(&mut words)?;
+        words.mark_nonstandard(module.words())?;
+
+        // NOTE: words which were redefined after the module was added are kept
+        let mut new_words = Vec::new();
+        for (name, entry) in words.iter()? {
+            match self.lookup_entry(&name, false)? {
+                Some(prev) if !is_same_cont(&prev.definition, &stub) => continue,
+                _ => new_words.push((name.as_ref().clone(), entry)),
+            }
+        }
+        self.clone().define_many(new_words)?;
+        Ok(true)
+    }
+
     pub fn undefine_word(&mut self, name: &String) -> Result<bool> {
-        let mut map = self.use_words_map()?;
+        let index_valid = self.index.borrow_mut().get(&self.words).is_valid();
+
+        let removed = {
+            let mut map = self.use_words_map()?;
+            let key = HashMapTreeKeyRef::from(name);
+            HashMapTreeNode::remove(&mut map, key).is_some()
+        };
+
+        if index_valid {
+            let mut cache = self.index.borrow_mut();
+            let index = cache.get(&self.words);
+            index.remove(name);
+            index.version = Some(self.words.version());
+        }
+        Ok(removed)
+    }
+}
 
-        let key = HashMapTreeKeyRef::from(name);
-        Ok(HashMapTreeNode::remove(&mut map, key).is_some())
+/// Indices of the recently used words boxes.
+///
+/// NOTE: dictionary clones share the cache, but `context` and `current`
+/// can point to different boxes, so several indices are kept at once.
+#[derive(Default)]
+struct WordIndexCache {
+    items: Vec<WordIndex>,
+}
+
+impl WordIndexCache {
+    const MAX_ITEMS: usize = 8;
+
+    fn get(&mut self, words: &SharedBox) -> &mut WordIndex {
+        let i = match self.items.iter().position(|item| item.words == *words) {
+            Some(i) => i,
+            None => {
+                if self.items.len() >= Self::MAX_ITEMS {
+                    self.items.remove(0);
+                }
+                self.items.push(WordIndex::new(words.clone()));
+                self.items.len() - 1
+            }
+        };
+        &mut self.items[i]
+    }
+}
+
+/// Words of the dictionary by names, synced with the words box version.
+///
+/// Names with a trailing space are looked up without it, so that tokens
+/// can be found without building a new string.
+struct WordIndex {
+    words: SharedBox,
+    /// Version of the words box for which the index was built.
+    version: Option<u64>,
+    /// Interned words, slots of removed words are reused.
+    entries: Vec<Option<WordIndexEntry>>,
+    free_ids: Vec<WordId>,
+    spaced: HashMap<WordName, WordId>,
+    plain: HashMap<WordName, WordId>,
+    /// Words by addresses of their definitions with the number of names.
+    definitions: HashMap<*const (), (WordId, usize)>,
+    /// Sorted names of words which can be used as token prefixes
+    /// (i.e. without a trailing space).
+    prefixes: Vec<Rc<String>>,
+}
+
+type WordId = usize;
+
+struct WordIndexEntry {
+    name: Rc<String>,
+    value: Rc<dyn StackValue>,
+}
+
+impl WordIndexEntry {
+    fn definition_ptr(&self) -> Option<*const ()> {
+        let (cont, _) = DictionaryEntry::cont_from_value(self.value.as_ref())?;
+        Some(Rc::as_ptr(cont) as *const ())
+    }
+}
+
+impl WordIndex {
+    fn new(words: SharedBox) -> Self {
+        Self {
+            words,
+            version: None,
+            entries: Default::default(),
+            free_ids: Default::default(),
+            spaced: Default::default(),
+            plain: Default::default(),
+            definitions: Default::default(),
+            prefixes: Default::default(),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.version == Some(self.words.version())
+    }
+
+    fn rebuild(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.free_ids.clear();
+        self.spaced.clear();
+        self.plain.clear();
+        self.definitions.clear();
+        self.prefixes.clear();
+        let map = self.words.fetch();
+        if !map.is_null() {
+            for entry in map.as_hashmap()? {
+                if let Ok(name) = entry.key.stack_value.clone().into_string() {
+                    self.insert_value(name, entry.value.clone());
+                }
+            }
+        }
+        self.prefixes.sort_unstable();
+        self.version = Some(self.words.version());
+        Ok(())
+    }
+
+    fn get(&self, name: &str, add_space: bool) -> Option<&Rc<dyn StackValue>> {
+        let id = if add_space {
+            self.spaced.get(name)
+        } else {
+            match name.strip_suffix(' ') {
+                Some(name) => self.spaced.get(name),
+                None => self.plain.get(name),
+            }
+        };
+        Some(&self.entries[*id?].as_ref()?.value)
+    }
+
+    fn resolve(&self, definition: *const ()) -> Option<Rc<String>> {
+        let (id, _) = self.definitions.get(&definition)?;
+        Some(self.entries[*id].as_ref()?.name.clone())
+    }
+
+    /// Inserts the name without keeping the prefixes sorted.
+    fn insert_value(&mut self, name: Rc<String>, value: Rc<dyn StackValue>) {
+        if self.insert_entry(name.clone(), value) && !name.ends_with(' ') {
+            self.prefixes.push(name);
+        }
+    }
+
+    fn insert(&mut self, name: Rc<String>, value: Rc<dyn StackValue>) {
+        if self.insert_entry(name.clone(), value) && !name.ends_with(' ') {
+            if let Err(i) = self.prefixes.binary_search(&name) {
+                self.prefixes.insert(i, name);
+            }
+        }
+    }
+
+    /// Returns `true` if the name is new.
+    fn insert_entry(&mut self, name: Rc<String>, value: Rc<dyn StackValue>) -> bool {
+        let names = if name.ends_with(' ') {
+            &mut self.spaced
+        } else {
+            &mut self.plain
+        };
+
+        let entry = WordIndexEntry { name, value };
+        if let Some(&id) = names.get(WordName::token(&entry.name)) {
+            self.remove_definition(id);
+            self.entries[id] = Some(entry);
+            self.add_definition(id);
+            false
+        } else {
+            let key = WordName(entry.name.clone());
+            let id = match self.free_ids.pop() {
+                Some(id) => {
+                    self.entries[id] = Some(entry);
+                    id
+                }
+                None => {
+                    self.entries.push(Some(entry));
+                    self.entries.len() - 1
+                }
+            };
+            names.insert(key, id);
+            self.add_definition(id);
+            true
+        }
+    }
+
+    fn extend(&mut self, items: Vec<(Rc<String>, Rc<dyn StackValue>)>) {
+        for (name, value) in items {
+            self.insert_value(name, value);
+        }
+        self.prefixes.sort_unstable();
+        self.prefixes.dedup();
+    }
+
+    fn remove(&mut self, name: &str) {
+        let id = match name.strip_suffix(' ') {
+            Some(token) => self.spaced.remove(token),
+            None => {
+                if let Ok(i) = self
+                    .prefixes
+                    .binary_search_by(|item| item.as_str().cmp(name))
+                {
+                    self.prefixes.remove(i);
+                }
+                self.plain.remove(name)
+            }
+        };
+        if let Some(id) = id {
+            self.remove_definition(id);
+            self.entries[id] = None;
+            self.free_ids.push(id);
+        }
+    }
+
+    fn add_definition(&mut self, id: WordId) {
+        if let Some(ptr) = self.entries[id].as_ref().and_then(|e| e.definition_ptr()) {
+            // NOTE: the first name is used for words with several names
+            self.definitions.entry(ptr).or_insert((id, 0)).1 += 1;
+        }
+    }
+
+    fn remove_definition(&mut self, id: WordId) {
+        let Some(ptr) = self.entries[id].as_ref().and_then(|e| e.definition_ptr()) else {
+            return;
+        };
+        let Some((first, count)) = self.definitions.get_mut(&ptr) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.definitions.remove(&ptr);
+        } else if *first == id {
+            // Find another name of the same definition
+            let entries = self.entries.iter().enumerate();
+            if let Some(other) = entries
+                .filter(|(other, _)| *other != id)
+                .find(|(_, e)| e.as_ref().and_then(|e| e.definition_ptr()) == Some(ptr))
+            {
+                *first = other.0;
+            }
+        }
+    }
+
+    fn find(&self, token: &str) -> Option<usize> {
+        let mut token = token;
+        loop {
+            // NOTE: all names which are prefixes of the token are not greater than
+            // the last name which is not greater than the token, and they are
+            // also prefixes of that name.
+            let i = self.prefixes.partition_point(|name| name.as_str() <= token);
+            let name = self.prefixes.get(i.checked_sub(1)?)?;
+            if token.starts_with(name.as_str()) {
+                return Some(name.len());
+            }
+            token = &token[..common_prefix_len(name, token)];
+        }
+    }
+}
+
+/// Word name which is hashed and compared without the trailing space.
+struct WordName(Rc<String>);
+
+impl WordName {
+    fn token(name: &str) -> &str {
+        name.strip_suffix(' ').unwrap_or(name)
+    }
+
+    fn as_token(&self) -> &str {
+        Self::token(&self.0)
+    }
+}
+
+impl std::borrow::Borrow<str> for WordName {
+    fn borrow(&self) -> &str {
+        self.as_token()
+    }
+}
+
+impl Eq for WordName {}
+impl PartialEq for WordName {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_token() == other.as_token()
+    }
+}
+
+impl std::hash::Hash for WordName {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_token().hash(state)
+    }
+}
+
+#[derive(Default)]
+struct ConstantPool {
+    values: HashMap<Rc<str>, Rc<dyn StackValue>>,
+}
+
+impl ConstantPool {
+    fn insert(&mut self, name: &str, value: Rc<dyn StackValue>) -> Option<Rc<dyn StackValue>> {
+        match self.values.get_mut(name) {
+            Some(prev) => Some(std::mem::replace(prev, value)),
+            None => {
+                self.values.insert(Rc::from(name), value);
+                None
+            }
+        }
+    }
+}
+
+// NOTE: definitions are kept to prevent reuse of their addresses
+type NonstandardDefinitions = HashMap<*const (), (Cont, &'static WordInfo)>;
+
+struct LazyModule {
+    stub: Cont,
+    module: Box<dyn Module>,
+}
+
+struct LazyModuleStub;
+
+impl ContImpl for LazyModuleStub {
+    fn run(self: Rc<Self>, _: &mut Context) -> Result<Option<Cont>> {
+        anyhow::bail!("Lazy module word was used without a dictionary lookup")
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<lazy module word>")
+    }
+}
+
+fn is_same_cont(a: &Cont, b: &Cont) -> bool {
+    std::ptr::eq(Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ())
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+pub struct DictionaryIter {
+    inner: Option<HashMapTreeOwnedIter>,
+}
+
+impl Iterator for DictionaryIter {
+    type Item = (Rc<String>, DictionaryEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let inner = self.inner.as_mut()?;
+        for node in inner.by_ref() {
+            let Ok(name) = node.key.stack_value.clone().into_string() else {
+                continue;
+            };
+            if let Some(entry) = DictionaryEntry::try_from_value(node.value.as_ref()) {
+                return Some((name, entry));
+            }
+        }
+        None
     }
 }
 
@@ -203,7 +803,7 @@ impl DictionaryEntry {
         })
     }
 
-    fn cont_from_value(value: &dyn StackValue) -> Option<(&Cont, bool)> {
+    pub(crate) fn cont_from_value(value: &dyn StackValue) -> Option<(&Cont, bool)> {
         if let Ok(cont) = value.as_cont() {
             return Some((cont, false));
         } else if let Ok(tuple) = value.as_tuple() {
@@ -272,3 +872,52 @@ impl Drop for WordsRefMut<'_> {
         self.words_box.store_opt(self.map.take());
     }
 }
+
+/// Defines ordinary words which push integer constants,
+/// e.g. `define_int_constants!(d, { "true" => -1, "false" => 0 })`.
+#[macro_export]
+macro_rules! define_int_constants {
+    ($d:expr, { $($name:literal => $value:expr),* $(,)? }) => {
+        $crate::core::Dictionary::define_many($d, [$((
+            concat!($name, " "),
+            ::std::rc::Rc::new($crate::core::cont::IntLitCont::from($value)),
+        )),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cont::IntLitCont;
+
+    #[test]
+    fn removed_slots_are_reused() {
+        let mut d = Dictionary::default();
+        let word = |i: i32| -> Cont { Rc::new(IntLitCont::from(i)) };
+        let index_len = |d: &Dictionary| {
+            let mut cache = d.index.borrow_mut();
+            let index = cache.get(&d.words);
+            assert!(index.is_valid());
+            index.entries.len()
+        };
+
+        let a = word(1);
+        d.define_word("a ", a.clone()).unwrap();
+        d.lookup("a ").unwrap().unwrap();
+        for i in 0..10 {
+            let b = word(i);
+            d.define_word("b ", b.clone()).unwrap();
+            d.define_word("c", b.clone()).unwrap();
+            assert_eq!(d.resolve_name(b.as_ref()).unwrap().as_str(), "b ");
+
+            d.undefine_word(&"b ".to_owned()).unwrap();
+            assert!(d.lookup("b ").unwrap().is_none());
+            assert_eq!(d.resolve_name(b.as_ref()).unwrap().as_str(), "c");
+            d.undefine_word(&"c".to_owned()).unwrap();
+            assert!(d.resolve_name(b.as_ref()).is_none());
+        }
+        assert_eq!(index_len(&d), 3);
+        assert_eq!(d.resolve_name(a.as_ref()).unwrap().as_str(), "a ");
+        assert!(d.lookup("a ").unwrap().is_some());
+    }
+}
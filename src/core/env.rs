@@ -1,5 +1,7 @@
 use std::io::BufRead;
 
+use everscale_types::cell::{Cell, HashBytes};
+
 pub trait Environment {
     fn now_ms(&self) -> u64;
 
@@ -7,13 +9,61 @@ pub trait Environment {
 
     fn file_exists(&self, name: &str) -> bool;
 
-    fn write_file(&mut self, name: &str, contents: &[u8]) -> std::io::Result<()>;
+    fn write_file(&mut self, name: &str, contents: &[u8], mode: WriteMode) -> std::io::Result<()>;
 
     fn read_file(&mut self, name: &str) -> std::io::Result<Vec<u8>>;
 
     fn read_file_part(&mut self, name: &str, offset: u64, len: u64) -> std::io::Result<Vec<u8>>;
 
     fn include(&self, name: &str) -> std::io::Result<SourceBlock>;
+
+    /// Returns a name which is the same for all names of the same file.
+    fn canonical_name(&self, name: &str) -> String {
+        name.to_owned()
+    }
+
+    /// Creates a directory with all missing parents.
+    fn create_dir(&mut self, name: &str) -> std::io::Result<()> {
+        _ = name;
+        Ok(())
+    }
+
+    /// Removes a file (e.g. a temporary one).
+    fn remove_file(&mut self, name: &str) -> std::io::Result<()> {
+        _ = name;
+        Ok(())
+    }
+
+    /// Compiles FunC (or Tolk) source files into a code cell using an external compiler.
+    fn compile_func(&mut self, files: &[String]) -> std::io::Result<Cell> {
+        _ = files;
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "FunC compiler is not configured",
+        ))
+    }
+
+    /// Resolves a library cell which is missing in the context VM libraries.
+    fn load_library(&mut self, hash: &HashBytes) -> Option<Cell> {
+        _ = hash;
+        None
+    }
+
+    /// Returns a path of the JSON file used by `kv-get` and `kv-put`.
+    fn kv_store_path(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum WriteMode {
+    /// Create a new file, fail if it already exists.
+    Create,
+    /// Append to the end of the file, create it if it doesn't exist.
+    Append,
+    /// Replace the file contents (atomically if possible).
+    #[default]
+    Truncate,
 }
 
 pub struct SourceBlock {
@@ -29,6 +79,11 @@ impl SourceBlock {
         }
     }
 
+    /// Creates a source block from the in-memory source code.
+    pub fn from_string<N: Into<String>, S: Into<String>>(name: N, source: S) -> Self {
+        Self::new(name, std::io::Cursor::new(source.into()))
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -53,7 +108,7 @@ impl Environment for EmptyEnvironment {
         false
     }
 
-    fn write_file(&mut self, _: &str, _: &[u8]) -> std::io::Result<()> {
+    fn write_file(&mut self, _: &str, _: &[u8], _: WriteMode) -> std::io::Result<()> {
         Ok(())
     }
 
@@ -1,7 +1,10 @@
+use std::rc::Rc;
+
 use anyhow::{Context, Result};
 
 use super::env::SourceBlock;
 use crate::error::UnexpectedEof;
+use crate::util::ImmediateInt;
 
 #[derive(Default)]
 pub struct Lexer {
@@ -13,6 +16,21 @@ impl Lexer {
         self.blocks.push(SourceBlockState::from(block));
     }
 
+    /// Pushes a block which ends the current interpreter loop
+    /// instead of continuing with the previous block.
+    ///
+    /// NOTE: such block must be popped explicitly.
+    pub fn push_nested_source_block(&mut self, block: SourceBlock) {
+        let mut block = SourceBlockState::from(block);
+        block.nested = true;
+        self.blocks.push(block);
+    }
+
+    /// Returns whether the current block was pushed as a nested one.
+    pub fn is_nested_source_block(&self) -> bool {
+        matches!(self.blocks.last(), Some(block) if block.nested)
+    }
+
     pub fn pop_source_block(&mut self) -> bool {
         self.blocks.pop().is_some()
     }
@@ -34,6 +52,25 @@ impl Lexer {
         })
     }
 
+    /// Returns the name of the current source block.
+    pub fn source_block_name(&self) -> Option<&str> {
+        Some(self.blocks.last()?.block.name())
+    }
+
+    /// Returns positions in all active source blocks,
+    /// starting from the innermost one (i.e. the current position).
+    pub fn include_chain(&self) -> Vec<SourcePosition> {
+        self.blocks
+            .iter()
+            .rev()
+            .map(|input| SourcePosition {
+                source_block_name: Rc::from(input.block.name()),
+                line_number: input.line_number,
+                column: input.prev_word_start + 1,
+            })
+            .collect()
+    }
+
     pub fn depth(&self) -> i32 {
         (self.blocks.len() as i32) - 1
     }
@@ -107,6 +144,198 @@ pub struct LexerPosition<'a> {
     pub line_number: usize,
 }
 
+impl LexerPosition<'_> {
+    pub fn to_source_position(&self) -> SourcePosition {
+        SourcePosition {
+            source_block_name: Rc::from(self.source_block_name),
+            line_number: self.line_number,
+            column: self.word_start + 1,
+        }
+    }
+}
+
+/// An owned position of a word in the source code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourcePosition {
+    pub source_block_name: Rc<str>,
+    pub line_number: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.source_block_name, self.line_number, self.column
+        )
+    }
+}
+
+/// A token of the source code produced by [`Tokenizer`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Token<'a> {
+    /// A word which is not a number.
+    Word(&'a str),
+    /// An integer or a fraction literal (e.g. `-10`, `0xff`, `1/3`).
+    Number(&'a str),
+    /// A string literal with a prefix word (e.g. `"`, `."`, `abort"`).
+    String { prefix: &'a str, value: &'a str },
+    /// A data literal with a prefix word (e.g. `x{`, `b{`, `B{`).
+    Data { prefix: &'a str, value: &'a str },
+    /// A comment including its delimiters (or a shebang line).
+    Comment(&'a str),
+    /// A string, data literal or comment without the end delimiter.
+    Unterminated(&'a str),
+}
+
+/// A range of the token in the source code.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Span {
+    /// Byte offset of the first token character.
+    pub start: usize,
+    /// Byte offset after the last token character.
+    pub end: usize,
+    /// Line number of the token start (starting from 1).
+    pub line_number: usize,
+    /// Byte column of the token start (starting from 1).
+    pub column: usize,
+}
+
+/// Splits the source code into tokens the same way as the interpreter
+/// with the standard `Fift.fif` words does, without executing anything.
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    offset: usize,
+    line_start: usize,
+    line_number: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    const STRING_PREFIXES: &'static [&'static str] = &["\"", ".\"", "+\"", "abort\""];
+    const DATA_PREFIXES: &'static [&'static str] = &["x{", "b{", "B{"];
+
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            offset: 0,
+            line_start: 0,
+            line_number: 1,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        for (offset, c) in self.input[self.offset..].char_indices() {
+            if !c.is_whitespace() {
+                self.offset += offset;
+                return;
+            }
+            if c == '\n' {
+                self.line_number += 1;
+                self.line_start = self.offset + offset + 1;
+            }
+        }
+        self.offset = self.input.len();
+    }
+
+    /// Returns the end of the delimited part starting at the offset,
+    /// and whether the delimiter was found on the same line.
+    fn find_in_line(&self, from: usize, delimiter: char) -> (usize, bool) {
+        let rest = &self.input[from..];
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+        match rest[..line_end].find(delimiter) {
+            Some(pos) => (from + pos, true),
+            None => (from + rest[..line_end].trim_end_matches('\r').len(), false),
+        }
+    }
+
+    /// Returns the end of a multiline comment (after `*/`), if any.
+    fn find_comment_end(&self, from: usize) -> Option<usize> {
+        let mut offset = from;
+        for word in self.input[from..].split_inclusive(char::is_whitespace) {
+            let end = offset + word.trim_end().len();
+            if word.trim_end() == "*/" {
+                return Some(end);
+            }
+            offset += word.len();
+        }
+        None
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (Token<'a>, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // NOTE: the interpreter skips the first line if it is a shebang
+        let shebang = self.offset == 0 && self.input.starts_with("#!");
+        if !shebang {
+            self.skip_whitespace();
+        }
+        if self.offset >= self.input.len() {
+            return None;
+        }
+
+        let start = self.offset;
+        let span_start = (start, self.line_number, start - self.line_start + 1);
+
+        let rest = &self.input[start..];
+        let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..word_len];
+
+        let (token, end) = if shebang || word == "//" {
+            let (end, _) = self.find_in_line(start, '\n');
+            (Token::Comment(&self.input[start..end]), end)
+        } else if word == "/*" {
+            match self.find_comment_end(start + word_len) {
+                Some(end) => (Token::Comment(&self.input[start..end]), end),
+                None => (Token::Unterminated(rest), self.input.len()),
+            }
+        } else if let Some(prefix) = Self::STRING_PREFIXES
+            .iter()
+            .chain(Self::DATA_PREFIXES)
+            .filter(|prefix| word.starts_with(**prefix))
+            .max_by_key(|prefix| prefix.len())
+        {
+            let is_string = Self::STRING_PREFIXES.contains(prefix);
+            let delimiter = if is_string { '"' } else { '}' };
+
+            let value_start = start + prefix.len();
+            let (value_end, found) = self.find_in_line(value_start, delimiter);
+            let value = &self.input[value_start..value_end];
+            match (found, is_string) {
+                (false, _) => (
+                    Token::Unterminated(&self.input[start..value_end]),
+                    value_end,
+                ),
+                (true, true) => (Token::String { prefix, value }, value_end + 1),
+                (true, false) => (Token::Data { prefix, value }, value_end + 1),
+            }
+        } else if matches!(ImmediateInt::try_from_str(word), Ok(Some(_))) {
+            (Token::Number(word), start + word_len)
+        } else {
+            (Token::Word(word), start + word_len)
+        };
+
+        // NOTE: multiline comments can contain line breaks
+        for (offset, c) in self.input[start..end].char_indices() {
+            if c == '\n' {
+                self.line_number += 1;
+                self.line_start = start + offset + 1;
+            }
+        }
+        self.offset = end;
+
+        let span = Span {
+            start: span_start.0,
+            end,
+            line_number: span_start.1,
+            column: span_start.2,
+        };
+        Some((token, span))
+    }
+}
+
 pub trait Delimiter {
     fn delim(&mut self, c: char) -> bool;
 }
@@ -132,6 +361,7 @@ struct SourceBlockState {
     prev_word_start: usize,
     prev_word_end: usize,
     line_number: usize,
+    nested: bool,
 }
 
 impl From<SourceBlock> for SourceBlockState {
@@ -144,6 +374,7 @@ impl From<SourceBlock> for SourceBlockState {
             prev_word_start: 0,
             prev_word_end: 0,
             line_number: 0,
+            nested: false,
         }
     }
 }
@@ -351,3 +582,112 @@ impl AsciiCharClassifier {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token<'_>> {
+        Tokenizer::new(input).map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn tokenizer() {
+        assert_eq!(
+            tokens("#!/usr/bin/env fift -s\n1 2 +"),
+            [
+                Token::Comment("#!/usr/bin/env fift -s"),
+                Token::Number("1"),
+                Token::Number("2"),
+                Token::Word("+"),
+            ]
+        );
+
+        // Prefix words are followed by the literal without a space
+        assert_eq!(
+            tokens(r#"."hello world" "" abort"oops" x{ab_} B{}"#),
+            [
+                Token::String {
+                    prefix: ".\"",
+                    value: "hello world"
+                },
+                Token::String {
+                    prefix: "\"",
+                    value: ""
+                },
+                Token::String {
+                    prefix: "abort\"",
+                    value: "oops"
+                },
+                Token::Data {
+                    prefix: "x{",
+                    value: "ab_"
+                },
+                Token::Data {
+                    prefix: "B{",
+                    value: ""
+                },
+            ]
+        );
+        assert_eq!(
+            tokens("\"unterminated\nnext"),
+            [Token::Unterminated("\"unterminated"), Token::Word("next")]
+        );
+
+        // Comments end only with a separate `*/` word
+        assert_eq!(
+            tokens("a /* b */c\n d */ e // f */\ng"),
+            [
+                Token::Word("a"),
+                Token::Comment("/* b */c\n d */"),
+                Token::Word("e"),
+                Token::Comment("// f */"),
+                Token::Word("g"),
+            ]
+        );
+        assert_eq!(tokens("/* open"), [Token::Unterminated("/* open")]);
+
+        assert_eq!(
+            tokens("-10 0xff 1/3 0b101 1e3 - 0x"),
+            [
+                Token::Number("-10"),
+                Token::Number("0xff"),
+                Token::Number("1/3"),
+                Token::Number("0b101"),
+                Token::Word("1e3"),
+                Token::Word("-"),
+                Token::Word("0x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_spans() {
+        let spans = Tokenizer::new("a\n  /* x\n */ bc")
+            .map(|(_, span)| span)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                Span {
+                    start: 0,
+                    end: 1,
+                    line_number: 1,
+                    column: 1
+                },
+                Span {
+                    start: 4,
+                    end: 12,
+                    line_number: 2,
+                    column: 3
+                },
+                Span {
+                    start: 13,
+                    end: 15,
+                    line_number: 3,
+                    column: 5
+                },
+            ]
+        );
+    }
+}
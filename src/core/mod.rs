@@ -1,41 +1,89 @@
 use std::io::Write;
 use std::num::NonZeroU32;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 
+use ahash::{HashMap, HashSet};
 use anyhow::{Context as _, Result};
+use everscale_types::prelude::{Cell, Dict, HashBytes};
+
+use crate::error::{
+    ContinuationDepthExceeded, ExecutionAborted, InterpreterDepthExceeded, NonstandardWord,
+    OutputLimitExceeded,
+};
 
 pub use fift_proc::fift_module;
 
+#[cfg(feature = "async")]
+pub use self::async_env::{AsyncEnvironment, BlockOn, BlockingEnvironment, ThreadBlockOn};
 pub use self::cont::{Cont, ContImpl};
-pub use self::dictionary::{Dictionaries, Dictionary, DictionaryEntry};
-pub use self::env::{Environment, SourceBlock};
-pub use self::lexer::Lexer;
+pub use self::coverage::Coverage;
+pub use self::dictionary::{Dictionaries, Dictionary, DictionaryEntry, DictionaryIter};
+pub use self::env::{Environment, SourceBlock, WriteMode};
+pub use self::lexer::{Lexer, SourcePosition};
+pub use self::profiler::{Profiler, WordStats};
+pub use self::rng::RngSource;
 pub use self::stack::{
-    HashMapTreeKey, HashMapTreeNode, OwnedCellSlice, SharedBox, Stack, StackTuple, StackValue,
-    StackValueType, WordList,
+    CustomType, CustomValue, DisplayLimits, Exception, HashMapTreeKey, HashMapTreeNode,
+    OwnedCellSlice, Rational, SharedBox, Stack, StackTuple, StackValue, StackValueType,
+    TaggedValue, WordList,
 };
 
+#[cfg(feature = "async")]
+pub mod async_env;
 pub mod cont;
+pub mod coverage;
 pub mod dictionary;
 pub mod env;
 pub mod lexer;
+pub mod profiler;
+pub mod rng;
 pub mod stack;
+pub mod suspend;
 
 pub struct Context<'a> {
     pub state: State,
     pub stack: Stack,
-    pub exit_code: u8,
+    pub outcome: RunOutcome,
     pub next: Option<Cont>,
     pub dicts: Dictionaries,
 
     pub limits: ExecutionLimits,
     pub stats: ExecutionStats,
+    pub display_limits: DisplayLimits,
+    pub target_version: Option<u32>,
+    pub vm_libraries: VmLibraries,
+    pub asm_cache: AsmCache,
+    pub library_cache: Option<LibraryCache>,
+    pub interrupts: Interrupts,
+    pub rng: RngSource,
+    /// Whether to record source positions of compiled words for backtraces.
+    pub source_maps: bool,
+    /// How to treat interpreted words which are missing in the reference implementation.
+    pub strict: StrictMode,
 
     pub input: Lexer,
     pub exit_interpret: SharedBox,
+    pub run_state: RunState,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+    exception_handlers: Vec<ExceptionHandler>,
+    interrupt_handler: Option<Cont>,
+    accessed_files: Vec<String>,
+    nonstandard_usages: Vec<NonstandardUsage>,
+    nonstandard_files: HashSet<String>,
+    /// Custom messages of TVM exit codes (a hashmap from codes to strings).
+    pub(crate) exit_codes: SharedBox,
+    /// The `vmlibs` box and its version after the last sync with `vm_libraries`.
+    pub(crate) vm_libraries_box: Option<(SharedBox, u64)>,
+    included_files: HashMap<String, usize>,
+    virtual_files: HashMap<String, Rc<[u8]>>,
+    interpreter_depth: u16,
+    words_info: HashMap<&'static str, &'static WordInfo>,
 
     pub env: &'a mut dyn Environment,
-    pub stdout: &'a mut dyn Write,
+    pub stdout: OutputWriter<'a>,
 }
 
 impl<'a> Context<'a> {
@@ -43,25 +91,170 @@ impl<'a> Context<'a> {
         Self {
             state: Default::default(),
             stack: Stack::new(None),
-            exit_code: 0,
+            outcome: RunOutcome::Finished,
             next: None,
             dicts: Default::default(),
             limits: Default::default(),
             stats: Default::default(),
+            display_limits: Default::default(),
+            target_version: None,
+            vm_libraries: Default::default(),
+            asm_cache: Default::default(),
+            library_cache: None,
+            interrupts: Default::default(),
+            rng: Default::default(),
+            source_maps: false,
+            strict: StrictMode::Off,
             input: Default::default(),
             exit_interpret: Default::default(),
+            run_state: Default::default(),
+            profiler: None,
+            coverage: None,
+            exception_handlers: Vec::new(),
+            interrupt_handler: None,
+            accessed_files: Vec::new(),
+            nonstandard_usages: Vec::new(),
+            nonstandard_files: Default::default(),
+            exit_codes: Default::default(),
+            vm_libraries_box: None,
+            included_files: Default::default(),
+            virtual_files: Default::default(),
+            interpreter_depth: 0,
+            words_info: Default::default(),
             env,
-            stdout,
+            stdout: OutputWriter::new(stdout),
         }
     }
 
+    /// Uses the specified registry of VM libraries instead of an own one.
+    pub fn with_vm_libraries(mut self, libraries: VmLibraries) -> Self {
+        self.vm_libraries = libraries;
+        self
+    }
+
+    /// Uses the specified cache of `asm-cache{` blocks instead of an own one.
+    pub fn with_asm_cache(mut self, cache: AsmCache) -> Self {
+        self.asm_cache = cache;
+        self
+    }
+
+    /// Restores words of the included files from the cache instead of
+    /// interpreting them again when their contents are the same.
+    pub fn with_library_cache(mut self, cache: LibraryCache) -> Self {
+        self.library_cache = Some(cache);
+        self
+    }
+
+    /// Uses the specified interrupt signal instead of an own one.
+    pub fn with_interrupts(mut self, interrupts: Interrupts) -> Self {
+        self.interrupts = interrupts;
+        self
+    }
+
+    /// Sets a continuation which is executed on [`Interrupts::trigger`]
+    /// before the next step (or removes it).
+    pub fn set_interrupt_handler(&mut self, handler: Option<Cont>) {
+        self.interrupts.set_armed(handler.is_some());
+        self.interrupt_handler = handler;
+    }
+
+    /// Uses the specified source of time and randomness.
+    ///
+    /// NOTE: a deterministic source also reseeds hashmaps on the current
+    /// thread, so it should be set before adding modules.
+    pub fn with_rng(mut self, rng: RngSource) -> Self {
+        self.set_rng(rng);
+        self
+    }
+
+    pub fn set_rng(&mut self, rng: RngSource) {
+        self.rng = rng;
+        self.rng.reseed_hashmaps();
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        self.rng.now_ms(self.env)
+    }
+
     pub fn with_module<T: Module>(mut self, module: T) -> Result<Self> {
         self.add_module(module)?;
         Ok(self)
     }
 
     pub fn add_module<T: Module>(&mut self, module: T) -> Result<()> {
-        module.init(&mut self.dicts.current)
+        module.init(&mut self.dicts.current)?;
+        self.dicts.current.mark_nonstandard(module.words())?;
+        for info in module.words() {
+            self.words_info.insert(info.name, info);
+        }
+        Ok(())
+    }
+
+    pub fn with_lazy_module<T: Module + 'static>(mut self, module: T) -> Result<Self> {
+        self.add_lazy_module(module)?;
+        Ok(self)
+    }
+
+    /// Adds a module which is initialized on the first lookup of any of its words.
+    pub fn add_lazy_module<T: Module + 'static>(&mut self, module: T) -> Result<()> {
+        for info in module.words() {
+            self.words_info.insert(info.name, info);
+        }
+        self.dicts.current.define_lazy_module(module)
+    }
+
+    pub fn word_info(&self, name: &str) -> Option<&'static WordInfo> {
+        self.words_info.get(name).copied()
+    }
+
+    /// Returns nonstandard words which were interpreted in [`StrictMode::Warn`].
+    pub fn nonstandard_usages(&self) -> &[NonstandardUsage] {
+        &self.nonstandard_usages
+    }
+
+    /// Disables strict mode checks in the file (e.g. for bundled libraries).
+    pub fn allow_nonstandard_in(&mut self, name: &str) {
+        let name = self.canonical_name(name);
+        self.nonstandard_files.insert(name);
+    }
+
+    pub(crate) fn check_standard_word(&mut self, definition: &Cont) -> Result<()> {
+        if self.strict == StrictMode::Off {
+            return Ok(());
+        }
+        // NOTE: user words with the same names are allowed, aliases are not
+        let Some(info) = self.dicts.current.nonstandard_info(definition) else {
+            return Ok(());
+        };
+        let position = self
+            .input
+            .get_position()
+            .map(|pos| pos.to_source_position());
+        if let Some(position) = &position {
+            let file = self.canonical_name(&position.source_block_name);
+            if self.nonstandard_files.contains(&file) {
+                return Ok(());
+            }
+        }
+
+        match self.strict {
+            StrictMode::Off => Ok(()),
+            StrictMode::Warn => {
+                self.nonstandard_usages.push(NonstandardUsage {
+                    word: info,
+                    position: self
+                        .input
+                        .get_position()
+                        .map(|pos| pos.to_source_position()),
+                });
+                Ok(())
+            }
+            StrictMode::Deny => Err(NonstandardWord {
+                name: info.name.trim_end().to_owned(),
+                suggestion: info.suggestion,
+            }
+            .into()),
+        }
     }
 
     pub fn with_source_block(mut self, block: SourceBlock) -> Self {
@@ -73,27 +266,304 @@ impl<'a> Context<'a> {
         self.input.push_source_block(block);
     }
 
+    pub fn with_source_str(mut self, name: &str, source: &str) -> Self {
+        self.add_source_str(name, source);
+        self
+    }
+
+    pub fn add_source_str(&mut self, name: &str, source: &str) {
+        self.add_source_block(SourceBlock::from_string(name, source));
+    }
+
     pub fn with_limits(mut self, limits: ExecutionLimits) -> Self {
         self.set_limits(limits);
         self
     }
 
     pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.stdout.limit = limits.max_output_bytes;
         self.limits = limits;
     }
 
-    pub fn run(&mut self) -> Result<u8> {
-        self.stats = Default::default();
-        let mut current = Some(Rc::new(cont::InterpreterCont) as Cont);
-        while let Some(cont) = current.take() {
-            self.stats.inc_step(&self.limits)?;
-            current = cont.run(self)?;
-            if current.is_none() {
-                current = self.next.take();
+    /// Accounts a new nested interpreter (e.g. from `include`).
+    pub(crate) fn enter_nested_interpreter(&mut self) -> Result<()> {
+        if let Some(limit) = self.limits.max_interpreter_depth {
+            if self.interpreter_depth >= limit {
+                return Err(InterpreterDepthExceeded { limit }.into());
             }
         }
+        self.interpreter_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave_nested_interpreter(&mut self) {
+        self.interpreter_depth = self.interpreter_depth.saturating_sub(1);
+    }
+
+    /// Returns names of all files which were successfully included or read.
+    pub fn accessed_files(&self) -> &[String] {
+        &self.accessed_files
+    }
+
+    pub(crate) fn add_accessed_file(&mut self, name: &str) {
+        if !self.accessed_files.iter().any(|item| item == name) {
+            self.accessed_files.push(name.to_owned());
+        }
+    }
+
+    /// Adds an in-memory file which is used by `include` and file words
+    /// instead of the file with the same name from the environment.
+    pub fn with_virtual_file<N, C>(mut self, name: N, contents: C) -> Self
+    where
+        N: Into<String>,
+        C: Into<Vec<u8>>,
+    {
+        self.add_virtual_file(name, contents);
+        self
+    }
 
-        Ok(self.exit_code)
+    pub fn add_virtual_file<N, C>(&mut self, name: N, contents: C)
+    where
+        N: Into<String>,
+        C: Into<Vec<u8>>,
+    {
+        self.virtual_files
+            .insert(name.into(), Rc::from(contents.into()));
+    }
+
+    pub(crate) fn include_file(&mut self, name: &str) -> Result<SourceBlock> {
+        let block = if let Some(contents) = self.virtual_files.get(name) {
+            SourceBlock::new(name, std::io::Cursor::new(contents.clone()))
+        } else {
+            let block = self.env.include(name)?;
+            self.add_accessed_file(name);
+            block
+        };
+        self.mark_included(name);
+        Ok(block)
+    }
+
+    /// Marks the file as included without reading it (e.g. for preloaded libraries).
+    pub fn mark_included(&mut self, name: &str) {
+        let name = self.canonical_name(name);
+        *self.included_files.entry(name).or_default() += 1;
+    }
+
+    pub(crate) fn included_files(&self) -> &HashMap<String, usize> {
+        &self.included_files
+    }
+
+    /// Returns how many times the file was included.
+    pub fn include_count(&self, name: &str) -> usize {
+        let name = self.canonical_name(name);
+        self.included_files.get(&name).copied().unwrap_or_default()
+    }
+
+    fn canonical_name(&self, name: &str) -> String {
+        if self.virtual_files.contains_key(name) {
+            name.to_owned()
+        } else {
+            self.env.canonical_name(name)
+        }
+    }
+
+    pub(crate) fn read_file(&mut self, name: &str) -> Result<Vec<u8>> {
+        if let Some(contents) = self.virtual_files.get(name) {
+            return Ok(contents.to_vec());
+        }
+        let data = self.env.read_file(name)?;
+        self.add_accessed_file(name);
+        Ok(data)
+    }
+
+    pub(crate) fn read_file_part(&mut self, name: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if let Some(contents) = self.virtual_files.get(name) {
+            let start = std::cmp::min(offset, contents.len() as u64) as usize;
+            let end =
+                std::cmp::min((start as u64).saturating_add(len), contents.len() as u64) as usize;
+            return Ok(contents[start..end].to_vec());
+        }
+        let data = self.env.read_file_part(name, offset, len)?;
+        self.add_accessed_file(name);
+        Ok(data)
+    }
+
+    pub(crate) fn file_exists(&self, name: &str) -> bool {
+        self.virtual_files.contains_key(name) || self.env.file_exists(name)
+    }
+
+    /// Serializes the whole stack (from the bottom) into a JSON array.
+    ///
+    /// Integers are decimal strings, tuples are arrays, `null` is `null`,
+    /// other values are objects with a single field named after the type:
+    /// `string`, `rational` (`"n/d"`), `bytes` (hex), `cell`, `builder`
+    /// and `slice` (base64 BOC), `atom`, `exception` (reason) and
+    /// `hashmap` (an array of `[key, value]` pairs). Continuations, word lists,
+    /// boxes and custom values are written as `{"opaque": "<type>"}`.
+    pub fn stack_to_json(&self) -> Result<String> {
+        let mut out = String::from("[");
+        for (i, item) in self.stack.items().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            crate::util::write_stack_value_json(&mut out, item.as_ref())?;
+        }
+        out.push(']');
+        Ok(out)
+    }
+
+    /// Moves all stack items into the stack of another context
+    /// (see [`Stack::drain_into`]).
+    pub fn drain_stack_into(&mut self, other: &mut Context<'_>) -> Result<usize> {
+        self.stack.drain_into(&mut other.stack)
+    }
+
+    pub fn enable_profiling(&mut self) {
+        self.profiler.get_or_insert_with(Default::default);
+    }
+
+    /// Enables collection of executed words and lines (implies source maps).
+    pub fn enable_coverage(&mut self) {
+        self.source_maps = true;
+        self.coverage.get_or_insert_with(Default::default);
+    }
+
+    /// Returns the raw exit code of the last run.
+    #[deprecated(note = "use `outcome` or the result of `run` instead")]
+    pub fn exit_code(&self) -> u8 {
+        self.outcome.exit_code()
+    }
+
+    pub fn run(&mut self) -> Result<RunOutcome> {
+        loop {
+            match self.step()? {
+                StepOutcome::Continue => continue,
+                StepOutcome::Finished(outcome) => return Ok(outcome),
+                StepOutcome::AwaitingInput => return Ok(self.outcome),
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome> {
+        let mut cont = match self.run_state.current.take() {
+            Some(cont) => cont,
+            None => {
+                self.stats = Default::default();
+                self.outcome = RunOutcome::Finished;
+                self.stdout.limit = self.limits.max_output_bytes;
+                self.stdout.written = 0;
+                self.stdout.captures.clear();
+                self.exception_handlers.clear();
+                self.interpreter_depth = 0;
+                Rc::new(cont::InterpreterCont) as Cont
+            }
+        };
+
+        // NOTE: the interrupted continuation is resumed after the handler
+        if let Some(handler) = &self.interrupt_handler {
+            if self.interrupts.take_pending() {
+                self.next = cont::SeqCont::make(Some(cont), self.next.take());
+                cont = handler.clone();
+            }
+        }
+
+        self.stats.inc_step(&self.limits)?;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(&cont, &self.dicts.current, &mut self.next)?;
+        }
+        if let Some(coverage) = &mut self.coverage {
+            let input = match self.state.is_compile() {
+                false => self.input.get_position(),
+                true => None,
+            };
+            coverage.enter(&cont, &self.dicts.current, input)?;
+        }
+
+        let mut next = match cont.run(self) {
+            Ok(next) => next,
+            Err(e) if !is_catchable(&e) => return Err(e),
+            Err(e) => match self.exception_handlers.pop() {
+                Some(handler) => Some(self.handle_exception(handler, e)?),
+                None => return Err(e),
+            },
+        };
+        if next.is_none() {
+            next = self.next.take();
+        }
+
+        Ok(match next {
+            Some(next) => {
+                if let Some(limit) = self.limits.max_cont_depth {
+                    let depth = next.depth() + self.next.as_ref().map_or(0, |next| next.depth());
+                    if depth > limit {
+                        return Err(ContinuationDepthExceeded { limit }.into());
+                    }
+                }
+                self.run_state.current = Some(next);
+                StepOutcome::Continue
+            }
+            // NOTE: all source blocks were consumed in the middle of a block,
+            // so the next run continues it with the new input
+            None if self.input.depth() < 0 && self.state.is_compile() => StepOutcome::AwaitingInput,
+            None => {
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.finish();
+                }
+                StepOutcome::Finished(self.outcome)
+            }
+        })
+    }
+
+    /// Registers an exception handler which is active until
+    /// the returned id is passed to [`Context::remove_exception_handler`].
+    pub(crate) fn add_exception_handler(&mut self, catch: Cont, after: Option<Cont>) -> usize {
+        let id = match self.exception_handlers.last() {
+            Some(last) => last.id + 1,
+            None => 0,
+        };
+        self.exception_handlers.push(ExceptionHandler {
+            id,
+            catch,
+            after,
+            depth: self.stack.depth(),
+            state: self.state.clone(),
+            input_depth: self.input.depth(),
+            interpreter_depth: self.interpreter_depth,
+        });
+        id
+    }
+
+    pub(crate) fn remove_exception_handler(&mut self, id: usize) {
+        // NOTE: handlers above the removed one belong to the abandoned
+        // continuations (e.g. after `call/cc`), so they are removed too
+        if let Some(pos) = self.exception_handlers.iter().position(|h| h.id == id) {
+            self.exception_handlers.truncate(pos);
+        }
+    }
+
+    fn handle_exception(&mut self, handler: ExceptionHandler, e: anyhow::Error) -> Result<Cont> {
+        let reason = match e.downcast::<ExecutionAborted>() {
+            Ok(e) => e.reason,
+            Err(e) => format!("{e:#}"),
+        };
+        let exception = Exception {
+            reason,
+            stack: self.stack.items().to_vec(),
+        };
+
+        self.stack.truncate(handler.depth);
+        self.stack.push(exception)?;
+
+        // NOTE: the rest of the files which were included after
+        // the handler was registered is skipped
+        while self.input.depth() > handler.input_depth {
+            self.input.pop_source_block();
+        }
+        self.interpreter_depth = handler.interpreter_depth;
+        self.state = handler.state;
+
+        self.next = handler.after;
+        Ok(handler.catch)
     }
 
     pub(crate) fn execute_stack_top(&mut self) -> Result<Cont> {
@@ -123,18 +593,101 @@ impl<'a> Context<'a> {
         let mut word_list = self.stack.pop_word_list()?;
         {
             let word_list = Rc::make_mut(&mut word_list);
+            let len = word_list.items.len();
             word_list.items.extend(cont);
 
             if !cont::NopCont::is_nop(&**word_def) {
                 word_list.items.push(Rc::clone(&word_def));
             }
+
+            if self.source_maps && word_list.items.len() > len {
+                let position = self.input.get_position().map(|p| p.to_source_position());
+                word_list.positions.resize(len, None);
+                if let (Some(coverage), Some(position)) = (&mut self.coverage, &position) {
+                    coverage.add_line(position);
+                }
+                word_list.positions.resize(word_list.items.len(), position);
+            }
         }
 
         self.stack.push_raw(word_list)
     }
 }
 
-#[derive(Debug, Default)]
+struct ExceptionHandler {
+    id: usize,
+    catch: Cont,
+    after: Option<Cont>,
+    depth: usize,
+    state: State,
+    input_depth: i32,
+    interpreter_depth: u16,
+}
+
+/// Resource limits and strict mode violations can't be caught by scripts.
+fn is_catchable(e: &anyhow::Error) -> bool {
+    !e.chain().any(|e| {
+        e.is::<InterpreterDepthExceeded>()
+            || e.is::<NonstandardWord>()
+            || e.is::<OutputLimitExceeded>()
+            || e.downcast_ref::<std::io::Error>()
+                .and_then(|e| e.get_ref())
+                .is_some_and(|e| e.is::<OutputLimitExceeded>())
+    })
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StepOutcome {
+    /// There are more continuations to execute.
+    Continue,
+    /// All input was consumed or the script was stopped (e.g. by `bye`).
+    Finished(RunOutcome),
+    /// All input was consumed inside an unfinished `{ }` block.
+    AwaitingInput,
+}
+
+/// How the script execution has ended.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum RunOutcome {
+    /// All input was consumed or `quit` was called.
+    #[default]
+    Finished,
+    /// `halt` was called with the specified exit code.
+    Halted(u8),
+    /// `bye` was called.
+    Bye,
+}
+
+impl RunOutcome {
+    /// Returns the raw exit code as it was returned by [`Context::run`]
+    /// before (`0` when finished, `255` for `bye`).
+    ///
+    /// NOTE: the `fift` binary exits with the bitwise inverse of this code.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Finished => 0,
+            Self::Halted(code) => code,
+            Self::Bye => u8::MAX,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RunState {
+    current: Option<Cont>,
+}
+
+impl RunState {
+    pub fn is_running(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub enum State {
     #[default]
     Interpret,
@@ -194,18 +747,352 @@ impl State {
 
 pub trait Module {
     fn init(&self, d: &mut Dictionary) -> Result<()>;
+
+    fn words(&self) -> &'static [WordInfo] {
+        &[]
+    }
 }
 
 impl<T: Module> Module for &T {
     fn init(&self, d: &mut Dictionary) -> Result<()> {
         T::init(self, d)
     }
+
+    fn words(&self) -> &'static [WordInfo] {
+        T::words(self)
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone, Copy)]
+pub struct WordInfo {
+    pub name: &'static str,
+    pub module: &'static str,
+    /// Name of the function which implements the word.
+    pub symbol: &'static str,
+    pub doc: &'static str,
+    pub stack_effect: Option<&'static str>,
+    /// Whether the word is missing in the reference implementation.
+    pub nonstandard: bool,
+    pub suggestion: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum StrictMode {
+    #[default]
+    Off,
+    /// Record usages of nonstandard words.
+    Warn,
+    /// Fail on the first usage of a nonstandard word.
+    Deny,
+}
+
+#[derive(Debug, Clone)]
+pub struct NonstandardUsage {
+    pub word: &'static WordInfo,
+    pub position: Option<SourcePosition>,
+}
+
+#[derive(Debug, Clone)]
 pub struct ExecutionLimits {
     pub max_steps: Option<usize>,
     pub max_include_depth: Option<u16>,
+    pub max_output_bytes: Option<u64>,
+    /// Max number of nested interpreters (`include`s), guards
+    /// against stack overflows on recursive includes.
+    pub max_interpreter_depth: Option<u16>,
+    /// Max number of nested continuations (e.g. non-tail recursive calls).
+    pub max_cont_depth: Option<usize>,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_include_depth: None,
+            max_output_bytes: None,
+            max_interpreter_depth: Some(Self::DEFAULT_MAX_INTERPRETER_DEPTH),
+            max_cont_depth: Some(Self::DEFAULT_MAX_CONT_DEPTH),
+        }
+    }
+}
+
+impl ExecutionLimits {
+    pub const DEFAULT_MAX_INTERPRETER_DEPTH: u16 = 1024;
+    pub const DEFAULT_MAX_CONT_DEPTH: usize = 1 << 20;
+}
+
+/// Output stream of the context which counts written bytes.
+pub struct OutputWriter<'a> {
+    inner: &'a mut dyn Write,
+    written: u64,
+    limit: Option<u64>,
+    captures: Vec<Vec<u8>>,
+}
+
+impl<'a> OutputWriter<'a> {
+    pub fn new(inner: &'a mut dyn Write) -> Self {
+        Self {
+            inner,
+            written: 0,
+            limit: None,
+            captures: Vec::new(),
+        }
+    }
+
+    /// Returns the number of bytes written during the current run.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Collects the output into a buffer until [`OutputWriter::end_capture`].
+    pub fn begin_capture(&mut self) {
+        self.captures.push(Vec::new());
+    }
+
+    /// Returns the output collected since the last [`OutputWriter::begin_capture`].
+    pub fn end_capture(&mut self) -> Option<Vec<u8>> {
+        self.captures.pop()
+    }
+}
+
+impl Write for OutputWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written.saturating_add(buf.len() as u64) > limit {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    OutputLimitExceeded { limit },
+                ));
+            }
+        }
+        let n = match self.captures.last_mut() {
+            Some(capture) => {
+                capture.extend_from_slice(buf);
+                buf.len()
+            }
+            None => self.inner.write(buf)?,
+        };
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A registry of VM libraries (`HashmapE 256 ^Cell`).
+///
+/// Each context has its own registry by default. Clones share the same
+/// storage, so a registry can be explicitly shared between contexts
+/// which run on different threads.
+#[derive(Default, Clone)]
+pub struct VmLibraries(Arc<Mutex<Dict<HashBytes, Cell>>>);
+
+impl VmLibraries {
+    pub fn root(&self) -> Option<Cell> {
+        self.lock().root().clone()
+    }
+
+    pub fn set_root(&self, root: Option<Cell>) {
+        *self.lock() = Dict::from_raw(root);
+    }
+
+    pub fn get(&self, hash: &HashBytes) -> Result<Option<Cell>> {
+        Ok(self.lock().get(hash)?)
+    }
+
+    /// Adds the library cell, returns `false` if it was already registered.
+    pub fn add(&self, library: Cell) -> Result<bool> {
+        Ok(self.lock().add(library.repr_hash(), &library)?)
+    }
+
+    /// Adds all root cells of the BOC, returns the number of new libraries.
+    pub fn add_boc(&self, bytes: &[u8]) -> Result<usize> {
+        let mut added = 0;
+        for library in crate::util::decode_boc_roots(bytes)? {
+            added += self.add(library)? as usize;
+        }
+        Ok(added)
+    }
+
+    pub fn remove(&self, hash: &HashBytes) -> Result<Option<Cell>> {
+        Ok(self.lock().remove(hash)?)
+    }
+
+    pub fn list(&self) -> Result<Vec<Cell>> {
+        let libraries = self.lock();
+        let mut result = Vec::new();
+        for library in libraries.values() {
+            result.push(library?);
+        }
+        Ok(result)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Dict<HashBytes, Cell>> {
+        // NOTE: the dictionary is always left in a consistent state
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A cache of cells produced by `asm-cache{ ... }asm-cache` blocks,
+/// keyed by the hash of the block source and the definitions of its words.
+///
+/// Clones share the same storage, so the cache can outlive a single run
+/// (e.g. when the same scripts are executed again after file changes).
+#[derive(Default, Clone)]
+pub struct AsmCache(Arc<Mutex<AsmCacheState>>);
+
+impl AsmCache {
+    /// Returns the cached cell and updates hit/miss statistics.
+    pub fn get(&self, key: &HashBytes) -> Option<Cell> {
+        let mut state = self.lock();
+        let cell = state.cells.get(key).cloned();
+        match cell {
+            Some(_) => state.stats.hits += 1,
+            None => state.stats.misses += 1,
+        }
+        cell
+    }
+
+    pub fn insert(&self, key: HashBytes, cell: Cell) {
+        let mut state = self.lock();
+        state.cells.insert(key, cell);
+        state.stats.entries = state.cells.len();
+    }
+
+    pub fn stats(&self) -> AsmCacheStats {
+        self.lock().stats
+    }
+
+    /// Removes all cached cells and resets statistics.
+    pub fn clear(&self) {
+        *self.lock() = Default::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AsmCacheState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Default)]
+struct AsmCacheState {
+    cells: HashMap<HashBytes, Cell>,
+    stats: AsmCacheStats,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct AsmCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// A cache of word definitions made by included files,
+/// keyed by the hash of the file contents, the definitions of the words
+/// it references and the number of times the file was included.
+///
+/// Clones share the same storage, so a fresh context (e.g. per request)
+/// can reuse libraries which were already interpreted by other contexts.
+/// Only the defined words and the dictionaries which the library switched to
+/// are restored (and nested includes are counted again),
+/// libraries with other side effects (output or stack changes) are never cached.
+#[derive(Default, Clone)]
+pub struct LibraryCache(Arc<Mutex<LibraryCacheState>>);
+
+impl LibraryCache {
+    /// Returns the serialized definitions and updates hit/miss statistics.
+    pub fn get(&self, key: &HashBytes) -> Option<Arc<[u8]>> {
+        let mut state = self.lock();
+        let data = state.libraries.get(key).cloned();
+        match data {
+            Some(_) => state.stats.hits += 1,
+            None => state.stats.misses += 1,
+        }
+        data
+    }
+
+    pub fn insert(&self, key: HashBytes, data: Vec<u8>) {
+        let mut state = self.lock();
+        state.libraries.insert(key, Arc::from(data));
+        state.stats.entries = state.libraries.len();
+    }
+
+    /// Remembers that the library can't be cached to not serialize it again.
+    pub fn insert_uncacheable(&self, key: HashBytes) {
+        self.lock().uncacheable.insert(key);
+    }
+
+    pub fn is_uncacheable(&self, key: &HashBytes) -> bool {
+        self.lock().uncacheable.contains(key)
+    }
+
+    pub fn stats(&self) -> LibraryCacheStats {
+        self.lock().stats
+    }
+
+    /// Removes all cached libraries and resets statistics.
+    pub fn clear(&self) {
+        *self.lock() = Default::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LibraryCacheState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Default)]
+struct LibraryCacheState {
+    libraries: HashMap<HashBytes, Arc<[u8]>>,
+    uncacheable: HashSet<HashBytes>,
+    stats: LibraryCacheStats,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct LibraryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// A flag which asks the interpreter to run the `on-interrupt` handler.
+///
+/// Clones share the same state and can be triggered from other threads
+/// (e.g. from signal handlers).
+#[derive(Default, Clone)]
+pub struct Interrupts(Arc<InterruptsState>);
+
+impl Interrupts {
+    /// Requests the handler to run before the next step.
+    ///
+    /// Returns `false` if no handler is registered or the previous request
+    /// was not handled yet (e.g. the script is blocked on input), so the caller
+    /// can fall back to the default behavior.
+    pub fn trigger(&self) -> bool {
+        self.0.armed.load(Ordering::Acquire) && !self.0.pending.swap(true, Ordering::AcqRel)
+    }
+
+    /// Returns whether a handler is registered.
+    pub fn is_armed(&self) -> bool {
+        self.0.armed.load(Ordering::Acquire)
+    }
+
+    fn set_armed(&self, armed: bool) {
+        self.0.armed.store(armed, Ordering::Release);
+        if !armed {
+            self.0.pending.store(false, Ordering::Release);
+        }
+    }
+
+    fn take_pending(&self) -> bool {
+        self.0.pending.load(Ordering::Relaxed) && self.0.pending.swap(false, Ordering::AcqRel)
+    }
+}
+
+#[derive(Default)]
+struct InterruptsState {
+    armed: AtomicBool,
+    pending: AtomicBool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -225,3 +1112,167 @@ impl ExecutionStats {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::env::EmptyEnvironment;
+
+    fn run_steps(ctx: &mut Context<'_>) -> Result<(usize, StepOutcome)> {
+        let mut steps = 0;
+        loop {
+            steps += 1;
+            match ctx.step()? {
+                StepOutcome::Continue => continue,
+                outcome => return Ok((steps, outcome)),
+            }
+        }
+    }
+
+    #[test]
+    fn step_until_finished() {
+        let mut env = EmptyEnvironment;
+        let mut stdout = Vec::new();
+        let mut ctx = Context::new(&mut env, &mut stdout)
+            .with_basic_modules()
+            .unwrap();
+
+        ctx.add_source_str("<test>", "1 2 + { 3 * } execute");
+        let (steps, outcome) = run_steps(&mut ctx).unwrap();
+        assert!(steps > 1);
+        assert_eq!(outcome, StepOutcome::Finished(RunOutcome::Finished));
+        assert_eq!(ctx.stack.pop_smallint_range(0, 255).unwrap(), 9);
+
+        // The unfinished block is continued with the next input
+        ctx.add_source_str("<test>", "{ 4");
+        let (_, outcome) = run_steps(&mut ctx).unwrap();
+        assert_eq!(outcome, StepOutcome::AwaitingInput);
+        ctx.add_source_str("<test>", "5 + } execute");
+        let (_, outcome) = run_steps(&mut ctx).unwrap();
+        assert_eq!(outcome, StepOutcome::Finished(RunOutcome::Finished));
+        assert_eq!(ctx.stack.pop_smallint_range(0, 255).unwrap(), 9);
+
+        ctx.add_source_str("<test>", "3 halt 1 2");
+        let (_, outcome) = run_steps(&mut ctx).unwrap();
+        assert_eq!(outcome, StepOutcome::Finished(RunOutcome::Halted(3)));
+        assert_eq!(ctx.stack.depth(), 0);
+    }
+
+    #[test]
+    fn outcome_is_reset_between_runs() {
+        let mut env = EmptyEnvironment;
+        let mut stdout = Vec::new();
+        let mut ctx = Context::new(&mut env, &mut stdout)
+            .with_basic_modules()
+            .unwrap();
+
+        ctx.add_source_str("<test>", "1 halt");
+        assert_eq!(ctx.run().unwrap(), RunOutcome::Halted(1));
+        ctx.add_source_str("<test>", "8 drop");
+        assert_eq!(ctx.run().unwrap(), RunOutcome::Finished);
+        ctx.add_source_str("<test>", "bye");
+        assert_eq!(ctx.run().unwrap(), RunOutcome::Bye);
+        ctx.add_source_str("<test>", "");
+        assert_eq!(ctx.run().unwrap(), RunOutcome::Finished);
+    }
+
+    #[test]
+    fn step_across_interrupt() {
+        let mut env = EmptyEnvironment;
+        let mut stdout = Vec::new();
+        let mut ctx = Context::new(&mut env, &mut stdout)
+            .with_basic_modules()
+            .unwrap();
+        let interrupts = ctx.interrupts.clone();
+
+        ctx.add_source_str(
+            "<test>",
+            "variable n 0 n ! { n @ 1+ n ! } on-interrupt 0 { 1+ } 10 times n @",
+        );
+        let mut triggered = false;
+        loop {
+            match ctx.step().unwrap() {
+                StepOutcome::Continue => {
+                    // NOTE: trigger once the handler is registered
+                    if !triggered && interrupts.is_armed() {
+                        triggered = interrupts.trigger();
+                    }
+                }
+                outcome => {
+                    assert_eq!(outcome, StepOutcome::Finished(RunOutcome::Finished));
+                    break;
+                }
+            }
+        }
+        assert!(triggered);
+
+        // The interrupted script is resumed after the handler
+        assert_eq!(ctx.stack.pop_smallint_range(0, 255).unwrap(), 1);
+        assert_eq!(ctx.stack.pop_smallint_range(0, 255).unwrap(), 10);
+        assert_eq!(ctx.stack.depth(), 0);
+    }
+
+    #[test]
+    fn read_virtual_file_part() {
+        let files = [("v.bin", "abcdef")];
+        let run = |source| crate::testing::ints(&crate::testing::run(&files, source).unwrap());
+
+        assert_eq!(run(r#""v.bin" 1 2 filepart>B Blen"#), [2]);
+        assert_eq!(
+            run(r#""v.bin" 1 18446744073709551615 filepart>B Blen"#),
+            [5]
+        );
+        assert_eq!(
+            run(r#""v.bin" 18446744073709551615 1 filepart>B Blen"#),
+            [0]
+        );
+    }
+
+    #[test]
+    fn strict_mode_checks_definitions() {
+        let files = [("lib.fif", "\"ab\" x>s drop")];
+        let run = |strict, source| {
+            crate::testing::run_with_output(&files, source, |ctx| {
+                ctx.strict = strict;
+                ctx.allow_nonstandard_in("lib.fif");
+            })
+            .0
+        };
+
+        let err = run(StrictMode::Deny, "\"ab\" x>s").err().unwrap();
+        let err = err.downcast::<NonstandardWord>().unwrap();
+        assert_eq!(err.name, "x>s");
+        assert_eq!(err.suggestion, Some("`x{`"));
+        assert!(run(StrictMode::Off, "\"ab\" x>s").is_ok());
+
+        // Words of lazy modules are checked after the module is initialized
+        let source = "\"[1]\" json>tuple drop \"[1]\" json>tuple";
+        assert!(run(StrictMode::Deny, source).is_err());
+
+        // Aliases are nonstandard too, words with the same names are not
+        assert!(run(StrictMode::Deny, "' x>s : hex>s \"ab\" hex>s").is_err());
+        let items = run(StrictMode::Deny, "{ 1 } : x>s x>s").unwrap();
+        assert_eq!(crate::testing::ints(&items), [1]);
+
+        // Allowed files can use nonstandard words
+        assert!(run(StrictMode::Deny, "\"lib.fif\" include").is_ok());
+
+        let (_, usages) = {
+            let mut env = crate::core::env::EmptyEnvironment;
+            let mut stdout = Vec::new();
+            let mut ctx = Context::new(&mut env, &mut stdout)
+                .with_basic_modules()
+                .unwrap();
+            ctx.strict = StrictMode::Warn;
+            ctx.add_source_str("<test>", "\"ab\" x>s drop \"[]\" json>tuple");
+            let result = ctx.run();
+            let usages = ctx
+                .nonstandard_usages()
+                .iter()
+                .map(|usage| usage.word.name.trim_end())
+                .collect::<Vec<_>>();
+            (result.unwrap(), usages)
+        };
+        assert_eq!(usages, ["x>s", "json>tuple"]);
+    }
+}
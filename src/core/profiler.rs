@@ -0,0 +1,263 @@
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use ahash::HashMap;
+use anyhow::Result;
+
+use super::cont::{Cont, ContImpl, SeqCont};
+use super::dictionary::{Dictionary, DictionaryEntry};
+use super::stack::SharedBox;
+use super::Context;
+
+#[derive(Default)]
+pub struct Profiler {
+    words: HashMap<*const (), Rc<str>>,
+    words_version: Option<(*const SharedBox, u64)>,
+    frames: Vec<Frame>,
+    stats: HashMap<Rc<str>, WordStats>,
+}
+
+impl Profiler {
+    pub fn stats(&self) -> Vec<(Rc<str>, WordStats)> {
+        let mut stats = self
+            .stats
+            .iter()
+            .map(|(name, stats)| (name.clone(), *stats))
+            .collect::<Vec<_>>();
+        stats.sort_by(|(a_name, a), (b_name, b)| {
+            b.total_time
+                .cmp(&a.total_time)
+                .then_with(|| a_name.cmp(b_name))
+        });
+        stats
+    }
+
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.stats.clear();
+    }
+
+    pub fn display_report(&self) -> impl std::fmt::Display + '_ {
+        struct DisplayReport<'a>(&'a Profiler);
+
+        impl std::fmt::Display for DisplayReport<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let stats = self.0.stats();
+                let width = stats
+                    .iter()
+                    .map(|(name, _)| name.len())
+                    .max()
+                    .unwrap_or_default()
+                    .max(4);
+
+                write!(
+                    f,
+                    "{:width$} {:>10} {:>12} {:>12}",
+                    "word", "calls", "self ms", "total ms"
+                )?;
+                for (name, stats) in stats {
+                    write!(
+                        f,
+                        "\n{name:width$} {:>10} {:>12.3} {:>12.3}",
+                        stats.calls,
+                        stats.self_time.as_secs_f64() * 1000.0,
+                        stats.total_time.as_secs_f64() * 1000.0,
+                    )?;
+                }
+                Ok(())
+            }
+        }
+
+        DisplayReport(self)
+    }
+
+    pub(crate) fn enter(
+        &mut self,
+        cont: &Cont,
+        d: &Dictionary,
+        next: &mut Option<Cont>,
+    ) -> Result<()> {
+        self.refresh_words(d)?;
+
+        let ptr = Rc::as_ptr(cont) as *const ();
+        let Some(name) = self.words.get(&ptr) else {
+            return Ok(());
+        };
+
+        self.stats.entry(name.clone()).or_default().calls += 1;
+        let name = name.clone();
+
+        // NOTE: the word is in a tail position if it returns directly to the exit
+        // marker of the caller, so the marker is reused instead of growing the
+        // continuation chain, and the previous tail call (if any) is finished
+        let caller = self.frames.iter().rposition(|frame| frame.exit.is_some());
+        if let (Some(caller), Some(next)) = (caller, next.as_ref()) {
+            let exit = self.frames[caller].exit.as_ref();
+            if exit.is_some_and(|exit| {
+                Weak::as_ptr(exit) as *const () == Rc::as_ptr(next) as *const ()
+            }) {
+                self.leave(caller + 1);
+                self.push_frame(name, None);
+                return Ok(());
+            }
+        }
+
+        // NOTE: `next` is the return continuation of the word,
+        // so the marker will be executed right after the word completes
+        let marker: Cont = Rc::new(FrameExitCont {
+            depth: self.frames.len(),
+        });
+        let exit = match next.take() {
+            Some(next) => Rc::new(SeqCont::new(Some(marker), Some(next))),
+            None => marker,
+        };
+        self.push_frame(name, Some(Rc::downgrade(&exit)));
+        *next = Some(exit);
+        Ok(())
+    }
+
+    pub fn finish(&mut self) {
+        self.leave(0);
+    }
+
+    fn push_frame(&mut self, name: Rc<str>, exit: Option<Weak<dyn ContImpl>>) {
+        self.frames.push(Frame {
+            name,
+            started_at: Instant::now(),
+            children_time: Duration::ZERO,
+            exit,
+        });
+    }
+
+    fn leave(&mut self, depth: usize) {
+        // NOTE: frames above `depth` could be left open if their words
+        // have dropped the return continuation (e.g. by exiting the interpreter)
+        while self.frames.len() > depth {
+            let Some(frame) = self.frames.pop() else {
+                break;
+            };
+
+            let elapsed = frame.started_at.elapsed();
+            if let Some(parent) = self.frames.last_mut() {
+                parent.children_time += elapsed;
+            }
+
+            let is_recursive = self.frames.iter().any(|f| f.name == frame.name);
+            let stats = self.stats.entry(frame.name).or_default();
+            stats.self_time += elapsed.saturating_sub(frame.children_time);
+            if !is_recursive {
+                stats.total_time += elapsed;
+            }
+        }
+    }
+
+    fn refresh_words(&mut self, d: &Dictionary) -> Result<()> {
+        let words_box = d.get_words_box();
+        let version = Some((Rc::as_ptr(words_box), words_box.version()));
+        if version == self.words_version {
+            return Ok(());
+        }
+
+        self.words.clear();
+        if let Some(map) = &d.clone_words_map()? {
+            for entry in map.as_ref() {
+                let Some((cont, _)) = DictionaryEntry::cont_from_value(entry.value.as_ref()) else {
+                    continue;
+                };
+                let name = entry.key.stack_value.as_string()?.trim_end();
+                self.words
+                    .insert(Rc::as_ptr(cont) as *const (), Rc::from(name));
+            }
+        }
+        self.words_version = version;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WordStats {
+    pub calls: u64,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+struct Frame {
+    name: Rc<str>,
+    started_at: Instant,
+    children_time: Duration,
+    /// Exit marker of the frame, tail calls share the marker of their caller.
+    exit: Option<Weak<dyn ContImpl>>,
+}
+
+struct FrameExitCont {
+    depth: usize,
+}
+
+impl ContImpl for FrameExitCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        if let Some(profiler) = &mut ctx.profiler {
+            profiler.leave(self.depth);
+        }
+        Ok(None)
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<profiler frame exit>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{ints, run_with_output};
+
+    const SOURCE: &str = "hole constant self \
+        { dup 0> { 1- self @ execute } { drop chain-len } cond } : countdown \
+        ' countdown self ! \
+        { 1+ countdown } : start \
+        9999 start (profile)";
+
+    fn run_countdown(profile: bool) -> (usize, Vec<(String, i32)>) {
+        let (items, _) = run_with_output(&[], SOURCE, |ctx| {
+            let d = &mut ctx.dicts.current;
+            d.define_closure_word("chain-len ", |ctx| {
+                let mut len = 0;
+                let mut next = ctx.next.as_ref();
+                while let Some(cont) = next {
+                    len += 1;
+                    next = cont.up();
+                }
+                ctx.stack.push_int(len)
+            })
+            .unwrap();
+            if profile {
+                ctx.enable_profiling();
+            }
+        });
+        let items = items.unwrap();
+
+        let mut calls = Vec::new();
+        for entry in items[1].as_tuple().unwrap() {
+            let entry = entry.as_tuple().unwrap();
+            let name = entry[0].as_string().unwrap();
+            if name == "countdown" || name == "start" {
+                calls.push((name.to_owned(), ints(&entry[1..2])[0]));
+            }
+        }
+        calls.sort();
+        (ints(&items[..1])[0] as usize, calls)
+    }
+
+    #[test]
+    fn tail_calls_are_not_wrapped() {
+        let (plain_len, calls) = run_countdown(false);
+        assert!(calls.is_empty());
+
+        // NOTE: each call would add a frame exit marker without tail calls
+        let (len, calls) = run_countdown(true);
+        assert!(len <= plain_len + 1, "{len} > {plain_len} + 1");
+        assert_eq!(
+            calls,
+            [("countdown".to_owned(), 10001), ("start".to_owned(), 1)]
+        );
+    }
+}
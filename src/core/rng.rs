@@ -0,0 +1,72 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use super::env::Environment;
+use super::stack::HashMapTreeNode;
+
+/// A source of the current time and random values for the context.
+#[derive(Default)]
+pub enum RngSource {
+    /// System time (from the environment) and thread-local RNG.
+    #[default]
+    System,
+    /// Fixed time and seeded RNG, used for reproducible runs.
+    Deterministic { rng: Box<StdRng>, now_ms: u64 },
+}
+
+impl RngSource {
+    pub fn deterministic(seed: u64, now_ms: u64) -> Self {
+        Self::Deterministic {
+            rng: Box::new(StdRng::seed_from_u64(seed)),
+            now_ms,
+        }
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        matches!(self, Self::Deterministic { .. })
+    }
+
+    pub fn now_ms(&self, env: &dyn Environment) -> u64 {
+        match self {
+            Self::System => env.now_ms(),
+            Self::Deterministic { now_ms, .. } => *now_ms,
+        }
+    }
+
+    /// Reseeds priorities of hashmap nodes created on the current thread.
+    pub(crate) fn reseed_hashmaps(&mut self) {
+        if self.is_deterministic() {
+            HashMapTreeNode::reseed(self.next_u64());
+        }
+    }
+}
+
+impl RngCore for RngSource {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Self::System => rand::thread_rng().next_u32(),
+            Self::Deterministic { rng, .. } => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Self::System => rand::thread_rng().next_u64(),
+            Self::Deterministic { rng, .. } => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Self::System => rand::thread_rng().fill_bytes(dest),
+            Self::Deterministic { rng, .. } => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            Self::System => rand::thread_rng().try_fill_bytes(dest),
+            Self::Deterministic { rng, .. } => rng.try_fill_bytes(dest),
+        }
+    }
+}
@@ -1,15 +1,20 @@
+use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 use ahash::HashMap;
 use anyhow::Result;
 use dyn_clone::DynClone;
+use everscale_types::models::{IntAddr, StdAddr, VarAddr};
+use everscale_types::num::Uint9;
 use everscale_types::prelude::*;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
 use num_traits::{One, ToPrimitive, Zero};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use super::cont::*;
+use super::lexer::SourcePosition;
 use crate::util::DisplaySliceExt;
 
 pub struct Stack {
@@ -121,6 +126,10 @@ impl Stack {
         }
     }
 
+    pub fn push_custom<T: CustomType>(&mut self, value: T) -> Result<()> {
+        self.push(CustomValue::new(value))
+    }
+
     pub fn push_int<T: Into<BigInt>>(&mut self, value: T) -> Result<()> {
         self.push(value.into())
     }
@@ -198,6 +207,15 @@ impl Stack {
         self.pop()?.into_int()
     }
 
+    /// Pops a rational number, integers are converted into it.
+    pub fn pop_rational(&mut self) -> Result<Rc<Rational>> {
+        let value = self.pop()?;
+        if value.ty() == StackValueType::Int {
+            return Ok(Rc::new(Rational::from(value.as_int()?.clone())));
+        }
+        value.into_rational()
+    }
+
     pub fn pop_string(&mut self) -> Result<Rc<String>> {
         self.pop()?.into_string()
     }
@@ -273,6 +291,14 @@ impl Stack {
         self.pop()?.into_atom()
     }
 
+    pub fn pop_exception(&mut self) -> Result<Rc<Exception>> {
+        self.pop()?.into_exception()
+    }
+
+    pub fn pop_custom<T: CustomType>(&mut self) -> Result<Rc<T>> {
+        self.pop()?.as_custom()?.downcast()
+    }
+
     pub fn pop_hashmap(&mut self) -> Result<Option<Rc<HashMapTreeNode>>> {
         let value = self.pop()?;
         if value.is_null() {
@@ -282,50 +308,237 @@ impl Stack {
         }
     }
 
+    pub fn pop_address(&mut self) -> Result<IntAddr> {
+        let address = self.pop()?;
+        let workchain = self.pop_smallint_signed_range(i32::MIN, i32::MAX)?;
+
+        if let Ok(int) = address.as_int() {
+            anyhow::ensure!(
+                int.sign() != Sign::Minus && int.bits() <= 256,
+                "Standard address must be a non-negative 256-bit integer"
+            );
+            let Ok(workchain) = i8::try_from(workchain) else {
+                anyhow::bail!("Standard address workchain must fit into 8 bits");
+            };
+
+            let mut bytes = int.to_bytes_le().1;
+            bytes.resize(32, 0);
+            bytes.reverse();
+
+            Ok(IntAddr::Std(StdAddr::new(
+                workchain,
+                HashBytes(bytes.try_into().unwrap()),
+            )))
+        } else {
+            let cs = address.as_slice()?;
+            let bits = cs.remaining_bits();
+            let address_len = Uint9::new(bits);
+            anyhow::ensure!(
+                address_len.is_valid(),
+                "Variable-length address must fit into 511 bits"
+            );
+
+            let mut address = vec![0; (bits as usize + 7) / 8];
+            cs.get_raw(0, &mut address, bits)?;
+
+            Ok(IntAddr::Var(VarAddr {
+                anycast: None,
+                address_len,
+                workchain,
+                address,
+            }))
+        }
+    }
+
+    pub fn push_address(&mut self, address: &IntAddr) -> Result<()> {
+        match address {
+            IntAddr::Std(addr) => {
+                self.push_int(addr.workchain)?;
+                self.push(BigInt::from_bytes_be(Sign::Plus, addr.address.as_slice()))
+            }
+            IntAddr::Var(addr) => {
+                let mut builder = CellBuilder::new();
+                builder.store_raw(&addr.address, addr.address_len.into_inner())?;
+                self.push_int(addr.workchain)?;
+                self.push(OwnedCellSlice::new(builder.build()?))
+            }
+        }
+    }
+
     pub fn items(&self) -> &[Rc<dyn StackValue>] {
         &self.items
     }
 
+    pub fn truncate(&mut self, depth: usize) {
+        self.items.truncate(depth);
+    }
+
     pub fn clear(&mut self) {
         self.items.clear();
     }
 
+    /// Moves all items into the stack of another context, returns their number.
+    ///
+    /// Atoms are recreated in the target stack, boxes and hashmaps are copied.
+    /// Continuations cannot be transferred, both stacks are left unchanged
+    /// in that case.
+    pub fn drain_into(&mut self, other: &mut Stack) -> Result<usize> {
+        let items = {
+            let mut transfer = StackTransfer {
+                atoms: &mut other.atoms,
+                anon_atoms: Default::default(),
+                boxes: Default::default(),
+            };
+            self.items
+                .iter()
+                .map(|item| transfer.value(item))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let count = items.len();
+        for item in items {
+            other.push_raw(item)?;
+        }
+        self.items.clear();
+        Ok(count)
+    }
+
     pub fn display_dump(&self) -> impl std::fmt::Display + '_ {
-        struct DisplayDump<'a>(&'a Stack);
+        self.display_dump_limited(DisplayLimits::default())
+    }
+
+    pub fn display_dump_limited(&self, limits: DisplayLimits) -> impl std::fmt::Display + '_ {
+        struct DisplayDump<'a>(&'a Stack, DisplayLimits);
 
         impl std::fmt::Display for DisplayDump<'_> {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 let mut first = true;
-                for item in &self.0.items {
+                for item in self.0.visible_items(&self.1, f)? {
                     if !std::mem::take(&mut first) {
                         f.write_str(" ")?;
                     }
-                    item.as_ref().fmt_dump(f)?;
+                    item.as_ref().fmt_dump_limited(f, &self.1, 0)?;
                 }
                 Ok(())
             }
         }
 
-        DisplayDump(self)
+        DisplayDump(self, limits)
     }
 
     pub fn display_list(&self) -> impl std::fmt::Display + '_ {
-        struct DisplayList<'a>(&'a Stack);
+        self.display_list_limited(DisplayLimits::default())
+    }
+
+    pub fn display_list_limited(&self, limits: DisplayLimits) -> impl std::fmt::Display + '_ {
+        struct DisplayList<'a>(&'a Stack, DisplayLimits);
 
         impl std::fmt::Display for DisplayList<'_> {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 let mut first = true;
-                for item in &self.0.items {
+                for item in self.0.visible_items(&self.1, f)? {
                     if !std::mem::take(&mut first) {
                         f.write_str(" ")?;
                     }
-                    item.as_ref().fmt_list(f)?;
+                    item.as_ref().fmt_list(f, &self.1, 0)?;
                 }
                 Ok(())
             }
         }
 
-        DisplayList(self)
+        DisplayList(self, limits)
+    }
+
+    /// Writes the truncation marker and returns the topmost items within the limit.
+    fn visible_items(
+        &self,
+        limits: &DisplayLimits,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> Result<&[Rc<dyn StackValue>], std::fmt::Error> {
+        match limits.max_items {
+            Some(max) if self.items.len() > max => {
+                write!(f, "...{} more", self.items.len() - max)?;
+                if max > 0 {
+                    f.write_str(" ")?;
+                }
+                Ok(&self.items[self.items.len() - max..])
+            }
+            _ => Ok(&self.items),
+        }
+    }
+}
+
+struct StackTransfer<'a> {
+    atoms: &'a mut Atoms,
+    anon_atoms: HashMap<i32, Atom>,
+    // NOTE: copies are reused to keep shared (or cyclic) boxes shared
+    boxes: HashMap<*const (), SharedBox>,
+}
+
+impl StackTransfer<'_> {
+    fn value(&mut self, value: &Rc<dyn StackValue>) -> Result<Rc<dyn StackValue>> {
+        if let Some(tagged) = value.as_tagged() {
+            let inner = self.value(&tagged.value)?;
+            return Ok(Rc::new(TaggedValue::new(tagged.tag.clone(), inner)));
+        }
+
+        Ok(match value.ty() {
+            StackValueType::Null
+            | StackValueType::Int
+            | StackValueType::Rational
+            | StackValueType::Cell
+            | StackValueType::Builder
+            | StackValueType::Slice
+            | StackValueType::String
+            | StackValueType::Bytes
+            | StackValueType::Custom => value.clone(),
+            StackValueType::Tuple => Rc::new(self.tuple(value.as_tuple()?)?),
+            StackValueType::Atom => Rc::new(match value.as_atom()? {
+                Atom::Named(name) => self.atoms.create_named(name),
+                Atom::Unnamed(id) => self
+                    .anon_atoms
+                    .entry(*id)
+                    .or_insert_with(|| self.atoms.create_anon())
+                    .clone(),
+            }),
+            StackValueType::SharedBox => {
+                let value = value.as_box()?;
+                let ptr = value.as_ptr();
+                if let Some(copy) = self.boxes.get(&ptr) {
+                    return Ok(Rc::new(copy.clone()));
+                }
+
+                let copy = SharedBox::default();
+                self.boxes.insert(ptr, copy.clone());
+                copy.store(self.value(&value.fetch())?);
+                Rc::new(copy)
+            }
+            StackValueType::HashMap => {
+                let mut map = None;
+                for entry in value.as_hashmap()? {
+                    let key = HashMapTreeKey::new(self.value(&entry.key.stack_value)?)?;
+                    HashMapTreeNode::set(&mut map, &key, &self.value(&entry.value)?);
+                }
+                match map {
+                    Some(map) => map,
+                    None => Stack::make_null(),
+                }
+            }
+            StackValueType::Exception => {
+                let value = value.as_exception()?;
+                Rc::new(Exception {
+                    reason: value.reason.clone(),
+                    stack: self.tuple(&value.stack)?,
+                })
+            }
+            ty @ (StackValueType::Cont | StackValueType::WordList) => {
+                return Err(StackError::NotTransferable(ty).into())
+            }
+        })
+    }
+
+    fn tuple(&mut self, tuple: &StackTuple) -> Result<StackTuple> {
+        tuple.iter().map(|item| self.value(item)).collect()
     }
 }
 
@@ -351,6 +564,10 @@ macro_rules! define_stack_value {
 
             fn fmt_dump(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
 
+            fn as_tagged(&self) -> Option<&TaggedValue> {
+                None
+            }
+
             $(fn $cast(&self) -> Result<$cast_res> {
                 Err(StackError::UnexpectedType {
                     expected: $value_type::$name,
@@ -400,6 +617,33 @@ macro_rules! define_stack_value {
 
             $($($other)*)?
         })*
+
+        impl $trait for TaggedValue {
+            fn ty(&self) -> $value_type {
+                self.value.ty()
+            }
+
+            fn is_equal(&self, other: &dyn $trait) -> bool {
+                self.value.is_equal(other)
+            }
+
+            fn fmt_dump(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "<{}>", self.tag)?;
+                self.value.fmt_dump(f)
+            }
+
+            fn as_tagged(&self) -> Option<&TaggedValue> {
+                Some(self)
+            }
+
+            $(fn $cast(&self) -> Result<$cast_res> {
+                self.value.$cast().map_err(|e| self.wrap_error(e))
+            })*
+
+            $(fn $into(self: Rc<Self>) -> Result<Rc<$ty>> {
+                self.value.clone().$into().map_err(|e| self.wrap_error(e))
+            })*
+        }
     };
 }
 
@@ -417,6 +661,12 @@ define_stack_value! {
             as_int(v): &BigInt = Ok(v),
             into_int,
         },
+        Rational(Rational) = {
+            eq(a, b) = a == b,
+            fmt_dump(v, f) = write!(f, "{}/{}", v.numer, v.denom),
+            as_rational(v): &Rational = Ok(v),
+            into_rational,
+        },
         Cell(Cell) = {
             eq(a, b) = a.as_ref() == b.as_ref(),
             fmt_dump(v, f) = write!(f, "C{{{}}}", v.repr_hash()),
@@ -435,7 +685,7 @@ define_stack_value! {
         Slice(OwnedCellSlice) = {
             eq(a, b) = *a == b,
             fmt_dump(v, f) = std::fmt::Display::fmt(v, f),
-            as_slice(v): CellSlice = v.apply(),
+            as_slice(v): CellSlice<'_> = v.apply(),
             into_slice,
         },
         String(String) = {
@@ -509,33 +759,53 @@ define_stack_value! {
             fmt_dump(v, f) = write!(f, "HashMap{{{:?}}}", &v as *const _),
             as_hashmap(v): &HashMapTreeNode = Ok(v),
             into_hashmap,
+        },
+        Exception(Exception) = {
+            eq(a, b) = a.reason == b.reason,
+            fmt_dump(v, f) = write!(f, "Exception{{{:?}}}", v.reason),
+            as_exception(v): &Exception = Ok(v),
+            into_exception,
+        },
+        Custom(CustomValue) = {
+            eq(a, b) = Rc::ptr_eq(&a.value, &b.value),
+            fmt_dump(v, f) = (v.fmt_dump)(v.value.as_ref(), f),
+            as_custom(v): &CustomValue = Ok(v),
+            into_custom,
         }
     }
 }
 
 impl dyn StackValue + '_ {
     pub fn display_dump(&self) -> impl std::fmt::Display + '_ {
-        pub struct DisplayDump<'a>(&'a dyn StackValue);
+        self.display_dump_limited(DisplayLimits::default())
+    }
+
+    pub fn display_dump_limited(&self, limits: DisplayLimits) -> impl std::fmt::Display + '_ {
+        pub struct DisplayDump<'a>(&'a dyn StackValue, DisplayLimits);
 
         impl std::fmt::Display for DisplayDump<'_> {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                self.0.fmt_dump(f)
+                self.0.fmt_dump_limited(f, &self.1, 0)
             }
         }
 
-        DisplayDump(self)
+        DisplayDump(self, limits)
     }
 
     pub fn display_list(&self) -> impl std::fmt::Display + '_ {
-        pub struct DisplayList<'a>(&'a dyn StackValue);
+        self.display_list_limited(DisplayLimits::default())
+    }
+
+    pub fn display_list_limited(&self, limits: DisplayLimits) -> impl std::fmt::Display + '_ {
+        pub struct DisplayList<'a>(&'a dyn StackValue, DisplayLimits);
 
         impl std::fmt::Display for DisplayList<'_> {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                self.0.fmt_list(f)
+                self.0.fmt_list(f, &self.1, 0)
             }
         }
 
-        DisplayList(self)
+        DisplayList(self, limits)
     }
 
     pub fn is_null(&self) -> bool {
@@ -562,45 +832,125 @@ impl dyn StackValue + '_ {
         Some((head, tail))
     }
 
-    fn fmt_list(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt_dump_limited(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        limits: &DisplayLimits,
+        depth: usize,
+    ) -> std::fmt::Result {
+        if let Some(tagged) = self.as_tagged() {
+            write!(f, "<{}>", tagged.tag)?;
+            return tagged.value.fmt_dump_limited(f, limits, depth);
+        }
+
+        match self.ty() {
+            StackValueType::String => {
+                let s = self.as_string().map_err(|_| std::fmt::Error)?;
+                match limits.max_len.and_then(|max| s.char_indices().nth(max)) {
+                    Some((end, _)) => write!(f, "\"{}...\"", &s[..end]),
+                    None => self.fmt_dump(f),
+                }
+            }
+            StackValueType::Bytes => {
+                let bytes = self.as_bytes().map_err(|_| std::fmt::Error)?;
+                match limits.max_len {
+                    Some(max) if bytes.len() > max => {
+                        write!(f, "BYTES:{}...", hex::encode_upper(&bytes[..max]))
+                    }
+                    _ => self.fmt_dump(f),
+                }
+            }
+            StackValueType::Tuple => {
+                let tuple = self.as_tuple().map_err(|_| std::fmt::Error)?;
+                if tuple.is_empty() {
+                    return f.write_str("[]");
+                } else if limits.depth_exceeded(depth) {
+                    return f.write_str("[ ... ]");
+                }
+
+                f.write_str("[ ")?;
+                for (i, item) in tuple.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    if limits.items_exceeded(i) {
+                        f.write_str("...")?;
+                        break;
+                    }
+                    item.as_ref().fmt_dump_limited(f, limits, depth + 1)?;
+                }
+                f.write_str(" ]")
+            }
+            _ => self.fmt_dump(f),
+        }
+    }
+
+    fn fmt_list(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        limits: &DisplayLimits,
+        depth: usize,
+    ) -> std::fmt::Result {
         if self.is_null() {
             f.write_str("()")
         } else if let Ok(tuple) = self.as_tuple() {
             if let Some((head, tail)) = self.as_list() {
+                if limits.depth_exceeded(depth) {
+                    return f.write_str("(...)");
+                }
                 f.write_str("(")?;
-                head.fmt_list(f)?;
-                tail.fmt_list_tail(f)?;
+                head.fmt_list(f, limits, depth + 1)?;
+                tail.fmt_list_tail(f, limits, depth + 1)?;
                 return Ok(());
             }
 
+            if !tuple.is_empty() && limits.depth_exceeded(depth) {
+                return f.write_str("[...]");
+            }
+
             f.write_str("[")?;
-            let mut first = true;
-            for item in tuple {
-                if !std::mem::take(&mut first) {
+            for (i, item) in tuple.iter().enumerate() {
+                if i > 0 {
                     f.write_str(" ")?;
                 }
-                item.as_ref().fmt_list(f)?;
+                if limits.items_exceeded(i) {
+                    f.write_str("...")?;
+                    break;
+                }
+                item.as_ref().fmt_list(f, limits, depth + 1)?;
             }
             f.write_str("]")?;
 
             Ok(())
         } else {
-            self.fmt_dump(f)
+            self.fmt_dump_limited(f, limits, depth)
         }
     }
 
-    fn fmt_list_tail(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt_list_tail(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        limits: &DisplayLimits,
+        depth: usize,
+    ) -> std::fmt::Result {
         let mut item = self;
+        let mut count = 1;
         while !item.is_null() {
             let Some((head, tail)) = item.as_pair() else {
                 f.write_str(" . ")?;
-                item.fmt_list(f)?;
+                item.fmt_list(f, limits, depth)?;
                 break;
             };
 
+            if limits.items_exceeded(count) {
+                f.write_str(" ...")?;
+                break;
+            }
+
             f.write_str(" ")?;
-            head.fmt_list(f)?;
+            head.fmt_list(f, limits, depth)?;
             item = tail;
+            count += 1;
         }
         f.write_str(")")
     }
@@ -608,6 +958,186 @@ impl dyn StackValue + '_ {
 
 pub type StackTuple = Vec<Rc<dyn StackValue>>;
 
+/// Truncation rules for displaying stack values, `None` means no limit.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct DisplayLimits {
+    /// Max number of stack items and tuple elements.
+    pub max_items: Option<usize>,
+    /// Max number of string chars or bytes.
+    pub max_len: Option<usize>,
+    /// Max nesting depth of tuples.
+    pub max_depth: Option<usize>,
+}
+
+impl DisplayLimits {
+    fn items_exceeded(&self, count: usize) -> bool {
+        matches!(self.max_items, Some(max) if count >= max)
+    }
+
+    fn depth_exceeded(&self, depth: usize) -> bool {
+        matches!(self.max_depth, Some(max) if depth >= max)
+    }
+}
+
+/// An error caught by `try` or `catch`.
+#[derive(Clone)]
+pub struct Exception {
+    pub reason: String,
+    /// Stack contents at the moment of the error.
+    pub stack: StackTuple,
+}
+
+/// A normalized fraction with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numer: BigInt,
+    denom: BigInt,
+}
+
+impl Rational {
+    pub fn new(numer: BigInt, denom: BigInt) -> Result<Self> {
+        anyhow::ensure!(!denom.is_zero(), "Division by zero");
+        let gcd = num_integer::Integer::gcd(&numer, &denom);
+        let (mut numer, mut denom) = (numer / &gcd, denom / gcd);
+        if denom.sign() == Sign::Minus {
+            numer = -numer;
+            denom = -denom;
+        }
+        Ok(Self { numer, denom })
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.numer
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.denom
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denom.is_one()
+    }
+}
+
+impl From<BigInt> for Rational {
+    fn from(numer: BigInt) -> Self {
+        Self {
+            numer,
+            denom: BigInt::one(),
+        }
+    }
+}
+
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_integer() {
+            std::fmt::Display::fmt(&self.numer, f)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+/// A host object which can be passed between embedder-registered words.
+pub trait CustomType: Any {
+    /// Type name used in dumps and errors.
+    const TYPE_TAG: &'static str;
+
+    fn fmt_dump(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}>", Self::TYPE_TAG)
+    }
+}
+
+/// An opaque value of some [`CustomType`].
+#[derive(Clone)]
+pub struct CustomValue {
+    tag: &'static str,
+    value: Rc<dyn Any>,
+    fmt_dump: fn(&dyn Any, &mut std::fmt::Formatter<'_>) -> std::fmt::Result,
+}
+
+impl CustomValue {
+    pub fn new<T: CustomType>(value: T) -> Self {
+        Self::from_rc(Rc::new(value))
+    }
+
+    pub fn from_rc<T: CustomType>(value: Rc<T>) -> Self {
+        fn fmt_dump<T: CustomType>(
+            value: &dyn Any,
+            f: &mut std::fmt::Formatter<'_>,
+        ) -> std::fmt::Result {
+            match value.downcast_ref::<T>() {
+                Some(value) => value.fmt_dump(f),
+                None => f.write_str(T::TYPE_TAG),
+            }
+        }
+
+        Self {
+            tag: T::TYPE_TAG,
+            value,
+            fmt_dump: fmt_dump::<T>,
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    pub fn downcast_ref<T: CustomType>(&self) -> Result<&T> {
+        match self.value.downcast_ref() {
+            Some(value) => Ok(value),
+            None => Err(self.type_mismatch::<T>()),
+        }
+    }
+
+    pub fn downcast<T: CustomType>(&self) -> Result<Rc<T>> {
+        match self.value.clone().downcast() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(self.type_mismatch::<T>()),
+        }
+    }
+
+    fn type_mismatch<T: CustomType>(&self) -> anyhow::Error {
+        StackError::UnexpectedCustomType {
+            expected: T::TYPE_TAG,
+            actual: self.tag,
+        }
+        .into()
+    }
+}
+
+#[derive(Clone)]
+pub struct TaggedValue {
+    pub tag: Rc<str>,
+    pub value: Rc<dyn StackValue>,
+}
+
+impl TaggedValue {
+    pub fn new(tag: Rc<str>, value: Rc<dyn StackValue>) -> Self {
+        // NOTE: retagging replaces the previous tag instead of nesting wrappers
+        let value = match value.as_tagged() {
+            Some(tagged) => tagged.value.clone(),
+            None => value,
+        };
+        Self { tag, value }
+    }
+
+    fn wrap_error(&self, e: anyhow::Error) -> anyhow::Error {
+        match e.downcast::<StackError>() {
+            Ok(StackError::UnexpectedType { expected, actual }) => {
+                StackError::UnexpectedTaggedType {
+                    tag: self.tag.to_string(),
+                    expected,
+                    actual,
+                }
+                .into()
+            }
+            Ok(e) => e.into(),
+            Err(e) => e,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct OwnedCellSlice {
     cell: Cell,
@@ -624,6 +1154,10 @@ impl OwnedCellSlice {
         self.range.apply(&self.cell).map_err(From::from)
     }
 
+    pub fn cell(&self) -> &Cell {
+        &self.cell
+    }
+
     pub fn range(&self) -> CellSliceRange {
         self.range
     }
@@ -664,19 +1198,21 @@ impl std::fmt::Display for OwnedCellSlice {
 #[derive(Default, Clone)]
 pub struct WordList {
     pub items: Vec<Cont>,
+    /// Source positions of the items (only filled when source maps are enabled).
+    pub positions: Vec<Option<SourcePosition>>,
 }
 
 impl WordList {
+    pub fn position(&self, index: usize) -> Option<&SourcePosition> {
+        self.positions.get(index)?.as_ref()
+    }
+
     pub fn finish(self: Rc<Self>) -> Cont {
         if self.items.len() == 1 {
             return self.items.first().unwrap().clone();
         }
 
-        Rc::new(ListCont {
-            after: None,
-            list: self,
-            pos: 0,
-        })
+        Rc::new(ListCont::new(self, None, 0))
     }
 }
 
@@ -695,7 +1231,12 @@ impl PartialEq for WordList {
 
 #[derive(Clone)]
 pub struct SharedBox {
-    value: Rc<RefCell<Rc<dyn StackValue>>>,
+    value: Rc<SharedBoxInner>,
+}
+
+struct SharedBoxInner {
+    value: RefCell<Rc<dyn StackValue>>,
+    version: std::cell::Cell<u64>,
 }
 
 impl Default for SharedBox {
@@ -716,31 +1257,52 @@ impl PartialEq for SharedBox {
 impl SharedBox {
     pub fn new(value: Rc<dyn StackValue>) -> Self {
         Self {
-            value: Rc::new(RefCell::new(value)),
+            value: Rc::new(SharedBoxInner {
+                value: RefCell::new(value),
+                version: Default::default(),
+            }),
         }
     }
 
     pub fn store(&self, value: Rc<dyn StackValue>) {
-        *self.value.borrow_mut() = value;
+        *self.value.value.borrow_mut() = value;
+        self.bump_version();
     }
 
     pub fn store_opt<T: StackValue + 'static>(&self, value: Option<Rc<T>>) {
-        *self.value.borrow_mut() = match value {
+        *self.value.value.borrow_mut() = match value {
             None => Stack::make_null(),
             Some(value) => value,
         };
+        self.bump_version();
     }
 
     pub fn fetch(&self) -> Rc<dyn StackValue> {
-        self.value.borrow().clone()
+        self.value.value.borrow().clone()
     }
 
     pub fn take(&self) -> Rc<dyn StackValue> {
-        std::mem::replace(&mut *self.value.borrow_mut(), Stack::make_null())
+        let value = std::mem::replace(&mut *self.value.value.borrow_mut(), Stack::make_null());
+        self.bump_version();
+        value
     }
 
     pub fn borrow(&self) -> std::cell::Ref<'_, Rc<dyn StackValue>> {
-        self.value.borrow()
+        self.value.value.borrow()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.value.version.get()
+    }
+
+    /// Returns an address which is the same for all clones of the box.
+    pub(crate) fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.value) as *const ()
+    }
+
+    fn bump_version(&self) {
+        let version = &self.value.version;
+        version.set(version.get().wrapping_add(1));
     }
 }
 
@@ -824,10 +1386,23 @@ impl HashMapTreeNode {
             value,
             left: None,
             right: None,
-            rand_offset: rand::thread_rng().gen(),
+            rand_offset: Self::gen_rand_offset(),
         }
     }
 
+    thread_local! {
+        static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+    }
+
+    /// Makes priorities of new nodes on the current thread deterministic.
+    pub fn reseed(seed: u64) {
+        Self::RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+    }
+
+    fn gen_rand_offset() -> u64 {
+        Self::RNG.with(|rng| rng.borrow_mut().gen())
+    }
+
     pub fn iter(&self) -> HashMapTreeIter<'_> {
         self.into_iter()
     }
@@ -851,7 +1426,7 @@ impl HashMapTreeNode {
             && !Self::replace(root_opt, key.as_equivalent(), value)
             && !value.is_null()
         {
-            Self::insert_internal(root_opt, key, value, rand::thread_rng().gen())
+            Self::insert_internal(root_opt, key, value, Self::gen_rand_offset())
         }
     }
 
@@ -1202,6 +1777,15 @@ impl From<String> for HashMapTreeKey {
     }
 }
 
+impl From<Rc<String>> for HashMapTreeKey {
+    fn from(value: Rc<String>) -> Self {
+        Self {
+            hash: Self::HASHER_STATE.with(|hasher| hasher.hash_one(value.as_ref())),
+            stack_value: value,
+        }
+    }
+}
+
 impl AsHashMapTreeKeyRef for HashMapTreeKey {
     fn as_equivalent(&self) -> HashMapTreeKeyRef<'_> {
         HashMapTreeKeyRef {
@@ -1232,6 +1816,13 @@ impl PartialEq for HashMapTreeKey {
     }
 }
 
+impl std::hash::Hash for HashMapTreeKey {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct HashMapTreeKeyRef<'a> {
     hash: u64,
@@ -1301,6 +1892,12 @@ pub enum StackError {
         expected: StackValueType,
         actual: StackValueType,
     },
+    #[error("Expected type `{expected:?}`, found type `{actual:?}` tagged as `{tag}`")]
+    UnexpectedTaggedType {
+        tag: String,
+        expected: StackValueType,
+        actual: StackValueType,
+    },
     #[error("Expected integer in range {min}..={max}, found {actual}")]
     IntegerOutOfRange {
         min: u32,
@@ -1315,4 +1912,11 @@ pub enum StackError {
     },
     #[error("Expected a valid utf8 char code, found {0}")]
     InvalidChar(String),
+    #[error("Value of type `{0:?}` cannot be transferred to another context")]
+    NotTransferable(StackValueType),
+    #[error("Expected custom value of type `{expected}`, found `{actual}`")]
+    UnexpectedCustomType {
+        expected: &'static str,
+        actual: &'static str,
+    },
 }
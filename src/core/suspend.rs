@@ -0,0 +1,849 @@
+use std::rc::Rc;
+
+use ahash::{HashMap, HashSet};
+use anyhow::{Context as _, Result};
+use everscale_types::prelude::*;
+use num_bigint::BigInt;
+
+use super::cont::{self, Cont};
+use super::dictionary::{Dictionary, DictionaryEntry};
+use super::stack::{
+    Atom, Atoms, HashMapTreeKey, HashMapTreeNode, OwnedCellSlice, SharedBox, Stack, StackValue,
+    StackValueType, WordList,
+};
+
+const MAGIC: &[u8; 8] = b"FIFTSUSP";
+const SESSION_MAGIC: &[u8; 8] = b"FIFTSESS";
+const VERSION: u8 = 1;
+// NOTE: serialization is recursive, so deeper chains (or nested values)
+// are rejected to not overflow the stack
+const MAX_DEPTH: usize = 1024;
+
+/// Serializes a continuation chain into bytes.
+///
+/// The chain is truncated at the first text interpreter continuation,
+/// so only the compiled part of the pending execution is saved.
+pub fn serialize(cont: Option<&Cont>, d: &Dictionary) -> Result<Vec<u8>> {
+    let mut s = Suspender::new(d, MAGIC)?;
+    s.write_opt_cont(cont)?;
+    Ok(s.data)
+}
+
+/// Restores a continuation chain produced by [`serialize`].
+///
+/// Named words are resolved in the specified dictionary,
+/// atoms are registered in the specified registry.
+pub fn deserialize(data: &[u8], d: &Dictionary, atoms: &mut Atoms) -> Result<Option<Cont>> {
+    let mut r = Resumer::new(data, MAGIC, d, atoms).context("Invalid suspended continuation")?;
+    let cont = r.read_opt_cont()?;
+    anyhow::ensure!(
+        r.data.is_empty(),
+        "Trailing data after suspended continuation"
+    );
+    Ok(cont)
+}
+
+/// Word definitions and stack items of an interpreter session.
+pub struct Session {
+    pub words: Vec<(String, DictionaryEntry)>,
+    pub stack: Vec<Rc<dyn StackValue>>,
+}
+
+/// Serializes word definitions and stack items into bytes.
+///
+/// Words are saved with their bodies (even if they are still in the dictionary),
+/// other words are referenced by name. Words which can't be serialized
+/// (e.g. native ones) are skipped and their names are returned.
+pub fn serialize_session(
+    words: &[(Rc<String>, DictionaryEntry)],
+    stack: &[Rc<dyn StackValue>],
+    d: &Dictionary,
+) -> Result<(Vec<u8>, Vec<Rc<String>>)> {
+    let mut s = Suspender::new(d, SESSION_MAGIC)?;
+
+    // NOTE: saved words reference each other by contents to keep the sharing,
+    // unless the definition is also used by some other word (e.g. an alias)
+    let session_names = words.iter().map(|(name, _)| name).collect::<HashSet<_>>();
+    let mut shared = HashSet::default();
+    for (name, entry) in d.iter()? {
+        if !session_names.contains(&name) {
+            let ptr = Rc::as_ptr(&entry.definition) as *const ();
+            s.names.insert(ptr, Rc::from(name.as_str()));
+            shared.insert(ptr);
+        }
+    }
+    for (_, entry) in words {
+        let ptr = Rc::as_ptr(&entry.definition) as *const ();
+        if !shared.contains(&ptr) {
+            s.names.remove(&ptr);
+        }
+    }
+
+    let mut skipped = Vec::new();
+    for (name, entry) in words {
+        let state = (s.data.len(), s.cont_count, s.list_count, s.boxes.len());
+        s.write_bool(true);
+        s.write_str(name);
+        s.write_bool(entry.active);
+        if s.write_cont(&entry.definition).is_err() {
+            s.data.truncate(state.0);
+            s.conts.retain(|_, id| *id < state.1);
+            s.lists.retain(|_, id| *id < state.2);
+            s.cont_count = state.1;
+            s.list_count = state.2;
+            s.boxes.retain(|_, id| *id < state.3);
+            s.stopped = false;
+            skipped.push(name.clone());
+        }
+    }
+    s.write_bool(false);
+
+    s.write_varuint(stack.len() as u64);
+    for item in stack {
+        s.write_value(item.as_ref())?;
+    }
+
+    Ok((s.data, skipped))
+}
+
+/// Restores a session produced by [`serialize_session`].
+///
+/// Words which are not in the session are resolved in the specified dictionary,
+/// atoms are registered in the specified registry.
+pub fn deserialize_session(data: &[u8], d: &Dictionary, atoms: &mut Atoms) -> Result<Session> {
+    let mut r = Resumer::new(data, SESSION_MAGIC, d, atoms).context("Invalid session")?;
+
+    let mut words = Vec::new();
+    while r.read_bool()? {
+        let name = r.read_str()?;
+        let active = r.read_bool()?;
+        let definition = r.read_cont()?;
+        words.push((name, DictionaryEntry { definition, active }));
+    }
+
+    let len = r.read_varuint()? as usize;
+    let mut stack = Vec::with_capacity(len.min(256));
+    for _ in 0..len {
+        stack.push(r.read_value()?);
+    }
+    anyhow::ensure!(r.data.is_empty(), "Trailing data after session");
+
+    Ok(Session { words, stack })
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ContTag {
+    None = 0,
+    Ref = 1,
+    Named = 2,
+    Nop = 3,
+    List = 4,
+    Seq = 5,
+    Times = 6,
+    Until = 7,
+    While = 8,
+    IntLit = 9,
+    Lit = 10,
+    MultiLit = 11,
+    Shuffle = 12,
+}
+
+impl ContTag {
+    fn from_u8(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0 => Self::None,
+            1 => Self::Ref,
+            2 => Self::Named,
+            3 => Self::Nop,
+            4 => Self::List,
+            5 => Self::Seq,
+            6 => Self::Times,
+            7 => Self::Until,
+            8 => Self::While,
+            9 => Self::IntLit,
+            10 => Self::Lit,
+            11 => Self::MultiLit,
+            12 => Self::Shuffle,
+            _ => return None,
+        })
+    }
+}
+
+const BOX_NEW: u8 = 0;
+const BOX_REF: u8 = 1;
+const BOX_DICTIONARY: u8 = 2;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+enum ValueTag {
+    Null = 0,
+    Int = 1,
+    String = 2,
+    Bytes = 3,
+    Cell = 4,
+    Slice = 5,
+    Tuple = 6,
+    Cont = 7,
+    Box = 8,
+    HashMap = 9,
+    Atom = 10,
+    Builder = 11,
+}
+
+impl ValueTag {
+    fn from_u8(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0 => Self::Null,
+            1 => Self::Int,
+            2 => Self::String,
+            3 => Self::Bytes,
+            4 => Self::Cell,
+            5 => Self::Slice,
+            6 => Self::Tuple,
+            7 => Self::Cont,
+            8 => Self::Box,
+            9 => Self::HashMap,
+            10 => Self::Atom,
+            11 => Self::Builder,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Continuation is not suspendable")]
+pub struct NotSuspendable;
+
+pub struct Suspender<'a> {
+    d: &'a Dictionary,
+    names: HashMap<*const (), Rc<str>>,
+    conts: HashMap<*const (), usize>,
+    lists: HashMap<*const WordList, usize>,
+    // NOTE: the same continuation can be written twice if it is reachable
+    // from itself (e.g. through a box), so ids are not derived from the maps
+    cont_count: usize,
+    list_count: usize,
+    boxes: HashMap<*const (), usize>,
+    stopped: bool,
+    depth: usize,
+    data: Vec<u8>,
+}
+
+impl<'a> Suspender<'a> {
+    fn new(d: &'a Dictionary, magic: &[u8]) -> Result<Self> {
+        let mut names = HashMap::default();
+        if let Some(map) = &d.clone_words_map()? {
+            for entry in map.as_ref() {
+                let Some((cont, _)) = DictionaryEntry::cont_from_value(entry.value.as_ref()) else {
+                    continue;
+                };
+                let name = entry.key.stack_value.as_string()?;
+                names.insert(Rc::as_ptr(cont) as *const (), Rc::from(name));
+            }
+        }
+
+        let mut data = magic.to_vec();
+        data.push(VERSION);
+
+        Ok(Self {
+            d,
+            names,
+            conts: Default::default(),
+            lists: Default::default(),
+            cont_count: 0,
+            list_count: 0,
+            boxes: Default::default(),
+            stopped: false,
+            depth: 0,
+            data,
+        })
+    }
+
+    /// Marks the end of the suspendable part of the chain.
+    pub fn stop(&mut self) {
+        self.write_tag(ContTag::None);
+        self.stopped = true;
+    }
+
+    pub fn write_tag(&mut self, tag: ContTag) {
+        self.data.push(tag as u8);
+    }
+
+    /// Writes a reference to the word which is resolved by its name on resume.
+    pub fn write_named(&mut self, name: &str) {
+        self.write_tag(ContTag::Named);
+        self.write_str(name);
+    }
+
+    pub fn write_opt_cont(&mut self, cont: Option<&Cont>) -> Result<()> {
+        match cont {
+            Some(cont) => self.write_cont(cont),
+            None => {
+                self.write_tag(ContTag::None);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn write_cont(&mut self, cont: &Cont) -> Result<()> {
+        if self.stopped {
+            self.write_tag(ContTag::None);
+            return Ok(());
+        }
+
+        let ptr = Rc::as_ptr(cont) as *const ();
+        if let Some(&id) = self.conts.get(&ptr) {
+            self.write_tag(ContTag::Ref);
+            self.write_varuint(id as u64);
+            return Ok(());
+        }
+
+        if let Some(name) = self.names.get(&ptr).cloned() {
+            self.write_named(&name);
+        } else if let Err(e) = self.suspend_nested(cont) {
+            if e.is::<NotSuspendable>() {
+                anyhow::bail!(
+                    "Cannot suspend native continuation `{}`",
+                    cont.display_name(self.d)
+                );
+            }
+            return Err(e);
+        }
+
+        self.conts.insert(ptr, self.cont_count);
+        self.cont_count += 1;
+        Ok(())
+    }
+
+    fn suspend_nested(&mut self, cont: &Cont) -> Result<()> {
+        anyhow::ensure!(
+            self.depth < MAX_DEPTH,
+            "Continuation is too deep to suspend (max depth {MAX_DEPTH})"
+        );
+        self.depth += 1;
+        let res = cont.suspend(self);
+        self.depth -= 1;
+        res
+    }
+
+    pub fn write_word_list(&mut self, list: &Rc<WordList>) -> Result<()> {
+        let ptr = Rc::as_ptr(list);
+        if let Some(&id) = self.lists.get(&ptr) {
+            self.write_bool(true);
+            self.write_varuint(id as u64);
+            return Ok(());
+        }
+
+        self.write_bool(false);
+        self.write_varuint(list.items.len() as u64);
+        for item in &list.items {
+            self.write_cont(item)?;
+        }
+
+        self.lists.insert(ptr, self.list_count);
+        self.list_count += 1;
+        Ok(())
+    }
+
+    pub fn write_value(&mut self, value: &dyn StackValue) -> Result<()> {
+        anyhow::ensure!(
+            self.depth < MAX_DEPTH,
+            "Value is too deep to suspend (max depth {MAX_DEPTH})"
+        );
+        self.depth += 1;
+        let res = self.write_value_impl(value);
+        self.depth -= 1;
+        res
+    }
+
+    fn write_value_impl(&mut self, value: &dyn StackValue) -> Result<()> {
+        match value.ty() {
+            StackValueType::Null => self.write_value_tag(ValueTag::Null),
+            StackValueType::Int => {
+                self.write_value_tag(ValueTag::Int);
+                self.write_int(value.as_int()?);
+            }
+            StackValueType::String => {
+                self.write_value_tag(ValueTag::String);
+                self.write_str(value.as_string()?);
+            }
+            StackValueType::Bytes => {
+                self.write_value_tag(ValueTag::Bytes);
+                self.write_bytes(value.as_bytes()?);
+            }
+            StackValueType::Cell => {
+                self.write_value_tag(ValueTag::Cell);
+                self.write_bytes(&Boc::encode(value.as_cell()?));
+            }
+            StackValueType::Slice => {
+                let cs = value.as_slice()?;
+                self.write_value_tag(ValueTag::Slice);
+                self.write_bytes(&Boc::encode(cs.cell()));
+                self.write_varuint(cs.bits_offset() as u64);
+                self.write_varuint(cs.refs_offset() as u64);
+                self.write_varuint(cs.remaining_bits() as u64);
+                self.write_varuint(cs.remaining_refs() as u64);
+            }
+            StackValueType::Builder => {
+                let builder = value.as_builder()?;
+                anyhow::ensure!(!builder.is_exotic(), "Cannot suspend exotic builder");
+                self.write_value_tag(ValueTag::Builder);
+                self.write_bytes(&Boc::encode(builder.clone().build()?));
+            }
+            StackValueType::Tuple => {
+                let tuple = value.as_tuple()?;
+                self.write_value_tag(ValueTag::Tuple);
+                self.write_varuint(tuple.len() as u64);
+                for item in tuple {
+                    self.write_value(item.as_ref())?;
+                }
+            }
+            StackValueType::Cont => {
+                self.write_value_tag(ValueTag::Cont);
+                self.write_cont(value.as_cont()?)?;
+            }
+            StackValueType::SharedBox => {
+                let value = value.as_box()?;
+                self.write_value_tag(ValueTag::Box);
+                if let Some(&id) = self.boxes.get(&value.as_ptr()) {
+                    self.data.push(BOX_REF);
+                    self.write_varuint(id as u64);
+                } else if value == self.d.get_words_box().as_ref() {
+                    // NOTE: words of the dictionary are not copied to keep
+                    // the wordlist itself (e.g. for `current!`)
+                    self.data.push(BOX_DICTIONARY);
+                } else {
+                    // NOTE: id is assigned before the contents to support cycles
+                    self.data.push(BOX_NEW);
+                    self.boxes.insert(value.as_ptr(), self.boxes.len());
+                    self.write_value(value.fetch().as_ref())?;
+                }
+            }
+            StackValueType::Atom => {
+                self.write_value_tag(ValueTag::Atom);
+                match value.as_atom()? {
+                    Atom::Named(name) => {
+                        self.write_bool(true);
+                        self.write_str(name);
+                    }
+                    Atom::Unnamed(idx) => {
+                        self.write_bool(false);
+                        self.write_varuint(idx.unsigned_abs() as u64);
+                    }
+                }
+            }
+            StackValueType::HashMap => {
+                let map = value.as_hashmap()?;
+                self.write_value_tag(ValueTag::HashMap);
+                self.write_varuint(map.iter().count() as u64);
+                for node in map {
+                    self.write_value(node.key.stack_value.as_ref())?;
+                    self.write_value(node.value.as_ref())?;
+                }
+            }
+            ty => anyhow::bail!("Cannot suspend value of type {ty:?}"),
+        }
+        Ok(())
+    }
+
+    pub fn write_int(&mut self, int: &BigInt) {
+        self.write_bytes(&int.to_signed_bytes_le());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_varuint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.data.push(byte);
+                break;
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    fn write_value_tag(&mut self, tag: ValueTag) {
+        self.data.push(tag as u8);
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_varuint(bytes.len() as u64);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+struct Resumer<'a> {
+    d: &'a Dictionary,
+    atoms: &'a mut Atoms,
+    data: &'a [u8],
+    conts: Vec<Cont>,
+    lists: Vec<Rc<WordList>>,
+    boxes: Vec<SharedBox>,
+    anon_atoms: HashMap<u64, Atom>,
+    depth: usize,
+}
+
+impl<'a> Resumer<'a> {
+    fn new(data: &'a [u8], magic: &[u8], d: &'a Dictionary, atoms: &'a mut Atoms) -> Result<Self> {
+        let data = data.strip_prefix(magic).context("Invalid magic")?;
+        let (&version, data) = data.split_first().context("Unexpected eof")?;
+        anyhow::ensure!(version == VERSION, "Unsupported version: {version}");
+
+        Ok(Self {
+            d,
+            atoms,
+            data,
+            conts: Vec::new(),
+            lists: Vec::new(),
+            boxes: Vec::new(),
+            anon_atoms: Default::default(),
+            depth: 0,
+        })
+    }
+
+    fn read_cont(&mut self) -> Result<Cont> {
+        self.read_opt_cont()?
+            .context("Unexpected empty suspended continuation")
+    }
+
+    fn read_opt_cont(&mut self) -> Result<Option<Cont>> {
+        anyhow::ensure!(
+            self.depth < MAX_DEPTH,
+            "Suspended continuation is too deep (max depth {MAX_DEPTH})"
+        );
+        self.depth += 1;
+        let res = self.read_opt_cont_impl();
+        self.depth -= 1;
+        res
+    }
+
+    fn read_opt_cont_impl(&mut self) -> Result<Option<Cont>> {
+        let tag = self.read_u8()?;
+        let tag = ContTag::from_u8(tag)
+            .with_context(|| format!("Unknown suspended continuation tag: {tag}"))?;
+
+        let cont: Cont = match tag {
+            ContTag::None => return Ok(None),
+            ContTag::Ref => {
+                let id = self.read_varuint()? as usize;
+                let cont = self
+                    .conts
+                    .get(id)
+                    .context("Invalid continuation reference")?;
+                return Ok(Some(cont.clone()));
+            }
+            ContTag::Named => {
+                let name = self.read_str()?;
+                let entry = self
+                    .d
+                    .lookup(&name)?
+                    .with_context(|| format!("Undefined word `{}`", name.trim_end()))?;
+                entry.definition
+            }
+            ContTag::Nop => cont::NopCont::instance(),
+            ContTag::List => {
+                let list = self.read_word_list()?;
+                let pos = self.read_varuint()? as usize;
+                let after = self.read_opt_cont()?;
+                Rc::new(cont::ListCont::new(list, after, pos))
+            }
+            ContTag::Seq => {
+                let first = self.read_opt_cont()?;
+                let second = self.read_opt_cont()?;
+                Rc::new(cont::SeqCont::new(first, second))
+            }
+            ContTag::Times => {
+                let body = self.read_opt_cont()?;
+                let after = self.read_opt_cont()?;
+                let count = self.read_varuint()? as usize;
+                Rc::new(cont::TimesCont::new(body, after, count))
+            }
+            ContTag::Until => {
+                let body = self.read_opt_cont()?;
+                let after = self.read_opt_cont()?;
+                Rc::new(cont::UntilCont::new(body, after))
+            }
+            ContTag::While => {
+                let condition = self.read_opt_cont()?;
+                let body = self.read_opt_cont()?;
+                let after = self.read_opt_cont()?;
+                let running_body = self.read_bool()?;
+                Rc::new(cont::WhileCont::new(condition, body, after, running_body))
+            }
+            ContTag::IntLit => Rc::new(cont::IntLitCont::from(self.read_int()?)),
+            ContTag::Lit => Rc::new(cont::LitCont(self.read_value()?)),
+            ContTag::MultiLit => {
+                let len = self.read_varuint()? as usize;
+                let mut values = Vec::with_capacity(len.min(256));
+                for _ in 0..len {
+                    values.push(self.read_value()?);
+                }
+                Rc::new(cont::MultiLitCont(values))
+            }
+            ContTag::Shuffle => Rc::new(cont::ShuffleCont::parse(&self.read_str()?)?),
+        };
+
+        self.conts.push(cont.clone());
+        Ok(Some(cont))
+    }
+
+    fn read_word_list(&mut self) -> Result<Rc<WordList>> {
+        if self.read_bool()? {
+            let id = self.read_varuint()? as usize;
+            let list = self.lists.get(id).context("Invalid word list reference")?;
+            return Ok(list.clone());
+        }
+
+        let len = self.read_varuint()? as usize;
+        let mut items = Vec::with_capacity(len.min(256));
+        for _ in 0..len {
+            items.push(self.read_cont()?);
+        }
+
+        let list = Rc::new(WordList {
+            items,
+            positions: Vec::new(),
+        });
+        self.lists.push(list.clone());
+        Ok(list)
+    }
+
+    fn read_value(&mut self) -> Result<Rc<dyn StackValue>> {
+        anyhow::ensure!(
+            self.depth < MAX_DEPTH,
+            "Suspended value is too deep (max depth {MAX_DEPTH})"
+        );
+        self.depth += 1;
+        let res = self.read_value_impl();
+        self.depth -= 1;
+        res
+    }
+
+    fn read_value_impl(&mut self) -> Result<Rc<dyn StackValue>> {
+        let tag = self.read_u8()?;
+        let tag = ValueTag::from_u8(tag)
+            .with_context(|| format!("Unknown suspended value tag: {tag}"))?;
+
+        // NOTE: nested values are read recursively, so the arms are kept
+        // in separate functions to not bloat the stack frame
+        Ok(match tag {
+            ValueTag::Null => Stack::make_null(),
+            ValueTag::Int => Rc::new(self.read_int()?),
+            ValueTag::String => Rc::new(self.read_str()?),
+            ValueTag::Bytes => Rc::new(self.read_bytes()?.to_vec()),
+            ValueTag::Cell => Rc::new(Boc::decode(self.read_bytes()?)?),
+            ValueTag::Slice => self.read_slice()?,
+            ValueTag::Builder => self.read_builder()?,
+            ValueTag::Tuple => self.read_tuple()?,
+            ValueTag::Cont => Rc::new(self.read_cont()?),
+            ValueTag::Box => self.read_box()?,
+            ValueTag::HashMap => self.read_hashmap()?,
+            ValueTag::Atom => self.read_atom()?,
+        })
+    }
+
+    fn read_slice(&mut self) -> Result<Rc<dyn StackValue>> {
+        let cell = Boc::decode(self.read_bytes()?)?;
+        let bits_offset = self.read_varuint()? as u16;
+        let refs_offset = self.read_varuint()? as u8;
+        let bits = self.read_varuint()? as u16;
+        let refs = self.read_varuint()? as u8;
+
+        let mut range = CellSliceRange::full(cell.as_ref());
+        range.advance(bits_offset, refs_offset)?;
+        let range = range.get_prefix(bits, refs);
+        Ok(Rc::new(OwnedCellSlice::from((cell, range))))
+    }
+
+    fn read_builder(&mut self) -> Result<Rc<dyn StackValue>> {
+        let cell = Boc::decode(self.read_bytes()?)?;
+        let mut builder = CellBuilder::new();
+        builder.store_slice(CellSlice::new(cell.as_ref())?)?;
+        Ok(Rc::new(builder))
+    }
+
+    fn read_tuple(&mut self) -> Result<Rc<dyn StackValue>> {
+        let len = self.read_varuint()? as usize;
+        let mut tuple = Vec::with_capacity(len.min(256));
+        for _ in 0..len {
+            tuple.push(self.read_value()?);
+        }
+        Ok(Rc::new(tuple))
+    }
+
+    fn read_box(&mut self) -> Result<Rc<dyn StackValue>> {
+        match self.read_u8()? {
+            BOX_NEW => {}
+            BOX_REF => {
+                let id = self.read_varuint()? as usize;
+                let value = self.boxes.get(id).context("Invalid box reference")?;
+                return Ok(Rc::new(value.clone()));
+            }
+            BOX_DICTIONARY => return Ok(self.d.get_words_box().clone()),
+            _ => anyhow::bail!("Invalid box kind"),
+        }
+
+        let value = SharedBox::default();
+        self.boxes.push(value.clone());
+        value.store(self.read_value()?);
+        Ok(Rc::new(value))
+    }
+
+    fn read_hashmap(&mut self) -> Result<Rc<dyn StackValue>> {
+        let len = self.read_varuint()? as usize;
+        let mut map = None;
+        for _ in 0..len {
+            let key = HashMapTreeKey::new(self.read_value()?)?;
+            let value = self.read_value()?;
+            HashMapTreeNode::set(&mut map, &key, &value);
+        }
+        Ok(map.context("Empty suspended hashmap")?)
+    }
+
+    fn read_atom(&mut self) -> Result<Rc<dyn StackValue>> {
+        if self.read_bool()? {
+            let name = self.read_str()?;
+            Ok(Rc::new(self.atoms.create_named(name)))
+        } else {
+            // NOTE: anonymous atoms are recreated to not collide with the existing ones
+            let id = self.read_varuint()?;
+            let atoms = &mut *self.atoms;
+            let atom = self
+                .anon_atoms
+                .entry(id)
+                .or_insert_with(|| atoms.create_anon());
+            Ok(Rc::new(atom.clone()))
+        }
+    }
+
+    fn read_int(&mut self) -> Result<BigInt> {
+        Ok(BigInt::from_signed_bytes_le(self.read_bytes()?))
+    }
+
+    fn read_str(&mut self) -> Result<String> {
+        let bytes = self.read_bytes()?;
+        Ok(std::str::from_utf8(bytes)?.to_owned())
+    }
+
+    fn read_bytes(&mut self) -> Result<&[u8]> {
+        let len = self.read_varuint()? as usize;
+        anyhow::ensure!(self.data.len() >= len, "Unexpected eof");
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => anyhow::bail!("Invalid boolean value"),
+        }
+    }
+
+    fn read_varuint(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        anyhow::bail!("Invalid varuint")
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let (&byte, rest) = self.data.split_first().context("Unexpected eof")?;
+        self.data = rest;
+        Ok(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_values() {
+        let d = Dictionary::default();
+        let mut atoms = Atoms::default();
+
+        let mut value: Rc<dyn StackValue> = Stack::make_null();
+        for _ in 0..MAX_DEPTH + 1 {
+            value = Rc::new(vec![value]);
+        }
+        let cont: Cont = Rc::new(cont::LitCont(value));
+        assert!(serialize(Some(&cont), &d).is_err());
+
+        // NOTE: nested one-item tuples, the reader must not overflow the stack
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&[VERSION, ContTag::Lit as u8]);
+        for _ in 0..1_000_000 {
+            data.extend_from_slice(&[ValueTag::Tuple as u8, 1]);
+        }
+        data.push(ValueTag::Null as u8);
+        assert!(deserialize(&data, &d, &mut atoms).is_err());
+
+        let mut value: Rc<dyn StackValue> = Stack::make_null();
+        for _ in 0..100 {
+            value = Rc::new(vec![value]);
+        }
+        let cont: Cont = Rc::new(cont::LitCont(value));
+        let data = serialize(Some(&cont), &d).unwrap();
+        assert!(deserialize(&data, &d, &mut atoms).unwrap().is_some());
+    }
+
+    #[test]
+    fn malformed_sessions() {
+        let d = Dictionary::default();
+        let mut atoms = Atoms::default();
+
+        let words = [(
+            Rc::new("five ".to_owned()),
+            DictionaryEntry {
+                definition: Rc::new(cont::IntLitCont::from(5)),
+                active: false,
+            },
+        )];
+        let stack: [Rc<dyn StackValue>; 2] = [
+            Rc::new(BigInt::from(1)),
+            Rc::new(vec![Rc::new("a".to_owned()) as Rc<dyn StackValue>]),
+        ];
+        let (data, skipped) = serialize_session(&words, &stack, &d).unwrap();
+        assert!(skipped.is_empty());
+
+        let session = deserialize_session(&data, &d, &mut atoms).unwrap();
+        assert_eq!(session.words.len(), 1);
+        assert_eq!(session.stack.len(), 2);
+
+        for len in 0..data.len() {
+            assert!(deserialize_session(&data[..len], &d, &mut atoms).is_err());
+        }
+
+        // NOTE: one word, no stack items
+        let mut data = SESSION_MAGIC.to_vec();
+        data.extend_from_slice(&[VERSION, 1, 0, 0, ContTag::Lit as u8]);
+        for _ in 0..1_000_000 {
+            data.extend_from_slice(&[ValueTag::Box as u8, BOX_NEW]);
+        }
+        data.extend_from_slice(&[ValueTag::Null as u8, 0, 0]);
+        assert!(deserialize_session(&data, &d, &mut atoms).is_err());
+
+        let mut data = SESSION_MAGIC.to_vec();
+        data.extend_from_slice(&[VERSION, 0, 1]);
+        for _ in 0..1_000_000 {
+            data.extend_from_slice(&[ValueTag::HashMap as u8, 1]);
+        }
+        assert!(deserialize_session(&data, &d, &mut atoms).is_err());
+    }
+}
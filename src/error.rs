@@ -9,3 +9,63 @@ pub struct ExecutionAborted {
 #[derive(Debug, thiserror::Error)]
 #[error("Unexpected eof")]
 pub struct UnexpectedEof;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Output limit exceeded: {limit} bytes")]
+pub struct OutputLimitExceeded {
+    pub limit: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Max interpreter nesting depth exceeded: {limit} (recursive include?)")]
+pub struct InterpreterDepthExceeded {
+    pub limit: u16,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Max continuation depth exceeded: {limit} (infinite recursion?)")]
+pub struct ContinuationDepthExceeded {
+    pub limit: usize,
+}
+
+/// Same as the TVM exception 4.
+#[derive(Debug, thiserror::Error)]
+#[error("integer overflow")]
+pub struct IntegerOverflow;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Nonstandard word `{name}` is not allowed in strict mode{}", match suggestion {
+    Some(suggestion) => format!(" (use {suggestion})"),
+    None => String::new(),
+})]
+pub struct NonstandardWord {
+    pub name: String,
+    pub suggestion: Option<&'static str>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid BOC at byte {offset}{}: {reason}", match root_count {
+    Some(count) => format!(" ({count} root{})", if *count == 1 { "" } else { "s" }),
+    None => String::new(),
+})]
+pub struct InvalidBoc {
+    /// Offset of the first invalid byte (or the length of the truncated data).
+    pub offset: usize,
+    /// Number of root cells declared in the header (if it was parsed).
+    pub root_count: Option<usize>,
+    pub reason: InvalidBocReason,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum InvalidBocReason {
+    #[error("unknown magic")]
+    UnknownMagic,
+    #[error("invalid {field} in header")]
+    InvalidHeader { field: &'static str },
+    #[error("unexpected end of data (expected {expected} bytes, got {actual})")]
+    Truncated { expected: usize, actual: usize },
+    #[error("CRC mismatch (expected {expected:08x}, got {actual:08x})")]
+    CrcMismatch { expected: u32, actual: u32 },
+    #[error("invalid cells ({0})")]
+    InvalidCells(String),
+}
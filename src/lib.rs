@@ -7,20 +7,131 @@ pub use self::core::Context;
 pub mod core;
 pub mod error;
 pub mod modules;
+pub mod prelude;
+pub mod render;
 pub mod util;
 
+#[cfg(test)]
+mod testing;
+
 impl Context<'_> {
     pub fn with_basic_modules(self) -> Result<Self> {
+        self.with_basic_modules_selected(BasicModules::default())
+    }
+
+    pub fn with_basic_modules_selected(mut self, selected: BasicModules) -> Result<Self> {
         use modules::*;
-        self.with_module(BaseModule)?
-            .with_module(Arithmetic)?
-            .with_module(CellUtils)?
-            .with_module(DictUtils)?
-            .with_module(Control)?
-            .with_module(DebugUtils)?
-            .with_module(StackUtils)?
-            .with_module(StringUtils)?
-            .with_module(Crypto)?
-            .with_module(VmUtils)
+
+        // NOTE: base and control words are required by the interpreter itself,
+        // rarely used modules are initialized on the first use of their words
+        self.add_module(BaseModule)?;
+        if selected.arithmetic {
+            self.add_module(Arithmetic)?;
+        }
+        if selected.cells {
+            self.add_module(CellUtils)?;
+            self.add_module(DictUtils)?;
+        }
+        self.add_module(Control)?;
+        if selected.debug {
+            self.add_module(DebugUtils)?;
+        }
+        if selected.stack {
+            self.add_module(StackUtils)?;
+            self.add_module(ListUtils)?;
+        }
+        if selected.strings {
+            self.add_module(StringUtils)?;
+            self.add_lazy_module(JsonUtils)?;
+        }
+        #[cfg(feature = "crypto")]
+        if selected.crypto {
+            self.add_module(Crypto)?;
+            self.add_lazy_module(MultisigUtils)?;
+            #[cfg(feature = "mnemonic")]
+            self.add_lazy_module(MnemonicUtils)?;
+        }
+        #[cfg(feature = "vm")]
+        if selected.vm {
+            self.add_module(VmUtils)?;
+        }
+        if selected.cells {
+            self.add_module(AddressUtils)?;
+            self.add_lazy_module(LayoutUtils)?;
+            self.add_lazy_module(FeeUtils)?;
+            self.add_lazy_module(MsgUtils)?;
+            self.add_lazy_module(SmcInfoUtils)?;
+        }
+        #[cfg(feature = "abi")]
+        if selected.abi {
+            self.add_lazy_module(AbiUtils)?;
+        }
+
+        Ok(self)
+    }
+
+    pub fn with_cmd_args(self, args: Vec<String>) -> Result<Self> {
+        self.with_module(modules::CmdArgsUtils::new(args))
+    }
+}
+
+/// A selection of module groups installed by [`Context::with_basic_modules_selected`].
+///
+/// All groups are enabled by default. Note that the standard `Fift.fif`
+/// preamble requires all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicModules {
+    pub arithmetic: bool,
+    /// Cells, dictionaries, addresses and data layouts.
+    pub cells: bool,
+    pub debug: bool,
+    pub stack: bool,
+    pub strings: bool,
+    #[cfg(feature = "crypto")]
+    pub crypto: bool,
+    #[cfg(feature = "vm")]
+    pub vm: bool,
+    #[cfg(feature = "abi")]
+    pub abi: bool,
+}
+
+impl BasicModules {
+    /// Only the base and control words.
+    pub const fn none() -> Self {
+        Self {
+            arithmetic: false,
+            cells: false,
+            debug: false,
+            stack: false,
+            strings: false,
+            #[cfg(feature = "crypto")]
+            crypto: false,
+            #[cfg(feature = "vm")]
+            vm: false,
+            #[cfg(feature = "abi")]
+            abi: false,
+        }
+    }
+
+    pub const fn all() -> Self {
+        Self {
+            arithmetic: true,
+            cells: true,
+            debug: true,
+            stack: true,
+            strings: true,
+            #[cfg(feature = "crypto")]
+            crypto: true,
+            #[cfg(feature = "vm")]
+            vm: true,
+            #[cfg(feature = "abi")]
+            abi: true,
+        }
+    }
+}
+
+impl Default for BasicModules {
+    fn default() -> Self {
+        Self::all()
     }
 }
@@ -0,0 +1,521 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use everscale_types::cell::{MAX_BIT_LEN, MAX_REF_COUNT};
+use everscale_types::prelude::*;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use sha2::Digest;
+
+use super::json_utils::{Json, JsonParser};
+use crate::core::*;
+use crate::util::*;
+
+pub struct AbiUtils;
+
+#[fift_module]
+impl AbiUtils {
+    /// Computes input and output ids of the ABI function.
+    #[cmd(name = "abi-fnid", stack, effect = "S abi -- in out", nonstandard)]
+    fn interpret_abi_function_id(stack: &mut Stack) -> Result<()> {
+        let abi = Abi::parse(&stack.pop_string()?)?;
+        let function = abi.function(&stack.pop_string()?)?;
+        let id = function.id(abi.major);
+        stack.push_int(id & !OUTPUT_ID_BIT)?;
+        stack.push_int(id | OUTPUT_ID_BIT)
+    }
+
+    /// Encodes an internal message body calling the ABI function.
+    #[cmd(name = "abi-encode", stack, effect = "t S abi -- c", nonstandard)]
+    fn interpret_abi_encode(stack: &mut Stack) -> Result<()> {
+        let abi = Abi::parse(&stack.pop_string()?)?;
+        let function = abi.function(&stack.pop_string()?)?;
+        let args = stack.pop_tuple()?;
+
+        let mut fragments = Vec::new();
+        encode_params(&function.inputs, &args, &mut fragments)
+            .with_context(|| format!("Failed to encode `{}` inputs", function.name))?;
+
+        let id = function.id(abi.major) & !OUTPUT_ID_BIT;
+        let mut header = CellBuilder::new();
+        header.store_u32(id)?;
+
+        stack.push(pack_fragments(header, fragments)?)
+    }
+
+    /// Decodes an output message body of the ABI function into a tuple.
+    #[cmd(name = "abi-decode", stack, effect = "s S abi -- t", nonstandard)]
+    fn interpret_abi_decode(stack: &mut Stack) -> Result<()> {
+        let abi = Abi::parse(&stack.pop_string()?)?;
+        let function = abi.function(&stack.pop_string()?)?;
+        let cs = stack.pop_slice()?;
+
+        let mut reader = FragmentReader::new(cs.as_ref());
+        let id = reader.load_uint(32)?;
+        let expected = (function.id(abi.major) | OUTPUT_ID_BIT) as u64;
+        anyhow::ensure!(
+            id == expected,
+            "Function id mismatch: expected {expected:08x}, found {id:08x}"
+        );
+
+        let values = decode_params(&function.outputs, &mut reader)
+            .with_context(|| format!("Failed to decode `{}` outputs", function.name))?;
+        reader.finish()?;
+
+        stack.push(values)
+    }
+}
+
+const OUTPUT_ID_BIT: u32 = 0x8000_0000;
+
+struct Abi {
+    major: u8,
+    functions: Vec<AbiFunction>,
+}
+
+impl Abi {
+    fn parse(s: &str) -> Result<Self> {
+        let json = JsonParser::parse(s).context("Invalid ABI JSON")?;
+
+        let major = match (json.get("ABI version"), json.get("version")) {
+            (Some(Json::Number(n)), _) => n.parse().context("Invalid ABI version")?,
+            (_, Some(Json::String(v))) => {
+                let major = v.split('.').next().unwrap_or_default();
+                major.parse().context("Invalid ABI version")?
+            }
+            _ => anyhow::bail!("ABI version not found"),
+        };
+        anyhow::ensure!(major == 2, "Unsupported ABI version: {major}");
+
+        let functions = match json.get("functions") {
+            Some(Json::Array(items)) => items
+                .iter()
+                .map(AbiFunction::parse)
+                .collect::<Result<_>>()?,
+            _ => anyhow::bail!("ABI functions not found"),
+        };
+
+        Ok(Self { major, functions })
+    }
+
+    fn function(&self, name: &str) -> Result<&AbiFunction> {
+        self.functions
+            .iter()
+            .find(|f| f.name == name)
+            .with_context(|| format!("ABI function `{name}` not found"))
+    }
+}
+
+struct AbiFunction {
+    name: String,
+    inputs: Vec<AbiParam>,
+    outputs: Vec<AbiParam>,
+    explicit_id: Option<u32>,
+}
+
+impl AbiFunction {
+    fn parse(json: &Json) -> Result<Self> {
+        let name = json
+            .get_str("name")
+            .context("ABI function name not found")?;
+
+        let explicit_id = match json.get_str("id") {
+            Some(id) => {
+                let id = id.strip_prefix("0x").unwrap_or(id);
+                Some(u32::from_str_radix(id, 16).context("Invalid ABI function id")?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            inputs: AbiParam::parse_list(json.get("inputs"))?,
+            outputs: AbiParam::parse_list(json.get("outputs"))?,
+            explicit_id,
+        })
+    }
+
+    fn id(&self, major: u8) -> u32 {
+        if let Some(id) = self.explicit_id {
+            return id;
+        }
+
+        let signature = format!(
+            "{}({})({})v{major}",
+            self.name,
+            AbiParam::signature_list(&self.inputs),
+            AbiParam::signature_list(&self.outputs),
+        );
+        let hash = sha2::Sha256::digest(signature.as_bytes());
+        u32::from_be_bytes(hash[..4].try_into().unwrap())
+    }
+}
+
+struct AbiParam {
+    name: String,
+    ty: AbiType,
+}
+
+impl AbiParam {
+    fn parse_list(json: Option<&Json>) -> Result<Vec<Self>> {
+        match json {
+            Some(Json::Array(items)) => items.iter().map(Self::parse).collect(),
+            None => Ok(Vec::new()),
+            Some(_) => anyhow::bail!("Expected an array of ABI params"),
+        }
+    }
+
+    fn parse(json: &Json) -> Result<Self> {
+        let name = json.get_str("name").context("ABI param name not found")?;
+        let ty = json.get_str("type").context("ABI param type not found")?;
+
+        let ty = match ty {
+            "bool" => AbiType::Bool,
+            "address" => AbiType::Address,
+            "cell" => AbiType::Cell,
+            "bytes" => AbiType::Bytes,
+            "string" => AbiType::String,
+            "tuple" => AbiType::Tuple(Self::parse_list(json.get("components"))?),
+            _ => {
+                let parse_bits = |bits: &str, max: u16| -> Result<u16> {
+                    let bits = bits.parse::<u16>().ok().filter(|&b| b > 0 && b <= max);
+                    bits.with_context(|| format!("Invalid ABI type `{ty}`"))
+                };
+
+                if let Some(bits) = ty.strip_prefix("uint") {
+                    AbiType::Uint(parse_bits(bits, 256)?)
+                } else if let Some(bits) = ty.strip_prefix("int") {
+                    AbiType::Int(parse_bits(bits, 256)?)
+                } else if let Some(size) = ty.strip_prefix("varuint") {
+                    AbiType::VarUint(parse_var_size(ty, size)?)
+                } else if let Some(size) = ty.strip_prefix("varint") {
+                    AbiType::VarInt(parse_var_size(ty, size)?)
+                } else if let Some(len) = ty.strip_prefix("fixedbytes") {
+                    AbiType::FixedBytes(parse_bits(len, 32)? as usize)
+                } else {
+                    anyhow::bail!("Unsupported ABI type `{ty}`")
+                }
+            }
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            ty,
+        })
+    }
+
+    fn signature_list(params: &[Self]) -> String {
+        let mut result = String::new();
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                result.push(',');
+            }
+            param.ty.write_signature(&mut result);
+        }
+        result
+    }
+}
+
+fn parse_var_size(ty: &str, size: &str) -> Result<u8> {
+    match size {
+        "16" => Ok(16),
+        "32" => Ok(32),
+        _ => anyhow::bail!("Invalid ABI type `{ty}`"),
+    }
+}
+
+enum AbiType {
+    Uint(u16),
+    Int(u16),
+    VarUint(u8),
+    VarInt(u8),
+    Bool,
+    Address,
+    Cell,
+    Bytes,
+    FixedBytes(usize),
+    String,
+    Tuple(Vec<AbiParam>),
+}
+
+impl AbiType {
+    fn write_signature(&self, s: &mut String) {
+        use std::fmt::Write;
+
+        _ = match self {
+            Self::Uint(bits) => write!(s, "uint{bits}"),
+            Self::Int(bits) => write!(s, "int{bits}"),
+            Self::VarUint(size) => write!(s, "varuint{size}"),
+            Self::VarInt(size) => write!(s, "varint{size}"),
+            Self::Bool => write!(s, "bool"),
+            Self::Address => write!(s, "address"),
+            Self::Cell => write!(s, "cell"),
+            Self::Bytes => write!(s, "bytes"),
+            Self::FixedBytes(len) => write!(s, "fixedbytes{len}"),
+            Self::String => write!(s, "string"),
+            Self::Tuple(params) => write!(s, "({})", AbiParam::signature_list(params)),
+        };
+    }
+}
+
+fn encode_params(
+    params: &[AbiParam],
+    values: &[Rc<dyn StackValue>],
+    fragments: &mut Vec<CellBuilder>,
+) -> Result<()> {
+    anyhow::ensure!(
+        params.len() == values.len(),
+        "Expected {} values, found {}",
+        params.len(),
+        values.len()
+    );
+
+    for (param, value) in params.iter().zip(values) {
+        encode_value(&param.ty, value.as_ref(), fragments)
+            .with_context(|| format!("Invalid value for `{}`", param.name))?;
+    }
+    Ok(())
+}
+
+fn encode_value(
+    ty: &AbiType,
+    value: &dyn StackValue,
+    fragments: &mut Vec<CellBuilder>,
+) -> Result<()> {
+    let mut b = CellBuilder::new();
+    match ty {
+        AbiType::Uint(bits) => store_int_to_builder(&mut b, value.as_int()?, *bits, false)?,
+        AbiType::Int(bits) => store_int_to_builder(&mut b, value.as_int()?, *bits, true)?,
+        AbiType::VarUint(size) | AbiType::VarInt(size) => {
+            let signed = matches!(ty, AbiType::VarInt(_));
+            let int = value.as_int()?;
+            let bytes = (bitsize(int, signed) as usize + 7) / 8;
+            anyhow::ensure!(bytes < *size as usize, "Integer is too big");
+
+            let len_bits = var_len_bits(*size);
+            b.store_uint(bytes as u64, len_bits)?;
+            store_int_to_builder(&mut b, int, bytes as u16 * 8, signed)?;
+        }
+        AbiType::Bool => b.store_bit(!value.as_int()?.is_zero())?,
+        AbiType::Address => b.store_slice(value.as_slice()?)?,
+        AbiType::Cell => b.store_reference(value.as_cell()?.clone())?,
+        AbiType::Bytes => b.store_reference(bytes_to_cell(value.as_bytes()?)?)?,
+        AbiType::String => b.store_reference(bytes_to_cell(value.as_string()?.as_bytes())?)?,
+        AbiType::FixedBytes(len) => {
+            let bytes = value.as_bytes()?;
+            anyhow::ensure!(bytes.len() == *len, "Expected {len} bytes");
+            b.store_raw(bytes, *len as u16 * 8)?;
+        }
+        AbiType::Tuple(params) => return encode_params(params, value.as_tuple()?, fragments),
+    }
+    fragments.push(b);
+    Ok(())
+}
+
+fn decode_params(params: &[AbiParam], reader: &mut FragmentReader) -> Result<StackTuple> {
+    let mut values = Vec::with_capacity(params.len());
+    for param in params {
+        let value = decode_value(&param.ty, reader)
+            .with_context(|| format!("Invalid value for `{}`", param.name))?;
+        values.push(value);
+    }
+    Ok(values)
+}
+
+fn decode_value(ty: &AbiType, reader: &mut FragmentReader) -> Result<Rc<dyn StackValue>> {
+    Ok(match ty {
+        AbiType::Uint(bits) => Rc::new(reader.load_int(*bits, false)?),
+        AbiType::Int(bits) => Rc::new(reader.load_int(*bits, true)?),
+        AbiType::VarUint(size) | AbiType::VarInt(size) => {
+            let signed = matches!(ty, AbiType::VarInt(_));
+            let bytes = reader.load_uint(var_len_bits(*size))? as u16;
+            Rc::new(reader.load_int(bytes * 8, signed)?)
+        }
+        AbiType::Bool => Rc::new(-BigInt::from(reader.load_uint(1)?)),
+        AbiType::Address => Rc::new(reader.load_address()?),
+        AbiType::Cell => Rc::new(reader.load_reference()?),
+        AbiType::Bytes => Rc::new(cell_to_bytes(reader.load_reference()?)?),
+        AbiType::String => Rc::new(String::from_utf8(cell_to_bytes(reader.load_reference()?)?)?),
+        AbiType::FixedBytes(len) => {
+            let int = reader.load_int(*len as u16 * 8, false)?;
+            let mut bytes = int.to_bytes_be().1;
+            let mut result = vec![0; len.saturating_sub(bytes.len())];
+            result.append(&mut bytes);
+            Rc::new(result)
+        }
+        AbiType::Tuple(params) => Rc::new(decode_params(params, reader)?),
+    })
+}
+
+fn var_len_bits(size: u8) -> u16 {
+    (u8::BITS - (size - 1).leading_zeros()) as u16
+}
+
+// NOTE: one reference is always reserved for the next cell in the chain
+const MAX_DATA_REFS: u8 = MAX_REF_COUNT as u8 - 1;
+
+fn pack_fragments(header: CellBuilder, fragments: Vec<CellBuilder>) -> Result<Cell> {
+    let mut cells = vec![header];
+    for fragment in fragments {
+        let current = cells.last_mut().unwrap();
+        let fits = current.bit_len() + fragment.bit_len() <= MAX_BIT_LEN
+            && current.references().len() + fragment.references().len() <= MAX_DATA_REFS as usize;
+        if !fits {
+            cells.push(CellBuilder::new());
+        }
+
+        let current = cells.last_mut().unwrap();
+        current.store_slice(fragment.as_data_slice())?;
+        for cell in fragment.references() {
+            current.store_reference(cell.clone())?;
+        }
+    }
+
+    let mut next = None::<Cell>;
+    while let Some(mut b) = cells.pop() {
+        if let Some(next) = next.take() {
+            b.store_reference(next)?;
+        }
+        next = Some(b.build()?);
+    }
+    Ok(next.unwrap())
+}
+
+fn bytes_to_cell(bytes: &[u8]) -> Result<Cell> {
+    const CHUNK: usize = 127;
+
+    let mut next = None::<Cell>;
+    for chunk in bytes.chunks(CHUNK).rev() {
+        let mut b = CellBuilder::new();
+        b.store_raw(chunk, chunk.len() as u16 * 8)?;
+        if let Some(next) = next.take() {
+            b.store_reference(next)?;
+        }
+        next = Some(b.build()?);
+    }
+
+    match next {
+        Some(cell) => Ok(cell),
+        None => Ok(Cell::empty_cell()),
+    }
+}
+
+fn cell_to_bytes(mut cell: Cell) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    loop {
+        let mut cs = CellSlice::new(cell.as_ref())?;
+        let bits = cs.remaining_bits();
+        anyhow::ensure!(bits % 8 == 0, "Invalid bytes cell");
+
+        let mut buffer = [0u8; 128];
+        result.extend_from_slice(cs.load_raw(&mut buffer, bits)?);
+
+        let next = match cs.remaining_refs() {
+            0 => return Ok(result),
+            _ => cs.load_reference_cloned()?,
+        };
+        cell = next;
+    }
+}
+
+/// Reads fragments of a chained cell, following the layout of `pack_fragments`.
+struct FragmentReader {
+    cell: Cell,
+    range: CellSliceRange,
+    data_refs: u8,
+}
+
+impl FragmentReader {
+    fn new(cs: &OwnedCellSlice) -> Self {
+        Self {
+            cell: cs.cell().clone(),
+            range: cs.range(),
+            data_refs: 0,
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        anyhow::ensure!(
+            self.range.is_data_empty() && self.range.is_refs_empty(),
+            "Unexpected trailing data"
+        );
+        Ok(())
+    }
+
+    fn ensure_bits(&mut self) -> Result<()> {
+        if self.range.is_data_empty() {
+            self.next_cell()?;
+        }
+        Ok(())
+    }
+
+    fn next_cell(&mut self) -> Result<()> {
+        let mut cs = self.range.apply(&self.cell)?;
+        anyhow::ensure!(cs.remaining_refs() == 1, "Unexpected end of the cell chain");
+        let next = cs.load_reference_cloned()?;
+        self.range = CellSliceRange::full(next.as_ref());
+        self.cell = next;
+        self.data_refs = 0;
+        Ok(())
+    }
+
+    fn with_slice<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut CellSlice<'_>) -> Result<T>,
+    {
+        let mut cs = self.range.apply(&self.cell)?;
+        let result = f(&mut cs)?;
+        self.range = cs.range();
+        Ok(result)
+    }
+
+    fn load_uint(&mut self, bits: u16) -> Result<u64> {
+        self.ensure_bits()?;
+        self.with_slice(|cs| Ok(cs.load_uint(bits)?))
+    }
+
+    fn load_int(&mut self, bits: u16, signed: bool) -> Result<BigInt> {
+        if bits == 0 {
+            return Ok(BigInt::default());
+        }
+        self.ensure_bits()?;
+        self.with_slice(|cs| Ok(load_int_from_slice(cs, bits, signed)?))
+    }
+
+    fn load_address(&mut self) -> Result<OwnedCellSlice> {
+        self.ensure_bits()?;
+        let cell = self.cell.clone();
+        self.with_slice(|cs| {
+            let bits = msg_address_bit_len(cs)?;
+            let range = cs.range().get_prefix(bits, 0);
+            cs.advance(bits, 0)?;
+            Ok(OwnedCellSlice::from((cell, range)))
+        })
+    }
+
+    fn load_reference(&mut self) -> Result<Cell> {
+        if self.data_refs >= MAX_DATA_REFS {
+            self.next_cell()?;
+        }
+        self.data_refs += 1;
+        self.with_slice(|cs| Ok(cs.load_reference_cloned()?))
+    }
+}
+
+// === JSON ===
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(items) => items.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use everscale_types::models::{Anycast, IntAddr};
+use everscale_types::num::{SplitDepth, Uint9};
+use everscale_types::prelude::*;
+use num_bigint::{BigInt, Sign};
+use num_traits::One;
+
+use crate::core::*;
+use crate::util::*;
+
+pub struct AddressUtils;
+
+#[fift_module]
+impl AddressUtils {
+    #[cmd(
+        name = "addr>slice",
+        stack,
+        args(with_anycast = false),
+        effect = "wc x -- s",
+        nonstandard
+    )]
+    #[cmd(
+        name = "anyaddr>slice",
+        stack,
+        args(with_anycast = true),
+        effect = "wc x a -- s",
+        nonstandard
+    )]
+    fn interpret_addr_to_slice(stack: &mut Stack, with_anycast: bool) -> Result<()> {
+        let anycast = if with_anycast {
+            pop_anycast(stack)?
+        } else {
+            None
+        };
+        let mut addr = stack.pop_address()?;
+        set_anycast(&mut addr, anycast);
+
+        let mut builder = CellBuilder::new();
+        addr.store_into(&mut builder, &mut Cell::empty_context())?;
+        stack.push(OwnedCellSlice::new(builder.build()?))
+    }
+
+    #[cmd(
+        name = "slice>addr",
+        stack,
+        args(with_anycast = false),
+        effect = "s -- wc x",
+        nonstandard
+    )]
+    #[cmd(
+        name = "slice>anyaddr",
+        stack,
+        args(with_anycast = true),
+        effect = "s -- wc x a",
+        nonstandard
+    )]
+    fn interpret_slice_to_addr(stack: &mut Stack, with_anycast: bool) -> Result<()> {
+        let cs_raw = stack.pop_slice()?;
+        let mut cs = cs_raw.apply()?;
+        let addr = IntAddr::load_from(&mut cs)?;
+        anyhow::ensure!(
+            cs.is_data_empty() && cs.is_refs_empty(),
+            "Unexpected data after the address"
+        );
+        push_int_addr(stack, &addr, with_anycast)
+    }
+
+    #[cmd(
+        name = "addr@",
+        stack,
+        args(advance = false, quiet = false),
+        nonstandard
+    )]
+    #[cmd(
+        name = "addr@+",
+        stack,
+        args(advance = true, quiet = false),
+        nonstandard
+    )]
+    #[cmd(
+        name = "addr@?",
+        stack,
+        args(advance = false, quiet = true),
+        nonstandard
+    )]
+    #[cmd(
+        name = "addr@?+",
+        stack,
+        args(advance = true, quiet = true),
+        nonstandard
+    )]
+    fn interpret_load_addr(stack: &mut Stack, advance: bool, quiet: bool) -> Result<()> {
+        load_from_slice(stack, advance, quiet, |stack, cs| {
+            let addr = IntAddr::load_from(cs)?;
+            push_int_addr(stack, &addr, false)
+        })
+    }
+
+    #[cmd(
+        name = "extaddr@",
+        stack,
+        args(advance = false, quiet = false),
+        nonstandard
+    )]
+    #[cmd(
+        name = "extaddr@+",
+        stack,
+        args(advance = true, quiet = false),
+        nonstandard
+    )]
+    #[cmd(
+        name = "extaddr@?",
+        stack,
+        args(advance = false, quiet = true),
+        nonstandard
+    )]
+    #[cmd(
+        name = "extaddr@?+",
+        stack,
+        args(advance = true, quiet = true),
+        nonstandard
+    )]
+    fn interpret_load_ext_addr(stack: &mut Stack, advance: bool, quiet: bool) -> Result<()> {
+        load_from_slice(stack, advance, quiet, |stack, cs| {
+            match cs.load_small_uint(2)? {
+                // addr_none$00
+                0b00 => stack.push_null(),
+                // addr_extern$01 len:(## 9) external_address:(bits len)
+                0b01 => {
+                    let len = cs.load_uint(Uint9::BITS)? as u16;
+                    let mut data = [0u8; 64];
+                    let data = cs.load_raw(&mut data, len)?;
+
+                    let mut builder = CellBuilder::new();
+                    builder.store_raw(data, len)?;
+                    stack.push(OwnedCellSlice::new(builder.build()?))
+                }
+                _ => anyhow::bail!("Expected an external address"),
+            }
+        })
+    }
+
+    #[cmd(name = "extaddr>slice", stack, effect = "s|null -- s", nonstandard)]
+    fn interpret_ext_addr_to_slice(stack: &mut Stack) -> Result<()> {
+        let data = stack.pop()?;
+
+        let mut builder = CellBuilder::new();
+        if data.is_null() {
+            builder.store_small_uint(0b00, 2)?;
+        } else {
+            let cs = data.as_slice()?;
+            let len = Uint9::new(cs.remaining_bits());
+            anyhow::ensure!(len.is_valid(), "External address must fit into 511 bits");
+            builder.store_small_uint(0b01, 2)?;
+            builder.store_uint(len.into_inner() as u64, Uint9::BITS)?;
+            builder.store_slice_data(cs)?;
+        }
+        stack.push(OwnedCellSlice::new(builder.build()?))
+    }
+
+    #[cmd(
+        name = "$>vaddr",
+        stack,
+        args(quiet = false),
+        effect = "S -- wc s",
+        nonstandard
+    )]
+    #[cmd(
+        name = "$>vaddr?",
+        stack,
+        args(quiet = true),
+        effect = "S -- wc s -1 or 0",
+        nonstandard
+    )]
+    fn interpret_parse_var_addr(stack: &mut Stack, quiet: bool) -> Result<()> {
+        fn parse_var_addr(s: &str) -> Result<(i32, Cell)> {
+            let (workchain, address) = s.split_once(':').context("Expected `wc:address`")?;
+            let workchain = workchain.parse::<i32>().context("Invalid workchain")?;
+            let builder = decode_hex_bitstring(address)?;
+            anyhow::ensure!(
+                Uint9::new(builder.bit_len()).is_valid(),
+                "Variable-length address must fit into 511 bits"
+            );
+            Ok((workchain, builder.build()?))
+        }
+
+        let string = stack.pop_string()?;
+        match parse_var_addr(&string) {
+            Ok((workchain, address)) => {
+                stack.push_int(workchain)?;
+                stack.push(OwnedCellSlice::new(address))?;
+                if quiet {
+                    stack.push_bool(true)?;
+                }
+                Ok(())
+            }
+            Err(_) if quiet => stack.push_bool(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cmd(name = "vaddr>$", stack, effect = "wc x -- S", nonstandard)]
+    fn interpret_format_var_addr(stack: &mut Stack) -> Result<()> {
+        let string = match stack.pop_address()? {
+            IntAddr::Std(addr) => addr.to_string(),
+            IntAddr::Var(addr) => {
+                let mut builder = CellBuilder::new();
+                builder.store_raw(&addr.address, addr.address_len.into_inner())?;
+                let cell = builder.build()?;
+                format!(
+                    "{}:{}",
+                    addr.workchain,
+                    encode_hex_bitstring(&cell.as_ref().as_slice()?)?
+                )
+            }
+        };
+        stack.push(string)
+    }
+
+    #[cmd(name = "addr+", stack, effect = "wc x n -- wc x'", nonstandard)]
+    fn interpret_addr_add(stack: &mut Stack) -> Result<()> {
+        let n = stack.pop_int()?;
+        let x = stack.pop_int()?;
+        let wc = stack.pop_smallint_signed_range(-0x80, 0x7f)?;
+
+        let modulo = BigInt::one() << 256;
+        let mut x: BigInt = (x.as_ref() + n.as_ref()) % &modulo;
+        if x.sign() == Sign::Minus {
+            x += modulo;
+        }
+
+        stack.push_int(wc)?;
+        stack.push(x)
+    }
+
+    #[cmd(
+        name = "addr=",
+        stack,
+        args(cmp = false),
+        effect = "wc x wc' x' -- ?",
+        nonstandard
+    )]
+    #[cmd(
+        name = "addrcmp",
+        stack,
+        args(cmp = true),
+        effect = "wc x wc' x' -- -1 or 0 or 1",
+        nonstandard
+    )]
+    fn interpret_addr_cmp(stack: &mut Stack, cmp: bool) -> Result<()> {
+        let rhs = stack.pop_address()?;
+        let lhs = stack.pop_address()?;
+        if cmp {
+            stack.push_int(lhs.cmp(&rhs) as i8)
+        } else {
+            stack.push_bool(lhs == rhs)
+        }
+    }
+
+    #[cmd(
+        name = "anycast-rewrite",
+        stack,
+        effect = "wc x a -- wc x'",
+        nonstandard
+    )]
+    fn interpret_anycast_rewrite(stack: &mut Stack) -> Result<()> {
+        let anycast = pop_anycast(stack)?;
+        let mut addr = stack.pop_address()?;
+        if let Some(anycast) = anycast {
+            let depth = anycast.depth.into_bit_len();
+            let prefix = &anycast.rewrite_prefix;
+            match &mut addr {
+                IntAddr::Std(addr) => rewrite_prefix(addr.address.as_mut_slice(), prefix, depth),
+                IntAddr::Var(addr) => {
+                    anyhow::ensure!(
+                        addr.address_len.into_inner() >= depth,
+                        "Anycast prefix is longer than the address"
+                    );
+                    rewrite_prefix(&mut addr.address, prefix, depth)
+                }
+            }
+        }
+        stack.push_address(&addr)
+    }
+}
+
+fn load_from_slice<F>(stack: &mut Stack, advance: bool, quiet: bool, f: F) -> Result<()>
+where
+    F: FnOnce(&mut Stack, &mut CellSlice<'_>) -> Result<()>,
+{
+    let mut cs_raw = stack.pop_slice()?;
+    let mut cs = cs_raw.apply()?;
+
+    let depth = stack.depth();
+    let res = f(stack, &mut cs);
+    let is_ok = res.is_ok();
+
+    match res {
+        Ok(()) => {
+            if advance {
+                let range = cs.range();
+                Rc::make_mut(&mut cs_raw).set_range(range);
+            }
+        }
+        Err(e) if !quiet => return Err(e),
+        Err(_) => {
+            while stack.depth() > depth {
+                stack.pop()?;
+            }
+        }
+    }
+
+    if advance {
+        stack.push_raw(cs_raw)?;
+    }
+
+    if quiet {
+        stack.push_bool(is_ok)?;
+    }
+    Ok(())
+}
+
+fn push_int_addr(stack: &mut Stack, addr: &IntAddr, with_anycast: bool) -> Result<()> {
+    let anycast = addr.anycast();
+    anyhow::ensure!(
+        with_anycast || anycast.is_none(),
+        "Unexpected anycast info in address"
+    );
+
+    stack.push_address(addr)?;
+    if with_anycast {
+        match anycast {
+            Some(anycast) => {
+                let mut builder = CellBuilder::new();
+                builder.store_raw(&anycast.rewrite_prefix, anycast.depth.into_bit_len())?;
+                stack.push(OwnedCellSlice::new(builder.build()?))?;
+            }
+            None => stack.push_null()?,
+        }
+    }
+    Ok(())
+}
+
+fn pop_anycast(stack: &mut Stack) -> Result<Option<Box<Anycast>>> {
+    let value = stack.pop()?;
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    let cs = value.as_slice()?;
+    let bits = cs.remaining_bits();
+    let depth = SplitDepth::from_bit_len(bits).context("Invalid anycast prefix length")?;
+
+    let mut rewrite_prefix = vec![0; (bits as usize + 7) / 8];
+    cs.get_raw(0, &mut rewrite_prefix, bits)?;
+
+    Ok(Some(Box::new(Anycast {
+        depth,
+        rewrite_prefix,
+    })))
+}
+
+fn set_anycast(addr: &mut IntAddr, anycast: Option<Box<Anycast>>) {
+    match addr {
+        IntAddr::Std(addr) => addr.anycast = anycast,
+        IntAddr::Var(addr) => addr.anycast = anycast,
+    }
+}
+
+fn rewrite_prefix(target: &mut [u8], prefix: &[u8], bits: u16) {
+    for i in 0..bits as usize {
+        let mask = 0x80 >> (i % 8);
+        let byte = &mut target[i / 8];
+        if prefix[i / 8] & mask != 0 {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use anyhow::Result;
 
-use fift::core::*;
+use crate::core::*;
 
 pub struct CmdArgsUtils {
     name: Rc<dyn StackValue>,
@@ -1,11 +1,13 @@
 use std::rc::Rc;
+use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use num_bigint::{BigInt, Sign};
 use num_integer::Integer;
 use num_traits::{One, Signed, Zero};
 
 use crate::core::*;
+use crate::error::IntegerOverflow;
 use crate::util::bitsize;
 
 pub struct Arithmetic;
@@ -14,16 +16,15 @@ pub struct Arithmetic;
 impl Arithmetic {
     #[init]
     fn init(&self, d: &mut Dictionary) -> Result<()> {
-        let mut make_int_lit =
-            |name: &str, value: i32| d.define_word(name, Rc::new(cont::IntLitCont::from(value)));
-
-        make_int_lit("false ", 0)?;
-        make_int_lit("true ", -1)?;
-        make_int_lit("0 ", 0)?;
-        make_int_lit("1 ", 1)?;
-        make_int_lit("2 ", 2)?;
-        make_int_lit("-1 ", -1)?;
-        make_int_lit("bl ", 32)
+        crate::define_int_constants!(d, {
+            "false" => 0,
+            "true" => -1,
+            "0" => 0,
+            "1" => 1,
+            "2" => 2,
+            "-1" => -1,
+            "bl" => 32,
+        })
     }
 
     // === Basic ===
@@ -287,6 +288,149 @@ impl Arithmetic {
         let bits = bitsize(&x, signed);
         stack.push_bool(bits <= y)
     }
+
+    // === TVM arithmetic ===
+
+    /// Same as the plain operation, but fails with TVM's integer overflow
+    /// if an operand or the result does not fit into 257 bits.
+    #[cmd(name = "+c", stack, args(op = CheckedOp::Add), effect = "x y -- x+y", nonstandard)]
+    #[cmd(name = "-c", stack, args(op = CheckedOp::Sub), effect = "x y -- x-y", nonstandard)]
+    #[cmd(name = "*c", stack, args(op = CheckedOp::Mul), effect = "x y -- x*y", nonstandard)]
+    fn interpret_checked_op(stack: &mut Stack, op: CheckedOp) -> Result<()> {
+        let y = stack.pop_int()?;
+        let mut x = stack.pop_int()?;
+        check_int257(&x)?;
+        check_int257(&y)?;
+        {
+            let x = Rc::make_mut(&mut x);
+            match op {
+                CheckedOp::Add => *x += y.as_ref(),
+                CheckedOp::Sub => *x -= y.as_ref(),
+                CheckedOp::Mul => *x *= y.as_ref(),
+            }
+            check_int257(x)?;
+        }
+        stack.push_raw(x)
+    }
+
+    #[cmd(name = "negatec", stack, effect = "x -- -x", nonstandard)]
+    fn interpret_checked_negate(stack: &mut Stack) -> Result<()> {
+        let mut x = stack.pop_int()?;
+        {
+            let x = Rc::make_mut(&mut x);
+            check_int257(x)?;
+            *x = -std::mem::take(x);
+            check_int257(x)?;
+        }
+        stack.push_raw(x)
+    }
+
+    /// Same as TVM `FITSX`/`UFITSX`: keeps the value if it fits into `y` bits.
+    #[cmd(
+        name = "fits!",
+        stack,
+        args(signed = true),
+        effect = "x y -- x",
+        nonstandard
+    )]
+    #[cmd(
+        name = "ufits!",
+        stack,
+        args(signed = false),
+        effect = "x y -- x",
+        nonstandard
+    )]
+    fn interpret_fits_checked(stack: &mut Stack, signed: bool) -> Result<()> {
+        let y = stack.pop_smallint_range(0, 1023)? as u16;
+        let x = stack.pop_int()?;
+        if (!signed && x.sign() == Sign::Minus) || bitsize(&x, signed) > y {
+            anyhow::bail!(IntegerOverflow);
+        }
+        stack.push_raw(x)
+    }
+
+    // === Rationals ===
+
+    #[cmd(name = "q+", stack, args(op = RationalOp::Add), effect = "q q' -- q''", nonstandard)]
+    #[cmd(name = "q-", stack, args(op = RationalOp::Sub), effect = "q q' -- q''", nonstandard)]
+    #[cmd(name = "q*", stack, args(op = RationalOp::Mul), effect = "q q' -- q''", nonstandard)]
+    #[cmd(name = "q/", stack, args(op = RationalOp::Div), effect = "q q' -- q''", nonstandard)]
+    fn interpret_rational_op(stack: &mut Stack, op: RationalOp) -> Result<()> {
+        let y = stack.pop_rational()?;
+        let x = stack.pop_rational()?;
+        let (xn, xd) = (x.numer(), x.denom());
+        let (yn, yd) = (y.numer(), y.denom());
+        let (numer, denom) = match op {
+            RationalOp::Add => (xn * yd + yn * xd, xd * yd),
+            RationalOp::Sub => (xn * yd - yn * xd, xd * yd),
+            RationalOp::Mul => (xn * yn, xd * yd),
+            RationalOp::Div => (xn * yd, xd * yn),
+        };
+        stack.push(Rational::new(numer, denom)?)
+    }
+
+    #[cmd(name = "qfloor", stack, args(r = Rounding::Floor), effect = "q -- x", nonstandard)]
+    #[cmd(name = "qround", stack, args(r = Rounding::Nearest), effect = "q -- x", nonstandard)]
+    fn interpret_rational_round(stack: &mut Stack, r: Rounding) -> Result<()> {
+        let x = stack.pop_rational()?;
+        stack.push(divmod(x.numer(), x.denom(), r)?.0)
+    }
+
+    #[cmd(name = "q>$", stack, effect = "q -- S", nonstandard)]
+    fn interpret_rational_to_string(stack: &mut Stack) -> Result<()> {
+        let x = stack.pop_rational()?;
+        stack.push(x.to_string())
+    }
+
+    /// Parses `a`, `a/b` or a decimal fraction `a.b`.
+    #[cmd(name = "$>q", stack, effect = "S -- q", nonstandard)]
+    fn interpret_string_to_rational(stack: &mut Stack) -> Result<()> {
+        let string = stack.pop_string()?;
+        let value = parse_rational(&string)
+            .with_context(|| format!("Invalid rational number `{string}`"))?;
+        stack.push(value)
+    }
+}
+
+fn parse_rational(s: &str) -> Result<Rational> {
+    fn parse_int(s: &str) -> Result<BigInt> {
+        Ok(BigInt::from_str(s)?)
+    }
+
+    if let Some((numer, denom)) = s.split_once('/') {
+        return Rational::new(parse_int(numer)?, parse_int(denom)?);
+    }
+
+    if let Some((int, frac)) = s.split_once('.') {
+        anyhow::ensure!(
+            !frac.is_empty() && frac.bytes().all(|c| c.is_ascii_digit()),
+            "Invalid fractional part"
+        );
+        let denom = num_traits::pow(BigInt::from(10), frac.len());
+        return Rational::new(parse_int(&format!("{int}{frac}"))?, denom);
+    }
+
+    Ok(Rational::from(parse_int(s)?))
+}
+
+fn check_int257(x: &BigInt) -> Result<()> {
+    if bitsize(x, true) > 257 {
+        anyhow::bail!(IntegerOverflow);
+    }
+    Ok(())
+}
+
+enum CheckedOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+enum RationalOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
 }
 
 enum Rounding {
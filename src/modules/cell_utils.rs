@@ -1,12 +1,12 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::rc::Rc;
 
 use anyhow::{Context as _, Result};
-use everscale_types::cell::{MAX_BIT_LEN, MAX_REF_COUNT};
+use everscale_types::cell::{LevelMask, MAX_BIT_LEN, MAX_REF_COUNT};
 use everscale_types::prelude::*;
 use num_bigint::{BigInt, Sign};
-use num_traits::Zero;
 
+use crate::core::cont::{LoopCont, LoopContImpl};
 use crate::core::*;
 use crate::util::*;
 
@@ -181,6 +181,72 @@ impl CellUtils {
         }
     }
 
+    /// Pushes the hash of the cell at the specified level (`0..=3`),
+    /// higher levels give the representation hash.
+    #[cmd(
+        name = "cell-hash-at",
+        stack,
+        args(as_uint = false),
+        effect = "c n -- B",
+        nonstandard
+    )]
+    #[cmd(
+        name = "cell-hashu-at",
+        stack,
+        args(as_uint = true),
+        effect = "c n -- x",
+        nonstandard
+    )]
+    fn interpret_cell_hash_at(stack: &mut Stack, as_uint: bool) -> Result<()> {
+        let level = stack.pop_smallint_range(0, LevelMask::MAX_LEVEL as u32)? as u8;
+        let cell = stack.pop_cell()?;
+        let hash = cell.hash(level);
+        if as_uint {
+            stack.push(BigInt::from_bytes_be(Sign::Plus, hash.as_slice()))
+        } else {
+            stack.push(hash.as_slice().to_vec())
+        }
+    }
+
+    #[cmd(name = "cell-depth", stack, effect = "c -- n", nonstandard)]
+    fn interpret_cell_depth(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        stack.push_int(cell.repr_depth())
+    }
+
+    #[cmd(name = "cell-depth-at", stack, effect = "c n -- n'", nonstandard)]
+    fn interpret_cell_depth_at(stack: &mut Stack) -> Result<()> {
+        let level = stack.pop_smallint_range(0, LevelMask::MAX_LEVEL as u32)? as u8;
+        let cell = stack.pop_cell()?;
+        stack.push_int(cell.depth(level))
+    }
+
+    /// Pushes the cell level and its level mask.
+    #[cmd(name = "cell-level", stack, effect = "c -- n m", nonstandard)]
+    fn interpret_cell_level(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        let mask = cell.level_mask();
+        stack.push_int(mask.level())?;
+        stack.push_int(mask.to_byte())
+    }
+
+    #[cmd(name = "exotic?", stack, effect = "c -- ?", nonstandard)]
+    fn interpret_is_exotic(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        stack.push_bool(cell.is_exotic())
+    }
+
+    /// Pushes the cell type as in `XCTOS`: `-1` for ordinary cells, `1` for pruned
+    /// branches, `2` for library references, `3` for Merkle proofs and `4` for Merkle updates.
+    #[cmd(name = "cell-type", stack, effect = "c -- n", nonstandard)]
+    fn interpret_cell_type(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        stack.push_int(match cell.cell_type() {
+            CellType::Ordinary => -1,
+            ty => ty.to_byte() as i32,
+        })
+    }
+
     // === Cell slice manipulation ===
 
     #[cmd(name = "<s", stack)]
@@ -245,32 +311,7 @@ impl CellUtils {
         let mut raw_cs = stack.pop_slice()?;
         let mut cs = raw_cs.apply()?;
 
-        let int = match bits {
-            0 => Ok(BigInt::zero()),
-            0..=64 if !sgn => cs.load_uint(bits).map(BigInt::from),
-            0..=64 if sgn => cs.load_uint(bits).map(|mut int| {
-                if bits < 64 {
-                    // Clone sign bit into all high bits
-                    int |= ((int >> (bits - 1)) * u64::MAX) << (bits - 1);
-                }
-                BigInt::from(int as i64)
-            }),
-            _ => {
-                let rem = bits % 8;
-                let mut buffer = [0u8; 33];
-                cs.load_raw(&mut buffer, bits).map(|buffer| {
-                    let mut int = if sgn {
-                        BigInt::from_signed_bytes_be(buffer)
-                    } else {
-                        BigInt::from_bytes_be(Sign::Plus, buffer)
-                    };
-                    if bits % 8 != 0 {
-                        int >>= 8 - rem;
-                    }
-                    int
-                })
-            }
-        };
+        let int = load_int_from_slice(&mut cs, bits, sgn);
         let is_ok = int.is_ok();
 
         match int {
@@ -403,6 +444,227 @@ impl CellUtils {
         Ok(())
     }
 
+    #[cmd(name = "sdcutfirst", stack, args(op = SliceCut::CutFirst, refs = false))]
+    #[cmd(name = "sdskipfirst", stack, args(op = SliceCut::SkipFirst, refs = false))]
+    #[cmd(name = "sdcutlast", stack, args(op = SliceCut::CutLast, refs = false))]
+    #[cmd(name = "sdskiplast", stack, args(op = SliceCut::SkipLast, refs = false))]
+    #[cmd(name = "scutfirst", stack, args(op = SliceCut::CutFirst, refs = true))]
+    #[cmd(name = "sskipfirst", stack, args(op = SliceCut::SkipFirst, refs = true))]
+    #[cmd(name = "scutlast", stack, args(op = SliceCut::CutLast, refs = true))]
+    #[cmd(name = "sskiplast", stack, args(op = SliceCut::SkipLast, refs = true))]
+    fn interpret_slice_cut(stack: &mut Stack, op: SliceCut, refs: bool) -> Result<()> {
+        let (bits, refs) = pop_bits_refs(stack, refs)?;
+        let mut cs = stack.pop_slice()?;
+
+        let range = cs.range();
+        if !range.has_remaining(bits, refs) {
+            anyhow::bail!(everscale_types::error::Error::CellUnderflow);
+        }
+        let rem_bits = range.remaining_bits() - bits;
+        let rem_refs = range.remaining_refs() - refs;
+
+        let range = match op {
+            // NOTE: cut words keep only the requested references,
+            // while skip words leave all other references intact
+            SliceCut::CutFirst => range.get_prefix(bits, refs),
+            SliceCut::SkipFirst => {
+                let mut range = range;
+                range.advance(bits, refs)?;
+                range
+            }
+            SliceCut::CutLast => {
+                let mut range = range;
+                range.advance(rem_bits, rem_refs)?;
+                range
+            }
+            SliceCut::SkipLast => range.get_prefix(rem_bits, rem_refs),
+        };
+
+        Rc::make_mut(&mut cs).set_range(range);
+        stack.push_raw(cs)
+    }
+
+    #[cmd(name = "sdsubstr", stack, args(refs = false))]
+    #[cmd(name = "subslice", stack, args(refs = true))]
+    fn interpret_subslice(stack: &mut Stack, refs: bool) -> Result<()> {
+        let (len_bits, len_refs) = pop_bits_refs(stack, refs)?;
+        let (offset_bits, offset_refs) = pop_bits_refs(stack, refs)?;
+        let mut cs = stack.pop_slice()?;
+
+        let mut range = cs.range();
+        if !range.try_advance(offset_bits, offset_refs) || !range.has_remaining(len_bits, len_refs)
+        {
+            anyhow::bail!(everscale_types::error::Error::CellUnderflow);
+        }
+
+        Rc::make_mut(&mut cs).set_range(range.get_prefix(len_bits, len_refs));
+        stack.push_raw(cs)
+    }
+
+    #[cmd(name = "split", stack)]
+    fn interpret_slice_split(stack: &mut Stack) -> Result<()> {
+        let (bits, refs) = pop_bits_refs(stack, true)?;
+        let mut cs = stack.pop_slice()?;
+
+        let mut range = cs.range();
+        if !range.try_advance(bits, refs) {
+            anyhow::bail!(everscale_types::error::Error::CellUnderflow);
+        }
+
+        let mut prefix = cs.as_ref().clone();
+        prefix.set_range(prefix.range().get_prefix(bits, refs));
+        stack.push(prefix)?;
+
+        Rc::make_mut(&mut cs).set_range(range);
+        stack.push_raw(cs)
+    }
+
+    #[cmd(name = "sdeq", stack)]
+    fn interpret_slice_data_eq(stack: &mut Stack) -> Result<()> {
+        let cs2 = stack.pop_slice()?;
+        let cs1 = stack.pop_slice()?;
+        let ord = cmp_slice_data(&cs1.apply()?, &cs2.apply()?)?;
+        stack.push_bool(ord.is_eq())
+    }
+
+    #[cmd(name = "sdlexcmp", stack)]
+    #[cmd(name = "sbitcmp", stack)]
+    fn interpret_slice_data_cmp(stack: &mut Stack) -> Result<()> {
+        let cs2 = stack.pop_slice()?;
+        let cs1 = stack.pop_slice()?;
+        let ord = cmp_slice_data(&cs1.apply()?, &cs2.apply()?)?;
+        stack.push_int(ord as i8)
+    }
+
+    #[cmd(
+        name = "sdpfx?",
+        stack,
+        args(rev = false, proper = false, suffix = false)
+    )]
+    #[cmd(
+        name = "sdpfxrev?",
+        stack,
+        args(rev = true, proper = false, suffix = false)
+    )]
+    #[cmd(
+        name = "sdppfx?",
+        stack,
+        args(rev = false, proper = true, suffix = false)
+    )]
+    #[cmd(
+        name = "sdppfxrev?",
+        stack,
+        args(rev = true, proper = true, suffix = false)
+    )]
+    #[cmd(
+        name = "sdsfx?",
+        stack,
+        args(rev = false, proper = false, suffix = true)
+    )]
+    #[cmd(
+        name = "sdsfxrev?",
+        stack,
+        args(rev = true, proper = false, suffix = true)
+    )]
+    #[cmd(
+        name = "sdpsfx?",
+        stack,
+        args(rev = false, proper = true, suffix = true)
+    )]
+    #[cmd(
+        name = "sdpsfxrev?",
+        stack,
+        args(rev = true, proper = true, suffix = true)
+    )]
+    fn interpret_slice_data_prefix_of(
+        stack: &mut Stack,
+        rev: bool,
+        proper: bool,
+        suffix: bool,
+    ) -> Result<()> {
+        let mut cs2 = stack.pop_slice()?;
+        let mut cs1 = stack.pop_slice()?;
+        if rev {
+            std::mem::swap(&mut cs1, &mut cs2);
+        }
+        let part = cs1.apply()?;
+        let mut whole = cs2.apply()?;
+
+        let (part_bits, whole_bits) = (part.remaining_bits(), whole.remaining_bits());
+        let res = if part_bits > whole_bits || proper && part_bits == whole_bits {
+            false
+        } else {
+            if suffix {
+                whole.advance(whole_bits - part_bits, 0)?;
+            }
+            whole.strip_data_prefix(&part).is_some()
+        };
+        stack.push_bool(res)
+    }
+
+    /// Compares slices by data and then by references (their representation hashes).
+    #[cmd(
+        name = "s=",
+        stack,
+        args(cmp = false),
+        effect = "s s' -- ?",
+        nonstandard = "`sdeq`"
+    )]
+    #[cmd(
+        name = "scmp",
+        stack,
+        args(cmp = true),
+        effect = "s s' -- -1 or 0 or 1",
+        nonstandard
+    )]
+    fn interpret_slice_cmp(stack: &mut Stack, cmp: bool) -> Result<()> {
+        let cs2 = stack.pop_slice()?;
+        let cs1 = stack.pop_slice()?;
+        let ord = cmp_slices(&cs1.apply()?, &cs2.apply()?)?;
+        if cmp {
+            stack.push_int(ord as i8)
+        } else {
+            stack.push_bool(ord.is_eq())
+        }
+    }
+
+    /// Checks whether the data and references of `s` are prefixes of those of `s'`.
+    #[cmd(name = "sprefix?", stack, effect = "s s' -- ?")]
+    fn interpret_slice_prefix_of(stack: &mut Stack) -> Result<()> {
+        let cs2 = stack.pop_slice()?;
+        let cs1 = stack.pop_slice()?;
+        let part = cs1.apply()?;
+        let whole = cs2.apply()?;
+
+        let res = part.remaining_refs() <= whole.remaining_refs()
+            && whole.strip_data_prefix(&part).is_some()
+            && part
+                .references()
+                .zip(whole.references())
+                .all(|(a, b)| a.repr_hash() == b.repr_hash());
+        stack.push_bool(res)
+    }
+
+    #[cmd(name = "sdcntlead0", stack, args(bit = false, trailing = false))]
+    #[cmd(name = "sdcntlead1", stack, args(bit = true, trailing = false))]
+    #[cmd(name = "sdcnttrail0", stack, args(bit = false, trailing = true))]
+    #[cmd(name = "sdcnttrail1", stack, args(bit = true, trailing = true))]
+    fn interpret_slice_count_bits(stack: &mut Stack, bit: bool, trailing: bool) -> Result<()> {
+        let cs = stack.pop_slice()?;
+        let cs = cs.apply()?;
+
+        let bits = cs.remaining_bits();
+        let mut count = 0;
+        while count < bits {
+            let offset = if trailing { bits - count - 1 } else { count };
+            if cs.get_bit(offset)? != bit {
+                break;
+            }
+            count += 1;
+        }
+        stack.push_int(count)
+    }
+
     #[cmd(name = "totalcsize", stack, args(load_slice = false))]
     #[cmd(name = "totalssize", stack, args(load_slice = true))]
     fn interpret_cell_datasize(stack: &mut Stack, load_slice: bool) -> Result<()> {
@@ -421,19 +683,82 @@ impl CellUtils {
         stack.push_int(refs)
     }
 
+    #[cmd(name = "totalbsize", stack, nonstandard)]
+    fn interpret_builder_datasize(stack: &mut Stack) -> Result<()> {
+        const LIMIT: usize = 1 << 22;
+        let builder = stack.pop_builder()?;
+        let (cells, bits, refs) = StorageStat::compute_for_builder(&builder, LIMIT)
+            .context("Storage compute depth limit reached")?;
+        stack.push_int(cells)?;
+        stack.push_int(bits)?;
+        stack.push_int(refs)
+    }
+
+    // === Cell traversal ===
+
+    /// Executes `e` for each reference of the cell while it returns true.
+    #[cmd(name = "cell-foreach-ref", tail, args(order = CellWalkOrder::Refs), effect = "c e -- ?", nonstandard)]
+    /// Executes `e` for the cell and its unique descendants in depth-first pre-order
+    /// (each cell before its children, children from the first reference).
+    #[cmd(name = "cell-dfs", tail, args(order = CellWalkOrder::Dfs), effect = "c e -- ?", nonstandard)]
+    /// Executes `e` for the cell and its unique descendants in breadth-first order.
+    #[cmd(name = "cell-bfs", tail, args(order = CellWalkOrder::Bfs), effect = "c e -- ?", nonstandard)]
+    fn interpret_cell_walk(ctx: &mut Context, order: CellWalkOrder) -> Result<Option<Cont>> {
+        let func = ctx.stack.pop_cont_owned()?;
+        let cell = ctx.stack.pop_cell()?;
+
+        let mut queue = VecDeque::new();
+        match order {
+            CellWalkOrder::Refs => {
+                for (i, child) in cell.references().cloned().enumerate() {
+                    queue.push_back((child, i));
+                }
+            }
+            CellWalkOrder::Dfs | CellWalkOrder::Bfs => queue.push_back((Cell::clone(&cell), 0)),
+        }
+
+        Ok(Some(Rc::new(LoopCont::new(
+            CellWalkCont {
+                queue,
+                order,
+                visited: Default::default(),
+                ok: true,
+            },
+            func,
+            ctx.next.take(),
+        ))))
+    }
+
     // === BOC manipulation ===
 
-    #[cmd(name = "B>boc", stack)]
-    fn interpret_boc_deserialize(stack: &mut Stack) -> Result<()> {
+    #[cmd(name = "B>boc", stack, args(quiet = false))]
+    #[cmd(
+        name = "B>boc?",
+        stack,
+        args(quiet = true),
+        effect = "B -- c -1 or 0",
+        nonstandard
+    )]
+    fn interpret_boc_deserialize(stack: &mut Stack, quiet: bool) -> Result<()> {
         let bytes = stack.pop_bytes()?;
-        let cell = Boc::decode(&*bytes)?;
-        stack.push(cell)
+        match decode_boc(&bytes) {
+            Ok(cell) => {
+                stack.push(cell)?;
+                if quiet {
+                    stack.push_bool(true)?;
+                }
+                Ok(())
+            }
+            Err(_) if quiet => stack.push_bool(false),
+            Err(e) => Err(e),
+        }
     }
 
     #[cmd(name = "base64>boc", stack)]
     fn interpret_boc_deserialize_base64(stack: &mut Stack) -> Result<()> {
-        let bytes = stack.pop_string()?;
-        let cell = Boc::decode_base64(&*bytes)?;
+        let string = stack.pop_string()?;
+        let bytes = decode_base64(&*string)?;
+        let cell = decode_boc(&bytes)?;
         stack.push(cell)
     }
 
@@ -442,28 +767,14 @@ impl CellUtils {
     #[cmd(name = "boc+>B", stack, args(ext = true, base64 = false))]
     #[cmd(name = "boc+>base64", stack, args(ext = true, base64 = true))]
     fn interpret_boc_serialize_ext(stack: &mut Stack, ext: bool, base64: bool) -> Result<()> {
-        use everscale_types::boc::ser::BocHeader;
-
-        const MODE_WITH_CRC: u32 = 0b00010;
-        const SUPPORTED_MODES: u32 = MODE_WITH_CRC;
-
         let mode = if ext {
             stack.pop_smallint_range(0, 31)?
         } else {
             0
         };
 
-        anyhow::ensure!(
-            mode & !SUPPORTED_MODES == 0,
-            "Unsupported BOC serialization mode 0x{mode:x}"
-        );
-
         let cell = stack.pop_cell()?;
-
-        let mut result = Vec::new();
-        BocHeader::<ahash::RandomState>::new(&**cell)
-            .with_crc(mode & MODE_WITH_CRC != 0)
-            .encode(&mut result);
+        let result = encode_boc_ext(&[cell.as_ref().as_ref()], mode)?;
 
         if base64 {
             stack.push(encode_base64(result))
@@ -472,6 +783,63 @@ impl CellUtils {
         }
     }
 
+    /// Serializes a tuple of root cells into a single BOC.
+    #[cmd(name = "bocs>B", stack, effect = "t x -- B", nonstandard = "`boc>B`")]
+    fn interpret_bocs_serialize(stack: &mut Stack) -> Result<()> {
+        let mode = stack.pop_smallint_range(0, 31)?;
+        let tuple = stack.pop_tuple()?;
+        let roots = tuple
+            .iter()
+            .map(|item| item.as_cell().map(AsRef::as_ref))
+            .collect::<Result<Vec<_>>>()?;
+        stack.push(encode_boc_ext(&roots, mode)?)
+    }
+
+    #[cmd(name = "B>bocs", stack, effect = "B -- t", nonstandard = "`B>boc`")]
+    fn interpret_bocs_deserialize(stack: &mut Stack) -> Result<()> {
+        let bytes = stack.pop_bytes()?;
+        let roots = decode_boc_roots(&bytes)?
+            .into_iter()
+            .map(|root| Rc::new(root) as Rc<dyn StackValue>)
+            .collect::<StackTuple>();
+        stack.push(roots)
+    }
+
+    #[cmd(
+        name = "x>s",
+        stack,
+        args(hex = true, quiet = false),
+        nonstandard = "`x{`"
+    )]
+    #[cmd(name = "x>s?", stack, args(hex = true, quiet = true), nonstandard)]
+    #[cmd(
+        name = "bin>s",
+        stack,
+        args(hex = false, quiet = false),
+        nonstandard = "`b{`"
+    )]
+    #[cmd(name = "bin>s?", stack, args(hex = false, quiet = true), nonstandard)]
+    fn interpret_string_to_bitstring(stack: &mut Stack, hex: bool, quiet: bool) -> Result<()> {
+        let string = stack.pop_string()?;
+        let builder = if hex {
+            decode_hex_bitstring(&string)
+        } else {
+            decode_binary_bitstring(&string)
+        };
+
+        match builder.and_then(|b| b.build().map_err(From::from)) {
+            Ok(cell) => {
+                stack.push(OwnedCellSlice::new(cell))?;
+                if quiet {
+                    stack.push_bool(true)?;
+                }
+                Ok(())
+            }
+            Err(_) if quiet => stack.push_bool(false),
+            Err(e) => Err(e),
+        }
+    }
+
     // === Prefix commands ===
 
     #[cmd(name = "x{", active, without_space)]
@@ -531,6 +899,18 @@ impl<'a> StorageStat<'a> {
         }
     }
 
+    fn compute_for_builder(builder: &'a CellBuilder, limit: usize) -> Option<(u64, u64, u64)> {
+        let mut this = Self::with_limit(limit);
+        this.bits = builder.bit_len() as u64;
+        this.refs = builder.references().len() as u64;
+        for cell in builder.references() {
+            if !this.add_cell(cell.as_ref()) {
+                return None;
+            }
+        }
+        Some((this.cells, this.bits, this.refs))
+    }
+
     fn add_slice<'b: 'a>(&mut self, slice: &'a CellSlice<'b>) -> bool {
         self.bits = self.bits.saturating_add(slice.remaining_bits() as u64);
         self.refs = self.refs.saturating_add(slice.remaining_refs() as u64);
@@ -566,6 +946,112 @@ impl<'a> StorageStat<'a> {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CellWalkOrder {
+    /// Only direct references with their indices.
+    Refs,
+    /// Unique cells with their depth, each cell before its children (pre-order).
+    Dfs,
+    /// Unique cells with their depth, level by level.
+    Bfs,
+}
+
+#[derive(Clone)]
+struct CellWalkCont {
+    queue: VecDeque<(Cell, usize)>,
+    order: CellWalkOrder,
+    visited: HashSet<HashBytes, ahash::RandomState>,
+    ok: bool,
+}
+
+impl LoopContImpl for CellWalkCont {
+    fn pre_exec(&mut self, ctx: &mut Context) -> Result<bool> {
+        let (cell, value) = loop {
+            let item = match self.order {
+                CellWalkOrder::Dfs => self.queue.pop_back(),
+                CellWalkOrder::Refs | CellWalkOrder::Bfs => self.queue.pop_front(),
+            };
+            let Some((cell, value)) = item else {
+                return Ok(false);
+            };
+
+            if self.order == CellWalkOrder::Refs || self.visited.insert(*cell.repr_hash()) {
+                break (cell, value);
+            }
+        };
+
+        match self.order {
+            CellWalkOrder::Refs => {}
+            // NOTE: children are pushed in reverse to visit the first one first
+            CellWalkOrder::Dfs => {
+                for i in (0..cell.reference_count()).rev() {
+                    let child = cell
+                        .reference_cloned(i)
+                        .context("Cell reference not found")?;
+                    self.queue.push_back((child, value + 1));
+                }
+            }
+            CellWalkOrder::Bfs => {
+                for child in cell.references().cloned() {
+                    self.queue.push_back((child, value + 1));
+                }
+            }
+        }
+
+        ctx.stack.push(cell)?;
+        ctx.stack.push_int(value)?;
+        Ok(true)
+    }
+
+    fn post_exec(&mut self, ctx: &mut Context) -> Result<bool> {
+        self.ok = ctx.stack.pop_bool()?;
+        Ok(self.ok && !self.queue.is_empty())
+    }
+
+    fn finalize(&mut self, ctx: &mut Context) -> Result<bool> {
+        ctx.stack.push_bool(self.ok)?;
+        Ok(true)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SliceCut {
+    CutFirst,
+    SkipFirst,
+    CutLast,
+    SkipLast,
+}
+
+fn pop_bits_refs(stack: &mut Stack, with_refs: bool) -> Result<(u16, u8)> {
+    let refs = if with_refs {
+        stack.pop_smallint_range(0, MAX_REF_COUNT as u32)? as u8
+    } else {
+        0
+    };
+    let bits = stack.pop_smallint_range(0, MAX_BIT_LEN as u32)? as u16;
+    Ok((bits, refs))
+}
+
+fn cmp_slice_data(a: &CellSlice<'_>, b: &CellSlice<'_>) -> Result<std::cmp::Ordering> {
+    let common = a.longest_common_data_prefix(b).remaining_bits();
+    let (a_bits, b_bits) = (a.remaining_bits(), b.remaining_bits());
+    Ok(if common == a_bits || common == b_bits {
+        a_bits.cmp(&b_bits)
+    } else {
+        a.get_bit(common)?.cmp(&b.get_bit(common)?)
+    })
+}
+
+fn cmp_slices(a: &CellSlice<'_>, b: &CellSlice<'_>) -> Result<std::cmp::Ordering> {
+    let ord = cmp_slice_data(a, b)?;
+    if ord.is_ne() {
+        return Ok(ord);
+    }
+    let a_refs = a.references().map(|cell| cell.repr_hash());
+    let b_refs = b.references().map(|cell| cell.repr_hash());
+    Ok(a_refs.cmp(b_refs))
+}
+
 fn len_as_bits<T: AsRef<[u8]>>(name: &str, data: T) -> Result<u16> {
     let bits = data.as_ref().len() * 8;
     anyhow::ensure!(
@@ -574,3 +1060,252 @@ fn len_as_bits<T: AsRef<[u8]>>(name: &str, data: T) -> Result<u16> {
     );
     Ok(bits as u16)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, ints};
+
+    fn run(source: &str) -> Result<Vec<i32>> {
+        Ok(ints(&testing::run(&[], source)?))
+    }
+
+    /// Applies the word to `1011` with two references and returns
+    /// the remaining bits, refs and the data as an integer.
+    fn cut(args: &str, word: &str) -> Result<Vec<i32>> {
+        run(&format!(
+            "<b 11 4 u, <b b> ref, <b b> ref, b> <s {args} {word} \
+            dup sbitrefs rot dup sbits u@"
+        ))
+    }
+
+    #[test]
+    fn slice_cut_boundaries() {
+        for (word, zero, full) in [
+            ("sdcutfirst", [0, 0, 0], [4, 0, 11]),
+            ("sdskipfirst", [4, 2, 11], [0, 2, 0]),
+            ("sdcutlast", [0, 0, 0], [4, 0, 11]),
+            ("sdskiplast", [4, 2, 11], [0, 2, 0]),
+        ] {
+            assert_eq!(cut("0", word).unwrap(), zero, "{word} 0");
+            assert_eq!(cut("4", word).unwrap(), full, "{word} 4");
+            assert!(cut("5", word).is_err(), "{word} 5");
+        }
+
+        for (word, zero, full) in [
+            ("scutfirst", [0, 0, 0], [4, 2, 11]),
+            ("sskipfirst", [4, 2, 11], [0, 0, 0]),
+            ("scutlast", [0, 0, 0], [4, 2, 11]),
+            ("sskiplast", [4, 2, 11], [0, 0, 0]),
+        ] {
+            assert_eq!(cut("0 0", word).unwrap(), zero, "{word} 0 0");
+            assert_eq!(cut("4 2", word).unwrap(), full, "{word} 4 2");
+            assert!(cut("5 2", word).is_err(), "{word} 5 2");
+            assert!(cut("4 3", word).is_err(), "{word} 4 3");
+        }
+
+        assert_eq!(cut("1 2", "scutfirst").unwrap(), [1, 2, 1]);
+        assert_eq!(cut("1 1", "sskipfirst").unwrap(), [3, 1, 3]);
+        assert_eq!(cut("3 1", "scutlast").unwrap(), [3, 1, 3]);
+        assert_eq!(cut("3 1", "sskiplast").unwrap(), [1, 1, 1]);
+    }
+
+    #[test]
+    fn subslice_boundaries() {
+        assert_eq!(cut("0 0", "sdsubstr").unwrap(), [0, 0, 0]);
+        assert_eq!(cut("0 4", "sdsubstr").unwrap(), [4, 0, 11]);
+        assert_eq!(cut("4 0", "sdsubstr").unwrap(), [0, 0, 0]);
+        assert_eq!(cut("1 3", "sdsubstr").unwrap(), [3, 0, 3]);
+        assert!(cut("0 5", "sdsubstr").is_err());
+        assert!(cut("5 0", "sdsubstr").is_err());
+        assert!(cut("4 1", "sdsubstr").is_err());
+
+        assert_eq!(cut("0 0 4 2", "subslice").unwrap(), [4, 2, 11]);
+        assert_eq!(cut("4 2 0 0", "subslice").unwrap(), [0, 0, 0]);
+        assert!(cut("0 0 4 3", "subslice").is_err());
+        assert!(cut("4 3 0 0", "subslice").is_err());
+    }
+
+    #[test]
+    fn slice_prefix_boundaries() {
+        const EMPTY: &str = "<b b> <s";
+        const PFX: &str = "<b 2 2 u, b> <s";
+        const SFX: &str = "<b 3 2 u, b> <s";
+        const WHOLE: &str = "<b 11 4 u, b> <s";
+        const LONGER: &str = "<b 22 5 u, b> <s";
+
+        let check = |a: &str, b: &str, word: &str| run(&format!("{a} {b} {word}")).unwrap();
+        for (word, empty, pfx, sfx, whole, longer) in [
+            ("sdpfx?", -1, -1, 0, -1, 0),
+            ("sdppfx?", -1, -1, 0, 0, 0),
+            ("sdsfx?", -1, 0, -1, -1, 0),
+            ("sdpsfx?", -1, 0, -1, 0, 0),
+        ] {
+            assert_eq!(check(EMPTY, WHOLE, word), [empty], "{word} empty");
+            assert_eq!(check(PFX, WHOLE, word), [pfx], "{word} prefix");
+            assert_eq!(check(SFX, WHOLE, word), [sfx], "{word} suffix");
+            assert_eq!(check(WHOLE, WHOLE, word), [whole], "{word} whole");
+            assert_eq!(check(LONGER, WHOLE, word), [longer], "{word} longer");
+
+            let rev = word.replace('?', "rev?");
+            assert_eq!(check(WHOLE, EMPTY, &rev), [empty], "{rev} empty");
+            assert_eq!(check(WHOLE, PFX, &rev), [pfx], "{rev} prefix");
+            assert_eq!(check(WHOLE, SFX, &rev), [sfx], "{rev} suffix");
+            assert_eq!(check(WHOLE, WHOLE, &rev), [whole], "{rev} whole");
+            assert_eq!(check(WHOLE, LONGER, &rev), [longer], "{rev} longer");
+        }
+    }
+
+    #[test]
+    fn boc_modes_roundtrip() {
+        // NOTE: the child is referenced twice to be marked in cache bits
+        const TREE: &str = "<b 2 8 u, b> dup <b 1 8 u, swap ref, swap ref, b>";
+
+        for mode in 0..32 {
+            let source = format!("{TREE} dup hashu swap {mode} boc+>B dup B>boc? swap hashu swap");
+            let items = testing::run(&[], &source).unwrap();
+            assert_eq!(items.len(), 4, "mode {mode}");
+            assert_eq!(items[0].as_int().unwrap(), items[2].as_int().unwrap());
+            assert_eq!(items[3].as_int().unwrap(), &(-1).into());
+
+            let boc = items[1].as_bytes().unwrap();
+            let has_index = mode & BOC_MODE_WITH_INDEX != 0;
+            let has_crc = mode & BOC_MODE_WITH_CRC != 0;
+            let has_cache_bits = has_index && mode & BOC_MODE_WITH_CACHE_BITS != 0;
+            assert_eq!(boc[4] & 0x80 != 0, has_index, "mode {mode}");
+            assert_eq!(boc[4] & 0x40 != 0, has_crc, "mode {mode}");
+            assert_eq!(boc[4] & 0x20 != 0, has_cache_bits, "mode {mode}");
+
+            if has_crc {
+                let (data, crc) = boc.split_at(boc.len() - 4);
+                assert_eq!(crc, CRC_32_C.checksum(data).to_le_bytes());
+
+                let mut corrupted = boc.to_vec();
+                *corrupted.last_mut().unwrap() ^= 1;
+                assert!(decode_boc(&corrupted).is_err(), "mode {mode}");
+            }
+
+            if has_index {
+                // Two cells with 1-byte refs: the index follows the root index
+                let offset_size = boc[5] as usize;
+                let entry = |i: usize| {
+                    let start = 10 + offset_size * (i + 1);
+                    boc[start..start + offset_size]
+                        .iter()
+                        .fold(0u64, |acc, b| (acc << 8) | *b as u64)
+                };
+                // Cells take 5 and 3 bytes (plus a hash and a depth if stored),
+                // only the shared child is cached
+                let hashes = |stored: bool| if stored { 32 + 2 } else { 0 };
+                let root_end =
+                    5 + hashes(mode & (BOC_MODE_WITH_TOP_HASH | BOC_MODE_WITH_INT_HASHES) != 0);
+                let child_end = root_end + 3 + hashes(mode & BOC_MODE_WITH_INT_HASHES != 0);
+                let expected = if has_cache_bits {
+                    [root_end * 2, child_end * 2 + 1]
+                } else {
+                    [root_end, child_end]
+                };
+                assert_eq!([entry(0), entry(1)], expected, "mode {mode}");
+            }
+        }
+    }
+
+    #[test]
+    fn cell_levels_and_types() {
+        let check = |cell: &Cell, words: &str| {
+            let source = format!("\"{}\" base64>boc {words}", Boc::encode_base64(cell));
+            run(&source).unwrap()
+        };
+
+        let left = CellBuilder::build_from(1u8).unwrap();
+        // NOTE: leaf cells are never pruned, so the right branch has a child
+        let mut builder = CellBuilder::new();
+        builder.store_u8(2).unwrap();
+        builder.store_reference(Cell::empty_cell()).unwrap();
+        let right = builder.build().unwrap();
+        let mut builder = CellBuilder::new();
+        builder.store_reference(left.clone()).unwrap();
+        builder.store_reference(right).unwrap();
+        let root = builder.build().unwrap();
+
+        assert_eq!(check(&root, "cell-depth"), [2]);
+        assert_eq!(check(&root, "cell-level"), [0, 0]);
+        assert_eq!(check(&root, "exotic?"), [0]);
+        assert_eq!(check(&root, "cell-type"), [-1]);
+        assert_eq!(check(&root, "dup 0 cell-hashu-at swap hashu ="), [-1]);
+        assert_eq!(check(&root, "0 cell-hash-at Blen"), [32]);
+        assert!(run("<b b> 4 cell-hash-at").is_err());
+
+        // Keep only the left branch, the right one is pruned
+        let proof =
+            everscale_types::merkle::MerkleProof::create_for_cell(root.as_ref(), left.repr_hash())
+                .build()
+                .unwrap();
+        let proof_cell = CellBuilder::build_from(&proof).unwrap();
+        let inner = proof.cell.clone();
+        let pruned = inner.reference_cloned(1).unwrap();
+
+        assert_eq!(check(&proof_cell, "exotic?"), [-1]);
+        assert_eq!(check(&proof_cell, "cell-type"), [3]);
+        assert_eq!(check(&proof_cell, "cell-level"), [0, 0]);
+
+        assert_eq!(check(&inner, "exotic?"), [0]);
+        assert_eq!(check(&inner, "cell-level"), [1, 1]);
+        assert_eq!(check(&inner, "cell-depth"), [1]);
+        assert_eq!(check(&inner, "0 cell-depth-at"), [2]);
+
+        assert_eq!(check(&pruned, "exotic?"), [-1]);
+        assert_eq!(check(&pruned, "cell-type"), [1]);
+        assert_eq!(check(&pruned, "cell-level"), [1, 1]);
+        assert_eq!(check(&pruned, "0 cell-depth-at"), [1]);
+
+        // Hashes below the cell level are the hashes of the original tree
+        let hash_at = |cell: &Cell, level: u8| {
+            let source = format!(
+                "\"{}\" base64>boc {level} cell-hash-at",
+                Boc::encode_base64(cell)
+            );
+            let items = testing::run(&[], &source).unwrap();
+            items[0].as_bytes().unwrap().to_vec()
+        };
+        assert_eq!(hash_at(&inner, 0), root.repr_hash().as_slice());
+        assert_eq!(hash_at(&inner, 1), inner.repr_hash().as_slice());
+        assert_ne!(hash_at(&inner, 0), hash_at(&inner, 1));
+    }
+
+    #[test]
+    fn cell_walk_orders() {
+        let check = |source: &str| ints(&testing::run(&[], source).unwrap());
+
+        // 0 -> (1 -> 2), 3, (1 -> 2)
+        let tree = "<b 1 8 u, <b 2 8 u, b> ref, b> \
+            <b 0 8 u, over ref, <b 3 8 u, b> ref, swap ref, b>";
+        let collect = "{ drop <s 8 u@ true }";
+        let depths = "{ nip true }";
+
+        assert_eq!(
+            check(&format!("{tree} {collect} cell-dfs")),
+            [0, 1, 2, 3, -1]
+        );
+        assert_eq!(
+            check(&format!("{tree} {depths} cell-dfs")),
+            [0, 1, 2, 1, -1]
+        );
+        assert_eq!(
+            check(&format!("{tree} {collect} cell-bfs")),
+            [0, 1, 3, 2, -1]
+        );
+        assert_eq!(
+            check(&format!("{tree} {depths} cell-bfs")),
+            [0, 1, 1, 2, -1]
+        );
+        assert_eq!(
+            check(&format!("{tree} {{ nip true }} cell-foreach-ref")),
+            [0, 1, 2, -1]
+        );
+
+        // Stops when `e` returns false
+        let until_two = "{ drop <s 8 u@ dup 2 <> }";
+        assert_eq!(check(&format!("{tree} {until_two} cell-dfs")), [0, 1, 2, 0]);
+    }
+}
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+use std::io::Write as _;
 use std::rc::Rc;
 
 use anyhow::{Context as _, Result};
+use everscale_types::prelude::HashBytes;
+use sha2::Digest;
 
 use crate::core::*;
 use crate::error::{ExecutionAborted, UnexpectedEof};
@@ -53,11 +57,11 @@ impl Control {
             0 => None,
             1 => Some(body),
             _ => {
-                ctx.next = Some(Rc::new(cont::TimesCont {
-                    body: Some(body.clone()),
-                    after: ctx.next.take(),
-                    count: count - 1,
-                }));
+                ctx.next = Some(Rc::new(cont::TimesCont::new(
+                    Some(body.clone()),
+                    ctx.next.take(),
+                    count - 1,
+                )));
                 Some(body)
             }
         })
@@ -94,26 +98,41 @@ impl Control {
         }))
     }
 
+    /// Dispatches by the value using a tuple of `[key e]` pairs,
+    /// an optional last bare continuation is executed when nothing matches.
+    #[cmd(name = "match", tail, effect = "x t -- ...", nonstandard)]
+    fn interpret_match(ctx: &mut Context) -> Result<Option<Cont>> {
+        let table = MatchCont::new(&*ctx.stack.pop_tuple()?)?;
+        Rc::new(table).run(ctx)
+    }
+
+    /// Builds a jump table for `match` once, e.g. `t <match> : dispatch`.
+    #[cmd(name = "<match>", stack, effect = "t -- e", nonstandard)]
+    fn interpret_make_match(stack: &mut Stack) -> Result<()> {
+        let table = MatchCont::new(&*stack.pop_tuple()?)?;
+        stack.push(Rc::new(table) as Cont)
+    }
+
     #[cmd(name = "while", tail)]
     fn interpret_while(ctx: &mut Context) -> Result<Option<Cont>> {
         let body = ctx.stack.pop_cont_owned()?;
         let cond = ctx.stack.pop_cont_owned()?;
-        ctx.next = Some(Rc::new(cont::WhileCont {
-            condition: Some(cond.clone()),
-            body: Some(body),
-            after: ctx.next.take(),
-            running_body: true,
-        }));
+        ctx.next = Some(Rc::new(cont::WhileCont::new(
+            Some(cond.clone()),
+            Some(body),
+            ctx.next.take(),
+            true,
+        )));
         Ok(Some(cond))
     }
 
     #[cmd(name = "until", tail)]
     fn interpret_until(ctx: &mut Context) -> Result<Option<Cont>> {
         let body = ctx.stack.pop_cont_owned()?;
-        ctx.next = Some(Rc::new(cont::UntilCont {
-            body: Some(body.clone()),
-            after: ctx.next.take(),
-        }));
+        ctx.next = Some(Rc::new(cont::UntilCont::new(
+            Some(body.clone()),
+            ctx.next.take(),
+        )));
         Ok(Some(body))
     }
 
@@ -244,12 +263,10 @@ impl Control {
             }
 
             // Then find the largest possible prefix
-            while !word.is_empty() {
-                word.pop();
-                if let Some(entry) = ctx.dicts.lookup(&word, false)? {
-                    rewind = Some(word.len());
-                    break 'entry (word, Some(entry));
-                }
+            if let Some((len, entry)) = ctx.dicts.lookup_prefix(token)? {
+                word.truncate(len);
+                rewind = Some(len);
+                break 'entry (word, Some(entry));
             }
 
             // Just push token otherwise
@@ -294,6 +311,31 @@ impl Control {
         )
     }
 
+    #[cmd(name = "constant", args(count = 1), effect = "x --")]
+    #[cmd(name = "2constant", args(count = 2), effect = "x y --")]
+    fn interpret_constant(ctx: &mut Context, count: usize) -> Result<()> {
+        let cont: Cont = if count == 1 {
+            Rc::new(cont::LitCont(ctx.stack.pop()?))
+        } else {
+            let mut values = Vec::with_capacity(count);
+            for _ in 0..count {
+                values.push(ctx.stack.pop()?);
+            }
+            values.reverse();
+            Rc::new(cont::MultiLitCont(values))
+        };
+        let word = ctx.input.scan_word()?.ok_or(UnexpectedEof)?.to_owned();
+        define_word(&mut ctx.dicts.current, word, cont, DefMode::default())
+    }
+
+    #[cmd(name = "variable")]
+    fn interpret_variable(ctx: &mut Context) -> Result<()> {
+        let value = Rc::new(SharedBox::default());
+        let word = ctx.input.scan_word()?.ok_or(UnexpectedEof)?.to_owned();
+        let cont = Rc::new(cont::LitCont(value));
+        define_word(&mut ctx.dicts.current, word, cont, DefMode::default())
+    }
+
     #[cmd(name = "(create)", args(mode = None))]
     fn interpret_create_aux(ctx: &mut Context, mode: Option<DefMode>) -> Result<()> {
         let mode = match mode {
@@ -317,7 +359,7 @@ impl Control {
     #[cmd(name = "::_", active, args(active = true, prefix = true))]
     fn interpret_colon(ctx: &mut Context, active: bool, prefix: bool) -> Result<()> {
         thread_local! {
-            static CREATE_AUX: Cont = Rc::new((|ctx| interpret_create_aux(ctx, None)) as cont::ContextWordFunc);
+            static CREATE_AUX: Cont = Rc::new(CreateAuxCont(|ctx| interpret_create_aux(ctx, None)));
         };
 
         let name = ctx.input.scan_word()?.ok_or(UnexpectedEof)?;
@@ -433,11 +475,51 @@ impl Control {
         ctx.stack.push_int(ctx.input.depth())
     }
 
-    #[cmd(name = "include", tail)]
-    fn interpret_include(ctx: &mut Context) -> Result<Option<Cont>> {
+    #[cmd(name = "include", tail, args(once = false))]
+    #[cmd(
+        name = "include-once",
+        tail,
+        args(once = true),
+        nonstandard = "`include`"
+    )]
+    fn interpret_include(ctx: &mut Context, once: bool) -> Result<Option<Cont>> {
         let name = ctx.stack.pop_string()?;
-        let source_block = ctx.env.include(&name)?;
-        ctx.input.push_source_block(source_block);
+        if once && ctx.include_count(&name) > 0 {
+            return Ok(None);
+        }
+
+        let mut source_block = ctx.include_file(&name)?;
+        let mut cache_key = None;
+        if let Some(cache) = ctx.library_cache.clone() {
+            let mut contents = Vec::new();
+            source_block.buffer_mut().read_to_end(&mut contents)?;
+
+            // NOTE: `.once` depends on how many times the file was included
+            let key = HashBytes(
+                sha2::Sha256::new()
+                    .chain_update(source_cache_key(ctx, &contents)?.0)
+                    .chain_update((ctx.include_count(&name) as u64).to_le_bytes())
+                    .finalize()
+                    .into(),
+            );
+            if let Some(data) = cache.get(&key) {
+                restore_cached_library(ctx, &data)?;
+                return Ok(None);
+            }
+            if !cache.is_uncacheable(&key) {
+                cache_key = Some(key);
+            }
+            source_block = SourceBlock::new(name.as_str(), std::io::Cursor::new(contents));
+        }
+
+        ctx.enter_nested_interpreter()?;
+        // NOTE: cached libraries are interpreted separately to store the results
+        // right after the end of the file
+        if cache_key.is_some() {
+            ctx.input.push_nested_source_block(source_block);
+        } else {
+            ctx.input.push_source_block(source_block);
+        }
 
         if let Some(max_include_depth) = ctx.limits.max_include_depth {
             anyhow::ensure!(
@@ -446,10 +528,26 @@ impl Control {
             );
         }
 
+        if let Some(key) = cache_key {
+            let store = LibraryCacheStoreCont::new(ctx, key)?;
+            ctx.next = cont::SeqCont::make(Some(Rc::new(store)), ctx.next.take());
+        }
         ctx.next = cont::SeqCont::make(Some(Rc::new(ExitSourceBlockCont)), ctx.next.take());
         Ok(Some(Rc::new(cont::InterpreterCont)))
     }
 
+    #[cmd(name = ".once", tail)]
+    fn interpret_once(ctx: &mut Context) -> Result<Option<Cont>> {
+        let Some(name) = ctx.input.source_block_name().map(str::to_owned) else {
+            return Ok(None);
+        };
+        if ctx.include_count(&name) > 1 {
+            interpret_skip_source(ctx)
+        } else {
+            Ok(None)
+        }
+    }
+
     #[cmd(name = "skip-to-eof", tail)]
     fn interpret_skip_source(ctx: &mut Context) -> Result<Option<Cont>> {
         let cont = ctx.exit_interpret.fetch();
@@ -461,6 +559,54 @@ impl Control {
         })
     }
 
+    /// Runs the source until `}asm-cache` once per distinct source text
+    /// and definitions of the words it uses, later blocks with the same
+    /// source and definitions push the cached cell.
+    #[cmd(name = "asm-cache{", active, effect = "-- c", nonstandard)]
+    fn interpret_asm_cache_begin(ctx: &mut Context) -> Result<()> {
+        let source = scan_source_until(&mut ctx.input, "}asm-cache")?;
+        ctx.stack.push(source)?;
+        ctx.stack.push_int(1)?;
+        ctx.stack.push(Rc::new(AsmCacheCont) as Cont)
+    }
+
+    #[cmd(name = "}asm-cache", nonstandard)]
+    fn interpret_asm_cache_end(_ctx: &mut Context) -> Result<()> {
+        anyhow::bail!("`}}asm-cache` without `asm-cache{{`")
+    }
+
+    #[cmd(
+        name = "asm-cache-stats",
+        effect = "-- hits misses entries",
+        nonstandard
+    )]
+    fn interpret_asm_cache_stats(ctx: &mut Context) -> Result<()> {
+        let stats = ctx.asm_cache.stats();
+        ctx.stack.push_int(stats.hits)?;
+        ctx.stack.push_int(stats.misses)?;
+        ctx.stack.push_int(stats.entries)
+    }
+
+    #[cmd(name = "asm-cache-clear", nonstandard)]
+    fn interpret_asm_cache_clear(ctx: &mut Context) -> Result<()> {
+        ctx.asm_cache.clear();
+        Ok(())
+    }
+
+    #[cmd(name = "suspend>B", effect = "-- B", nonstandard)]
+    fn interpret_suspend(ctx: &mut Context) -> Result<()> {
+        let data = suspend::serialize(ctx.next.as_ref(), &ctx.dicts.current)?;
+        ctx.stack.push(data)
+    }
+
+    #[cmd(name = "B>resume", effect = "B --", nonstandard)]
+    fn interpret_resume(ctx: &mut Context) -> Result<()> {
+        let data = ctx.stack.pop_bytes()?;
+        let cont = suspend::deserialize(&data, &ctx.dicts.current, ctx.stack.atoms_mut())?;
+        ctx.next = cont::SeqCont::make(cont, ctx.next.take());
+        Ok(())
+    }
+
     #[cmd(name = "abort")]
     fn interpret_abort(ctx: &mut Context) -> Result<()> {
         ctx.stdout.flush()?;
@@ -468,28 +614,136 @@ impl Control {
         Err(ExecutionAborted { reason }.into())
     }
 
+    /// Executes `e-body`. If it fails, drops everything it left on the stack,
+    /// pushes the exception and executes `e-handler`.
+    #[cmd(name = "try", tail, effect = "e-body e-handler --", nonstandard)]
+    fn interpret_try(ctx: &mut Context) -> Result<Option<Cont>> {
+        let handler = ctx.stack.pop_cont_owned()?;
+        let body = ctx.stack.pop_cont_owned()?;
+        Ok(Some(enter_try(ctx, body, handler, false)))
+    }
+
+    /// Executes `e` and pushes `null` on success or the exception on failure.
+    #[cmd(name = "catch", tail, effect = "e -- ... x", nonstandard)]
+    fn interpret_catch(ctx: &mut Context) -> Result<Option<Cont>> {
+        let body = ctx.stack.pop_cont_owned()?;
+        Ok(Some(enter_try(ctx, body, cont::NopCont::instance(), true)))
+    }
+
+    /// Executes `e-body` and then `e-cleanup` even if `e-body` fails,
+    /// in which case the exception is rethrown after `e-cleanup`.
+    #[cmd(name = "finally", tail, effect = "e-body e-cleanup --", nonstandard)]
+    fn interpret_finally(ctx: &mut Context) -> Result<Option<Cont>> {
+        let cleanup = ctx.stack.pop_cont_owned()?;
+        let body = ctx.stack.pop_cont_owned()?;
+        Ok(Some(enter_guarded(ctx, body, cleanup)))
+    }
+
+    /// Executes `e` and pushes everything it printed instead of printing it.
+    #[cmd(name = "with-capture", tail, effect = "e -- S", nonstandard)]
+    fn interpret_with_capture(ctx: &mut Context) -> Result<Option<Cont>> {
+        let body = ctx.stack.pop_cont_owned()?;
+        ctx.stdout.begin_capture();
+        let release = Rc::new(EndCaptureCont { file: None });
+        Ok(Some(enter_guarded(ctx, body, release)))
+    }
+
+    /// Executes `e` and writes everything it printed to the file `S`
+    /// (also when `e` fails).
+    #[cmd(name = "with-file", tail, effect = "S e --", nonstandard)]
+    fn interpret_with_file(ctx: &mut Context) -> Result<Option<Cont>> {
+        let body = ctx.stack.pop_cont_owned()?;
+        let file = ctx.stack.pop_string()?;
+        ctx.stdout.begin_capture();
+        let release = Rc::new(EndCaptureCont { file: Some(file) });
+        Ok(Some(enter_guarded(ctx, body, release)))
+    }
+
+    #[cmd(name = "rethrow", nonstandard)]
+    fn interpret_rethrow(ctx: &mut Context) -> Result<()> {
+        let exception = ctx.stack.pop_exception()?;
+        Err(ExecutionAborted {
+            reason: exception.reason.clone(),
+        }
+        .into())
+    }
+
+    #[cmd(name = "exc>$", stack, effect = "x -- S", nonstandard)]
+    fn interpret_exception_reason(stack: &mut Stack) -> Result<()> {
+        let exception = stack.pop_exception()?;
+        stack.push(exception.reason.clone())
+    }
+
+    #[cmd(name = "exc>stack", stack, effect = "x -- t", nonstandard)]
+    fn interpret_exception_stack(stack: &mut Stack) -> Result<()> {
+        let exception = stack.pop_exception()?;
+        stack.push(exception.stack.clone())
+    }
+
+    /// Registers a continuation which is executed when the script is interrupted
+    /// (e.g. on SIGINT or SIGTERM in the CLI), `null` removes it.
+    ///
+    /// The interrupted execution is resumed after the handler, so the handler
+    /// should finish with `bye` (or `halt`) for a graceful shutdown.
+    #[cmd(name = "on-interrupt", effect = "e --", nonstandard)]
+    fn interpret_on_interrupt(ctx: &mut Context) -> Result<()> {
+        let handler = ctx.stack.pop()?;
+        let handler = if handler.is_null() {
+            None
+        } else {
+            Some(handler.into_cont()?.as_ref().clone())
+        };
+        ctx.set_interrupt_handler(handler);
+        Ok(())
+    }
+
     #[cmd(name = "quit")]
     fn interpret_quit(ctx: &mut Context) -> Result<()> {
-        ctx.exit_code = 0;
+        ctx.outcome = RunOutcome::Finished;
         ctx.next = None;
         Ok(())
     }
 
     #[cmd(name = "bye")]
     fn interpret_bye(ctx: &mut Context) -> Result<()> {
-        ctx.exit_code = u8::MAX;
+        ctx.outcome = RunOutcome::Bye;
         ctx.next = None;
         Ok(())
     }
 
     #[cmd(name = "halt")]
     fn interpret_halt(ctx: &mut Context) -> Result<()> {
-        ctx.exit_code = ctx.stack.pop_smallint_range(0, 255)? as u8;
+        ctx.outcome = RunOutcome::Halted(ctx.stack.pop_smallint_range(0, 255)? as u8);
         ctx.next = None;
         Ok(())
     }
 }
 
+fn enter_try(ctx: &mut Context, body: Cont, catch: Cont, push_null: bool) -> Cont {
+    let after = ctx.next.take();
+    let id = ctx.add_exception_handler(catch, after.clone());
+    ctx.next = cont::SeqCont::make(Some(Rc::new(EndTryCont { id, push_null })), after);
+    body
+}
+
+/// Executes `body` and then `release` even if `body` fails,
+/// in which case the exception is rethrown after `release`.
+fn enter_guarded(ctx: &mut Context, body: Cont, release: Cont) -> Cont {
+    let after = ctx.next.take();
+    let catch = Rc::new(ReleaseOnErrorCont {
+        release: release.clone(),
+    });
+    let id = ctx.add_exception_handler(catch, after.clone());
+    ctx.next = cont::SeqCont::make(
+        Some(Rc::new(EndTryCont {
+            id,
+            push_null: false,
+        })),
+        cont::SeqCont::make(Some(release), after),
+    );
+    body
+}
+
 fn define_word(d: &mut Dictionary, mut word: String, cont: Cont, mode: DefMode) -> Result<()> {
     anyhow::ensure!(!word.is_empty(), "Word definition is empty");
     if !mode.prefix {
@@ -510,6 +764,58 @@ struct DefMode {
     prefix: bool,
 }
 
+struct MatchCont {
+    table: HashMap<HashMapTreeKey, Cont>,
+    default: Option<Cont>,
+}
+
+impl MatchCont {
+    fn new(items: &StackTuple) -> Result<Self> {
+        let mut table = HashMap::with_capacity(items.len());
+        let mut default = None;
+        for (i, item) in items.iter().enumerate() {
+            if let Ok(cont) = item.as_cont() {
+                anyhow::ensure!(
+                    i + 1 == items.len(),
+                    "Default match branch must be the last one"
+                );
+                default = Some(cont.clone());
+                continue;
+            }
+
+            let pair = item.as_tuple()?;
+            anyhow::ensure!(pair.len() == 2, "Expected a `[key e]` pair");
+            let key = HashMapTreeKey::new(pair[0].clone())?;
+            let cont = pair[1].as_cont()?.clone();
+            if table.insert(key, cont).is_some() {
+                anyhow::bail!("Duplicate match key: {}", pair[0].display_dump());
+            }
+        }
+        Ok(Self { table, default })
+    }
+}
+
+impl cont::ContImpl for MatchCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        let value = ctx.stack.pop()?;
+        let key = HashMapTreeKey::new(value.clone())?;
+        if let Some(cont) = self.table.get(&key) {
+            return Ok(Some(cont.clone()));
+        }
+
+        // NOTE: the default branch receives the value
+        let Some(default) = &self.default else {
+            anyhow::bail!("No match for {}", value.display_dump());
+        };
+        ctx.stack.push_raw(value)?;
+        Ok(Some(default.clone()))
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<match of {} keys>", self.table.len())
+    }
+}
+
 struct ResetContextCont(Rc<SharedBox>);
 
 impl cont::ContImpl for ResetContextCont {
@@ -523,6 +829,27 @@ impl cont::ContImpl for ResetContextCont {
     }
 }
 
+/// Same as `(create)`, but is not affected by its redefinition.
+///
+/// NOTE: it is resumed as the `(create)` word from the dictionary.
+struct CreateAuxCont(cont::ContextWordFunc);
+
+impl cont::ContImpl for CreateAuxCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        (self.0)(ctx)?;
+        Ok(None)
+    }
+
+    fn suspend(&self, s: &mut suspend::Suspender<'_>) -> Result<()> {
+        s.write_named("(create) ");
+        Ok(())
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(create)")
+    }
+}
+
 struct ExitInterpretCont;
 
 impl cont::ContImpl for ExitInterpretCont {
@@ -536,11 +863,244 @@ impl cont::ContImpl for ExitInterpretCont {
     }
 }
 
+/// Reads the source text until the specified word (which is skipped).
+///
+/// NOTE: words are scanned one by one, so the end word inside a string
+/// literal also ends the text.
+fn scan_source_until(input: &mut Lexer, end: &str) -> Result<String> {
+    let mut source = String::new();
+    let mut last = None::<(usize, usize)>;
+    loop {
+        let word = input
+            .scan_word()?
+            .with_context(|| format!("`{end}` not found"))?;
+        if word == end {
+            return Ok(source);
+        }
+
+        let pos = input.get_position().ok_or(UnexpectedEof)?;
+        let start = match last {
+            // NOTE: spaces between words on the same line are preserved
+            Some((line_number, word_end)) if line_number == pos.line_number => word_end,
+            Some(_) => {
+                source.push('\n');
+                pos.word_start
+            }
+            None => pos.word_start,
+        };
+        source.push_str(&pos.line[start..pos.word_end]);
+        last = Some((pos.line_number, pos.word_end));
+    }
+}
+
+struct AsmCacheCont;
+
+impl cont::ContImpl for AsmCacheCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        let source = ctx.stack.pop_string()?;
+        let key = source_cache_key(ctx, source.as_bytes())?;
+        if let Some(cell) = ctx.asm_cache.get(&key) {
+            ctx.stack.push(cell)?;
+            return Ok(None);
+        }
+
+        ctx.enter_nested_interpreter()?;
+        ctx.input
+            .push_nested_source_block(SourceBlock::from_string("<asm-cache>", source.as_str()));
+
+        ctx.next = cont::SeqCont::make(
+            Some(Rc::new(ExitSourceBlockCont)),
+            cont::SeqCont::make(Some(Rc::new(AsmCacheStoreCont { key })), ctx.next.take()),
+        );
+        Ok(Some(Rc::new(cont::InterpreterCont)))
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<asm-cache>")
+    }
+}
+
+/// Hashes the source together with the current definitions
+/// of the words it references, so that redefined words (e.g. constants)
+/// produce a different key.
+///
+/// NOTE: only words which are directly visible from the source are resolved,
+/// words used by their definitions are referenced by name.
+fn source_cache_key(ctx: &Context, source: &[u8]) -> Result<HashBytes> {
+    let mut words = Vec::<(Rc<String>, DictionaryEntry)>::new();
+    for token in String::from_utf8_lossy(source).split_whitespace() {
+        if let Some(entry) = ctx.dicts.lookup_token(token)? {
+            words.push((Rc::new(format!("{token} ")), entry));
+        }
+        if let Some((len, entry)) = ctx.dicts.lookup_prefix(token)? {
+            words.push((Rc::new(token[..len].to_owned()), entry));
+        }
+    }
+    words.sort_by(|(a, _), (b, _)| a.cmp(b));
+    words.dedup_by(|(a, _), (b, _)| a == b);
+
+    let d = &ctx.dicts.current;
+    let (data, skipped) = suspend::serialize_session(&words, &[], d)?;
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update((source.len() as u64).to_le_bytes());
+    hasher.update(source);
+    hasher.update(&data);
+    // NOTE: native words can't be serialized, so their names are used instead
+    for (name, entry) in &words {
+        if skipped.contains(name) {
+            hasher.update(format!("{name}={}\0", entry.definition.display_name(d)));
+        }
+    }
+    Ok(HashBytes(hasher.finalize().into()))
+}
+
+struct AsmCacheStoreCont {
+    key: HashBytes,
+}
+
+impl cont::ContImpl for AsmCacheStoreCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        let cell = ctx
+            .stack
+            .pop_cell()
+            .context("`asm-cache{` block must produce a cell")?;
+        ctx.asm_cache.insert(self.key, cell.as_ref().clone());
+        ctx.stack.push_raw(cell)?;
+        Ok(None)
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<asm-cache store>")
+    }
+}
+
+struct LibraryCacheStoreCont {
+    key: HashBytes,
+    /// Definitions are kept alive so that their addresses are not reused.
+    baseline: HashMap<Rc<String>, Cont>,
+    included: ahash::HashMap<String, usize>,
+    accessed: usize,
+    current: Rc<SharedBox>,
+    context: Rc<SharedBox>,
+    stack_depth: usize,
+    written: u64,
+}
+
+impl LibraryCacheStoreCont {
+    fn new(ctx: &Context, key: HashBytes) -> Result<Self> {
+        let mut baseline = HashMap::new();
+        for (name, entry) in ctx.dicts.current.iter()? {
+            baseline.insert(name, entry.definition);
+        }
+        Ok(Self {
+            key,
+            baseline,
+            included: ctx.included_files().clone(),
+            accessed: ctx.accessed_files().len(),
+            current: ctx.dicts.current.get_words_box().clone(),
+            context: ctx.dicts.context.get_words_box().clone(),
+            stack_depth: ctx.stack.depth(),
+            written: ctx.stdout.written(),
+        })
+    }
+
+    /// Serializes words defined by the library, returns `None`
+    /// if the library had some other side effects.
+    ///
+    /// The session stack contains a tuple of files included by the library,
+    /// a tuple of other accessed files and an optional context dictionary.
+    fn serialize(&self, ctx: &Context) -> Result<Option<Vec<u8>>> {
+        let current = ctx.dicts.current.get_words_box();
+        if ctx.stack.depth() != self.stack_depth
+            || ctx.stdout.written() != self.written
+            || *current != self.current
+        {
+            return Ok(None);
+        }
+
+        // NOTE: nested includes are replayed on restore to keep `include-once` working
+        let mut included = Vec::new();
+        for (name, &count) in ctx.included_files() {
+            let baseline = self.included.get(name).copied().unwrap_or_default();
+            for _ in baseline..count {
+                included.push(Rc::new(name.clone()) as Rc<dyn StackValue>);
+            }
+        }
+        let accessed = ctx.accessed_files()[self.accessed..]
+            .iter()
+            .map(|name| Rc::new(name.clone()) as Rc<dyn StackValue>)
+            .collect::<StackTuple>();
+        let mut stack: Vec<Rc<dyn StackValue>> = vec![Rc::new(included), Rc::new(accessed)];
+
+        // NOTE: the library can switch context to its own namespace
+        let context = ctx.dicts.context.get_words_box();
+        if *context == *current && *context != self.context {
+            return Ok(None);
+        } else if *context != self.context {
+            stack.push(context.clone());
+        }
+
+        let d = &ctx.dicts.current;
+        let words = d
+            .iter()?
+            .filter(|(name, entry)| match self.baseline.get(name) {
+                Some(definition) => !Rc::ptr_eq(definition, &entry.definition),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(match suspend::serialize_session(&words, &stack, d) {
+            Ok((data, skipped)) if skipped.is_empty() => Some(data),
+            _ => None,
+        })
+    }
+}
+
+impl cont::ContImpl for LibraryCacheStoreCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        if let Some(cache) = &ctx.library_cache {
+            match self.serialize(ctx)? {
+                Some(data) => cache.insert(self.key, data),
+                None => cache.insert_uncacheable(self.key),
+            }
+        }
+        Ok(None)
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<library cache store>")
+    }
+}
+
+fn restore_cached_library(ctx: &mut Context, data: &[u8]) -> Result<()> {
+    let session = suspend::deserialize_session(data, &ctx.dicts.current, ctx.stack.atoms_mut())?;
+    for (name, entry) in session.words {
+        ctx.dicts.current.define_word(name, entry)?;
+    }
+
+    let mut stack = session.stack.into_iter();
+    let (Some(included), Some(accessed)) = (stack.next(), stack.next()) else {
+        anyhow::bail!("Invalid cached library");
+    };
+    for name in included.as_tuple()? {
+        ctx.mark_included(name.as_string()?);
+    }
+    for name in accessed.as_tuple()? {
+        ctx.add_accessed_file(name.as_string()?);
+    }
+    if let Some(context) = stack.next() {
+        ctx.dicts.context.set_words_box(context.into_shared_box()?);
+    }
+    Ok(())
+}
+
 struct ExitSourceBlockCont;
 
 impl cont::ContImpl for ExitSourceBlockCont {
     fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
         ctx.input.pop_source_block();
+        ctx.leave_nested_interpreter();
         Ok(None)
     }
 
@@ -548,3 +1108,362 @@ impl cont::ContImpl for ExitSourceBlockCont {
         f.write_str("<exit source block>")
     }
 }
+
+struct ReleaseOnErrorCont {
+    release: Cont,
+}
+
+impl cont::ContImpl for ReleaseOnErrorCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        let exception = ctx.stack.pop_exception()?;
+        ctx.next = cont::SeqCont::make(
+            Some(Rc::new(RethrowCont(exception.reason.clone()))),
+            ctx.next.take(),
+        );
+        Ok(Some(self.release.clone()))
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<release on error>")
+    }
+}
+
+struct RethrowCont(String);
+
+impl cont::ContImpl for RethrowCont {
+    fn run(self: Rc<Self>, _: &mut Context) -> Result<Option<Cont>> {
+        Err(ExecutionAborted {
+            reason: self.0.clone(),
+        }
+        .into())
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<rethrow>")
+    }
+}
+
+struct EndCaptureCont {
+    file: Option<Rc<String>>,
+}
+
+impl cont::ContImpl for EndCaptureCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        let output = ctx.stdout.end_capture().unwrap_or_default();
+        match &self.file {
+            Some(file) => ctx
+                .env
+                .write_file(file.as_str(), &output, WriteMode::Truncate)?,
+            None => ctx
+                .stack
+                .push(String::from_utf8_lossy(&output).into_owned())?,
+        }
+        Ok(None)
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<end capture>")
+    }
+}
+
+struct EndTryCont {
+    id: usize,
+    push_null: bool,
+}
+
+impl cont::ContImpl for EndTryCont {
+    fn run(self: Rc<Self>, ctx: &mut Context) -> Result<Option<Cont>> {
+        ctx.remove_exception_handler(self.id);
+        if self.push_null {
+            ctx.stack.push_null()?;
+        }
+        Ok(None)
+    }
+
+    fn fmt_name(&self, _: &Dictionary, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<end try>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::env::EmptyEnvironment;
+    use crate::testing::{ints, run, run_with_output};
+
+    fn run_cached(cache: &LibraryCache, files: &[(&str, &str)], source: &str) -> Result<Vec<i32>> {
+        let (items, _) = run_with_output(files, source, |ctx| {
+            ctx.library_cache = Some(cache.clone());
+        });
+        Ok(ints(&items?))
+    }
+
+    #[test]
+    fn constants_in_interpret_mode() {
+        let items = run(&[], "5 constant five 1 2 2constant pair five pair").unwrap();
+        assert_eq!(ints(&items), [5, 1, 2]);
+
+        let items = run(&[], "variable v 3 v ! v @ v @ 1+").unwrap();
+        assert_eq!(ints(&items), [3, 4]);
+    }
+
+    #[test]
+    fn constants_in_compile_mode() {
+        let source = "{ 7 constant } : def-seven def-seven seven \
+            { { 1 2 2constant } execute } : def-pair def-pair pair \
+            { variable } : def-var def-var v 10 v ! \
+            seven pair v @";
+        let items = run(&[], source).unwrap();
+        assert_eq!(ints(&items), [7, 1, 2, 10]);
+    }
+
+    #[test]
+    fn constants_from_included_files() {
+        let files = [(
+            "defs.fif",
+            "11 constant eleven\n3 4 2constant pair\nvariable counter\n",
+        )];
+        let source = "\"defs.fif\" include 5 counter ! eleven pair counter @";
+        let items = run(&files, source).unwrap();
+        assert_eq!(ints(&items), [11, 3, 4, 5]);
+    }
+
+    #[test]
+    fn libraries_from_cache() {
+        let files = [
+            (
+                "lib.fif",
+                "variable counter 1 counter !\n{ counter @ 1+ counter ! } : inc\n",
+            ),
+            ("noisy.fif", "1 2\n"),
+        ];
+        let source = "\"lib.fif\" include inc inc counter @ \"noisy.fif\" include";
+
+        let cache = LibraryCache::default();
+        assert_eq!(run_cached(&cache, &files, source).unwrap(), [3, 1, 2]);
+        // NOTE: variables of the cached library are not shared between contexts
+        assert_eq!(run_cached(&cache, &files, source).unwrap(), [3, 1, 2]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 1));
+    }
+
+    #[test]
+    fn include_once() {
+        let files = [
+            ("inc.fif", "n @ 1+ n !\n"),
+            ("guarded.fif", "n @ 1+ n ! .once n @ 10 + n !\n"),
+        ];
+        let run = |source: &str| {
+            let items = run(&files, &format!("variable n 0 n ! {source} n @")).unwrap();
+            ints(&items)
+        };
+
+        assert_eq!(run(r#""inc.fif" include-once "inc.fif" include-once"#), [1]);
+        assert_eq!(run(r#""inc.fif" include "inc.fif" include-once"#), [1]);
+        assert_eq!(run(r#""inc.fif" include-once "inc.fif" include"#), [2]);
+
+        // NOTE: only the part after `.once` is skipped
+        assert_eq!(run(r#""guarded.fif" include"#), [11]);
+        assert_eq!(run(r#""guarded.fif" include "guarded.fif" include"#), [12]);
+        assert_eq!(
+            run(r#""guarded.fif" include "guarded.fif" include-once"#),
+            [11]
+        );
+
+        // `.once` outside of included files does nothing
+        assert_eq!(run(".once 5 n !"), [5]);
+    }
+
+    #[test]
+    fn cached_libraries_read_callers() {
+        let files = [
+            ("lib.fif", "X 1+ constant Y\n"),
+            ("once.fif", "\"inner.fif\" include-once\n"),
+            ("inner.fif", ".once Z 1+ constant Z\n"),
+        ];
+
+        let cache = LibraryCache::default();
+        let source = "\"lib.fif\" include Y";
+        assert_eq!(
+            run_cached(&cache, &files, &format!("1 constant X {source}")).unwrap(),
+            [2]
+        );
+        assert_eq!(
+            run_cached(&cache, &files, &format!("2 constant X {source}")).unwrap(),
+            [3]
+        );
+        assert_eq!(
+            run_cached(&cache, &files, &format!("1 constant X {source}")).unwrap(),
+            [2]
+        );
+
+        // Nested includes of a cached library are still counted
+        let source = "0 constant Z \"once.fif\" include \"inner.fif\" include Z";
+        assert_eq!(run_cached(&cache, &files, source).unwrap(), [1]);
+        assert_eq!(run_cached(&cache, &files, source).unwrap(), [1]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (3, 5));
+    }
+
+    #[test]
+    fn asm_cache_tracks_definitions() {
+        let block = "asm-cache{ <b X 8 u, b> }asm-cache <s 8 u@";
+        let run_cached = |source: &str, cache: &AsmCache| {
+            let (items, _) = run_with_output(&[], source, |ctx| ctx.asm_cache = cache.clone());
+            ints(&items.unwrap())
+        };
+
+        let cache = AsmCache::default();
+        let source = format!("1 constant X {block} {block} 2 constant X {block}");
+        assert_eq!(run_cached(&source, &cache), [1, 1, 2]);
+
+        // Other contexts reuse cells only for the same definitions
+        assert_eq!(run_cached(&format!("2 constant X {block}"), &cache), [2]);
+        assert_eq!(run_cached(&format!("3 constant X {block}"), &cache), [3]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 3, 3));
+    }
+
+    #[test]
+    fn match_dispatch() {
+        let table = "1 { 10 + } 2 tuple 2 { 20 + } 2 tuple { drop -1 } 3 tuple <match>";
+        let source = format!("{table} : dispatch 5 1 dispatch 5 2 dispatch 5 3 dispatch");
+        let items = run(&[], &source).unwrap();
+        assert_eq!(ints(&items), [15, 25, 5, -1]);
+
+        assert!(run(&[], "1 2 { } 2 tuple 1 tuple match").is_err());
+        assert!(run(&[], "1 { } 2 tuple 1 { } 2 tuple 2 tuple <match>").is_err());
+    }
+
+    #[test]
+    fn catch_across_include() {
+        let files = [(
+            "bad.fif",
+            "\"head \" type \"oops\" abort \"tail \" type 5\n",
+        )];
+
+        let source = "{ \"bad.fif\" include } { exc>$ } try 7";
+        let (items, output) = run_with_output(&files, source, |_| {});
+        let items = items.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_string().unwrap(), "oops");
+        assert_eq!(ints(&items[1..]), [7]);
+        assert_eq!(output, "head ");
+
+        // Unfinished blocks of the failed include are dropped
+        let files = [("block.fif", "{ 1 undefined-word\n2 }\n")];
+        let source = "{ \"block.fif\" include } catch 7";
+        let (items, _) = run_with_output(&files, source, |_| {});
+        let items = items.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(ints(&items[1..]), [7]);
+
+        // NOTE: nested interpreters of the failed includes are not leaked
+        let source = "0 { { \"bad.fif\" include } { drop 1+ } try } 2000 times";
+        let (items, _) = run_with_output(&files, source, |_| {});
+        assert_eq!(ints(&items.unwrap()), [2000]);
+    }
+
+    #[test]
+    fn guards_across_include() {
+        let files = [(
+            "bad.fif",
+            "\"head \" type \"oops\" abort \"tail \" type 5\n",
+        )];
+
+        let source = "{ { \"bad.fif\" include } with-capture } { exc>$ } try \"after\" type";
+        let (items, output) = run_with_output(&files, source, |_| {});
+        let items = items.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].as_string().unwrap(), "oops");
+        assert_eq!(output, "after");
+
+        let source = "variable n 0 n ! \
+            { { \"bad.fif\" include } { n @ 1+ n ! } finally } catch drop n @";
+        let (items, output) = run_with_output(&files, source, |_| {});
+        assert_eq!(ints(&items.unwrap()), [1]);
+        assert_eq!(output, "head ");
+
+        struct RecordingEnvironment(Vec<(String, Vec<u8>)>);
+
+        impl crate::core::Environment for RecordingEnvironment {
+            fn now_ms(&self) -> u64 {
+                0
+            }
+
+            fn get_env(&self, _: &str) -> Option<String> {
+                None
+            }
+
+            fn file_exists(&self, _: &str) -> bool {
+                false
+            }
+
+            fn write_file(
+                &mut self,
+                name: &str,
+                contents: &[u8],
+                _: WriteMode,
+            ) -> std::io::Result<()> {
+                self.0.push((name.to_owned(), contents.to_vec()));
+                Ok(())
+            }
+
+            fn read_file(&mut self, name: &str) -> std::io::Result<Vec<u8>> {
+                EmptyEnvironment.read_file(name)
+            }
+
+            fn read_file_part(&mut self, name: &str, _: u64, _: u64) -> std::io::Result<Vec<u8>> {
+                self.read_file(name)
+            }
+
+            fn include(&self, name: &str) -> std::io::Result<crate::core::SourceBlock> {
+                EmptyEnvironment.include(name)
+            }
+        }
+
+        let mut env = RecordingEnvironment(Vec::new());
+        let mut stdout = Vec::new();
+        {
+            let mut ctx = crate::Context::new(&mut env, &mut stdout)
+                .with_basic_modules()
+                .unwrap();
+            for (name, contents) in files {
+                ctx.add_virtual_file(name, contents);
+            }
+            ctx.add_source_str(
+                "<test>",
+                "{ \"out.txt\" { \"bad.fif\" include } with-file } catch drop \"after\" type",
+            );
+            ctx.run().unwrap();
+        }
+        assert_eq!(env.0, [("out.txt".to_owned(), b"head ".to_vec())]);
+        assert_eq!(stdout, b"after");
+    }
+
+    #[test]
+    fn limits_cannot_be_caught() {
+        let (result, _) = run_with_output(&[], "{ \"0123456789\" type } catch", |ctx| {
+            let mut limits = ctx.limits.clone();
+            limits.max_output_bytes = Some(4);
+            ctx.set_limits(limits);
+        });
+        assert!(result.is_err());
+
+        let files = [("self.fif", "\"self.fif\" include\n")];
+        let (result, _) = run_with_output(&files, "{ \"self.fif\" include } catch", |_| {});
+        assert!(result.is_err());
+
+        let files = [("strict.fif", "\"ab\" $>B B.\n")];
+        let source = "{ \"strict.fif\" include } catch";
+        let (result, output) = run_with_output(&files, source, |ctx| {
+            ctx.strict = StrictMode::Deny;
+            ctx.allow_nonstandard_in("<test>");
+        });
+        assert!(result.is_err());
+        assert_eq!(output, "");
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context as _, Result};
 use everscale_crypto::ed25519;
+use rand::rngs::OsRng;
 
 use crate::core::*;
 use crate::util::{CRC_16, CRC_32, CRC_32_C};
@@ -8,9 +9,11 @@ pub struct Crypto;
 
 #[fift_module]
 impl Crypto {
+    // NOTE: keys are always generated from the OS RNG,
+    // even for deterministic runs
     #[cmd(name = "newkeypair", stack)]
     fn interpret_newkeypair(stack: &mut Stack) -> Result<()> {
-        let secret = ed25519::SecretKey::generate(&mut rand::thread_rng());
+        let secret = ed25519::SecretKey::generate(&mut OsRng);
         let public = ed25519::PublicKey::from(&secret);
         stack.push(secret.as_bytes().to_vec())?;
         stack.push(public.as_bytes().to_vec())
@@ -1,6 +1,13 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::rc::Rc;
+
 use anyhow::Result;
+use everscale_types::prelude::*;
+use num_bigint::BigInt;
 
 use crate::core::*;
+use crate::error::UnexpectedEof;
 use crate::util::*;
 
 pub struct DebugUtils;
@@ -15,6 +22,21 @@ impl DebugUtils {
         Ok(())
     }
 
+    #[cmd(name = ".f", args(space_after = true), effect = "x n --", nonstandard)]
+    #[cmd(
+        name = ".f_",
+        args(space_after = false),
+        effect = "x n --",
+        nonstandard
+    )]
+    fn interpret_dot_fixed(ctx: &mut Context, space_after: bool) -> Result<()> {
+        let decimals = ctx.stack.pop_smallint_range(0, 255)?;
+        let int = ctx.stack.pop_int()?;
+        let space = opt_space(space_after);
+        write!(ctx.stdout, "{}{space}", format_fixed(&int, decimals))?;
+        Ok(())
+    }
+
     #[cmd(name = "x.", args(uppercase = false, space_after = true))]
     #[cmd(name = "x._", args(uppercase = false, space_after = false))]
     #[cmd(name = "X.", args(uppercase = true, space_after = true))]
@@ -54,6 +76,60 @@ impl DebugUtils {
         Ok(())
     }
 
+    #[cmd(
+        name = "ctree.",
+        args(pop_limit = false),
+        effect = "c --",
+        nonstandard = "`csr.`"
+    )]
+    #[cmd(
+        name = "lctree.",
+        args(pop_limit = true),
+        effect = "c n --",
+        nonstandard = "`lcsr.`"
+    )]
+    fn interpret_dot_cell_tree(ctx: &mut Context, pop_limit: bool) -> Result<()> {
+        const DEFAULT_RECURSIVE_PRINT_LIMIT: usize = 100;
+
+        let limit = if pop_limit {
+            ctx.stack.pop_smallint_range(0, u16::MAX as u32)? as usize
+        } else {
+            DEFAULT_RECURSIVE_PRINT_LIMIT
+        };
+
+        let cell = ctx.stack.pop_cell()?;
+        write!(
+            ctx.stdout,
+            "{}",
+            cell.as_ref().as_ref().display_cell_tree(limit)
+        )?;
+        Ok(())
+    }
+
+    /// Prints the serialized BOC size of the cell and `k` heaviest subtrees.
+    /// Cells shared between subtrees are counted in each of them.
+    #[cmd(name = "boc-size-report", effect = "c k --", nonstandard)]
+    fn interpret_boc_size_report(ctx: &mut Context) -> Result<()> {
+        let k = ctx.stack.pop_smallint_range(0, 1000)? as usize;
+        let cell = ctx.stack.pop_cell()?;
+        let report = BocSizeReport::new(cell.as_ref().as_ref());
+
+        writeln!(
+            ctx.stdout,
+            "BOC size: {} bytes, {} unique cells",
+            Boc::encode(cell.as_ref()).len(),
+            report.cells.len()
+        )?;
+        for (path, size) in report.top_subtrees(k) {
+            writeln!(
+                ctx.stdout,
+                "{:>10} bytes {:>8} cells  {path}",
+                size.bytes, size.cells
+            )?;
+        }
+        Ok(())
+    }
+
     #[cmd(name = "Bx.")]
     fn interpret_bytes_hex_print_raw(ctx: &mut Context) -> Result<()> {
         const CHUNK: usize = 16;
@@ -67,29 +143,59 @@ impl DebugUtils {
         Ok(())
     }
 
+    #[cmd(name = "B.", effect = "B --", nonstandard)]
+    fn interpret_bytes_hexdump(ctx: &mut Context) -> Result<()> {
+        let bytes = ctx.stack.pop_bytes()?;
+        ctx.stdout.write_all(hexdump(&bytes).as_bytes())?;
+        Ok(())
+    }
+
     #[cmd(name = ".s")]
     fn interpret_dotstack(ctx: &mut Context) -> Result<()> {
-        writeln!(ctx.stdout, "{}", ctx.stack.display_dump())?;
+        let limits = ctx.display_limits;
+        writeln!(ctx.stdout, "{}", ctx.stack.display_dump_limited(limits))?;
         Ok(())
     }
 
     #[cmd(name = ".sl")]
     fn interpret_dotstack_list(ctx: &mut Context) -> Result<()> {
-        writeln!(ctx.stdout, "{}", ctx.stack.display_list())?;
+        let limits = ctx.display_limits;
+        writeln!(ctx.stdout, "{}", ctx.stack.display_list_limited(limits))?;
         Ok(())
     }
 
     #[cmd(name = ".dump")]
     fn interpret_dump(ctx: &mut Context) -> Result<()> {
         let item = ctx.stack.pop()?;
-        write!(ctx.stdout, "{} ", item.display_dump())?;
+        let limits = ctx.display_limits;
+        write!(ctx.stdout, "{} ", item.display_dump_limited(limits))?;
+        Ok(())
+    }
+
+    /// Writes a JSON line with the value in the `--dump-stack-json` format
+    /// (e.g. `{"cell":"<base64 BOC>"}`).
+    #[cmd(name = "jdump", effect = "x --", nonstandard = "`.dump`")]
+    fn interpret_json_dump(ctx: &mut Context) -> Result<()> {
+        let item = ctx.stack.pop()?;
+        let mut line = String::new();
+        write_stack_value_json(&mut line, item.as_ref())?;
+        writeln!(ctx.stdout, "{line}")?;
+        Ok(())
+    }
+
+    /// Writes a JSON line with all stack items (from the bottom) without removing them.
+    #[cmd(name = "jdump-stack", nonstandard = "`.s`")]
+    fn interpret_json_dump_stack(ctx: &mut Context) -> Result<()> {
+        let line = ctx.stack_to_json()?;
+        writeln!(ctx.stdout, "{line}")?;
         Ok(())
     }
 
     #[cmd(name = ".l")]
     fn interpret_print_list(ctx: &mut Context) -> Result<()> {
         let item = ctx.stack.pop()?;
-        write!(ctx.stdout, "{} ", item.display_list())?;
+        let limits = ctx.display_limits;
+        write!(ctx.stdout, "{} ", item.display_list_limited(limits))?;
         Ok(())
     }
 
@@ -101,6 +207,32 @@ impl DebugUtils {
         Ok(())
     }
 
+    /// Prints the current source position and the chain of including files.
+    #[cmd(name = "where", nonstandard)]
+    fn interpret_print_where(ctx: &mut Context) -> Result<()> {
+        for (i, position) in ctx.input.include_chain().iter().enumerate() {
+            let prefix = if i == 0 { "at" } else { "  included from" };
+            writeln!(ctx.stdout, "{prefix} {position}")?;
+        }
+        Ok(())
+    }
+
+    /// Returns a tuple of `[name line column]` entries for the current
+    /// source position and the chain of including files.
+    #[cmd(name = "(where)", effect = "-- t", nonstandard)]
+    fn interpret_where(ctx: &mut Context) -> Result<()> {
+        let chain = ctx.input.include_chain();
+        let mut tuple = StackTuple::with_capacity(chain.len());
+        for position in chain {
+            tuple.push(Rc::new(vec![
+                Rc::new(position.source_block_name.to_string()) as Rc<dyn StackValue>,
+                Rc::new(BigInt::from(position.line_number)),
+                Rc::new(BigInt::from(position.column)),
+            ]));
+        }
+        ctx.stack.push(tuple)
+    }
+
     #[cmd(name = "cont.")]
     fn interpret_print_continuation(ctx: &mut Context) -> Result<()> {
         let cont = ctx.stack.pop_cont()?;
@@ -108,6 +240,46 @@ impl DebugUtils {
         Ok(())
     }
 
+    /// Sets limits for `.s`, `.sl`, `.dump` and `.l`: max number of items,
+    /// max string or bytes length and max tuple depth. Zero means no limit.
+    #[cmd(name = "dump-limits!", effect = "items len depth --", nonstandard)]
+    fn interpret_set_dump_limits(ctx: &mut Context) -> Result<()> {
+        let mut pop_limit = || {
+            let limit = ctx.stack.pop_usize()?;
+            Ok::<_, anyhow::Error>((limit > 0).then_some(limit))
+        };
+        let max_depth = pop_limit()?;
+        let max_len = pop_limit()?;
+        let max_items = pop_limit()?;
+        ctx.display_limits = DisplayLimits {
+            max_items,
+            max_len,
+            max_depth,
+        };
+        Ok(())
+    }
+
+    #[cmd(name = "cell>dot", stack, effect = "c -- S", nonstandard)]
+    fn interpret_cell_to_dot(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        let dot = cell.as_ref().as_ref().display_cell_dot().to_string();
+        stack.push(dot)
+    }
+
+    #[cmd(name = "cont>dot", effect = "cont -- S", nonstandard)]
+    fn interpret_cont_to_dot(ctx: &mut Context) -> Result<()> {
+        let cont = ctx.stack.pop_cont()?;
+        let dot = cont.display_dot(&ctx.dicts.current).to_string();
+        ctx.stack.push(dot)
+    }
+
+    /// Serializes the whole stack without changing it (see [`Context::stack_to_json`]).
+    #[cmd(name = "stack>json", effect = "-- S", nonstandard)]
+    fn interpret_stack_to_json(ctx: &mut Context) -> Result<()> {
+        let json = ctx.stack_to_json()?;
+        ctx.stack.push(json)
+    }
+
     #[cmd(name = "(dump)", stack)]
     fn interpret_dump_internal(stack: &mut Stack) -> Result<()> {
         let string = stack.pop()?.display_dump().to_string();
@@ -120,6 +292,38 @@ impl DebugUtils {
         stack.push(string)
     }
 
+    #[cmd(name = "(sdump)", stack, args(boc = false), nonstandard = "`(dump)`")]
+    #[cmd(name = "(sdump64)", stack, args(boc = true), nonstandard = "`(dump)`")]
+    fn interpret_safe_dump_internal(stack: &mut Stack, boc: bool) -> Result<()> {
+        let mut string = String::new();
+        write_safe_dump(&mut string, stack.pop()?.as_ref(), boc)?;
+        stack.push(string)
+    }
+
+    #[cmd(name = "tag!", stack, effect = "x S -- x'", nonstandard)]
+    fn interpret_tag_value(stack: &mut Stack) -> Result<()> {
+        let tag = stack.pop_string()?;
+        let value = stack.pop()?;
+        stack.push(TaggedValue::new(Rc::from(tag.as_str()), value))
+    }
+
+    #[cmd(name = "tag@", stack, effect = "x -- x S or x null", nonstandard)]
+    fn interpret_get_tag(stack: &mut Stack) -> Result<()> {
+        let value = stack.pop()?;
+        let tag = value.as_tagged().map(|tagged| tagged.tag.to_string());
+        stack.push_raw(value)?;
+        stack.push_opt(tag)
+    }
+
+    #[cmd(name = "untag", stack, effect = "x' -- x", nonstandard)]
+    fn interpret_untag_value(stack: &mut Stack) -> Result<()> {
+        let value = stack.pop()?;
+        match value.as_tagged() {
+            Some(tagged) => stack.push_raw(tagged.value.clone()),
+            None => stack.push_raw(value),
+        }
+    }
+
     #[cmd(name = "(.)", stack)]
     fn interpret_dot_internal(stack: &mut Stack) -> Result<()> {
         let string = stack.pop_int()?.to_string();
@@ -145,6 +349,27 @@ impl DebugUtils {
         stack.push(string)
     }
 
+    /// Returns a tuple of `[name calls self_us total_us]` entries
+    /// collected by the profiler, or an empty tuple if profiling is disabled.
+    #[cmd(name = "(profile)", effect = "-- t", nonstandard)]
+    fn interpret_profile(ctx: &mut Context) -> Result<()> {
+        let stats = match &ctx.profiler {
+            Some(profiler) => profiler.stats(),
+            None => Vec::new(),
+        };
+
+        let mut tuple = StackTuple::with_capacity(stats.len());
+        for (name, stats) in stats {
+            tuple.push(Rc::new(vec![
+                Rc::new(name.to_string()) as Rc<dyn StackValue>,
+                Rc::new(BigInt::from(stats.calls)),
+                Rc::new(BigInt::from(stats.self_time.as_micros())),
+                Rc::new(BigInt::from(stats.total_time.as_micros())),
+            ]));
+        }
+        ctx.stack.push(tuple)
+    }
+
     #[cmd(name = "words")]
     fn interpret_words(ctx: &mut Context) -> Result<()> {
         let Some(map) = ctx.dicts.current.clone_words_map()? else {
@@ -165,6 +390,40 @@ impl DebugUtils {
         }
         Ok(())
     }
+
+    /// Prints the definition of the next word: a decompiled word list
+    /// (as `{ ... } : name`) or the function which implements a native word.
+    #[cmd(name = "see", nonstandard)]
+    fn interpret_see(ctx: &mut Context) -> Result<()> {
+        let word = ctx.input.scan_word()?.ok_or(UnexpectedEof)?.to_owned();
+        // NOTE: words without a trailing space are prefix words
+        let (entry, name) = match ctx.dicts.lookup(&word, false)? {
+            Some(entry) => (entry, word.clone()),
+            None => match ctx.dicts.lookup_token(&word)? {
+                Some(entry) => (entry, format!("{word} ")),
+                None => anyhow::bail!("Undefined word `{word}`"),
+            },
+        };
+        let prefix = if name.ends_with(' ') { "" } else { "_" };
+        let active = if entry.active { "active " } else { "" };
+
+        let definition = entry.definition.as_ref();
+        if definition.is_native() {
+            write!(ctx.stdout, "{word} is {active}native")?;
+            if let Some(info) = ctx.word_info(&name) {
+                write!(ctx.stdout, " ({}::{})", info.module, info.symbol)?;
+            }
+            writeln!(ctx.stdout)?;
+        } else {
+            let colon = if entry.active { "::" } else { ":" };
+            writeln!(
+                ctx.stdout,
+                "{} {colon}{prefix} {word}",
+                definition.display_definition(&ctx.dicts)
+            )?;
+        }
+        Ok(())
+    }
 }
 
 const fn opt_space(space_after: bool) -> &'static str {
@@ -174,3 +433,184 @@ const fn opt_space(space_after: bool) -> &'static str {
         ""
     }
 }
+
+fn write_safe_dump(out: &mut String, value: &dyn StackValue, boc: bool) -> Result<()> {
+    match value.ty() {
+        StackValueType::Null => out.push_str("null"),
+        StackValueType::Int => write!(out, "{}", value.as_int()?)?,
+        StackValueType::Rational => {
+            let value = value.as_rational()?;
+            write!(out, "{}/{}", value.numer(), value.denom())?
+        }
+        StackValueType::String => write_escaped_string(out, value.as_string()?),
+        StackValueType::Bytes => write!(out, "B{{{}}}", hex::encode(value.as_bytes()?))?,
+        StackValueType::Cell => {
+            let cell = value.as_cell()?;
+            if boc {
+                write!(out, "C{{{}}}", Boc::encode_base64(cell.as_ref()))?;
+            } else {
+                write!(out, "C{{{}}}", cell.repr_hash())?;
+            }
+        }
+        StackValueType::Builder => {
+            let builder = value.as_builder()?;
+            let cell = builder.clone().build()?;
+            write_cell_parts(out, "BC", &cell.as_ref().as_slice()?, boc)?;
+        }
+        StackValueType::Slice => {
+            write_cell_parts(out, "CS", &value.as_slice()?, boc)?;
+        }
+        StackValueType::Tuple => {
+            let tuple = value.as_tuple()?;
+            out.push('[');
+            for item in tuple {
+                out.push(' ');
+                write_safe_dump(out, item.as_ref(), boc)?;
+            }
+            out.push_str(if tuple.is_empty() { "]" } else { " ]" });
+        }
+        StackValueType::Atom => match value.as_atom()? {
+            stack::Atom::Named(name) => write!(out, "`{name}")?,
+            atom @ stack::Atom::Unnamed(_) => write!(out, "{atom}")?,
+        },
+        StackValueType::HashMap => {
+            // NOTE: entries are sorted to make output independent of the hasher state
+            let mut entries = Vec::new();
+            for entry in value.as_hashmap()? {
+                let mut key = String::new();
+                write_safe_dump(&mut key, entry.key.stack_value.as_ref(), boc)?;
+                entries.push((key, entry.value.as_ref()));
+            }
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            out.push_str("hmap{");
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                out.push_str(if i == 0 { " " } else { ", " });
+                out.push_str(&key);
+                out.push_str(": ");
+                write_safe_dump(out, value, boc)?;
+            }
+            out.push_str(" }");
+        }
+        StackValueType::Cont => out.push_str("<cont>"),
+        StackValueType::WordList => out.push_str("<wordlist>"),
+        StackValueType::SharedBox => out.push_str("<box>"),
+        StackValueType::Exception => {
+            out.push_str("exception{");
+            write_escaped_string(out, &value.as_exception()?.reason);
+            out.push('}');
+        }
+        StackValueType::Custom => write!(out, "<{}>", value.as_custom()?.tag())?,
+    }
+    Ok(())
+}
+
+fn write_cell_parts(out: &mut String, prefix: &str, cs: &CellSlice<'_>, boc: bool) -> Result<()> {
+    if boc {
+        let mut builder = CellBuilder::new();
+        builder.store_slice(cs)?;
+        let cell = builder.build()?;
+        write!(out, "{prefix}{{{}}}", Boc::encode_base64(cell))?;
+    } else {
+        write!(out, "{prefix}{{x{{{}}}", encode_hex_bitstring(cs)?)?;
+        for cell in cs.references() {
+            write!(out, ",C{{{}}}", cell.repr_hash())?;
+        }
+        out.push('}');
+    }
+    Ok(())
+}
+
+fn write_escaped_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                _ = write!(out, "\\u{{{:04x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct BocSizeReport<'a> {
+    /// Unique cells in BFS order with the shortest path from the root.
+    cells: Vec<(&'a DynCell, String)>,
+    sizes: ahash::HashMap<&'a HashBytes, SubtreeSize>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct SubtreeSize {
+    bytes: usize,
+    cells: usize,
+}
+
+impl<'a> BocSizeReport<'a> {
+    fn new(root: &'a DynCell) -> Self {
+        let mut cells = vec![(root, "root".to_owned())];
+        let mut visited = ahash::HashSet::default();
+        visited.insert(root.repr_hash());
+
+        let mut i = 0;
+        while i < cells.len() {
+            let (cell, path) = &cells[i];
+            let (cell, path) = (*cell, path.clone());
+            for (ref_index, child) in cell.references().enumerate() {
+                if visited.insert(child.repr_hash()) {
+                    cells.push((child, format!("{path}.{ref_index}")));
+                }
+            }
+            i += 1;
+        }
+
+        // NOTE: size of the cell index in the serialized references
+        let ref_bits = (usize::BITS - cells.len().leading_zeros()) as usize;
+        let ref_size = std::cmp::max((ref_bits + 7) / 8, 1);
+
+        let mut report = Self {
+            cells,
+            sizes: Default::default(),
+        };
+        report.compute_size(root, ref_size);
+        report
+    }
+
+    fn compute_size(&mut self, cell: &'a DynCell, ref_size: usize) -> SubtreeSize {
+        if let Some(size) = self.sizes.get(cell.repr_hash()) {
+            return *size;
+        }
+
+        let mut size = SubtreeSize {
+            bytes: 2
+                + (cell.bit_len() as usize + 7) / 8
+                + cell.reference_count() as usize * ref_size,
+            cells: 1,
+        };
+        for child in cell.references() {
+            let child = self.compute_size(child, ref_size);
+            size.bytes += child.bytes;
+            size.cells += child.cells;
+        }
+
+        self.sizes.insert(cell.repr_hash(), size);
+        size
+    }
+
+    fn top_subtrees(&self, k: usize) -> Vec<(&str, SubtreeSize)> {
+        let mut subtrees = self
+            .cells
+            .iter()
+            .skip(1)
+            .map(|(cell, path)| (path.as_str(), self.sizes[cell.repr_hash()]))
+            .collect::<Vec<_>>();
+        subtrees.sort_by_key(|(_, size)| std::cmp::Reverse(size.bytes));
+        subtrees.truncate(k);
+        subtrees
+    }
+}
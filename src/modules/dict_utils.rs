@@ -229,6 +229,30 @@ impl DictUtils {
             ctx.next.take(),
         ))))
     }
+
+    #[cmd(name = "hmap>cell", stack, effect = "h -- c", nonstandard)]
+    fn interpret_hmap_to_cell(stack: &mut Stack) -> Result<()> {
+        let map = stack.pop_hashmap()?;
+        let mut builder = CellBuilder::new();
+        store_hmap(&mut builder, map.as_deref(), 0)?;
+        stack.push(builder.build()?)
+    }
+
+    #[cmd(name = "cell>hmap", stack, effect = "c -- h", nonstandard)]
+    fn interpret_cell_to_hmap(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        let mut cs = CellSlice::new(cell.as_ref().as_ref())?;
+        anyhow::ensure!(
+            cs.load_u8()? == HMAP_ITEM_HASHMAP,
+            "Cell does not contain a serialized hashmap"
+        );
+        let map = load_hmap(&mut cs)?;
+        anyhow::ensure!(
+            cs.is_data_empty() && cs.is_refs_empty(),
+            "Extra data in a hashmap cell"
+        );
+        stack.push_opt_raw(map)
+    }
 }
 
 #[derive(Clone)]
@@ -465,7 +489,8 @@ impl OwnedDictIter {
 
         // SAFETY: iter lifetime is bounded to the `DynCell` which lives as long
         // as `Cell` lives. By storing root we guarantee that it will live enough.
-        let inner = unsafe { std::mem::transmute::<_, dict::RawIter<'static>>(inner) };
+        let inner =
+            unsafe { std::mem::transmute::<dict::RawIter<'_>, dict::RawIter<'static>>(inner) };
 
         Self { root, inner }
     }
@@ -535,3 +560,211 @@ fn builder_to_int(builder: &CellBuilder, signed: bool) -> Result<BigInt> {
 }
 
 const MAX_KEY_BITS: u32 = 1023;
+
+// NOTE: serialized hashmap items are `tag:uint8` followed by the payload
+const HMAP_ITEM_NULL: u8 = 0;
+const HMAP_ITEM_INT: u8 = 1;
+const HMAP_ITEM_STRING: u8 = 2;
+const HMAP_ITEM_BYTES: u8 = 3;
+const HMAP_ITEM_CELL: u8 = 4;
+const HMAP_ITEM_SLICE: u8 = 5;
+const HMAP_ITEM_TUPLE: u8 = 6;
+const HMAP_ITEM_HASHMAP: u8 = 7;
+
+const MAX_HMAP_ITEM_DEPTH: usize = 256;
+
+/// Stores a hashmap tag and `HashmapE 256 ^[^key ^value]` keyed by the hashes of the key cells.
+fn store_hmap(
+    builder: &mut CellBuilder,
+    map: Option<&HashMapTreeNode>,
+    depth: usize,
+) -> Result<()> {
+    let mut dict = Dict::<HashBytes, (Cell, Cell)>::new();
+    for node in map.into_iter().flatten() {
+        let key = match node.key.stack_value.ty() {
+            StackValueType::Int | StackValueType::String | StackValueType::Bytes => {
+                build_hmap_item(node.key.stack_value.as_ref(), depth)?
+            }
+            ty => anyhow::bail!("Unsupported hashmap key type: {ty:?}"),
+        };
+        let value = build_hmap_item(node.value.as_ref(), depth)?;
+        dict.set(key.repr_hash(), (key.clone(), value))?;
+    }
+
+    builder.store_u8(HMAP_ITEM_HASHMAP)?;
+    dict.store_into(builder, &mut Cell::empty_context())?;
+    Ok(())
+}
+
+fn load_hmap(cs: &mut CellSlice<'_>) -> Result<Option<Rc<HashMapTreeNode>>> {
+    let dict = Dict::<HashBytes, (Cell, Cell)>::load_from(cs)?;
+
+    let mut map = None;
+    for entry in dict.iter() {
+        let (hash, (key, value)) = entry?;
+        anyhow::ensure!(*key.repr_hash() == hash, "Hashmap key hash mismatch");
+        let key = HashMapTreeKey::new(parse_hmap_item(key.as_ref())?)?;
+        let value = parse_hmap_item(value.as_ref())?;
+        HashMapTreeNode::set(&mut map, &key, &value);
+    }
+    Ok(map)
+}
+
+fn build_hmap_item(value: &dyn StackValue, depth: usize) -> Result<Cell> {
+    anyhow::ensure!(
+        depth < MAX_HMAP_ITEM_DEPTH,
+        "Hashmap is nested too deeply to be serialized"
+    );
+
+    let mut b = CellBuilder::new();
+    match value.ty() {
+        StackValueType::Null => b.store_u8(HMAP_ITEM_NULL)?,
+        StackValueType::Int => {
+            b.store_u8(HMAP_ITEM_INT)?;
+            store_int_to_builder(&mut b, value.as_int()?, 257, true)?;
+        }
+        StackValueType::String => {
+            b.store_u8(HMAP_ITEM_STRING)?;
+            b.store_reference(build_snake_bytes(value.as_string()?.as_bytes())?)?;
+        }
+        StackValueType::Bytes => {
+            b.store_u8(HMAP_ITEM_BYTES)?;
+            b.store_reference(build_snake_bytes(value.as_bytes()?)?)?;
+        }
+        StackValueType::Cell => {
+            b.store_u8(HMAP_ITEM_CELL)?;
+            b.store_reference(value.as_cell()?.clone())?;
+        }
+        StackValueType::Slice => {
+            let cs = value.as_slice()?;
+            let mut slice = CellBuilder::new();
+            slice.store_slice(cs)?;
+            b.store_u8(HMAP_ITEM_SLICE)?;
+            b.store_reference(slice.build()?)?;
+        }
+        StackValueType::Tuple => {
+            let tuple = value.as_tuple()?;
+            let mut items = Dict::<u32, Cell>::new();
+            for (i, item) in tuple.iter().enumerate() {
+                items.set(i as u32, build_hmap_item(item.as_ref(), depth + 1)?)?;
+            }
+            b.store_u8(HMAP_ITEM_TUPLE)?;
+            b.store_u32(tuple.len() as u32)?;
+            items.store_into(&mut b, &mut Cell::empty_context())?;
+        }
+        StackValueType::HashMap => store_hmap(&mut b, Some(value.as_hashmap()?), depth + 1)?,
+        ty => anyhow::bail!("Unsupported hashmap value type: {ty:?}"),
+    }
+    Ok(b.build()?)
+}
+
+fn parse_hmap_item(cell: &DynCell) -> Result<Rc<dyn StackValue>> {
+    let mut cs = CellSlice::new(cell)?;
+    let value: Rc<dyn StackValue> = match cs.load_u8()? {
+        HMAP_ITEM_NULL => Stack::make_null(),
+        HMAP_ITEM_INT => Rc::new(load_int_from_slice(&mut cs, 257, true)?),
+        HMAP_ITEM_STRING => {
+            let bytes = parse_snake_bytes(cs.load_reference()?)?;
+            Rc::new(String::from_utf8(bytes).context("Invalid string in a hashmap cell")?)
+        }
+        HMAP_ITEM_BYTES => Rc::new(parse_snake_bytes(cs.load_reference()?)?),
+        HMAP_ITEM_CELL => Rc::new(cs.load_reference_cloned()?),
+        HMAP_ITEM_SLICE => Rc::new(OwnedCellSlice::new(cs.load_reference_cloned()?)),
+        HMAP_ITEM_TUPLE => {
+            let len = cs.load_u32()?;
+            let items = Dict::<u32, Cell>::load_from(&mut cs)?;
+            let mut tuple = StackTuple::with_capacity(len as usize);
+            for i in 0..len {
+                let item = items
+                    .get(i)?
+                    .context("Missing tuple item in a hashmap cell")?;
+                tuple.push(parse_hmap_item(item.as_ref())?);
+            }
+            Rc::new(tuple)
+        }
+        HMAP_ITEM_HASHMAP => match load_hmap(&mut cs)? {
+            Some(map) => map,
+            None => Stack::make_null(),
+        },
+        tag => anyhow::bail!("Unknown hashmap item tag: {tag}"),
+    };
+    anyhow::ensure!(
+        cs.is_data_empty() && cs.is_refs_empty(),
+        "Extra data in a hashmap cell"
+    );
+    Ok(value)
+}
+
+fn build_snake_bytes(bytes: &[u8]) -> Result<Cell> {
+    const CHUNK: usize = 127;
+
+    let mut next = None::<Cell>;
+    for chunk in bytes.chunks(CHUNK).rev() {
+        let mut b = CellBuilder::new();
+        b.store_raw(chunk, chunk.len() as u16 * 8)?;
+        if let Some(next) = next.take() {
+            b.store_reference(next)?;
+        }
+        next = Some(b.build()?);
+    }
+    Ok(next.unwrap_or_else(Cell::empty_cell))
+}
+
+fn parse_snake_bytes(mut cell: &DynCell) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    loop {
+        let mut cs = CellSlice::new(cell)?;
+        let bits = cs.remaining_bits();
+        anyhow::ensure!(bits % 8 == 0, "Invalid bytes cell");
+
+        let mut buffer = [0u8; 128];
+        result.extend_from_slice(cs.load_raw(&mut buffer, bits)?);
+        match cs.remaining_refs() {
+            0 => return Ok(result),
+            _ => cell = cs.load_reference()?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::run;
+
+    #[test]
+    fn hmap_cell_roundtrip() {
+        let long = "ab".repeat(200);
+        let source = format!(
+            "hmapnew \
+            \"one\" 1 rot hmap!+ \
+            1 256 << negate \"min\" rot hmap!+ \
+            hmapnew \"x\" 7 rot hmap!+ \"k\" $>B rot hmap!+ \
+            <b 5 8 u, b> \"cell\" rot hmap!+ \
+            <b 5 8 u, b> <s \"slice\" rot hmap!+ \
+            1 \"a\" null 3 tuple \"tuple\" rot hmap!+ \
+            \"{long}\" $>B \"long\" rot hmap!+ \
+            hmap>cell dup cell>hmap hmap>cell \
+            2dup hashu swap hashu = -rot drop cell>hmap \
+            1 over hmap@ swap \
+            \"min\" over hmap@ swap \
+            7 \"k\" $>B 2 pick hmap@ hmap@ swap \
+            \"slice\" over hmap@ 8 u@ swap \
+            \"tuple\" over hmap@ 3 untuple 3 roll \
+            \"long\" swap hmap@ Blen"
+        );
+
+        let items = run(&[], &source).unwrap();
+        assert_eq!(items[0].as_int().unwrap(), &(-1).into());
+        assert_eq!(items[1].as_string().unwrap(), "one");
+        assert_eq!(
+            *items[2].as_int().unwrap(),
+            -(num_bigint::BigInt::from(1) << 256u32)
+        );
+        assert_eq!(items[3].as_string().unwrap(), "x");
+        assert_eq!(items[4].as_int().unwrap(), &5.into());
+        assert_eq!(items[5].as_int().unwrap(), &1.into());
+        assert_eq!(items[6].as_string().unwrap(), "a");
+        assert!(items[7].is_null());
+        assert_eq!(items[8].as_int().unwrap(), &400.into());
+        assert_eq!(items.len(), 9);
+    }
+}
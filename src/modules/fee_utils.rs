@@ -0,0 +1,89 @@
+use anyhow::{Context as _, Result};
+use everscale_types::models::{BlockchainConfig, StoragePrices};
+use everscale_types::prelude::*;
+use num_bigint::BigInt;
+
+use crate::core::*;
+
+pub struct FeeUtils;
+
+#[fift_module]
+impl FeeUtils {
+    /// Computes the storage fee for `cells` and `bits` stored during `seconds`,
+    /// using the latest storage prices from the config param 18.
+    #[cmd(
+        name = "storage-fee",
+        stack,
+        effect = "cells bits seconds mc? D-config -- fee",
+        nonstandard
+    )]
+    fn interpret_storage_fee(stack: &mut Stack) -> Result<()> {
+        let config = pop_config(stack)?;
+        let masterchain = stack.pop_bool()?;
+        let seconds = pop_u64(stack)?;
+        let bits = pop_u64(stack)?;
+        let cells = pop_u64(stack)?;
+
+        let prices = config
+            .get_storage_prices()
+            .context("Storage prices not found in config")?;
+        let mut latest: Option<StoragePrices> = None;
+        for entry in prices.values() {
+            let entry = entry?;
+            if latest.map_or(true, |l| l.utime_since <= entry.utime_since) {
+                latest = Some(entry);
+            }
+        }
+        let prices = latest.context("Storage prices are empty")?;
+
+        let (bit_price, cell_price) = if masterchain {
+            (prices.mc_bit_price_ps, prices.mc_cell_price_ps)
+        } else {
+            (prices.bit_price_ps, prices.cell_price_ps)
+        };
+
+        let total = BigInt::from(bits) * bit_price + BigInt::from(cells) * cell_price;
+        stack.push_int(shr_ceil(total * seconds, 16))
+    }
+
+    /// Computes the forward fee of a message with the specified number of
+    /// `cells` and `bits` (excluding the root cell), using the config
+    /// param 24 (masterchain) or 25.
+    #[cmd(
+        name = "fwd-fee",
+        stack,
+        effect = "cells bits mc? D-config -- fee",
+        nonstandard
+    )]
+    fn interpret_fwd_fee(stack: &mut Stack) -> Result<()> {
+        let config = pop_config(stack)?;
+        let masterchain = stack.pop_bool()?;
+        let bits = pop_u64(stack)?;
+        let cells = pop_u64(stack)?;
+
+        let prices = config
+            .get_msg_forward_prices(masterchain)
+            .context("Message forward prices not found in config")?;
+
+        let total = BigInt::from(bits) * prices.bit_price + BigInt::from(cells) * prices.cell_price;
+        stack.push_int(shr_ceil(total, 16) + prices.lump_price)
+    }
+}
+
+fn pop_config(stack: &mut Stack) -> Result<BlockchainConfig> {
+    let params = stack.pop_cell()?;
+    Ok(BlockchainConfig {
+        address: HashBytes::ZERO,
+        params: Dict::from_raw(Some(params.as_ref().clone())),
+    })
+}
+
+fn pop_u64(stack: &mut Stack) -> Result<u64> {
+    let int = stack.pop_int()?;
+    u64::try_from(int.as_ref()).context("Expected a non-negative 64-bit integer")
+}
+
+fn shr_ceil(value: BigInt, shift: u32) -> BigInt {
+    let mask = (BigInt::from(1) << shift) - 1;
+    (value + mask) >> shift
+}
@@ -0,0 +1,497 @@
+use std::fmt::Write;
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use num_bigint::BigInt;
+
+use crate::core::*;
+use crate::util::write_json_string;
+
+pub struct JsonUtils;
+
+#[fift_module]
+impl JsonUtils {
+    /// Parses a JSON string into Fift values.
+    ///
+    /// Objects become hashmaps with string keys (so an empty object becomes `null`
+    /// and fields with `null` values are omitted), arrays become tuples, integers become ints, `true`/`false` become `-1`/`0`
+    /// and `null` stays `null`. Fractional numbers are kept as strings.
+    #[cmd(name = "json>tuple", stack, effect = "S -- x", nonstandard)]
+    fn interpret_json_to_tuple(stack: &mut Stack) -> Result<()> {
+        let s = stack.pop_string()?;
+        let json = JsonParser::parse(&s).context("Invalid JSON")?;
+        stack.push_raw(json_to_value(json)?)
+    }
+
+    /// Serializes Fift values into a JSON string.
+    ///
+    /// Hashmaps become objects with sorted keys, tuples become arrays,
+    /// named atoms `true`, `false` and `null` become the corresponding literals.
+    #[cmd(
+        name = "tuple>json",
+        stack,
+        args(pretty = false),
+        effect = "x -- S",
+        nonstandard
+    )]
+    #[cmd(
+        name = "tuple>json-pretty",
+        stack,
+        args(pretty = true),
+        effect = "x -- S",
+        nonstandard
+    )]
+    fn interpret_tuple_to_json(stack: &mut Stack, pretty: bool) -> Result<()> {
+        let value = stack.pop()?;
+        let mut out = String::new();
+        write_json(&mut out, value.as_ref(), pretty.then_some(0))?;
+        stack.push(out)
+    }
+
+    /// Looks up a value in the persistent key-value store of the environment.
+    ///
+    /// Values are stored as JSON, so only values supported by `tuple>json` survive between runs.
+    #[cmd(name = "kv-get", effect = "S -- x -1 or 0", nonstandard)]
+    fn interpret_kv_get(ctx: &mut Context) -> Result<()> {
+        let key = HashMapTreeKey::from(ctx.stack.pop_string()?);
+        let map = load_kv_store(ctx)?.1;
+        match HashMapTreeNode::lookup(&map, key) {
+            Some(node) => {
+                ctx.stack.push_raw(node.value.clone())?;
+                ctx.stack.push_bool(true)
+            }
+            None => ctx.stack.push_bool(false),
+        }
+    }
+
+    /// Stores a value in the persistent key-value store of the environment,
+    /// `null` removes the key.
+    #[cmd(name = "kv-put", effect = "x S --", nonstandard)]
+    fn interpret_kv_put(ctx: &mut Context) -> Result<()> {
+        let key = HashMapTreeKey::from(ctx.stack.pop_string()?);
+        let value = ctx.stack.pop()?;
+        let (path, mut map) = load_kv_store(ctx)?;
+        HashMapTreeNode::set(&mut map, &key, &value);
+
+        let mut out = String::new();
+        match map {
+            Some(map) => write_json(&mut out, map.as_ref(), Some(0))?,
+            None => out.push_str("{}"),
+        }
+        out.push('\n');
+        ctx.env
+            .write_file(&path, out.as_bytes(), WriteMode::Truncate)
+            .with_context(|| format!("Failed to write the key-value store `{path}`"))
+    }
+}
+
+fn load_kv_store(ctx: &mut Context) -> Result<(String, Option<Rc<HashMapTreeNode>>)> {
+    let path = ctx
+        .env
+        .kv_store_path()
+        .context("Key-value store is not configured")?;
+    if !ctx.file_exists(&path) {
+        return Ok((path, None));
+    }
+
+    let data = ctx.read_file(&path)?;
+    let map = std::str::from_utf8(&data)
+        .map_err(anyhow::Error::from)
+        .and_then(JsonParser::parse)
+        .and_then(|json| match json {
+            Json::Object(items) => json_to_map(items),
+            _ => anyhow::bail!("Expected a JSON object"),
+        })
+        .with_context(|| format!("Invalid key-value store `{path}`"))?;
+    Ok((path, map))
+}
+
+fn json_to_value(json: Json) -> Result<Rc<dyn StackValue>> {
+    Ok(match json {
+        Json::Null => Stack::make_null(),
+        Json::Bool(value) => Rc::new(-BigInt::from(value as u8)),
+        Json::Number(n) => match n.parse::<BigInt>() {
+            Ok(int) => Rc::new(int),
+            Err(_) => Rc::new(n),
+        },
+        Json::String(s) => Rc::new(s),
+        Json::Array(items) => Rc::new(
+            items
+                .into_iter()
+                .map(json_to_value)
+                .collect::<Result<StackTuple>>()?,
+        ),
+        Json::Object(items) => match json_to_map(items)? {
+            Some(map) => map,
+            None => Stack::make_null(),
+        },
+    })
+}
+
+fn json_to_map(items: Vec<(String, Json)>) -> Result<Option<Rc<HashMapTreeNode>>> {
+    let mut map = None;
+    for (key, value) in items {
+        let key = HashMapTreeKey::from(key);
+        HashMapTreeNode::set(&mut map, &key, &json_to_value(value)?);
+    }
+    Ok(map)
+}
+
+fn write_json(out: &mut String, value: &dyn StackValue, indent: Option<usize>) -> Result<()> {
+    const INDENT: usize = 2;
+
+    let write_newline = |out: &mut String, indent: Option<usize>| {
+        if let Some(indent) = indent {
+            out.push('\n');
+            out.extend(std::iter::repeat(' ').take(indent));
+        }
+    };
+    let nested = indent.map(|indent| indent + INDENT);
+
+    match value.ty() {
+        StackValueType::Null => out.push_str("null"),
+        StackValueType::Int => write!(out, "{}", value.as_int()?)?,
+        StackValueType::String => write_json_string(out, value.as_string()?)?,
+        StackValueType::Atom => match value.as_atom()? {
+            stack::Atom::Named(name) if matches!(name.as_ref(), "true" | "false" | "null") => {
+                out.push_str(name)
+            }
+            atom => anyhow::bail!("Atom {atom} cannot be converted to JSON"),
+        },
+        StackValueType::Tuple => {
+            let tuple = value.as_tuple()?;
+            out.push('[');
+            for (i, item) in tuple.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_newline(out, nested);
+                write_json(out, item.as_ref(), nested)?;
+            }
+            if !tuple.is_empty() {
+                write_newline(out, indent);
+            }
+            out.push(']');
+        }
+        StackValueType::HashMap => {
+            let mut entries = Vec::new();
+            for entry in value.as_hashmap()? {
+                let key = &entry.key.stack_value;
+                let key = match key.ty() {
+                    StackValueType::String => key.as_string()?.to_owned(),
+                    StackValueType::Int => key.as_int()?.to_string(),
+                    ty => anyhow::bail!("Unsupported JSON object key type: {ty:?}"),
+                };
+                entries.push((key, entry.value.clone()));
+            }
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_newline(out, nested);
+                write_json_string(out, key)?;
+                out.push_str(if indent.is_some() { ": " } else { ":" });
+                write_json(out, value.as_ref(), nested)?;
+            }
+            if !entries.is_empty() {
+                write_newline(out, indent);
+            }
+            out.push('}');
+        }
+        ty => anyhow::bail!("Value of type {ty:?} cannot be converted to JSON"),
+    }
+    Ok(())
+}
+
+// === JSON ===
+
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+pub(crate) struct JsonParser<'a> {
+    s: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    // NOTE: parsing is recursive, so deeper documents are rejected to not overflow the stack
+    const MAX_DEPTH: usize = 256;
+
+    pub fn parse(s: &'a str) -> Result<Json> {
+        let mut parser = Self {
+            s,
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        anyhow::ensure!(
+            parser.pos == s.len(),
+            "Trailing characters at {}",
+            parser.pos
+        );
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Json> {
+        anyhow::ensure!(
+            self.depth < Self::MAX_DEPTH,
+            "JSON is too deep at {} (max depth {})",
+            self.pos,
+            Self::MAX_DEPTH
+        );
+        self.depth += 1;
+        let res = self.parse_value_impl();
+        self.depth -= 1;
+        res
+    }
+
+    fn parse_value_impl(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        let rest = &self.s[self.pos..];
+        Ok(
+            match rest.chars().next().context("Unexpected end of JSON")? {
+                '{' => {
+                    self.pos += 1;
+                    let mut items = Vec::new();
+                    if !self.try_consume('}') {
+                        loop {
+                            self.skip_whitespace();
+                            let key = self.parse_string()?;
+                            self.expect(':')?;
+                            items.push((key, self.parse_value()?));
+                            if self.try_consume('}') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+                    Json::Object(items)
+                }
+                '[' => {
+                    self.pos += 1;
+                    let mut items = Vec::new();
+                    if !self.try_consume(']') {
+                        loop {
+                            items.push(self.parse_value()?);
+                            if self.try_consume(']') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+                    Json::Array(items)
+                }
+                '"' => Json::String(self.parse_string()?),
+                _ if rest.starts_with("null") => {
+                    self.pos += 4;
+                    Json::Null
+                }
+                _ if rest.starts_with("true") => {
+                    self.pos += 4;
+                    Json::Bool(true)
+                }
+                _ if rest.starts_with("false") => {
+                    self.pos += 5;
+                    Json::Bool(false)
+                }
+                c if c == '-' || c.is_ascii_digit() => {
+                    let len = number_len(rest)
+                        .with_context(|| format!("Invalid number at {}", self.pos))?;
+                    self.pos += len;
+                    Json::Number(rest[..len].to_owned())
+                }
+                c => anyhow::bail!("Unexpected character `{c}` at {}", self.pos),
+            },
+        )
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        let mut chars = self.s[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(result);
+                }
+                '\\' => {
+                    let (_, c) = chars.next().context("Unexpected end of JSON")?;
+                    result.push(match c {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let mut code = read_hex4(&mut chars)?;
+                            // NOTE: characters outside of the BMP are encoded as surrogate pairs
+                            if (0xd800..0xdc00).contains(&code) {
+                                anyhow::ensure!(
+                                    matches!(chars.next(), Some((_, '\\')))
+                                        && matches!(chars.next(), Some((_, 'u'))),
+                                    "Unpaired surrogate in escape"
+                                );
+                                let low = read_hex4(&mut chars)?;
+                                anyhow::ensure!(
+                                    (0xdc00..0xe000).contains(&low),
+                                    "Unpaired surrogate in escape"
+                                );
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).context("Invalid escape")?
+                        }
+                        c => c,
+                    });
+                }
+                c => result.push(c),
+            }
+        }
+        anyhow::bail!("Unterminated JSON string")
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn try_consume(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        anyhow::ensure!(self.try_consume(c), "Expected `{c}` at {}", self.pos);
+        Ok(())
+    }
+}
+
+fn read_hex4(chars: &mut std::str::CharIndices<'_>) -> Result<u32> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let (_, c) = chars.next().context("Unexpected end of JSON")?;
+        code = code * 16 + c.to_digit(16).context("Invalid escape")?;
+    }
+    Ok(code)
+}
+
+/// Returns the length of the JSON number at the start of `s`.
+fn number_len(s: &str) -> Option<usize> {
+    let s = s.as_bytes();
+    let digits = |pos: usize| s[pos..].iter().take_while(|c| c.is_ascii_digit()).count();
+
+    let mut pos = (s.first() == Some(&b'-')) as usize;
+    match digits(pos) {
+        0 => return None,
+        // NOTE: leading zeros are not allowed
+        n if n > 1 && s[pos] == b'0' => return None,
+        n => pos += n,
+    }
+    if s.get(pos) == Some(&b'.') {
+        match digits(pos + 1) {
+            0 => return None,
+            n => pos += 1 + n,
+        }
+    }
+    if matches!(s.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        if matches!(s.get(pos), Some(b'+' | b'-')) {
+            pos += 1;
+        }
+        match digits(pos) {
+            0 => return None,
+            n => pos += n,
+        }
+    }
+    // NOTE: numbers must be followed by a delimiter (e.g. `1-2` is invalid)
+    match s.get(pos) {
+        Some(c) if c.is_ascii_alphanumeric() || matches!(c, b'-' | b'+' | b'.') => None,
+        _ => Some(pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(s: &str) -> Result<String> {
+        let value = json_to_value(JsonParser::parse(s)?)?;
+        let mut out = String::new();
+        write_json(&mut out, value.as_ref(), None)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        for s in [
+            "null",
+            "[]",
+            "[1,-2,[\"a\",null],123456789012345678901234567890]",
+            "{\"a\":[0,-1],\"b\":{\"c\":\"d\"}}",
+            "\"quote \\\" backslash \\\\ newline \\n\"",
+        ] {
+            assert_eq!(roundtrip(s).unwrap(), s);
+        }
+
+        assert_eq!(roundtrip(" [ 1 , true , false ] ").unwrap(), "[1,-1,0]");
+        assert_eq!(
+            roundtrip("[1.5e-3, 0.25, 2E+10]").unwrap(),
+            "[\"1.5e-3\",\"0.25\",\"2E+10\"]"
+        );
+        assert_eq!(roundtrip("\"\\u0041\\u00e9\"").unwrap(), "\"Aé\"");
+
+        let Json::String(s) = JsonParser::parse("\"\\ud83d\\ude00\"").unwrap() else {
+            panic!("expected a string");
+        };
+        assert_eq!(s, "\u{1f600}");
+    }
+
+    #[test]
+    fn invalid_json() {
+        for s in [
+            "",
+            "-",
+            "--1",
+            "1-2",
+            "01",
+            "1.",
+            ".5",
+            "1e",
+            "1e+",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"abc",
+            "\"\\ud83d\"",
+            "\"\\ud83d\\u0041\"",
+            "\"\\ude00\"",
+            "\"\\u12\"",
+            "[1] 2",
+        ] {
+            assert!(JsonParser::parse(s).is_err(), "{s:?}");
+        }
+
+        // NOTE: deep documents are rejected instead of overflowing the stack
+        let s = "[".repeat(200_000);
+        assert!(JsonParser::parse(&s).is_err());
+
+        let depth = JsonParser::MAX_DEPTH - 1;
+        let s = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(JsonParser::parse(&s).is_ok());
+    }
+}
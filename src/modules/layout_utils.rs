@@ -0,0 +1,317 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use everscale_types::prelude::*;
+use num_traits::Zero;
+
+use crate::core::*;
+use crate::util::*;
+
+pub struct LayoutUtils;
+
+#[fift_module]
+impl LayoutUtils {
+    /// Reads a field of the data cell described by the layout.
+    ///
+    /// Layout is a list of `name:type` pairs, where type is one of
+    /// `uintN`, `intN`, `bitsN`, `bool`, `coins`, `addr`, `cell`, `dict`
+    /// or a nested layout in a reference `^( ... )`.
+    /// Nested fields are addressed by a dotted path, e.g. `params.owner`.
+    #[cmd(
+        name = "data-field@",
+        stack,
+        effect = "c S-layout S-path -- x",
+        nonstandard
+    )]
+    fn interpret_data_field_get(stack: &mut Stack) -> Result<()> {
+        let path = stack.pop_string()?;
+        let layout = parse_layout(&stack.pop_string()?)?;
+        let cell = stack.pop_cell()?;
+
+        let path = path.split('.').collect::<Vec<_>>();
+        stack.push_raw(read_field(cell.as_ref(), &layout, &path)?)
+    }
+
+    /// Replaces a field of the data cell described by the layout.
+    #[cmd(
+        name = "data-field!",
+        stack,
+        effect = "x c S-layout S-path -- c'",
+        nonstandard
+    )]
+    fn interpret_data_field_set(stack: &mut Stack) -> Result<()> {
+        let path = stack.pop_string()?;
+        let layout = parse_layout(&stack.pop_string()?)?;
+        let cell = stack.pop_cell()?;
+        let value = stack.pop()?;
+
+        let path = path.split('.').collect::<Vec<_>>();
+        stack.push(patch_field(cell.as_ref(), &layout, &path, value.as_ref())?)
+    }
+}
+
+struct Field {
+    name: String,
+    ty: FieldType,
+}
+
+enum FieldType {
+    Uint(u16),
+    Int(u16),
+    Bits(u16),
+    Bool,
+    Coins,
+    Addr,
+    Cell,
+    Dict,
+    Nested(Vec<Field>),
+}
+
+impl FieldType {
+    fn size(&self, cs: &CellSlice<'_>) -> Result<(u16, u8)> {
+        Ok(match self {
+            Self::Uint(bits) | Self::Int(bits) | Self::Bits(bits) => (*bits, 0),
+            Self::Bool => (1, 0),
+            Self::Coins => (4 + cs.get_small_uint(0, 4)? as u16 * 8, 0),
+            Self::Addr => (msg_address_bit_len(cs)?, 0),
+            Self::Cell | Self::Nested(_) => (0, 1),
+            Self::Dict => {
+                let has_root = cs.get_bit(0)?;
+                (1, has_root as u8)
+            }
+        })
+    }
+
+    fn load(&self, cell: &Cell, mut cs: CellSlice<'_>) -> Result<Rc<dyn StackValue>> {
+        Ok(match self {
+            Self::Uint(bits) => Rc::new(load_int_from_slice(&mut cs, *bits, false)?),
+            Self::Int(bits) => Rc::new(load_int_from_slice(&mut cs, *bits, true)?),
+            Self::Bool => Rc::new(-num_bigint::BigInt::from(cs.load_bit()? as u8)),
+            Self::Coins => {
+                let len = cs.load_small_uint(4)? as u16;
+                Rc::new(load_int_from_slice(&mut cs, len * 8, false)?)
+            }
+            Self::Bits(_) | Self::Addr => Rc::new(OwnedCellSlice::from((cell.clone(), cs.range()))),
+            Self::Cell | Self::Nested(_) => Rc::new(cs.load_reference_cloned()?),
+            Self::Dict => match cs.load_bit()? {
+                true => Rc::new(cs.load_reference_cloned()?),
+                false => Stack::make_null(),
+            },
+        })
+    }
+
+    fn store(&self, b: &mut CellBuilder, value: &dyn StackValue) -> Result<()> {
+        match self {
+            Self::Uint(bits) => store_int_to_builder(b, value.as_int()?, *bits, false)?,
+            Self::Int(bits) => store_int_to_builder(b, value.as_int()?, *bits, true)?,
+            Self::Bits(bits) => match value.ty() {
+                StackValueType::Int => store_int_to_builder(b, value.as_int()?, *bits, false)?,
+                StackValueType::Bytes => {
+                    let bytes = value.as_bytes()?;
+                    anyhow::ensure!(
+                        bytes.len() * 8 == *bits as usize,
+                        "Expected {bits} bits, found {} bytes",
+                        bytes.len()
+                    );
+                    b.store_raw(bytes, *bits)?;
+                }
+                _ => {
+                    let cs = value.as_slice()?;
+                    anyhow::ensure!(
+                        cs.remaining_bits() == *bits && cs.is_refs_empty(),
+                        "Expected {bits} bits, found {} bits",
+                        cs.remaining_bits()
+                    );
+                    b.store_slice_data(cs)?;
+                }
+            },
+            Self::Bool => b.store_bit(!value.as_int()?.is_zero())?,
+            Self::Coins => {
+                let int = value.as_int()?;
+                let len = (bitsize(int, false) + 7) / 8;
+                anyhow::ensure!(len < 16, "Coins value is too big");
+                b.store_small_uint(len as u8, 4)?;
+                store_int_to_builder(b, int, len * 8, false)?;
+            }
+            Self::Addr => {
+                let cs = value.as_slice()?;
+                anyhow::ensure!(
+                    msg_address_bit_len(&cs)? == cs.remaining_bits() && cs.is_refs_empty(),
+                    "Invalid address slice"
+                );
+                b.store_slice_data(cs)?;
+            }
+            Self::Cell | Self::Nested(_) => b.store_reference(value.as_cell()?.clone())?,
+            Self::Dict => {
+                if value.is_null() {
+                    b.store_bit_zero()?;
+                } else {
+                    b.store_bit_one()?;
+                    b.store_reference(value.as_cell()?.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_field(cell: &Cell, layout: &[Field], path: &[&str]) -> Result<Rc<dyn StackValue>> {
+    let (name, rest) = path.split_first().context("Empty field path")?;
+
+    let mut cs = CellSlice::new(cell.as_ref())?;
+    for field in layout {
+        let (bits, refs) = field.ty.size(&cs)?;
+        let part = cs.get_prefix(bits, refs);
+        cs.advance(bits, refs)?;
+
+        if field.name != *name {
+            continue;
+        }
+
+        return match (&field.ty, rest) {
+            (_, []) => field.ty.load(cell, part),
+            (FieldType::Nested(layout), rest) => {
+                let child = part.get_reference_cloned(0)?;
+                read_field(&child, layout, rest)
+            }
+            _ => anyhow::bail!("Field `{name}` has no nested layout"),
+        };
+    }
+
+    anyhow::bail!("Field `{name}` not found")
+}
+
+fn patch_field(
+    cell: &Cell,
+    layout: &[Field],
+    path: &[&str],
+    value: &dyn StackValue,
+) -> Result<Cell> {
+    let (name, rest) = path.split_first().context("Empty field path")?;
+
+    let mut found = false;
+    let mut cs = CellSlice::new(cell.as_ref())?;
+    let mut b = CellBuilder::new();
+    for field in layout {
+        let (bits, refs) = field.ty.size(&cs)?;
+        let part = cs.get_prefix(bits, refs);
+        cs.advance(bits, refs)?;
+
+        if field.name != *name {
+            b.store_slice(part)?;
+            continue;
+        }
+
+        match (&field.ty, rest) {
+            (_, []) => field.ty.store(&mut b, value)?,
+            (FieldType::Nested(layout), rest) => {
+                let child = part.get_reference_cloned(0)?;
+                b.store_reference(patch_field(&child, layout, rest, value)?)?;
+            }
+            _ => anyhow::bail!("Field `{name}` has no nested layout"),
+        }
+        found = true;
+    }
+    anyhow::ensure!(found, "Field `{name}` not found");
+
+    // Keep data which is not described by the layout
+    b.store_slice(cs)?;
+    Ok(b.build()?)
+}
+
+fn parse_layout(s: &str) -> Result<Vec<Field>> {
+    let mut tokens = tokenize_layout(s).into_iter().peekable();
+    let layout = parse_layout_fields(&mut tokens)?;
+    if let Some(token) = tokens.next() {
+        anyhow::bail!("Unexpected `{token}` in layout");
+    }
+    Ok(layout)
+}
+
+fn parse_layout_fields<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Result<Vec<Field>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut fields = Vec::new();
+    while let Some(&token) = tokens.peek() {
+        if token == ")" {
+            break;
+        }
+        tokens.next();
+
+        let name = token;
+        anyhow::ensure!(
+            tokens.next() == Some(":"),
+            "Expected `:` after field `{name}`"
+        );
+        anyhow::ensure!(
+            !fields.iter().any(|f: &Field| f.name == name),
+            "Duplicate field `{name}`"
+        );
+
+        let ty = match tokens.next() {
+            Some("^") => {
+                anyhow::ensure!(tokens.next() == Some("("), "Expected `(` after `^`");
+                let nested = parse_layout_fields(tokens)?;
+                anyhow::ensure!(tokens.next() == Some(")"), "Expected `)`");
+                FieldType::Nested(nested)
+            }
+            Some(ty) => parse_field_type(ty)?,
+            None => anyhow::bail!("Expected type of field `{name}`"),
+        };
+
+        fields.push(Field {
+            name: name.to_owned(),
+            ty,
+        });
+    }
+    Ok(fields)
+}
+
+fn parse_field_type(ty: &str) -> Result<FieldType> {
+    let parse_bits = |bits: &str, max: u16| {
+        let bits = bits.parse::<u16>().ok().filter(|&b| b <= max);
+        bits.with_context(|| format!("Invalid field type `{ty}`"))
+    };
+
+    Ok(match ty {
+        "bool" => FieldType::Bool,
+        "coins" => FieldType::Coins,
+        "addr" => FieldType::Addr,
+        "cell" => FieldType::Cell,
+        "dict" => FieldType::Dict,
+        _ => {
+            if let Some(bits) = ty.strip_prefix("uint") {
+                FieldType::Uint(parse_bits(bits, 256)?)
+            } else if let Some(bits) = ty.strip_prefix("int") {
+                FieldType::Int(parse_bits(bits, 257)?)
+            } else if let Some(bits) = ty.strip_prefix("bits") {
+                FieldType::Bits(parse_bits(bits, 1023)?)
+            } else {
+                anyhow::bail!("Unknown field type `{ty}`")
+            }
+        }
+    })
+}
+
+fn tokenize_layout(s: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        let is_punct = matches!(c, ':' | '^' | '(' | ')');
+        if c.is_whitespace() || is_punct {
+            if let Some(start) = start.take() {
+                tokens.push(&s[start..i]);
+            }
+            if is_punct {
+                tokens.push(&s[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&s[start..]);
+    }
+    tokens
+}
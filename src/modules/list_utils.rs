@@ -0,0 +1,105 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+
+use crate::core::*;
+
+/// Native Lisp-style list primitives (see `Lists.fif`).
+pub struct ListUtils;
+
+#[fift_module]
+impl ListUtils {
+    #[cmd(name = "cons", stack, effect = "x l -- (x . l)")]
+    fn interpret_cons(stack: &mut Stack) -> Result<()> {
+        let tail = stack.pop()?;
+        let head = stack.pop()?;
+        stack.push(vec![head, tail])
+    }
+
+    #[cmd(name = "uncons", stack, effect = "(x . l) -- x l")]
+    fn interpret_uncons(stack: &mut Stack) -> Result<()> {
+        let (head, tail) = uncons(stack.pop()?.as_ref())?;
+        stack.push_raw(head)?;
+        stack.push_raw(tail)
+    }
+
+    #[cmd(name = "car", stack, args(idx = 0), effect = "(x . l) -- x")]
+    #[cmd(name = "cdr", stack, args(idx = 1), effect = "(x . l) -- l")]
+    fn interpret_car_cdr(stack: &mut Stack, idx: usize) -> Result<()> {
+        let tuple = stack.pop_tuple()?;
+        let value = tuple
+            .get(idx)
+            .with_context(|| format!("Index {idx} is out of the tuple range"))?
+            .clone();
+        stack.push_raw(value)
+    }
+
+    #[cmd(name = "list", stack, effect = "x1 .. xn n -- (x1 .. xn)")]
+    fn interpret_list(stack: &mut Stack) -> Result<()> {
+        let n = stack.pop_smallint_range(0, u32::MAX)? as usize;
+        stack.check_underflow(n)?;
+        let mut list = Stack::make_null();
+        for _ in 0..n {
+            list = Rc::new(vec![stack.pop()?, list]);
+        }
+        stack.push_raw(list)
+    }
+
+    /// Returns the first entry `(a . v)` of the list with the first item equal to `a`.
+    #[cmd(name = "assq", stack, effect = "a l -- (a . v) -1 or 0")]
+    fn interpret_assq(stack: &mut Stack) -> Result<()> {
+        let mut list = stack.pop()?;
+        let key = stack.pop()?;
+        while !list.is_null() {
+            let (entry, tail) = uncons(list.as_ref())?;
+            let first = entry
+                .as_tuple()?
+                .first()
+                .context("Index 0 is out of the tuple range")?;
+            if first.is_equal(key.as_ref()) {
+                stack.push_raw(entry)?;
+                return stack.push_bool(true);
+            }
+            list = tail;
+        }
+        stack.push_bool(false)
+    }
+
+    #[cmd(name = "length", stack, effect = "l -- n")]
+    fn interpret_length(stack: &mut Stack) -> Result<()> {
+        let len = list_items(stack.pop()?)?.len();
+        stack.push_int(len)
+    }
+
+    #[cmd(name = "reverse-list", stack, effect = "(a1 .. an) -- (an .. a1)")]
+    fn interpret_reverse_list(stack: &mut Stack) -> Result<()> {
+        let mut list = Stack::make_null();
+        for item in list_items(stack.pop()?)? {
+            list = Rc::new(vec![item, list]);
+        }
+        stack.push_raw(list)
+    }
+
+    #[cmd(name = "list>tuple", stack, effect = "(a1 .. an) -- [a1 .. an]")]
+    fn interpret_list_to_tuple(stack: &mut Stack) -> Result<()> {
+        let items = list_items(stack.pop()?)?;
+        stack.push(items)
+    }
+}
+
+fn uncons(value: &dyn StackValue) -> Result<(Rc<dyn StackValue>, Rc<dyn StackValue>)> {
+    match value.as_tuple()?.as_slice() {
+        [head, tail] => Ok((head.clone(), tail.clone())),
+        tuple => anyhow::bail!("Tuple size mismatch. Expected: 2, actual: {}", tuple.len()),
+    }
+}
+
+fn list_items(mut list: Rc<dyn StackValue>) -> Result<StackTuple> {
+    let mut items = Vec::new();
+    while !list.is_null() {
+        let (head, tail) = uncons(list.as_ref())?;
+        items.push(head);
+        list = tail;
+    }
+    Ok(items)
+}
@@ -0,0 +1,201 @@
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha2::Sha512;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::core::*;
+
+/// TON mnemonic phrases (24 words from the BIP39 english word list).
+pub struct MnemonicUtils;
+
+#[fift_module]
+impl MnemonicUtils {
+    // NOTE: phrases are always generated from the OS RNG,
+    // even for deterministic runs
+    #[cmd(name = "mnemonic-new", stack, effect = "-- S", nonstandard)]
+    fn interpret_mnemonic_new(stack: &mut Stack) -> Result<()> {
+        let words = word_list();
+        let phrase = loop {
+            let phrase = (0..MNEMONIC_WORDS)
+                .map(|_| words[OsRng.gen_range(0..words.len())])
+                .collect::<Vec<_>>()
+                .join(" ");
+            if is_basic_seed(&mnemonic_to_entropy(&phrase, "")) {
+                break phrase;
+            }
+        };
+        stack.push(phrase)
+    }
+
+    #[cmd(name = "mnemonic-validate", stack, effect = "S -- ?", nonstandard)]
+    fn interpret_mnemonic_validate(stack: &mut Stack) -> Result<()> {
+        let phrase = stack.pop_string()?;
+        stack.push_bool(normalize_mnemonic(&phrase, "").is_ok())
+    }
+
+    /// Derives an Ed25519 private key from the mnemonic phrase
+    /// (and the password for phrases which were generated with it).
+    #[cmd(
+        name = "mnemonic>priv",
+        stack,
+        args(with_password = false),
+        effect = "S -- B",
+        nonstandard
+    )]
+    #[cmd(
+        name = "mnemonic-password>priv",
+        stack,
+        args(with_password = true),
+        effect = "S S' -- B",
+        nonstandard
+    )]
+    fn interpret_mnemonic_to_private_key(stack: &mut Stack, with_password: bool) -> Result<()> {
+        let password = if with_password {
+            stack.pop_string()?.nfkd().collect()
+        } else {
+            String::new()
+        };
+        let phrase = normalize_mnemonic(&stack.pop_string()?, &password)?;
+        let entropy = mnemonic_to_entropy(&phrase, &password);
+
+        let mut seed = [0; 64];
+        pbkdf2_sha512(&entropy, b"TON default seed", 100000, &mut seed);
+        stack.push(seed[..32].to_vec())
+    }
+}
+
+const MNEMONIC_WORDS: usize = 24;
+
+fn word_list() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| include_str!("bip39_english.txt").lines().collect())
+}
+
+/// Returns an NFKD-normalized phrase with words separated by a single space.
+///
+/// The password must be already normalized.
+fn normalize_mnemonic(phrase: &str, password: &str) -> Result<String> {
+    let words = word_list();
+    let phrase = phrase.nfkd().collect::<String>();
+    let phrase = phrase
+        .split_whitespace()
+        .map(|word| {
+            let word = word.to_lowercase();
+            anyhow::ensure!(
+                words.binary_search(&word.as_str()).is_ok(),
+                "Unknown mnemonic word `{word}`"
+            );
+            Ok(word)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    anyhow::ensure!(
+        phrase.len() == MNEMONIC_WORDS,
+        "Expected {MNEMONIC_WORDS} mnemonic words, found {}",
+        phrase.len()
+    );
+
+    let phrase = phrase.join(" ");
+    if !password.is_empty() {
+        let entropy = mnemonic_to_entropy(&phrase, "");
+        anyhow::ensure!(
+            is_password_seed(&entropy) && !is_basic_seed(&entropy),
+            "Mnemonic phrase does not need a password"
+        );
+    }
+    anyhow::ensure!(
+        is_basic_seed(&mnemonic_to_entropy(&phrase, password)),
+        "Invalid mnemonic checksum"
+    );
+    Ok(phrase)
+}
+
+fn mnemonic_to_entropy(phrase: &str, password: &str) -> [u8; 64] {
+    hmac_sha512(phrase.as_bytes(), password.as_bytes())
+}
+
+fn is_basic_seed(entropy: &[u8; 64]) -> bool {
+    // NOTE: iterations count is `max(1, floor(100000 / 256))`
+    let mut seed = [0; 64];
+    pbkdf2_sha512(entropy, b"TON seed version", 390, &mut seed);
+    seed[0] == 0
+}
+
+fn is_password_seed(entropy: &[u8; 64]) -> bool {
+    let mut seed = [0; 64];
+    pbkdf2_sha512(entropy, b"TON fast seed version", 1, &mut seed);
+    seed[0] == 1
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn pbkdf2_sha512(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, iterations, output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_known_answer() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex::encode(hmac_sha512(b"Jefe", b"what do ya want for nothing?")),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    #[test]
+    fn pbkdf2_known_answers() {
+        let mut output = [0; 64];
+        pbkdf2_sha512(b"password", b"salt", 1, &mut output);
+        assert_eq!(
+            hex::encode(output),
+            "867f70cf1ade02cff3752599a3a53dc4af34c7a669815ae5d513554e1c8cf252\
+             c02d470a285a0501bad999bfe943c08f050235d7d68b1da55e63f73b60a57fce"
+        );
+        pbkdf2_sha512(b"password", b"salt", 2, &mut output);
+        assert_eq!(
+            hex::encode(output),
+            "e1d9c16aa681708a45f5c7c4e215ceb66e011a2e9f0040713f18aefdb866d53c\
+             f76cab2868a39b9f7840edce4fef5a82be67335c77a6068e04112754f27ccf4e"
+        );
+    }
+
+    #[test]
+    fn phrases_are_normalized() {
+        let mut stack = Stack::new(None);
+        interpret_mnemonic_new(&mut stack).unwrap();
+        let phrase = stack.pop_string().unwrap();
+
+        // Fullwidth letters and ideographic spaces are decomposed by NFKD
+        let fullwidth = phrase
+            .chars()
+            .map(|c| match c {
+                ' ' => '\u{3000}',
+                c => char::from_u32(c as u32 - 'a' as u32 + 0xff41).unwrap(),
+            })
+            .collect::<String>();
+        assert_eq!(normalize_mnemonic(&fullwidth, "").unwrap(), *phrase);
+
+        let derive = |phrase: &str| {
+            let mut stack = Stack::new(None);
+            stack.push(phrase.to_owned()).unwrap();
+            interpret_mnemonic_to_private_key(&mut stack, false).unwrap();
+            stack.pop_bytes().unwrap()
+        };
+        assert_eq!(derive(&phrase), derive(&fullwidth.to_uppercase()));
+
+        // Basic phrases don't accept passwords
+        assert!(normalize_mnemonic(&phrase, "secret").is_err());
+    }
+}
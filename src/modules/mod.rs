@@ -2,27 +2,62 @@ use std::iter::Peekable;
 use std::rc::Rc;
 
 use anyhow::{Context as _, Result};
+use everscale_types::prelude::*;
+use sha2::Digest;
 
 use crate::core::*;
+use crate::util::{decode_boc, ImmediateInt};
 
+#[cfg(feature = "abi")]
+pub use self::abi_utils::AbiUtils;
+pub use self::address_utils::AddressUtils;
+pub use self::args::CmdArgsUtils;
 pub use self::arithmetic::Arithmetic;
 pub use self::cell_utils::CellUtils;
 pub use self::control::Control;
+#[cfg(feature = "crypto")]
 pub use self::crypto::Crypto;
 pub use self::debug_utils::DebugUtils;
 pub use self::dict_utils::DictUtils;
+pub use self::fee_utils::FeeUtils;
+pub use self::json_utils::JsonUtils;
+pub use self::layout_utils::LayoutUtils;
+pub use self::list_utils::ListUtils;
+#[cfg(feature = "mnemonic")]
+pub use self::mnemonic_utils::MnemonicUtils;
+pub use self::msg_utils::MsgUtils;
+#[cfg(feature = "crypto")]
+pub use self::multisig_utils::MultisigUtils;
+pub use self::smc_info_utils::SmcInfoUtils;
 pub use self::stack_utils::StackUtils;
 pub use self::string_utils::StringUtils;
+#[cfg(feature = "vm")]
 pub use self::vm_utils::VmUtils;
 
+#[cfg(feature = "abi")]
+mod abi_utils;
+mod address_utils;
+mod args;
 mod arithmetic;
 mod cell_utils;
 mod control;
+#[cfg(feature = "crypto")]
 mod crypto;
 mod debug_utils;
 mod dict_utils;
+mod fee_utils;
+mod json_utils;
+mod layout_utils;
+mod list_utils;
+#[cfg(feature = "mnemonic")]
+mod mnemonic_utils;
+mod msg_utils;
+#[cfg(feature = "crypto")]
+mod multisig_utils;
+mod smc_info_utils;
 mod stack_utils;
 mod string_utils;
+#[cfg(feature = "vm")]
 mod vm_utils;
 
 pub struct BaseModule;
@@ -41,15 +76,33 @@ impl FiftModule for BaseModule {
 
     #[cmd(name = "null?", stack, args(ty = StackValueType::Null))]
     #[cmd(name = "integer?", stack, args(ty = StackValueType::Int))]
+    #[cmd(name = "rational?", stack, args(ty = StackValueType::Rational), nonstandard)]
     #[cmd(name = "string?", stack, args(ty = StackValueType::String))]
     #[cmd(name = "tuple?", stack, args(ty = StackValueType::Tuple))]
     #[cmd(name = "box?", stack, args(ty = StackValueType::SharedBox))]
     #[cmd(name = "atom?", stack, args(ty = StackValueType::Atom))]
+    #[cmd(name = "exception?", stack, args(ty = StackValueType::Exception), nonstandard)]
     fn interpret_is_type(stack: &mut Stack, ty: StackValueType) -> Result<()> {
         let is_ty = stack.pop()?.ty() == ty;
         stack.push_bool(is_ty)
     }
 
+    #[cmd(name = "as-int?", stack, args(ty = StackValueType::Int), nonstandard)]
+    #[cmd(name = "as-string?", stack, args(ty = StackValueType::String), nonstandard)]
+    #[cmd(name = "as-bytes?", stack, args(ty = StackValueType::Bytes), nonstandard)]
+    #[cmd(name = "as-cell?", stack, args(ty = StackValueType::Cell), nonstandard)]
+    #[cmd(name = "as-slice?", stack, args(ty = StackValueType::Slice), nonstandard)]
+    fn interpret_quiet_cast(stack: &mut Stack, ty: StackValueType) -> Result<()> {
+        let value = stack.pop()?;
+        match coerce_value(value, ty) {
+            Some(value) => {
+                stack.push_raw(value)?;
+                stack.push_bool(true)
+            }
+            None => stack.push_bool(false),
+        }
+    }
+
     #[cmd(name = "hole", stack)]
     fn interpret_hole(stack: &mut Stack) -> Result<()> {
         stack.push(SharedBox::default())
@@ -101,6 +154,45 @@ impl FiftModule for BaseModule {
         stack.push(atom.to_string())
     }
 
+    #[cmd(name = "const!", effect = "x a --", nonstandard)]
+    fn interpret_constant_set(ctx: &mut Context) -> Result<()> {
+        let name = pop_constant_name(&mut ctx.stack)?;
+        let value = ctx.stack.pop()?;
+        ctx.dicts.current.define_constant(name.as_ref(), value);
+        Ok(())
+    }
+
+    #[cmd(name = "const@", args(check = false), effect = "a -- x", nonstandard)]
+    #[cmd(
+        name = "const@?",
+        args(check = true),
+        effect = "a -- x -1 or 0",
+        nonstandard
+    )]
+    fn interpret_constant_get(ctx: &mut Context, check: bool) -> Result<()> {
+        let name = pop_constant_name(&mut ctx.stack)?;
+        match ctx.dicts.current.get_constant(&name) {
+            Some(value) if check => {
+                ctx.stack.push_raw(value)?;
+                ctx.stack.push_bool(true)
+            }
+            Some(value) => ctx.stack.push_raw(value),
+            None if check => ctx.stack.push_bool(false),
+            None => anyhow::bail!("Undefined constant `{name}`"),
+        }
+    }
+
+    /// Returns a tuple of `[a x]` pairs of all constants sorted by name.
+    #[cmd(name = "constants", effect = "-- t", nonstandard)]
+    fn interpret_constants(ctx: &mut Context) -> Result<()> {
+        let mut items = StackTuple::new();
+        for (name, value) in ctx.dicts.current.constants() {
+            let atom = ctx.stack.atoms_mut().create_named(name);
+            items.push(Rc::new(vec![Rc::new(atom) as Rc<dyn StackValue>, value]));
+        }
+        ctx.stack.push(items)
+    }
+
     #[cmd(name = "eq?", stack)]
     fn interpret_is_eq(stack: &mut Stack) -> Result<()> {
         let y = stack.pop()?;
@@ -366,6 +458,35 @@ impl FiftModule for BaseModule {
         stack.push_bool(not_empty)
     }
 
+    #[cmd(name = "hmap[", stack, nonstandard)]
+    fn interpret_hmap_begin(stack: &mut Stack) -> Result<()> {
+        let marker = stack.atoms_mut().create_named(HMAP_LITERAL_MARKER);
+        stack.push(marker)
+    }
+
+    #[cmd(name = "]hmap", stack, effect = "k1 v1 ... kn vn -- h", nonstandard)]
+    fn interpret_hmap_end(stack: &mut Stack) -> Result<()> {
+        let marker = stack
+            .items()
+            .iter()
+            .rposition(|item| matches!(item.as_atom(), Ok(atom) if *atom == HMAP_LITERAL_MARKER))
+            .context("Unmatched `]hmap`")?;
+
+        let items = stack.items()[marker + 1..].to_vec();
+        anyhow::ensure!(
+            items.len() % 2 == 0,
+            "Hashmap literal must contain key-value pairs"
+        );
+        stack.truncate(marker);
+
+        let mut map = None;
+        for pair in items.chunks_exact(2) {
+            let key = HashMapTreeKey::new(pair[0].clone())?;
+            HashMapTreeNode::set(&mut map, &key, &pair[1]);
+        }
+        stack.push_opt_raw(map)
+    }
+
     #[cmd(name = "hmapforeach", tail)]
     fn interpret_hmap_foreach(ctx: &mut Context) -> Result<Option<Cont>> {
         let func = ctx.stack.pop_cont_owned()?;
@@ -386,12 +507,12 @@ impl FiftModule for BaseModule {
 
     #[cmd(name = "now")]
     fn interpret_now(ctx: &mut Context) -> Result<()> {
-        ctx.stack.push_int(ctx.env.now_ms() / 1000)
+        ctx.stack.push_int(ctx.now_ms() / 1000)
     }
 
-    #[cmd(name = "nowms")]
+    #[cmd(name = "nowms", nonstandard = "`now`")]
     fn interpret_now_ms(ctx: &mut Context) -> Result<()> {
-        ctx.stack.push_int(ctx.env.now_ms())
+        ctx.stack.push_int(ctx.now_ms())
     }
 
     #[cmd(name = "getenv")]
@@ -417,7 +538,7 @@ impl FiftModule for BaseModule {
     #[cmd(name = "file>B")]
     fn interpret_read_file(ctx: &mut Context) -> Result<()> {
         let name = ctx.stack.pop_string()?;
-        let data = ctx.env.read_file(name.as_str())?;
+        let data = ctx.read_file(name.as_str())?;
         ctx.stack.push(data)
     }
 
@@ -426,24 +547,209 @@ impl FiftModule for BaseModule {
         let size = ctx.stack.pop_usize()? as u64;
         let offset = ctx.stack.pop_usize()? as u64;
         let name = ctx.stack.pop_string()?;
-        let data = ctx.env.read_file_part(name.as_str(), offset, size)?;
+        let data = ctx.read_file_part(name.as_str(), offset, size)?;
         ctx.stack.push(data)
     }
 
-    #[cmd(name = "B>file")]
-    fn interpret_write_file(ctx: &mut Context) -> Result<()> {
+    #[cmd(name = "B>file", args(mode = WriteMode::Truncate))]
+    #[cmd(name = "B>file.append", args(mode = WriteMode::Append), nonstandard = "`B>file`")]
+    fn interpret_write_file(ctx: &mut Context, mode: WriteMode) -> Result<()> {
         let name = ctx.stack.pop_string()?;
         let data = ctx.stack.pop_bytes()?;
-        ctx.env.write_file(name.as_str(), data.as_slice())?;
+        ctx.env.write_file(name.as_str(), data.as_slice(), mode)?;
+        Ok(())
+    }
+
+    #[cmd(name = "file-touch", nonstandard)]
+    fn interpret_touch_file(ctx: &mut Context) -> Result<()> {
+        let name = ctx.stack.pop_string()?;
+        ctx.env.write_file(name.as_str(), &[], WriteMode::Append)?;
         Ok(())
     }
 
     #[cmd(name = "file-exists?")]
     fn interpret_file_exists(ctx: &mut Context) -> Result<()> {
         let name = ctx.stack.pop_string()?;
-        let exists = ctx.env.file_exists(&name);
+        let exists = ctx.file_exists(&name);
         ctx.stack.push_bool(exists)
     }
+
+    /// Compiles a FunC source file (or a tuple of files) into a code cell
+    /// using the compiler configured in the environment.
+    #[cmd(name = "func>cell", effect = "S|t -- c", nonstandard)]
+    fn interpret_func_to_cell(ctx: &mut Context) -> Result<()> {
+        let value = ctx.stack.pop()?;
+        let files = match value.as_tuple() {
+            Ok(tuple) => tuple
+                .iter()
+                .map(|item| Ok(item.as_string()?.to_owned()))
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => vec![value.as_string()?.to_owned()],
+        };
+        anyhow::ensure!(!files.is_empty(), "No source files to compile");
+
+        let code = ctx.env.compile_func(&files)?;
+        for file in &files {
+            ctx.add_accessed_file(file);
+        }
+        ctx.stack.push(code)
+    }
+
+    /// Compiles a FunC source code into a code cell using the compiler
+    /// configured in the environment.
+    ///
+    /// The source is saved into a temporary file next to the calling script,
+    /// so relative includes are resolved from the script directory.
+    #[cmd(name = "funcsrc>cell", effect = "S -- c", nonstandard)]
+    fn interpret_func_source_to_cell(ctx: &mut Context) -> Result<()> {
+        let source = ctx.stack.pop_string()?;
+        let hash = HashBytes(sha2::Sha256::digest(source.as_bytes()).into());
+
+        // NOTE: the source is saved next to the calling script
+        // so that relative `#include`s are resolved the same way
+        let script = ctx.input.source_block_name().unwrap_or_default();
+        let script = std::path::PathBuf::from(ctx.env.canonical_name(script));
+        let name = match script.parent() {
+            Some(dir) if script.is_absolute() => dir.join(format!(".funcsrc-{hash}.fc")),
+            _ => std::path::PathBuf::from(format!(".funcsrc-{hash}.fc")),
+        };
+        let name = name.to_string_lossy().into_owned();
+
+        ctx.env
+            .write_file(&name, source.as_bytes(), WriteMode::Truncate)?;
+        let code = ctx.env.compile_func(std::slice::from_ref(&name));
+        ctx.env.remove_file(&name).ok();
+        ctx.stack.push(code?)
+    }
+
+    /// Saves a cell or bytes into the artifact store, returns its hash
+    /// (a representation hash for cells and SHA-256 for bytes).
+    #[cmd(name = "store-artifact", effect = "x -- B", nonstandard = "`B>file`")]
+    fn interpret_store_artifact(ctx: &mut Context) -> Result<()> {
+        let value = ctx.stack.pop()?;
+        let (hash, ext, data) = match value.ty() {
+            StackValueType::Cell => {
+                let cell = value.as_cell()?;
+                (*cell.repr_hash(), ARTIFACT_CELL_EXT, Boc::encode(cell))
+            }
+            _ => {
+                let bytes = value.as_bytes()?;
+                let hash = HashBytes(sha2::Sha256::digest(bytes).into());
+                (hash, ARTIFACT_BYTES_EXT, bytes.to_vec())
+            }
+        };
+
+        // NOTE: virtual files and include dirs are not the store,
+        // so the file is created in the store unless it is already there
+        let dir = artifacts_dir(ctx);
+        let name = format!("{dir}/{hash}.{ext}");
+        ctx.env.create_dir(&dir)?;
+        match ctx.env.write_file(&name, &data, WriteMode::Create) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+            _ => {}
+        }
+        ctx.stack.push(hash.0.to_vec())
+    }
+
+    /// Loads a cell or bytes with the specified hash from the artifact store.
+    #[cmd(name = "load-artifact", effect = "B -- x", nonstandard = "`file>B`")]
+    fn interpret_load_artifact(ctx: &mut Context) -> Result<()> {
+        let hash = ctx.stack.pop_bytes()?;
+        anyhow::ensure!(hash.len() == 32, "Artifact hash must be 32 bytes long");
+        let hash = HashBytes::from_slice(&hash);
+
+        let dir = artifacts_dir(ctx);
+        let name = format!("{dir}/{hash}.{ARTIFACT_CELL_EXT}");
+        if let Some(data) = read_artifact(ctx, &name)? {
+            let cell = decode_boc(&data)?;
+            anyhow::ensure!(cell.repr_hash() == &hash, "Artifact `{name}` is corrupted");
+            return ctx.stack.push(cell);
+        }
+
+        let name = format!("{dir}/{hash}.{ARTIFACT_BYTES_EXT}");
+        if let Some(data) = read_artifact(ctx, &name)? {
+            anyhow::ensure!(
+                sha2::Sha256::digest(&data).as_slice() == hash.as_slice(),
+                "Artifact `{name}` is corrupted"
+            );
+            return ctx.stack.push(data);
+        }
+
+        anyhow::bail!("Artifact {hash} not found in `{dir}`")
+    }
+}
+
+const HMAP_LITERAL_MARKER: &str = "hmap[";
+
+const ARTIFACT_CELL_EXT: &str = "boc";
+const ARTIFACT_BYTES_EXT: &str = "bin";
+
+/// Returns the artifact store directory (`$FIFT_ARTIFACTS` or `.fift-artifacts`).
+fn artifacts_dir(ctx: &Context) -> String {
+    match ctx.env.get_env("FIFT_ARTIFACTS") {
+        Some(dir) if !dir.is_empty() => dir.trim_end_matches('/').to_owned(),
+        _ => ".fift-artifacts".to_owned(),
+    }
+}
+
+fn read_artifact(ctx: &mut Context, name: &str) -> Result<Option<Vec<u8>>> {
+    match ctx.env.read_file(name) {
+        Ok(data) => {
+            ctx.add_accessed_file(name);
+            Ok(Some(data))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn pop_constant_name(stack: &mut Stack) -> Result<Rc<str>> {
+    match stack.pop_atom()?.as_ref() {
+        stack::Atom::Named(name) => Ok(name.clone()),
+        atom @ stack::Atom::Unnamed(_) => anyhow::bail!("Anonymous {atom} cannot name a constant"),
+    }
+}
+
+fn coerce_value(value: Rc<dyn StackValue>, ty: StackValueType) -> Option<Rc<dyn StackValue>> {
+    fn slice_to_cell(cs: CellSlice<'_>) -> Option<Cell> {
+        let mut builder = CellBuilder::new();
+        builder.store_slice(cs).ok()?;
+        builder.build().ok()
+    }
+
+    if value.ty() == ty {
+        return Some(value);
+    }
+
+    Some(match (value.ty(), ty) {
+        (StackValueType::String, StackValueType::Int) => {
+            let int = ImmediateInt::try_from_str(value.as_string().ok()?).ok()??;
+            if int.denom.is_some() {
+                return None;
+            }
+            Rc::new(int.num)
+        }
+        (StackValueType::String, StackValueType::Bytes) => {
+            Rc::new(value.as_string().ok()?.as_bytes().to_vec())
+        }
+        (StackValueType::Bytes, StackValueType::String) => {
+            Rc::new(String::from_utf8(value.as_bytes().ok()?.to_vec()).ok()?)
+        }
+        (StackValueType::Builder, StackValueType::Cell) => {
+            Rc::new(value.as_builder().ok()?.clone().build().ok()?)
+        }
+        (StackValueType::Slice, StackValueType::Cell) => {
+            Rc::new(slice_to_cell(value.as_slice().ok()?)?)
+        }
+        (StackValueType::Cell, StackValueType::Slice) => {
+            Rc::new(OwnedCellSlice::new(value.as_cell().ok()?.clone()))
+        }
+        (StackValueType::Builder, StackValueType::Slice) => {
+            let cell = value.as_builder().ok()?.clone().build().ok()?;
+            Rc::new(OwnedCellSlice::new(cell))
+        }
+        _ => return None,
+    })
 }
 
 #[derive(Clone)]
@@ -474,3 +780,66 @@ impl cont::LoopContImpl for HmapIterCont {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryEnvironment;
+
+    fn run_in(
+        env: &mut MemoryEnvironment,
+        setup: impl FnOnce(&mut Context<'_>),
+        source: &str,
+    ) -> Result<Vec<Rc<dyn StackValue>>> {
+        let mut stdout = std::io::sink();
+        let mut ctx = Context::new(env, &mut stdout).with_basic_modules()?;
+        setup(&mut ctx);
+        ctx.add_source_str("<test>", source);
+        ctx.run()?;
+        Ok(ctx.stack.items().to_vec())
+    }
+
+    #[test]
+    fn artifact_store() {
+        let mut env = MemoryEnvironment::default();
+        env.vars
+            .insert("FIFT_ARTIFACTS".to_owned(), "store/".to_owned());
+
+        let bytes_name = format!("store/{}.bin", hex::encode(sha2::Sha256::digest(b"abc")));
+        let cell = CellBuilder::build_from(123u8).unwrap();
+        let cell_name = format!("store/{}.boc", cell.repr_hash());
+
+        let source = "\"abc\" $>B store-artifact dup load-artifact \
+            <b 123 8 u, b> store-artifact dup load-artifact hashu";
+        // NOTE: virtual files must not be mistaken for stored artifacts
+        let items = run_in(
+            &mut env,
+            |ctx| ctx.add_virtual_file(bytes_name.clone(), "stale"),
+            source,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[0].as_bytes().unwrap(),
+            sha2::Sha256::digest(b"abc").as_slice()
+        );
+        assert_eq!(items[1].as_bytes().unwrap(), b"abc");
+        assert_eq!(items[2].as_bytes().unwrap(), cell.repr_hash().as_slice());
+        assert_eq!(
+            items[3].as_int().unwrap().to_bytes_be().1,
+            cell.repr_hash().as_slice()
+        );
+        assert_eq!(env.files[&bytes_name], b"abc");
+        assert_eq!(env.files[&cell_name], Boc::encode(&cell));
+
+        // Existing artifacts are kept, corrupted ones are detected on load
+        env.files.insert(bytes_name, b"abd".to_vec());
+        let source = "\"abc\" $>B store-artifact load-artifact";
+        let err = run_in(&mut env, |_| {}, source).err().unwrap();
+        assert!(format!("{err:?}").contains("corrupted"), "{err:?}");
+
+        let source =
+            "B{0000000000000000000000000000000000000000000000000000000000000000} load-artifact";
+        assert!(run_in(&mut env, |_| {}, source).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context as _, Result};
+use everscale_types::models::{
+    CurrencyCollection, ExtInMsgInfo, IntMsgInfo, MsgInfo, OwnedMessage, StateInit,
+};
+use everscale_types::num::Tokens;
+use everscale_types::prelude::*;
+use num_bigint::{BigInt, Sign};
+
+use crate::core::*;
+
+/// Words to build state init and messages from the typed models.
+pub struct MsgUtils;
+
+#[fift_module]
+impl MsgUtils {
+    /// Builds a state init with the optional code and data.
+    #[cmd(name = "state-init", stack, effect = "code data -- c", nonstandard)]
+    fn interpret_state_init(stack: &mut Stack) -> Result<()> {
+        let data = pop_maybe_cell(stack)?;
+        let code = pop_maybe_cell(stack)?;
+        let state_init = StateInit {
+            code,
+            data,
+            ..Default::default()
+        };
+        stack.push(CellBuilder::build_from(state_init)?)
+    }
+
+    /// Computes the address of the account with the specified state init.
+    #[cmd(name = "state-init>addr", stack, effect = "wc c -- wc x", nonstandard)]
+    fn interpret_state_init_addr(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        let workchain = stack.pop_smallint_signed_range(-0x80, 0x7f)?;
+        cell.parse::<StateInit>()?;
+
+        stack.push_int(workchain)?;
+        stack.push_int(BigInt::from_bytes_be(
+            Sign::Plus,
+            cell.repr_hash().as_slice(),
+        ))
+    }
+
+    #[cmd(
+        name = "ext-in-msg",
+        stack,
+        args(with_init = false),
+        effect = "wc x body -- c",
+        nonstandard
+    )]
+    #[cmd(
+        name = "ext-in-msg+init",
+        stack,
+        args(with_init = true),
+        effect = "wc x init body -- c",
+        nonstandard
+    )]
+    fn interpret_ext_in_msg(stack: &mut Stack, with_init: bool) -> Result<()> {
+        let body = stack.pop_cell()?;
+        let init = pop_state_init(stack, with_init)?;
+        let dst = stack.pop_address()?;
+
+        let info = MsgInfo::ExtIn(ExtInMsgInfo {
+            dst,
+            ..Default::default()
+        });
+        push_message(stack, info, init, &body)
+    }
+
+    #[cmd(
+        name = "int-msg",
+        stack,
+        args(with_init = false),
+        effect = "wc x amount bounce? body -- c",
+        nonstandard
+    )]
+    #[cmd(
+        name = "int-msg+init",
+        stack,
+        args(with_init = true),
+        effect = "wc x amount bounce? init body -- c",
+        nonstandard
+    )]
+    fn interpret_int_msg(stack: &mut Stack, with_init: bool) -> Result<()> {
+        let body = stack.pop_cell()?;
+        let init = pop_state_init(stack, with_init)?;
+        let bounce = stack.pop_bool()?;
+        let amount = pop_tokens(stack)?;
+        let dst = stack.pop_address()?;
+
+        let info = MsgInfo::Int(IntMsgInfo {
+            bounce,
+            dst,
+            value: CurrencyCollection::new(amount.into_inner()),
+            ..Default::default()
+        });
+        push_message(stack, info, init, &body)
+    }
+}
+
+fn push_message(
+    stack: &mut Stack,
+    info: MsgInfo,
+    init: Option<StateInit>,
+    body: &Cell,
+) -> Result<()> {
+    let message = OwnedMessage {
+        info,
+        init,
+        body: (body.clone(), CellSliceRange::full(body.as_ref())),
+        layout: None,
+    };
+    stack.push(CellBuilder::build_from(message)?)
+}
+
+fn pop_state_init(stack: &mut Stack, with_init: bool) -> Result<Option<StateInit>> {
+    if !with_init {
+        return Ok(None);
+    }
+    let cell = stack.pop_cell()?;
+    Ok(Some(
+        cell.parse::<StateInit>().context("Invalid state init")?,
+    ))
+}
+
+fn pop_tokens(stack: &mut Stack) -> Result<Tokens> {
+    let int = stack.pop_int()?;
+    let tokens = u128::try_from(int.as_ref())
+        .ok()
+        .map(Tokens::new)
+        .filter(Tokens::is_valid)
+        .context("Amount must be a non-negative 120-bit integer")?;
+    Ok(tokens)
+}
+
+fn pop_maybe_cell(stack: &mut Stack) -> Result<Option<Cell>> {
+    let value = stack.pop()?;
+    Ok(if value.is_null() {
+        None
+    } else {
+        Some(value.into_cell()?.as_ref().clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use everscale_types::models::{IntAddr, StdAddr};
+
+    use super::*;
+    use crate::testing::run;
+
+    fn parse_message(source: &str) -> OwnedMessage {
+        let items = run(&[], source).unwrap();
+        assert_eq!(items.len(), 1);
+        items[0].as_cell().unwrap().parse::<OwnedMessage>().unwrap()
+    }
+
+    fn std_addr(workchain: i8, address: u8) -> IntAddr {
+        let mut bytes = [0; 32];
+        bytes[31] = address;
+        IntAddr::Std(StdAddr::new(workchain, HashBytes(bytes)))
+    }
+
+    #[test]
+    fn state_init_address() {
+        // Representation hash of the state init with empty code and data cells
+        let expected = "ad31eb762e688fc1ba21575d4359b0f9c48738af653e166a233300bdc6b29ae9";
+
+        let items = run(&[], "-1 <b b> <b b> state-init state-init>addr").unwrap();
+        assert_eq!(items[0].as_int().unwrap(), &(-1).into());
+        let (_, address) = items[1].as_int().unwrap().to_bytes_be();
+        assert_eq!(hex::encode(address), expected);
+
+        let items = run(&[], "<b b> null state-init").unwrap();
+        let init = items[0].as_cell().unwrap().parse::<StateInit>().unwrap();
+        assert!(init.code.is_some() && init.data.is_none());
+
+        assert!(run(&[], "0 <b 1 1 u, b> state-init>addr").is_err());
+    }
+
+    #[test]
+    fn build_messages() {
+        let message = parse_message("0 255 1000 -1 <b 7 8 u, b> int-msg");
+        let MsgInfo::Int(info) = &message.info else {
+            panic!("internal message expected");
+        };
+        assert!(info.bounce);
+        assert_eq!(info.dst, std_addr(0, 255));
+        assert_eq!(info.value.tokens, Tokens::new(1000));
+        assert!(message.init.is_none());
+        let (body, range) = &message.body;
+        assert_eq!(range.apply(body).unwrap().load_u8().unwrap(), 7);
+
+        let message = parse_message("0 1 0 0 <b b> null state-init <b b> int-msg+init");
+        assert!(matches!(&message.info, MsgInfo::Int(info) if !info.bounce));
+        assert!(message.init.unwrap().code.is_some());
+
+        let message = parse_message("-1 2 <b b> ext-in-msg");
+        let MsgInfo::ExtIn(info) = &message.info else {
+            panic!("external inbound message expected");
+        };
+        assert_eq!(info.dst, std_addr(-1, 2));
+
+        assert!(run(&[], "0 1 -1 0 <b b> int-msg").is_err());
+        assert!(run(&[], "0 1 <b b> <b b> ext-in-msg+init").is_err());
+    }
+}
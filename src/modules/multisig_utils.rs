@@ -0,0 +1,163 @@
+use anyhow::{Context as _, Result};
+use everscale_crypto::ed25519;
+use everscale_types::dict::RawDict;
+use everscale_types::prelude::*;
+
+use crate::core::*;
+
+pub struct MultisigUtils;
+
+#[fift_module]
+impl MultisigUtils {
+    /// Builds an order cell: `query_id:uint64 expire_at:uint32 mode:uint8 ^message`.
+    #[cmd(
+        name = "msig-order",
+        stack,
+        effect = "query-id expire-at mode c-msg -- c",
+        nonstandard
+    )]
+    fn interpret_msig_order(stack: &mut Stack) -> Result<()> {
+        let msg = stack.pop_cell()?;
+        let mode = stack.pop_smallint_range(0, 255)? as u8;
+        let expire_at = stack.pop_smallint_range(0, u32::MAX)?;
+        let query_id = stack.pop_int()?;
+        let query_id = u64::try_from(query_id.as_ref()).context("Invalid query id")?;
+
+        let mut b = CellBuilder::new();
+        b.store_u64(query_id)?;
+        b.store_u32(expire_at)?;
+        b.store_u8(mode)?;
+        b.store_reference(msg.as_ref().clone())?;
+        stack.push(b.build()?)
+    }
+
+    /// Returns the hash of the order which must be signed by each signer.
+    #[cmd(name = "msig-hash", stack, effect = "c -- B", nonstandard)]
+    fn interpret_msig_hash(stack: &mut Stack) -> Result<()> {
+        let order = stack.pop_cell()?;
+        stack.push(order.repr_hash().as_slice().to_vec())
+    }
+
+    /// Adds signatures with signer indices into the signature dictionary.
+    #[cmd(
+        name = "msig-sigs!",
+        stack,
+        effect = "D B1 i1 ... Bn in n -- D'",
+        nonstandard
+    )]
+    fn interpret_msig_merge_signatures(stack: &mut Stack) -> Result<()> {
+        let n = stack.pop_smallint_range(0, 255)?;
+        let mut signatures = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let index = stack.pop_smallint_range(0, 255)? as u8;
+            let signature = pop_signature(stack)?;
+            signatures.push((index, signature));
+        }
+        let mut dict = pop_signatures_dict(stack)?;
+
+        for (index, signature) in signatures.into_iter().rev() {
+            match get_signature(&dict, index)? {
+                Some(existing) if existing == signature => continue,
+                Some(_) => anyhow::bail!("Conflicting signatures of signer {index}"),
+                None => {}
+            }
+
+            let mut value = CellBuilder::new();
+            value.store_raw(&signature, 512)?;
+            dict.set(signer_key(index)?.as_data_slice(), value.as_data_slice())?;
+        }
+
+        stack.push_opt(dict.root().clone())
+    }
+
+    /// Counts valid signatures of the order and checks that there are
+    /// at least `k` of them. Signers' public keys are indexed by signer index.
+    #[cmd(name = "msig-chksign", stack, effect = "c D t k -- n ?", nonstandard)]
+    fn interpret_msig_check_signatures(stack: &mut Stack) -> Result<()> {
+        let threshold = stack.pop_smallint_range(0, 256)?;
+        let keys = stack.pop_tuple()?;
+        let dict = pop_signatures_dict(stack)?;
+        let order = stack.pop_cell()?;
+        let hash = order.repr_hash();
+
+        let mut valid = 0u32;
+        for entry in dict.iter() {
+            let (key, mut value) = entry?;
+            let index = key.as_data_slice().load_u8()?;
+            let key = keys
+                .get(index as usize)
+                .with_context(|| format!("Unknown signer {index}"))?;
+            let key = key.as_bytes()?;
+            let key = key
+                .try_into()
+                .ok()
+                .and_then(ed25519::PublicKey::from_bytes)
+                .with_context(|| format!("Invalid public key of signer {index}"))?;
+
+            let mut signature = [0; 64];
+            value.load_raw(&mut signature, 512)?;
+            if key.verify_raw(hash.as_slice(), &signature) {
+                valid += 1;
+            }
+        }
+
+        stack.push_int(valid)?;
+        stack.push_bool(valid >= threshold)
+    }
+
+    /// Packs the order with its signatures: `signatures:(HashmapE 8 bits512) ^order`.
+    #[cmd(name = "msig-pack", stack, effect = "c D -- c'", nonstandard)]
+    fn interpret_msig_pack(stack: &mut Stack) -> Result<()> {
+        let dict = pop_signatures_dict(stack)?;
+        let order = stack.pop_cell()?;
+
+        let mut b = CellBuilder::new();
+        dict.store_into(&mut b, &mut Cell::empty_context())?;
+        b.store_reference(order.as_ref().clone())?;
+        stack.push(b.build()?)
+    }
+
+    #[cmd(name = "msig-unpack", stack, effect = "c' -- c D", nonstandard)]
+    fn interpret_msig_unpack(stack: &mut Stack) -> Result<()> {
+        let cell = stack.pop_cell()?;
+        let mut cs = CellSlice::new(cell.as_ref().as_ref())?;
+        let dict = RawDict::<8>::load_from(&mut cs)?;
+        let order = cs.load_reference_cloned()?;
+        anyhow::ensure!(
+            cs.is_data_empty() && cs.is_refs_empty(),
+            "Invalid signed order"
+        );
+
+        stack.push(order)?;
+        stack.push_opt(dict.root().clone())
+    }
+}
+
+fn pop_signatures_dict(stack: &mut Stack) -> Result<RawDict<8>> {
+    let value = stack.pop()?;
+    Ok(RawDict::from(if value.is_null() {
+        None
+    } else {
+        Some(value.as_cell()?.clone())
+    }))
+}
+
+fn pop_signature(stack: &mut Stack) -> Result<[u8; 64]> {
+    let b = stack.pop_bytes()?;
+    b.as_slice().try_into().ok().context("Invalid signature")
+}
+
+fn get_signature(dict: &RawDict<8>, index: u8) -> Result<Option<[u8; 64]>> {
+    let Some(mut value) = dict.get(signer_key(index)?.as_data_slice())? else {
+        return Ok(None);
+    };
+    let mut signature = [0; 64];
+    value.load_raw(&mut signature, 512)?;
+    Ok(Some(signature))
+}
+
+fn signer_key(index: u8) -> Result<CellBuilder> {
+    let mut b = CellBuilder::new();
+    b.store_u8(index)?;
+    Ok(b)
+}
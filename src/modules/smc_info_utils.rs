@@ -0,0 +1,235 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use everscale_types::prelude::*;
+use num_bigint::{BigInt, Sign};
+
+use crate::core::*;
+
+/// Words to prepare the environment of `runvmx` for contract tests.
+pub struct SmcInfoUtils;
+
+const SMC_INFO_MAGIC: u32 = 0x076ef1ea;
+const TICK_TOCK_SELECTOR: i32 = -2;
+
+#[fift_module]
+impl SmcInfoUtils {
+    /// Builds the `SmartContractInfo` tuple with the specified time, logical time
+    /// (used as the block and the transaction lt), random seed, balance,
+    /// address and config root (or `null`). `smc-c7` wraps it
+    /// into the `c7` tuple for `runvmx`.
+    #[cmd(
+        name = "smc-info",
+        stack,
+        args(wrap = false),
+        effect = "now lt seed balance wc x D-config -- t",
+        nonstandard
+    )]
+    #[cmd(
+        name = "smc-c7",
+        stack,
+        args(wrap = true),
+        effect = "now lt seed balance wc x D-config -- c7",
+        nonstandard
+    )]
+    fn interpret_smc_info(stack: &mut Stack, wrap: bool) -> Result<()> {
+        let config = stack.pop()?;
+        if !config.is_null() {
+            config.as_cell()?;
+        }
+        let address = CellBuilder::build_from(stack.pop_address()?)?;
+        let balance = pop_uint(stack, 128).context("Invalid balance")?;
+        let seed = pop_uint(stack, 256).context("Invalid random seed")?;
+        let lt = pop_uint(stack, 64).context("Invalid logical time")?;
+        let now = pop_uint(stack, 32).context("Invalid time")?;
+
+        let balance: StackTuple = vec![balance, Stack::make_null()];
+        let smc_info: StackTuple = vec![
+            Rc::new(BigInt::from(SMC_INFO_MAGIC)),
+            Rc::new(BigInt::from(0)),
+            Rc::new(BigInt::from(0)),
+            now,
+            lt.clone(),
+            lt,
+            seed,
+            Rc::new(balance),
+            Rc::new(OwnedCellSlice::new(address)),
+            config,
+        ];
+
+        if wrap {
+            stack.push(vec![Rc::new(smc_info) as Rc<dyn StackValue>])
+        } else {
+            stack.push(smc_info)
+        }
+    }
+
+    /// Pushes the initial stack of a tick (or tock) transaction
+    /// for the account with the specified balance and address.
+    #[cmd(
+        name = "ticktock",
+        stack,
+        effect = "balance x tock? -- balance 0 x tock? -2",
+        nonstandard
+    )]
+    fn interpret_ticktock(stack: &mut Stack) -> Result<()> {
+        let tock = stack.pop_bool()?;
+        let address = pop_uint(stack, 256).context("Invalid account address")?;
+        let balance = pop_uint(stack, 128).context("Invalid balance")?;
+
+        stack.push_raw(balance)?;
+        stack.push_int(0)?;
+        stack.push_raw(address)?;
+        stack.push_bool(tock)?;
+        stack.push_int(TICK_TOCK_SELECTOR)
+    }
+
+    /// Returns the config param with the specified index.
+    #[cmd(
+        name = "config-param@",
+        stack,
+        effect = "i D-config -- c -1 or 0",
+        nonstandard
+    )]
+    fn interpret_config_param_get(stack: &mut Stack) -> Result<()> {
+        let params = pop_config_params(stack)?;
+        let index = pop_param_index(stack)?;
+        match params.get(index)? {
+            Some(param) => {
+                stack.push(param)?;
+                stack.push_bool(true)
+            }
+            None => stack.push_bool(false),
+        }
+    }
+
+    /// Sets the config param with the specified index (`null` removes it).
+    #[cmd(
+        name = "config-param!",
+        stack,
+        effect = "c i D-config -- D-config'",
+        nonstandard
+    )]
+    fn interpret_config_param_set(stack: &mut Stack) -> Result<()> {
+        let mut params = pop_config_params(stack)?;
+        let index = pop_param_index(stack)?;
+        let param = stack.pop()?;
+        if param.is_null() {
+            params.remove(index)?;
+        } else {
+            params.set(index, param.as_cell()?.clone())?;
+        }
+        stack.push_opt(params.root().clone())
+    }
+}
+
+fn pop_uint(stack: &mut Stack, bits: u64) -> Result<Rc<dyn StackValue>> {
+    let int = stack.pop_int()?;
+    anyhow::ensure!(
+        int.sign() != Sign::Minus && int.bits() <= bits,
+        "Expected a non-negative {bits}-bit integer"
+    );
+    Ok(int)
+}
+
+fn pop_config_params(stack: &mut Stack) -> Result<Dict<u32, Cell>> {
+    let root = stack.pop()?;
+    Ok(Dict::from_raw(if root.is_null() {
+        None
+    } else {
+        Some(root.as_cell()?.clone())
+    }))
+}
+
+fn pop_param_index(stack: &mut Stack) -> Result<u32> {
+    // NOTE: config params are stored with signed 32-bit keys
+    Ok(stack.pop_smallint_signed_range(i32::MIN, i32::MAX)? as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use everscale_types::models::{IntAddr, StdAddr};
+
+    use super::*;
+    use crate::testing::{ints, run};
+
+    #[test]
+    fn smc_c7_layout() {
+        // Same state init as in the `msg_utils` address test
+        let expected = "ad31eb762e688fc1ba21575d4359b0f9c48738af653e166a233300bdc6b29ae9";
+
+        let items = run(
+            &[],
+            "1700000000 5 7 1000 -1 <b b> <b b> state-init state-init>addr null smc-c7",
+        )
+        .unwrap();
+        assert_eq!(items.len(), 1);
+        let c7 = items[0].as_tuple().unwrap();
+        assert_eq!(c7.len(), 1);
+
+        let info = c7[0].as_tuple().unwrap();
+        assert_eq!(info.len(), 10);
+        assert_eq!(
+            ints(&info[..7]),
+            [SMC_INFO_MAGIC as i32, 0, 0, 1700000000, 5, 5, 7]
+        );
+
+        let balance = info[7].as_tuple().unwrap();
+        assert_eq!(ints(&balance[..1]), [1000]);
+        assert!(balance[1].is_null());
+
+        let address = IntAddr::load_from(&mut info[8].as_slice().unwrap()).unwrap();
+        let IntAddr::Std(StdAddr {
+            workchain, address, ..
+        }) = address
+        else {
+            panic!("standard address expected");
+        };
+        assert_eq!(workchain, -1);
+        assert_eq!(hex::encode(address.as_slice()), expected);
+        assert!(info[9].is_null());
+
+        let items = run(&[], "0 0 0 0 0 0 <b b> smc-info").unwrap();
+        let info = items[0].as_tuple().unwrap();
+        assert_eq!(info.len(), 10);
+        assert!(info[9].as_cell().is_ok());
+
+        for source in [
+            "-1 0 0 0 0 0 null smc-c7",
+            "0 0 0 1 128 << 0 0 null smc-c7",
+            "0 0 0 0 0 0 123 smc-c7",
+        ] {
+            assert!(run(&[], source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn ticktock_stack() {
+        let check = |source: &str| ints(&run(&[], source).unwrap());
+
+        assert_eq!(check("1000 255 -1 ticktock"), [1000, 0, 255, -1, -2]);
+        assert_eq!(check("1000 255 0 ticktock"), [1000, 0, 255, 0, -2]);
+        assert!(run(&[], "-1 255 0 ticktock").is_err());
+    }
+
+    #[test]
+    fn config_params() {
+        let check = |source: &str| ints(&run(&[], source).unwrap());
+
+        let config = "<b 5 8 u, b> 12 null config-param!";
+        assert_eq!(
+            check(&format!("{config} 12 swap config-param@ swap <s 8 u@")),
+            [-1, 5]
+        );
+        assert_eq!(check(&format!("{config} 13 swap config-param@")), [0]);
+        assert_eq!(
+            check(&format!("{config} null 12 rot config-param! null?")),
+            [-1]
+        );
+        assert_eq!(
+            check("<b b> -1 null config-param! -1 swap config-param@ nip"),
+            [-1]
+        );
+        assert_eq!(check("0 null config-param@"), [0]);
+    }
+}
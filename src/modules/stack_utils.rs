@@ -147,6 +147,21 @@ impl StackUtils {
         stack.push_raw(item)
     }
 
+    /// Rearranges the top stack items by the pattern (e.g. `"abc-cab"` is `rot`).
+    #[cmd(name = "shuffle", stack, effect = "... S -- ...", nonstandard)]
+    fn interpret_shuffle(stack: &mut Stack) -> Result<()> {
+        let pattern = stack.pop_string()?;
+        cont::ShuffleCont::parse(&pattern)?.apply(stack)
+    }
+
+    #[cmd(name = "shuffle\"", active, without_space, nonstandard)]
+    fn interpret_shuffle_lit(ctx: &mut Context) -> Result<()> {
+        let pattern = ctx.input.scan_until_delimiter('"')?;
+        let cont = cont::ShuffleCont::parse(pattern)?;
+        ctx.stack.push_int(0)?;
+        ctx.stack.push(Rc::new(cont) as Cont)
+    }
+
     /// === Low-level stack manipulation ===
 
     #[cmd(name = "<xchg>", stack)]
@@ -174,6 +189,12 @@ impl StackUtils {
         }
     }
 
+    #[cmd(name = "<shuffle>", stack, effect = "S -- e", nonstandard)]
+    fn interpret_make_shuffle(stack: &mut Stack) -> Result<()> {
+        let pattern = stack.pop_string()?;
+        stack.push(Rc::new(cont::ShuffleCont::parse(&pattern)?) as Cont)
+    }
+
     #[cmd(name = "<pop>", stack)]
     fn interpret_make_pop(stack: &mut Stack) -> Result<()> {
         let x = stack.pop_smallint_range(0, 255)?;
@@ -228,3 +249,49 @@ impl cont::ContImpl for PopCont {
         write!(f, "<pop {}>", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ints, run, run_with_output};
+
+    #[test]
+    fn shuffle_patterns() {
+        let check = |source: &str| ints(&run(&[], source).unwrap());
+
+        assert_eq!(check(r#"1 2 3 "abc-cab" shuffle"#), [3, 1, 2]);
+        assert_eq!(check(r#"1 2 3 "a b c - c c a" shuffle"#), [3, 3, 1]);
+        assert_eq!(check(r#"1 2 3 "ab-" shuffle"#), [1]);
+        assert_eq!(check(r#"1 2 "-" shuffle"#), [1, 2]);
+        assert_eq!(check(r#"1 2 3 { shuffle"abc-bca" } execute"#), [2, 3, 1]);
+        assert_eq!(check(r#"1 2 3 "bc-cb" <shuffle> execute"#), [1, 3, 2]);
+
+        for source in [
+            r#"1 2 "ab" shuffle"#,
+            r#"1 2 "aa-a" shuffle"#,
+            r#"1 2 "ab-c" shuffle"#,
+            r#"1 "ab-ba" shuffle"#,
+        ] {
+            assert!(run(&[], source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn shuffle_suspend() {
+        let (items, _) = run_with_output(&[], "execute", |ctx| {
+            let cont: Cont = Rc::new(cont::ShuffleCont::parse(" abc - bac ").unwrap());
+            let d = &ctx.dicts.current;
+            let data = suspend::serialize(Some(&cont), d).unwrap();
+            let cont = suspend::deserialize(&data, d, ctx.stack.atoms_mut())
+                .unwrap()
+                .unwrap();
+            assert_eq!(cont.display_name(d).to_string(), "shuffle\"abc - bac\"");
+
+            for i in 1..=3 {
+                ctx.stack.push_int(i).unwrap();
+            }
+            ctx.stack.push(cont).unwrap();
+        });
+        assert_eq!(ints(&items.unwrap()), [2, 1, 3]);
+    }
+}
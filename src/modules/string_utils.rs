@@ -1,12 +1,15 @@
+use std::io::Write as _;
 use std::rc::Rc;
 use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use everscale_types::models::StdAddr;
 use everscale_types::prelude::HashBytes;
 use num_bigint::{BigInt, Sign};
-use num_traits::Num;
+use num_traits::{Num, ToPrimitive};
 use sha2::Digest;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::core::*;
 use crate::error::UnexpectedEof;
@@ -111,8 +114,22 @@ impl StringUtils {
         stack.push_int(res)
     }
 
+    #[cmd(name = "fixed>$", stack, effect = "x n -- S", nonstandard)]
+    fn interpret_fixed_to_string(stack: &mut Stack) -> Result<()> {
+        let decimals = stack.pop_smallint_range(0, 255)?;
+        let int = stack.pop_int()?;
+        stack.push(format_fixed(&int, decimals))
+    }
+
+    #[cmd(name = "$>fixed", stack, effect = "S n -- x", nonstandard)]
+    fn interpret_string_to_fixed(stack: &mut Stack) -> Result<()> {
+        let decimals = stack.pop_smallint_range(0, 255)?;
+        let string = stack.pop_string()?;
+        stack.push(parse_fixed(&string, decimals)?)
+    }
+
     #[cmd(name = "$|", stack)]
-    #[cmd(name = "$Split", stack)]
+    #[cmd(name = "$Split", stack, nonstandard = "`$|`")]
     fn interpret_str_split(stack: &mut Stack) -> Result<()> {
         let at = stack.pop_smallint_range(0, i32::MAX as _)? as usize;
         let mut head = stack.pop_string()?;
@@ -157,7 +174,7 @@ impl StringUtils {
     }
 
     #[cmd(name = "$pos", stack)]
-    #[cmd(name = "$Pos", stack)]
+    #[cmd(name = "$Pos", stack, nonstandard = "`$pos`")]
     fn interpret_str_pos(stack: &mut Stack) -> Result<()> {
         let substring = stack.pop_string()?;
         let string = stack.pop_string()?;
@@ -168,7 +185,7 @@ impl StringUtils {
     }
 
     // $at (S n -- S')
-    #[cmd(name = "$at", stack)]
+    #[cmd(name = "$at", stack, nonstandard)]
     fn interpret_str_at(stack: &mut Stack) -> Result<()> {
         let index = stack.pop_usize()?;
         let string = stack.pop_string()?;
@@ -180,7 +197,7 @@ impl StringUtils {
     }
 
     // $mul (S n -- S*n)
-    #[cmd(name = "$mul", stack)]
+    #[cmd(name = "$mul", stack, nonstandard)]
     fn interpret_str_mul(stack: &mut Stack) -> Result<()> {
         let factor = stack.pop_usize()?;
         let string = stack.pop_string()?;
@@ -189,7 +206,7 @@ impl StringUtils {
     }
 
     // $sybs (S -- t[S'0, S'1, S'2, ..., S'n])
-    #[cmd(name = "$sybs", stack)]
+    #[cmd(name = "$sybs", stack, nonstandard)]
     fn interpret_str_sybs(stack: &mut Stack) -> Result<()> {
         let string = stack.pop_string()?;
         let symbols = string
@@ -201,7 +218,7 @@ impl StringUtils {
     }
 
     // $sub (S x y -- S')
-    #[cmd(name = "$sub", stack)]
+    #[cmd(name = "$sub", stack, nonstandard)]
     fn interpret_str_sub(stack: &mut Stack) -> Result<()> {
         let y = stack.pop_usize()?;
         let x = stack.pop_usize()?;
@@ -218,7 +235,7 @@ impl StringUtils {
     }
 
     // $sep (S S1 -- t(...))
-    #[cmd(name = "$sep", stack)]
+    #[cmd(name = "$sep", stack, nonstandard)]
     fn interpret_str_split_by_str(stack: &mut Stack) -> Result<()> {
         let sep = stack.pop_string()?;
         let string = stack.pop_string()?;
@@ -232,8 +249,8 @@ impl StringUtils {
         stack.push(substrings)
     }
 
-    #[cmd(name = "$rep", stack, args(pop_n = false))] // $rep  (S S1 S2   -- S')
-    #[cmd(name = "$repn", stack, args(pop_n = true))] // $repn (S S1 S2 n -- S')
+    #[cmd(name = "$rep", stack, args(pop_n = false), nonstandard)] // $rep  (S S1 S2   -- S')
+    #[cmd(name = "$repn", stack, args(pop_n = true), nonstandard)] // $repn (S S1 S2 n -- S')
     fn interpret_str_replace(stack: &mut Stack, pop_n: bool) -> Result<()> {
         let n = if pop_n { stack.pop_usize()? } else { 1 };
 
@@ -244,7 +261,7 @@ impl StringUtils {
         stack.push(string.replacen(s1.as_str(), s2.as_str(), n))
     }
 
-    #[cmd(name = "$repm", stack)] // $repm (S S1 S2 -- S')
+    #[cmd(name = "$repm", stack, nonstandard)] // $repm (S S1 S2 -- S')
     fn interpret_str_replace_max(stack: &mut Stack) -> Result<()> {
         let s2 = stack.pop_string()?;
         let s1 = stack.pop_string()?;
@@ -288,6 +305,83 @@ impl StringUtils {
         stack.push_int(len)
     }
 
+    #[cmd(name = "$Glen", stack, effect = "S -- n", nonstandard = "`$len`")]
+    fn interpret_str_graphemes_len(stack: &mut Stack) -> Result<()> {
+        let string = stack.pop_string()?;
+        let len = string.graphemes(true).count();
+        stack.push_int(len)
+    }
+
+    #[cmd(name = "$G@", stack, effect = "S n -- S'", nonstandard)]
+    fn interpret_str_grapheme_at(stack: &mut Stack) -> Result<()> {
+        let index = stack.pop_usize()?;
+        let string = stack.pop_string()?;
+        match string.graphemes(true).nth(index) {
+            Some(grapheme) => stack.push(grapheme.to_owned()),
+            None => anyhow::bail!("Grapheme index {index} is out of range"),
+        }
+    }
+
+    /// Returns the code point of the n-th char.
+    #[cmd(name = "$@utf8", stack, effect = "S n -- c", nonstandard)]
+    fn interpret_str_char_code_at(stack: &mut Stack) -> Result<()> {
+        let index = stack.pop_usize()?;
+        let string = stack.pop_string()?;
+        match string.chars().nth(index) {
+            Some(c) => stack.push_int(c as u32),
+            None => anyhow::bail!("Char index {index} is out of range"),
+        }
+    }
+
+    #[cmd(
+        name = "$upper",
+        stack,
+        args(upper = true),
+        effect = "S -- S'",
+        nonstandard
+    )]
+    #[cmd(
+        name = "$lower",
+        stack,
+        args(upper = false),
+        effect = "S -- S'",
+        nonstandard
+    )]
+    fn interpret_str_change_case(stack: &mut Stack, upper: bool) -> Result<()> {
+        let string = stack.pop_string()?;
+        stack.push(if upper {
+            string.to_uppercase()
+        } else {
+            string.to_lowercase()
+        })
+    }
+
+    #[cmd(name = "$nfc", stack, args(f = to_nfc), effect = "S -- S'", nonstandard)]
+    #[cmd(name = "$nfd", stack, args(f = to_nfd), effect = "S -- S'", nonstandard)]
+    #[cmd(name = "$nfkc", stack, args(f = to_nfkc), effect = "S -- S'", nonstandard)]
+    #[cmd(name = "$nfkd", stack, args(f = to_nfkd), effect = "S -- S'", nonstandard)]
+    fn interpret_str_normalize(stack: &mut Stack, f: fn(&str) -> String) -> Result<()> {
+        let string = stack.pop_string()?;
+        stack.push(f(&string))
+    }
+
+    #[cmd(name = "$valid-utf8?", stack, effect = "B -- ?", nonstandard)]
+    fn interpret_is_valid_utf8(stack: &mut Stack) -> Result<()> {
+        let bytes = stack.pop_bytes()?;
+        stack.push_bool(std::str::from_utf8(&bytes).is_ok())
+    }
+
+    #[cmd(name = "char-alpha?", stack, args(f = char::is_alphabetic), effect = "c -- ?", nonstandard)]
+    #[cmd(name = "char-digit?", stack, args(f = char::is_numeric), effect = "c -- ?", nonstandard)]
+    #[cmd(name = "char-alnum?", stack, args(f = char::is_alphanumeric), effect = "c -- ?", nonstandard)]
+    #[cmd(name = "char-space?", stack, args(f = char::is_whitespace), effect = "c -- ?", nonstandard)]
+    #[cmd(name = "char-upper?", stack, args(f = char::is_uppercase), effect = "c -- ?", nonstandard)]
+    #[cmd(name = "char-lower?", stack, args(f = char::is_lowercase), effect = "c -- ?", nonstandard)]
+    fn interpret_char_class(stack: &mut Stack, f: fn(char) -> bool) -> Result<()> {
+        let c = stack.pop_smallint_char()?;
+        stack.push_bool(f(c))
+    }
+
     #[cmd(name = "B>X", stack, args(upper = true))]
     #[cmd(name = "B>x", stack, args(upper = false))]
     fn interpret_bytes_to_hex(stack: &mut Stack, upper: bool) -> Result<()> {
@@ -479,26 +573,22 @@ impl StringUtils {
         stack.push(bytes)
     }
 
-    #[cmd(name = "smca>$", stack)]
+    /// Packs a standard address into the user-friendly base64 form.
+    ///
+    /// Flags are either an integer (`+1` non-bounceable, `+2` testnet,
+    /// `+4` base64url) or a tuple of atoms `non-bounceable`, `testnet`, `url`.
+    #[cmd(name = "smca>$", stack, effect = "wc x flags -- S")]
     fn interpret_pack_std_smc_addr(stack: &mut Stack) -> Result<()> {
-        let mode = stack.pop_smallint_range(0, 7)? as u8;
-        let int = stack.pop_int()?;
-        anyhow::ensure!(int.sign() != Sign::Minus, "Expected non-negative integer");
-        anyhow::ensure!(int.bits() <= 256, "Integer does not fit into the buffer");
-
-        let workchain = stack.pop_smallint_signed_range(-0x80, 0x7f)? as i8;
-        let testnet = mode & 2 != 0;
-        let bounceable = mode & 1 == 0;
-        let url_safe = mode & 4 != 0;
-
-        let mut bytes = int.to_bytes_le().1;
-        bytes.resize(32, 0);
-        bytes.reverse();
+        let mode = pop_smca_flags(stack)?;
+        let addr = pop_std_addr(stack)?;
+        let testnet = mode & SMCA_TESTNET != 0;
+        let bounceable = mode & SMCA_NON_BOUNCEABLE == 0;
+        let url_safe = mode & SMCA_URL != 0;
 
         let mut buffer = [0u8; 36];
         buffer[0] = 0x51 - (bounceable as u8) * 0x40 + (testnet as u8) * 0x80;
-        buffer[1] = workchain as u8;
-        buffer[2..34].copy_from_slice(&bytes);
+        buffer[1] = addr.workchain as u8;
+        buffer[2..34].copy_from_slice(addr.address.as_slice());
 
         let crc = CRC_16.checksum(&buffer[..34]);
         buffer[34] = (crc >> 8) as u8;
@@ -511,20 +601,23 @@ impl StringUtils {
         })
     }
 
-    #[cmd(name = "$>smca", stack)]
+    /// Parses a raw or a user-friendly address, flags are the same as for `smca>$`
+    /// (`+4` is set only for strings which are not valid as plain base64).
+    #[cmd(name = "$>smca", stack, effect = "S -- wc x flags -1 or 0")]
     fn interpret_unpack_std_smc_addr(stack: &mut Stack) -> Result<()> {
         struct AddrFlags {
             testnet: bool,
             bounceable: bool,
+            url_safe: bool,
         }
 
         fn unpack_base64_addr(s: &str) -> Result<(AddrFlags, StdAddr)> {
             anyhow::ensure!(s.len() == 48, "Invalid address string length");
 
-            let buffer = match decode_base64(s) {
-                Ok(buffer) => buffer,
+            let (buffer, url_safe) = match decode_base64(s) {
+                Ok(buffer) => (buffer, false),
                 Err(e) => match decode_base64_url(s) {
-                    Ok(buffer) => buffer,
+                    Ok(buffer) => (buffer, true),
                     Err(_) => return Err(e.into()),
                 },
             };
@@ -540,6 +633,7 @@ impl StringUtils {
             let flags = AddrFlags {
                 testnet: flags & 0x80 != 0,
                 bounceable: flags & 0x40 == 0,
+                url_safe,
             };
 
             Ok((
@@ -557,6 +651,7 @@ impl StringUtils {
                 let flags = AddrFlags {
                     testnet: false,
                     bounceable: true,
+                    url_safe: false,
                 };
                 if let Ok(addr) = StdAddr::from_str(&string) {
                     break 'addr (flags, addr);
@@ -569,7 +664,127 @@ impl StringUtils {
 
         stack.push_int(addr.workchain)?;
         stack.push_int(BigInt::from_bytes_be(Sign::Plus, addr.address.as_slice()))?;
-        stack.push_int(((flags.testnet as u8) << 1) + !flags.bounceable as u8)?;
+        let mut mode = 0;
+        if !flags.bounceable {
+            mode |= SMCA_NON_BOUNCEABLE;
+        }
+        if flags.testnet {
+            mode |= SMCA_TESTNET;
+        }
+        if flags.url_safe {
+            mode |= SMCA_URL;
+        }
+        stack.push_int(mode)?;
         stack.push_bool(true)
     }
+
+    #[cmd(name = "raw>$", stack, effect = "wc x -- S", nonstandard)]
+    fn interpret_format_raw_addr(stack: &mut Stack) -> Result<()> {
+        let addr = pop_std_addr(stack)?;
+        stack.push(addr.to_string())
+    }
+
+    #[cmd(
+        name = "$>raw",
+        stack,
+        args(quiet = false),
+        effect = "S -- wc x",
+        nonstandard
+    )]
+    #[cmd(
+        name = "$>raw?",
+        stack,
+        args(quiet = true),
+        effect = "S -- wc x -1 or 0",
+        nonstandard
+    )]
+    fn interpret_parse_raw_addr(stack: &mut Stack, quiet: bool) -> Result<()> {
+        fn parse_raw_addr(s: &str) -> Result<StdAddr> {
+            let (workchain, address) = s.split_once(':').context("Expected `wc:hex64`")?;
+            let workchain = workchain
+                .parse::<i8>()
+                .ok()
+                .context("Workchain must be an 8-bit integer")?;
+            anyhow::ensure!(
+                address.len() == 64,
+                "Address must consist of exactly 64 hexadecimal digits"
+            );
+            let mut bytes = [0; 32];
+            hex::decode_to_slice(address, &mut bytes)
+                .ok()
+                .context("Address must consist of exactly 64 hexadecimal digits")?;
+            Ok(StdAddr::new(workchain, HashBytes(bytes)))
+        }
+
+        let string = stack.pop_string()?;
+        match parse_raw_addr(&string) {
+            Ok(addr) => {
+                stack.push_int(addr.workchain)?;
+                stack.push_int(BigInt::from_bytes_be(Sign::Plus, addr.address.as_slice()))?;
+                if quiet {
+                    stack.push_bool(true)?;
+                }
+                Ok(())
+            }
+            Err(_) if quiet => stack.push_bool(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn to_nfc(s: &str) -> String {
+    s.nfc().collect()
+}
+
+fn to_nfd(s: &str) -> String {
+    s.nfd().collect()
+}
+
+fn to_nfkc(s: &str) -> String {
+    s.nfkc().collect()
+}
+
+fn to_nfkd(s: &str) -> String {
+    s.nfkd().collect()
+}
+
+const SMCA_NON_BOUNCEABLE: u8 = 1;
+const SMCA_TESTNET: u8 = 2;
+const SMCA_URL: u8 = 4;
+
+fn pop_smca_flags(stack: &mut Stack) -> Result<u8> {
+    let flags = stack.pop()?;
+    if flags.ty() != StackValueType::Tuple {
+        let flags = flags.as_int()?;
+        return match flags.to_u8() {
+            Some(flags) if flags <= 7 => Ok(flags),
+            _ => anyhow::bail!("Address flags must be in range 0..=7"),
+        };
+    }
+
+    let mut mode = 0;
+    for item in flags.as_tuple()? {
+        mode |= match item.as_atom()? {
+            atom if *atom == "non-bounceable" => SMCA_NON_BOUNCEABLE,
+            atom if *atom == "testnet" => SMCA_TESTNET,
+            atom if *atom == "url" => SMCA_URL,
+            atom => anyhow::bail!("Unknown address flag `{atom}`"),
+        };
+    }
+    Ok(mode)
+}
+
+fn pop_std_addr(stack: &mut Stack) -> Result<StdAddr> {
+    let int = stack.pop_int()?;
+    anyhow::ensure!(int.sign() != Sign::Minus, "Expected non-negative integer");
+    anyhow::ensure!(int.bits() <= 256, "Integer does not fit into the buffer");
+    let workchain = stack.pop_smallint_signed_range(-0x80, 0x7f)? as i8;
+
+    let mut bytes = int.to_bytes_le().1;
+    bytes.resize(32, 0);
+    bytes.reverse();
+    Ok(StdAddr::new(
+        workchain,
+        HashBytes(bytes.try_into().unwrap()),
+    ))
 }
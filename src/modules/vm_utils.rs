@@ -2,8 +2,10 @@ use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::sync::OnceLock;
 
-use anyhow::Result;
-use everscale_types::prelude::CellSlice;
+use anyhow::{Context as _, Result};
+use everscale_types::prelude::{CellSlice, HashBytes};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 
 use crate::core::*;
 
@@ -11,59 +13,280 @@ pub struct VmUtils;
 
 #[fift_module]
 impl VmUtils {
-    #[init]
-    fn init(&self, d: &mut Dictionary) -> Result<()> {
-        thread_local! {
-            static VM_LIBRARIES: Rc<dyn StackValue> = Rc::new(SharedBox::default());
-        }
+    /// Returns a box with the libraries dictionary, kept for compatibility.
+    ///
+    /// The box is refreshed on each call, values stored into it
+    /// replace the registered libraries.
+    #[cmd(name = "vmlibs", effect = "-- p")]
+    fn interpret_vm_libraries_box(ctx: &mut Context) -> Result<()> {
+        sync_vm_libraries_box(ctx)?;
+        let libraries = match &ctx.vm_libraries_box {
+            Some((libraries, _)) => libraries.clone(),
+            None => SharedBox::default(),
+        };
+        libraries.store_opt(ctx.vm_libraries.root().map(Rc::new));
+        ctx.vm_libraries_box = Some((libraries.clone(), libraries.version()));
+        ctx.stack.push(libraries)
+    }
 
-        let vm_libraries = VM_LIBRARIES.with(|b| b.clone());
+    #[cmd(name = "vmlibs@", effect = "-- D", nonstandard)]
+    fn interpret_vm_libraries_get(ctx: &mut Context) -> Result<()> {
+        sync_vm_libraries_box(ctx)?;
+        ctx.stack.push_opt(ctx.vm_libraries.root())
+    }
 
-        d.define_word("vmlibs ", Rc::new(cont::LitCont(vm_libraries)))?;
+    #[cmd(name = "vmlibs!", effect = "D --", nonstandard)]
+    fn interpret_vm_libraries_set(ctx: &mut Context) -> Result<()> {
+        let root = ctx.stack.pop()?;
+        let root = if root.is_null() {
+            None
+        } else {
+            Some(root.as_cell()?.clone())
+        };
+        sync_vm_libraries_box(ctx)?;
+        ctx.vm_libraries.set_root(root);
+        Ok(())
+    }
 
+    /// Registers a library cell, returns `false` if it was already registered.
+    #[cmd(
+        name = "vmlib+",
+        args(push_flag = true),
+        effect = "c -- ?",
+        nonstandard
+    )]
+    #[cmd(
+        name = "vmlib-add",
+        args(push_flag = false),
+        effect = "c --",
+        nonstandard
+    )]
+    fn interpret_vm_library_add(ctx: &mut Context, push_flag: bool) -> Result<()> {
+        let library = ctx.stack.pop_cell()?;
+        sync_vm_libraries_box(ctx)?;
+        let added = ctx.vm_libraries.add(library.as_ref().clone())?;
+        if push_flag {
+            ctx.stack.push_bool(added)?;
+        }
         Ok(())
     }
 
+    /// Removes a library by its hash, returns `false` if it was not registered.
+    #[cmd(name = "vmlib-", effect = "x -- ?", nonstandard)]
+    fn interpret_vm_library_remove(ctx: &mut Context) -> Result<()> {
+        let hash = pop_hash(&mut ctx.stack)?;
+        sync_vm_libraries_box(ctx)?;
+        let removed = ctx.vm_libraries.remove(&hash)?.is_some();
+        ctx.stack.push_bool(removed)
+    }
+
+    #[cmd(name = "vmlibs-list", effect = "-- t", nonstandard)]
+    fn interpret_vm_libraries_list(ctx: &mut Context) -> Result<()> {
+        sync_vm_libraries_box(ctx)?;
+        let libraries = ctx.vm_libraries.list()?;
+        ctx.stack.push(
+            libraries
+                .into_iter()
+                .map(|cell| Rc::new(cell) as Rc<dyn StackValue>)
+                .collect::<StackTuple>(),
+        )
+    }
+
+    /// Serializes all registered library cells as roots of a single BOC.
+    #[cmd(name = "vmlibs>B", effect = "-- B", nonstandard)]
+    fn interpret_vm_libraries_serialize(ctx: &mut Context) -> Result<()> {
+        sync_vm_libraries_box(ctx)?;
+        let libraries = ctx.vm_libraries.list()?;
+        anyhow::ensure!(!libraries.is_empty(), "No VM libraries registered");
+        let roots = libraries.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        ctx.stack.push(crate::util::encode_boc_ext(&roots, 0)?)
+    }
+
     #[cmd(name = "runvmx")]
     #[cmd(name = "dbrunvm")]
-    #[cmd(name = "dbrunvm-parallel")]
+    #[cmd(name = "dbrunvm-parallel", nonstandard = "`dbrunvm`")]
     #[cmd(name = "vmcont")]
     #[cmd(name = "vmcont@")]
     fn interpret_run_vm(_ctx: &mut Context) -> Result<()> {
         anyhow::bail!("Unimplemented");
     }
 
-    #[cmd(name = "(vmoplen)", stack)]
-    fn interpret_vmop_len(stack: &mut Stack) -> Result<()> {
-        let cp = stack.pop_smallint_signed_range(i32::MIN, i32::MAX)?;
+    /// Sets the global TVM version which the script targets,
+    /// `null` means the latest supported version.
+    ///
+    /// Opcodes introduced in later versions are not disassembled.
+    #[cmd(name = "target-version!", effect = "n|null --", nonstandard)]
+    fn interpret_set_target_version(ctx: &mut Context) -> Result<()> {
+        let version = ctx.stack.pop()?;
+        ctx.target_version = if version.is_null() {
+            None
+        } else {
+            let version = version.as_int()?;
+            Some(u32::try_from(version).ok().context("Invalid TVM version")?)
+        };
+        Ok(())
+    }
+
+    #[cmd(name = "target-version@", effect = "-- n|null", nonstandard)]
+    fn interpret_get_target_version(ctx: &mut Context) -> Result<()> {
+        ctx.stack.push_opt(ctx.target_version.map(BigInt::from))
+    }
+
+    #[cmd(name = "exitcode>$", effect = "n -- S", nonstandard)]
+    fn interpret_exit_code_to_string(ctx: &mut Context) -> Result<()> {
+        let code = ctx.stack.pop_int()?;
+
+        let map = ctx.exit_codes.fetch();
+        let custom = if map.is_null() {
+            None
+        } else {
+            let map = Some(map.into_hashmap()?);
+            let key = HashMapTreeKey::new(code.clone())?;
+            HashMapTreeNode::lookup(&map, key).map(|node| node.value.clone())
+        };
+
+        match custom {
+            Some(value) => ctx.stack.push_raw(value),
+            None => ctx.stack.push(exit_code_message(&code)),
+        }
+    }
+
+    #[cmd(name = "exitcode!", effect = "S n --", nonstandard)]
+    fn interpret_register_exit_code(ctx: &mut Context) -> Result<()> {
+        let code = ctx.stack.pop_int()?;
+        let message = ctx.stack.pop_string()?;
+
+        let key = HashMapTreeKey::new(code)?;
+        let map = ctx.exit_codes.fetch();
+        let mut map = if map.is_null() {
+            None
+        } else {
+            Some(map.into_hashmap()?)
+        };
+        HashMapTreeNode::set(&mut map, &key, &(message as Rc<dyn StackValue>));
+        ctx.exit_codes.store_opt(map);
+        Ok(())
+    }
+
+    /// Returns a box with custom exit code messages of the context.
+    #[cmd(name = "exitcodes", effect = "-- p", nonstandard)]
+    fn interpret_exit_codes(ctx: &mut Context) -> Result<()> {
+        ctx.stack.push(ctx.exit_codes.clone())
+    }
+
+    // NOTE: opcodes which were introduced after the target version are invalid
+    #[cmd(name = "(vmoplen)")]
+    fn interpret_vmop_len(ctx: &mut Context) -> Result<()> {
+        let cp = ctx.stack.pop_smallint_signed_range(i32::MIN, i32::MAX)?;
         anyhow::ensure!(cp == 0, "Unknown VM codepage");
 
-        let cs_raw = stack.pop_slice()?;
+        let cs_raw = ctx.stack.pop_slice()?;
         let cs = cs_raw.apply()?;
 
-        let (bits, refs) = cp0().compute_len(&cs).unwrap_or_default();
-        stack.push_int(((refs as u64) << 16) | (bits as u64))
+        let (bits, refs) = cp0()
+            .compute_len(&cs, ctx.target_version)
+            .unwrap_or_default();
+        ctx.stack.push_int(((refs as u64) << 16) | (bits as u64))
     }
 
-    #[cmd(name = "(vmopdump)", stack)]
-    fn interpret_vmopdump(stack: &mut Stack) -> Result<()> {
-        let cp = stack.pop_smallint_signed_range(i32::MIN, i32::MAX)?;
+    #[cmd(name = "(vmopdump)")]
+    fn interpret_vmopdump(ctx: &mut Context) -> Result<()> {
+        let cp = ctx.stack.pop_smallint_signed_range(i32::MIN, i32::MAX)?;
         anyhow::ensure!(cp == 0, "Unknown VM codepage");
 
-        let mut cs_raw = stack.pop_slice()?;
+        let mut cs_raw = ctx.stack.pop_slice()?;
         let mut cs = cs_raw.apply()?;
 
         let mut dump = String::new();
-        cp0().load_dump(&mut cs, &mut dump)?;
+        cp0().load_dump(&mut cs, ctx.target_version, &mut dump)?;
 
         let range = cs.range();
         Rc::make_mut(&mut cs_raw).set_range(range);
 
-        stack.push_raw(cs_raw)?;
-        stack.push(dump)
+        ctx.stack.push_raw(cs_raw)?;
+        ctx.stack.push(dump)
     }
 }
 
+/// Applies the value which was stored into the `vmlibs` box by the script.
+fn sync_vm_libraries_box(ctx: &mut Context) -> Result<()> {
+    let Some((libraries, version)) = &mut ctx.vm_libraries_box else {
+        return Ok(());
+    };
+    if libraries.version() != *version {
+        let root = libraries.fetch();
+        let root = if root.is_null() {
+            None
+        } else {
+            Some(root.as_cell()?.clone())
+        };
+        ctx.vm_libraries.set_root(root);
+        *version = libraries.version();
+    }
+    Ok(())
+}
+
+fn pop_hash(stack: &mut Stack) -> Result<HashBytes> {
+    let value = stack.pop()?;
+    if value.ty() == StackValueType::Bytes {
+        let bytes = value.as_bytes()?;
+        return Ok(HashBytes(
+            bytes.try_into().ok().context("Expected 32 bytes")?,
+        ));
+    }
+
+    let (sign, bytes) = value.as_int()?.to_bytes_be();
+    anyhow::ensure!(
+        sign != num_bigint::Sign::Minus && bytes.len() <= 32,
+        "Expected a 256-bit unsigned integer"
+    );
+    let mut hash = HashBytes::ZERO;
+    hash.0[32 - bytes.len()..].copy_from_slice(&bytes);
+    Ok(hash)
+}
+
+fn exit_code_message(code: &BigInt) -> String {
+    let Some(code) = code.to_i32() else {
+        return format!("Unknown exit code {code}");
+    };
+
+    let message = match code {
+        0 => "Success",
+        1 => "Alternative success",
+        2 => "Stack underflow",
+        3 => "Stack overflow",
+        4 => "Integer overflow",
+        5 => "Integer out of expected range",
+        6 => "Invalid opcode",
+        7 => "Type check error",
+        8 => "Cell overflow",
+        9 => "Cell underflow",
+        10 => "Dictionary error",
+        11 => "Unknown error",
+        12 => "Fatal error",
+        13 | -14 => "Out of gas",
+        14 => "Virtualization error",
+        32 => "Action list is invalid",
+        33 => "Action list is too long",
+        34 => "Action is invalid or not supported",
+        35 => "Invalid source address in outbound message",
+        36 => "Invalid destination address in outbound message",
+        37 => "Not enough balance",
+        38 => "Not enough extra currencies",
+        39 => "Outbound message does not fit into a cell after rewriting",
+        40 => "Cannot process a message",
+        41 => "Library reference is null during library change action",
+        42 => "Library change action error",
+        43 => "Library limits exceeded",
+        50 => "Account state size exceeded limits",
+        0xffff => "Unknown operation",
+        code if (0..128).contains(&code) => "Reserved TVM exit code",
+        code if code >= 128 => "User-defined exit code",
+        _ => return format!("Unknown exit code {code}"),
+    };
+    message.to_owned()
+}
+
 fn cp0() -> &'static DispatchTable {
     fn make_cp0() -> Result<DispatchTable> {
         let mut t = OpcodeTable::default();
@@ -649,6 +872,7 @@ fn register_ton_ops(t: &mut OpcodeTable) -> Result<()> {
     t.add_simple(0xf804, 16, "GRAMTOGAS")?;
     t.add_simple(0xf805, 16, "GASTOGRAM")?;
     t.add_simple(0xf806, 16, "GASREMAINING")?;
+    t.add_simple_since(4, 0xf807, 16, "GASCONSUMED")?;
     t.add_simple(0xf80f, 16, "COMMIT")?;
 
     // PRNG
@@ -674,6 +898,13 @@ fn register_ton_ops(t: &mut OpcodeTable) -> Result<()> {
     t.add_simple(0xf830, 16, "CONFIGDICT")?;
     t.add_simple(0xf832, 16, "CONFIGPARAM")?;
     t.add_simple(0xf833, 16, "CONFIGOPTPARAM")?;
+    t.add_simple_since(6, 0xf836, 16, "GETGASFEE")?;
+    t.add_simple_since(6, 0xf837, 16, "GETSTORAGEFEE")?;
+    t.add_simple_since(6, 0xf838, 16, "GETFORWARDFEE")?;
+    t.add_simple_since(6, 0xf839, 16, "GETPRECOMPILEDGAS")?;
+    t.add_simple_since(6, 0xf83a, 16, "GETORIGINALFWDFEE")?;
+    t.add_simple_since(6, 0xf83b, 16, "GETGASFEESIMPLE")?;
+    t.add_simple_since(6, 0xf83c, 16, "GETFORWARDFEESIMPLE")?;
     t.add_simple(0xf840, 16, "GETGLOBVAR")?;
     t.add_fixed_range(0xf841, 0xf860, 16, 5, dump_1c_and(31, "GETGLOB "))?;
     t.add_simple(0xf860, 16, "SETGLOBVAR")?;
@@ -749,18 +980,34 @@ impl DispatchTable {
         (opcode, bits)
     }
 
-    fn load_dump(&self, slice: &mut CellSlice<'_>, f: &mut dyn std::fmt::Write) -> Result<()> {
+    fn load_dump(
+        &self,
+        slice: &mut CellSlice<'_>,
+        version: Option<u32>,
+        f: &mut dyn std::fmt::Write,
+    ) -> Result<()> {
         let (opcode, bits) = Self::get_opcode_from_slice(slice);
-        let op = self.lookup(opcode);
-        op.load_dump(slice, opcode, bits, f)
+        match self.lookup_versioned(opcode, version) {
+            Some(op) => op.load_dump(slice, opcode, bits, f),
+            None => Ok(()),
+        }
     }
 
-    fn compute_len(&self, slice: &CellSlice<'_>) -> Option<(u16, u8)> {
+    fn compute_len(&self, slice: &CellSlice<'_>, version: Option<u32>) -> Option<(u16, u8)> {
         let (opcode, bits) = Self::get_opcode_from_slice(slice);
-        let op = self.lookup(opcode);
+        let op = self.lookup_versioned(opcode, version)?;
         op.compute_len(slice, opcode, bits)
     }
 
+    /// Finds an opcode which is supported by the TVM version (`None` means the latest).
+    fn lookup_versioned(&self, opcode: u32, version: Option<u32>) -> Option<&dyn Opcode> {
+        let op = self.lookup(opcode);
+        match version {
+            Some(version) if op.required_version() > version => None,
+            _ => Some(op),
+        }
+    }
+
     fn lookup(&self, opcode: u32) -> &dyn Opcode {
         let mut i = 0;
         let mut j = self.opcodes.len();
@@ -818,12 +1065,24 @@ impl OpcodeTable {
     }
 
     fn add_simple(&mut self, opcode: u32, bits: u16, name: &'static str) -> Result<()> {
+        self.add_simple_since(0, opcode, bits, name)
+    }
+
+    /// Adds a simple opcode which was introduced in the specified TVM version.
+    fn add_simple_since(
+        &mut self,
+        version: u32,
+        opcode: u32,
+        bits: u16,
+        name: &'static str,
+    ) -> Result<()> {
         let remaining_bits = MAX_OPCODE_BITS - bits;
         self.add_opcode(Box::new(SimpleOpcode {
             name,
             opcode_min: opcode << remaining_bits,
             opcode_max: (opcode + 1) << remaining_bits,
             bits,
+            version,
         }))
     }
 
@@ -932,6 +1191,11 @@ const MAX_OPCODE: u32 = 1 << MAX_OPCODE_BITS;
 trait Opcode: Send + Sync {
     fn range(&self) -> (u32, u32);
 
+    /// The first TVM version which supports this opcode.
+    fn required_version(&self) -> u32 {
+        0
+    }
+
     fn compute_len(&self, slice: &CellSlice<'_>, opcode: u32, bits: u16) -> Option<(u16, u8)>;
 
     fn load_dump(
@@ -973,6 +1237,7 @@ struct SimpleOpcode {
     opcode_min: u32,
     opcode_max: u32,
     bits: u16,
+    version: u32,
 }
 
 impl Opcode for SimpleOpcode {
@@ -980,6 +1245,10 @@ impl Opcode for SimpleOpcode {
         (self.opcode_min, self.opcode_max)
     }
 
+    fn required_version(&self) -> u32 {
+        self.version
+    }
+
     fn compute_len(&self, _: &CellSlice<'_>, _: u32, bits: u16) -> Option<(u16, u8)> {
         (bits >= self.bits).then_some((self.bits, 0))
     }
@@ -1919,4 +2188,96 @@ mod tests {
     fn all_opcodes_are_valid() {
         assert!(!cp0().opcodes.is_empty());
     }
+
+    #[test]
+    fn vm_libraries_box() {
+        let source = "<b 1 8 u, b> vmlib+ drop \
+            vmlibs @ hashu vmlibs@ hashu = \
+            <b 2 8 u, b> vmlib-add vmlibs-list count \
+            null vmlibs ! vmlibs-list count vmlibs @ null?";
+        let items = crate::testing::run(&[], source).unwrap();
+        assert_eq!(crate::testing::ints(&items), [-1, 2, 0, -1]);
+    }
+
+    #[test]
+    fn exit_codes_are_per_context() {
+        let items = crate::testing::run(&[], "\"custom\" 100 exitcode! 100 exitcode>$ $len");
+        assert_eq!(crate::testing::ints(&items.unwrap()), [6]);
+
+        let items = crate::testing::run(&[], "exitcodes @ null? 100 exitcode>$ \"custom\" $=");
+        assert_eq!(crate::testing::ints(&items.unwrap()), [-1, 0]);
+    }
+
+    #[test]
+    fn version_specific_opcodes() {
+        let len = |version: &str, code: &str| {
+            let source = format!("{version} target-version! x{{{code}}} 0 (vmoplen)");
+            crate::testing::ints(&crate::testing::run(&[], &source).unwrap())[0]
+        };
+
+        // GASCONSUMED (v4) and GETGASFEE (v6)
+        for (version, expected) in [
+            ("3", [0, 0]),
+            ("4", [16, 0]),
+            ("6", [16, 16]),
+            ("null", [16, 16]),
+        ] {
+            assert_eq!(
+                [len(version, "F807"), len(version, "F836")],
+                expected,
+                "{version}"
+            );
+        }
+        assert_eq!(len("0", "F806"), 16);
+
+        let source = "4 target-version! x{F807F836} 0 (vmopdump) swap 0 (vmopdump) nip $len";
+        let items = crate::testing::run(&[], source).unwrap();
+        assert_eq!(items[0].as_string().unwrap(), "GASCONSUMED");
+        assert_eq!(crate::testing::ints(&items[1..]), [0]);
+    }
+
+    #[test]
+    fn target_version_words() {
+        let mut env = crate::core::env::EmptyEnvironment;
+        let mut stdout = std::io::sink();
+        let mut ctx = crate::Context::new(&mut env, &mut stdout)
+            .with_basic_modules()
+            .unwrap();
+
+        let mut eval = |source: &str| {
+            ctx.add_source_str("<test>", source);
+            ctx.run().map(|_| ctx.target_version)
+        };
+
+        // NOTE: `null` means the latest supported version
+        assert_eq!(eval("").unwrap(), None);
+        for version in [0, 4, 9, u32::MAX] {
+            let source = format!("{version} target-version!");
+            assert_eq!(eval(&source).unwrap(), Some(version), "{version}");
+        }
+        assert_eq!(eval("4 target-version!").unwrap(), Some(4));
+
+        // Invalid versions keep the previous one
+        for source in ["-1 target-version!", "4294967296 target-version!"] {
+            assert!(eval(source).is_err(), "{source}");
+            assert_eq!(eval("").unwrap(), Some(4), "{source}");
+        }
+        assert!(eval("\"4\" target-version!").is_err());
+
+        ctx.stack.clear();
+        ctx.add_source_str(
+            "<test>",
+            "target-version@ 9 target-version! target-version@",
+        );
+        ctx.run().unwrap();
+        let items = ctx.stack.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_int().unwrap(), &BigInt::from(4));
+        assert_eq!(items[1].as_int().unwrap(), &BigInt::from(9));
+
+        ctx.add_source_str("<test>", "null target-version! target-version@");
+        ctx.run().unwrap();
+        assert_eq!(ctx.target_version, None);
+        assert!(ctx.stack.pop().unwrap().is_null());
+    }
 }
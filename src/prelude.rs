@@ -0,0 +1,23 @@
+//! Commonly used items for embedding the interpreter.
+//!
+//! NOTE: items re-exported here are kept stable between releases,
+//! even when the modules which define them are reorganized. Breaking
+//! changes to them only happen with a bump of the minor version.
+
+pub use std::rc::Rc;
+
+pub use everscale_types::prelude::{Boc, Cell, CellBuilder, CellSlice, Dict, HashBytes};
+pub use num_bigint::BigInt;
+
+pub use crate::core::stack::{Atom, StackError};
+pub use crate::core::{
+    fift_module, Cont, ContImpl, Context, CustomType, CustomValue, Dictionary, DictionaryEntry,
+    DisplayLimits, Environment, Exception, ExecutionLimits, HashMapTreeKey, HashMapTreeNode,
+    Module, OwnedCellSlice, Rational, RngSource, RunOutcome, SharedBox, SourceBlock, Stack,
+    StackTuple, StackValue, StackValueType, StepOutcome, TaggedValue, VmLibraries, WordInfo,
+    WordList, WriteMode,
+};
+pub use crate::error::{
+    Error, ExecutionAborted, InterpreterDepthExceeded, OutputLimitExceeded, UnexpectedEof,
+};
+pub use crate::BasicModules;
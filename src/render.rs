@@ -0,0 +1,310 @@
+//! Rendering of interpreter errors for terminals and other frontends.
+
+use std::fmt::{Debug, Display, Formatter, Result};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::core::lexer::LexerPosition;
+use crate::core::SourcePosition;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub color: bool,
+    pub charset: Charset,
+}
+
+impl Theme {
+    pub const fn ascii() -> Self {
+        Self {
+            color: false,
+            charset: Charset::Ascii,
+        }
+    }
+
+    pub const fn unicode() -> Self {
+        Self {
+            color: false,
+            charset: Charset::Unicode,
+        }
+    }
+
+    pub const fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn paint<T>(&self, codes: &'static str, value: T) -> Painted<T> {
+        Painted {
+            codes: if self.color { codes } else { "" },
+            value,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::ascii()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+impl Charset {
+    fn arrow(self) -> &'static str {
+        match self {
+            Self::Ascii => "-->",
+            Self::Unicode => "──▶",
+        }
+    }
+
+    fn block(self) -> char {
+        match self {
+            Self::Ascii => '|',
+            Self::Unicode => '│',
+        }
+    }
+
+    fn underline(self) -> char {
+        match self {
+            Self::Ascii => '-',
+            Self::Unicode => '─',
+        }
+    }
+
+    fn note(self) -> char {
+        match self {
+            Self::Ascii => '=',
+            Self::Unicode => '·',
+        }
+    }
+}
+
+/// An error with the underlined word of the source line where it occurred.
+pub struct Report<'a, E> {
+    pub pos: LexerPosition<'a>,
+    /// Position of the compiled word which was executed (if known).
+    pub origin: Option<&'a SourcePosition>,
+    pub error: E,
+    pub theme: Theme,
+}
+
+impl<'a, E> Report<'a, E> {
+    pub fn new(pos: LexerPosition<'a>, error: E) -> Self {
+        Self {
+            pos,
+            origin: None,
+            error,
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn with_origin(mut self, origin: Option<&'a SourcePosition>) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl<E: Debug> Display for Report<'_, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        const RED: &str = "\x1b[31m";
+        const BOLD: &str = "\x1b[1m";
+        const BLUE_BOLD: &str = "\x1b[34m\x1b[1m";
+
+        let theme = &self.theme;
+        let charset = theme.charset;
+
+        let line_number = self.pos.line_number.to_string();
+        let offset_len = line_number.len();
+        let offset = format!("{:offset_len$}", "");
+
+        let arrow = theme.paint(BLUE_BOLD, charset.arrow());
+        let block = theme.paint(BLUE_BOLD, charset.block());
+        let line_number = theme.paint(BLUE_BOLD, line_number);
+
+        let line = self.pos.line.trim_end();
+        let word_start = std::cmp::min(self.pos.word_start, line.len());
+        let word_end = std::cmp::min(self.pos.word_end, line.len());
+        let (line_start, rest) = line.split_at(word_start);
+        let (underlined, line_end) = rest.split_at(word_end - word_start);
+
+        let line_start_len = UnicodeWidthStr::width(line_start);
+        let underlined_len = UnicodeWidthStr::width(underlined);
+        let underline = std::iter::repeat(charset.underline())
+            .take(underlined_len)
+            .collect::<String>();
+
+        write!(
+            f,
+            "{}{:?}\n\
+            {offset}{arrow} {}:{}:{}\n\
+            {offset} {block}\n\
+            {line_number} {block} {}{}{}\n\
+            {offset} {block} {:line_start_len$}{}\n\
+            {offset} {block}",
+            theme.paint(RED, "error: "),
+            theme.paint(BOLD, &self.error),
+            self.pos.source_block_name,
+            self.pos.line_number,
+            self.pos.word_start + 1,
+            line_start,
+            theme.paint(RED, underlined),
+            line_end,
+            "",
+            theme.paint(RED, underline),
+        )?;
+
+        if let Some(origin) = self.origin {
+            write!(
+                f,
+                "\n{offset} {} in a word compiled at {origin}",
+                theme.paint(BLUE_BOLD, charset.note())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Dumps of the continuations which were executed when an error occurred
+/// (see [`ContImpl::backtrace`]).
+///
+/// [`ContImpl::backtrace`]: crate::core::ContImpl
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    /// Dumps from the innermost continuation.
+    pub frames: Vec<String>,
+    /// The number of continuations which were not dumped.
+    pub omitted: usize,
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut newline = "";
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "{newline}{:>4}: {frame}", i + 1)?;
+            newline = "\n";
+        }
+        if self.omitted > 0 {
+            write!(f, "{newline}... {} more levels ...", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+/// A machine-readable description of an error, see [`Report`] for the rendered one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// Line number of the word (starting from 1).
+    pub line: usize,
+    /// Byte column of the word (starting from 1).
+    pub col: usize,
+    pub word: String,
+    pub message: String,
+    /// Position of the compiled word which was executed (if known).
+    pub origin: Option<SourcePosition>,
+    pub backtrace: Option<Backtrace>,
+}
+
+impl Diagnostic {
+    pub fn new(pos: LexerPosition<'_>, message: String) -> Self {
+        let line = pos.line.trim_end();
+        let word_start = std::cmp::min(pos.word_start, line.len());
+        let word_end = std::cmp::min(pos.word_end, line.len());
+        Self {
+            file: pos.source_block_name.to_owned(),
+            line: pos.line_number,
+            col: pos.word_start + 1,
+            word: line[word_start..word_end].to_owned(),
+            message,
+            origin: None,
+            backtrace: None,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: Option<&SourcePosition>) -> Self {
+        self.origin = origin.cloned();
+        self
+    }
+
+    pub fn with_backtrace(mut self, backtrace: Option<Backtrace>) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// Serializes the diagnostic into a single line JSON object.
+    ///
+    /// `origin` is a `file:line:col` string or `null`, `backtrace` is
+    /// an array of frames (with `"..."` at the end if some were omitted).
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out).unwrap();
+        out
+    }
+
+    fn write_json(&self, out: &mut String) -> Result {
+        use std::fmt::Write;
+
+        use crate::util::write_json_string;
+
+        out.push_str("{\"file\":");
+        write_json_string(out, &self.file)?;
+        write!(
+            out,
+            ",\"line\":{},\"col\":{},\"word\":",
+            self.line, self.col
+        )?;
+        write_json_string(out, &self.word)?;
+        out.push_str(",\"message\":");
+        write_json_string(out, &self.message)?;
+        out.push_str(",\"origin\":");
+        match &self.origin {
+            Some(origin) => write_json_string(out, &origin.to_string())?,
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"backtrace\":[");
+        if let Some(backtrace) = &self.backtrace {
+            let omitted = (backtrace.omitted > 0).then_some("...");
+            let frames = backtrace.frames.iter().map(String::as_str).chain(omitted);
+            for (i, frame) in frames.enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(out, frame)?;
+            }
+        }
+        out.push_str("]}");
+        Ok(())
+    }
+}
+
+struct Painted<T> {
+    codes: &'static str,
+    value: T,
+}
+
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.codes.is_empty() {
+            return Display::fmt(&self.value, f);
+        }
+        write!(f, "{}{}\x1b[0m", self.codes, self.value)
+    }
+}
+
+impl<T: Debug> Debug for Painted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.codes.is_empty() {
+            return Debug::fmt(&self.value, f);
+        }
+        write!(f, "{}{:?}\x1b[0m", self.codes, self.value)
+    }
+}
@@ -0,0 +1,109 @@
+//! Helpers for unit tests which run scripts in a fresh context.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::core::env::EmptyEnvironment;
+use crate::core::{Environment, SourceBlock, StackValue, WriteMode};
+use crate::Context;
+
+/// Runs the source with all basic modules and returns the stack items.
+///
+/// Virtual `files` can be included by the source.
+pub fn run(files: &[(&str, &str)], source: &str) -> Result<Vec<Rc<dyn StackValue>>> {
+    run_with_output(files, source, |_| {}).0
+}
+
+/// Same as [`run`], but also returns everything printed and allows
+/// to configure the context before the run.
+pub fn run_with_output(
+    files: &[(&str, &str)],
+    source: &str,
+    setup: impl FnOnce(&mut Context<'_>),
+) -> (Result<Vec<Rc<dyn StackValue>>>, String) {
+    let mut env = EmptyEnvironment;
+    let mut stdout = Vec::new();
+    let result = {
+        let mut ctx = Context::new(&mut env, &mut stdout)
+            .with_basic_modules()
+            .unwrap();
+        for (name, contents) in files {
+            ctx.add_virtual_file(*name, *contents);
+        }
+        setup(&mut ctx);
+        ctx.add_source_str("<test>", source);
+        ctx.run().map(|_| ctx.stack.items().to_vec())
+    };
+    (result, String::from_utf8(stdout).unwrap())
+}
+
+/// Converts small integer stack items.
+pub fn ints(items: &[Rc<dyn StackValue>]) -> Vec<i32> {
+    items
+        .iter()
+        .map(|item| i32::try_from(item.as_int().unwrap()).unwrap())
+        .collect()
+}
+
+/// An environment which keeps written files in memory.
+#[derive(Default)]
+pub struct MemoryEnvironment {
+    pub files: HashMap<String, Vec<u8>>,
+    pub vars: HashMap<String, String>,
+}
+
+impl Environment for MemoryEnvironment {
+    fn now_ms(&self) -> u64 {
+        0
+    }
+
+    fn get_env(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned()
+    }
+
+    fn file_exists(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    fn write_file(&mut self, name: &str, contents: &[u8], mode: WriteMode) -> std::io::Result<()> {
+        match mode {
+            WriteMode::Create if self.files.contains_key(name) => {
+                return Err(std::io::ErrorKind::AlreadyExists.into())
+            }
+            WriteMode::Append => {
+                let file = self.files.entry(name.to_owned()).or_default();
+                file.extend_from_slice(contents);
+            }
+            _ => {
+                self.files.insert(name.to_owned(), contents.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn read_file(&mut self, name: &str) -> std::io::Result<Vec<u8>> {
+        match self.files.get(name) {
+            Some(contents) => Ok(contents.clone()),
+            None => EmptyEnvironment.read_file(name),
+        }
+    }
+
+    fn read_file_part(&mut self, name: &str, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        let contents = self.read_file(name)?;
+        let start = std::cmp::min(offset, contents.len() as u64) as usize;
+        let end = std::cmp::min((start as u64).saturating_add(len), contents.len() as u64) as usize;
+        Ok(contents[start..end].to_vec())
+    }
+
+    fn include(&self, name: &str) -> std::io::Result<SourceBlock> {
+        match self.files.get(name) {
+            Some(contents) => Ok(SourceBlock::new(
+                name,
+                std::io::Cursor::new(contents.clone()),
+            )),
+            None => EmptyEnvironment.include(name),
+        }
+    }
+}
@@ -8,6 +8,9 @@ use num_bigint::{BigInt, BigUint, Sign};
 use num_traits::{Num, One, ToPrimitive, Zero};
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::core::{StackValue, StackValueType};
+use crate::error::{InvalidBoc, InvalidBocReason};
+
 pub const CRC_16: Crc<u16> = Crc::<u16>::new(&crc::CRC_16_XMODEM);
 pub const CRC_32: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
 pub const CRC_32_C: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISCSI);
@@ -170,33 +173,246 @@ impl std::fmt::Display for DisplayCellSlice<'_, '_> {
     }
 }
 
+pub trait DisplayCellExt {
+    fn display_cell_tree(&self, limit: usize) -> DisplayCellTree<'_>;
+
+    fn display_cell_dot(&self) -> DisplayCellDot<'_>;
+}
+
+impl DisplayCellExt for DynCell {
+    fn display_cell_tree(&self, limit: usize) -> DisplayCellTree<'_> {
+        DisplayCellTree { cell: self, limit }
+    }
+
+    fn display_cell_dot(&self) -> DisplayCellDot<'_> {
+        DisplayCellDot { cell: self }
+    }
+}
+
+pub struct DisplayCellDot<'a> {
+    cell: &'a DynCell,
+}
+
+impl std::fmt::Display for DisplayCellDot<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ids = ahash::HashMap::<HashBytes, usize>::default();
+        let mut stack = vec![self.cell];
+        ids.insert(*self.cell.repr_hash(), 0);
+
+        f.write_str("digraph cell {\n  node [shape=box];\n")?;
+        while let Some(cell) = stack.pop() {
+            let id = ids[cell.repr_hash()];
+            writeln!(
+                f,
+                "  c{id} [label=\"{}{} bits, {} refs\\n#{}\"];",
+                if cell.cell_type().is_exotic() {
+                    "SPECIAL "
+                } else {
+                    ""
+                },
+                cell.bit_len(),
+                cell.reference_count(),
+                hex::encode(&cell.repr_hash().as_slice()[..4]),
+            )?;
+
+            for (i, child) in cell.references().enumerate() {
+                let child_id = match ids.get(child.repr_hash()) {
+                    Some(child_id) => *child_id,
+                    None => {
+                        let child_id = ids.len();
+                        ids.insert(*child.repr_hash(), child_id);
+                        stack.push(child);
+                        child_id
+                    }
+                };
+                writeln!(f, "  c{id} -> c{child_id} [label=\"{i}\"];")?;
+            }
+        }
+        f.write_str("}")
+    }
+}
+
+/// Writes a quoted and escaped JSON string.
+pub fn write_json_string(out: &mut String, s: &str) -> std::fmt::Result {
+    use std::fmt::Write;
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+/// Writes a stack value in the format described in [`Context::stack_to_json`].
+///
+/// [`Context::stack_to_json`]: crate::Context::stack_to_json
+pub(crate) fn write_stack_value_json(out: &mut String, value: &dyn StackValue) -> Result<()> {
+    use std::fmt::Write;
+
+    fn write_tagged(out: &mut String, tag: &str, value: &str) -> std::fmt::Result {
+        write!(out, "{{\"{tag}\":")?;
+        write_json_string(out, value)?;
+        out.push('}');
+        Ok(())
+    }
+
+    match value.ty() {
+        StackValueType::Null => out.push_str("null"),
+        StackValueType::Int => write_json_string(out, &value.as_int()?.to_string())?,
+        StackValueType::Rational => {
+            write_tagged(out, "rational", &value.as_rational()?.to_string())?
+        }
+        StackValueType::String => write_tagged(out, "string", value.as_string()?)?,
+        StackValueType::Bytes => write_tagged(out, "bytes", &hex::encode(value.as_bytes()?))?,
+        StackValueType::Cell => write_tagged(out, "cell", &Boc::encode_base64(value.as_cell()?))?,
+        StackValueType::Builder => {
+            let cell = value.as_builder()?.clone().build()?;
+            write_tagged(out, "builder", &Boc::encode_base64(cell))?
+        }
+        StackValueType::Slice => {
+            let mut builder = CellBuilder::new();
+            builder.store_slice(value.as_slice()?)?;
+            write_tagged(out, "slice", &Boc::encode_base64(builder.build()?))?
+        }
+        StackValueType::Atom => write_tagged(out, "atom", &value.as_atom()?.to_string())?,
+        StackValueType::Exception => write_tagged(out, "exception", &value.as_exception()?.reason)?,
+        StackValueType::Tuple => {
+            out.push('[');
+            for (i, item) in value.as_tuple()?.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_stack_value_json(out, item.as_ref())?;
+            }
+            out.push(']');
+        }
+        StackValueType::HashMap => {
+            out.push_str("{\"hashmap\":[");
+            for (i, entry) in value.as_hashmap()?.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push('[');
+                write_stack_value_json(out, entry.key.stack_value.as_ref())?;
+                out.push(',');
+                write_stack_value_json(out, entry.value.as_ref())?;
+                out.push(']');
+            }
+            out.push_str("]}");
+        }
+        ty @ (StackValueType::Cont
+        | StackValueType::WordList
+        | StackValueType::SharedBox
+        | StackValueType::Custom) => write_tagged(out, "opaque", &format!("{ty:?}"))?,
+    }
+    Ok(())
+}
+
+pub fn escape_dot_label(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+pub struct DisplayCellTree<'a> {
+    cell: &'a DynCell,
+    limit: usize,
+}
+
+impl std::fmt::Display for DisplayCellTree<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut seen = ahash::HashSet::default();
+        let mut stack = vec![(0, self.cell)];
+
+        let mut i = 0;
+        while let Some((indent, cell)) = stack.pop() {
+            i += 1;
+            if i > self.limit {
+                return f.write_str("<cell output limit reached>\n");
+            }
+
+            let hash = cell.repr_hash();
+            write!(
+                f,
+                "{:indent$}{}{} bits, {} refs, #{}",
+                "",
+                if cell.cell_type().is_exotic() {
+                    "SPECIAL "
+                } else {
+                    ""
+                },
+                cell.bit_len(),
+                cell.reference_count(),
+                hex::encode(&hash.as_slice()[..4]),
+                indent = indent * 2,
+            )?;
+
+            // Subtrees with the same hash are printed only once
+            if !seen.insert(*hash) {
+                writeln!(f, " (shared)")?;
+                continue;
+            }
+            writeln!(f)?;
+
+            for i in (0..cell.reference_count()).rev() {
+                if let Some(child) = cell.reference(i) {
+                    stack.push((indent + 1, child));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct DisplaySliceData<'a, 'b>(&'a CellSlice<'b>);
 
 impl std::fmt::Display for DisplaySliceData<'_, '_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut cs = *self.0;
+        let cs = self.0;
 
         if cs.cell_type().is_exotic() {
             f.write_str("SPECIAL ")?;
         }
 
-        let mut buffer: [u8; 128] = [0; 128];
+        let result = encode_hex_bitstring(cs).map_err(|_| std::fmt::Error)?;
+        write!(f, "x{{{}}}", result)
+    }
+}
 
-        let bits = cs.remaining_bits();
-        cs.load_raw(&mut buffer, bits)
-            .map_err(|_| std::fmt::Error)?;
-        append_tag(&mut buffer, bits);
+pub fn encode_hex_bitstring(cs: &CellSlice<'_>) -> Result<String> {
+    let mut buffer: [u8; 128] = [0; 128];
 
-        let mut result = hex::encode(&buffer[..(bits as usize + 7) / 8]);
-        if (1..=4).contains(&(bits % 8)) {
-            result.pop();
-        }
-        if bits % 4 != 0 {
-            result.push('_');
-        }
+    let bits = cs.remaining_bits();
+    cs.get_raw(0, &mut buffer, bits)?;
+    append_tag(&mut buffer, bits);
 
-        write!(f, "x{{{}}}", result)
+    let mut result = hex::encode(&buffer[..(bits as usize + 7) / 8]);
+    if (1..=4).contains(&(bits % 8)) {
+        result.pop();
+    }
+    if bits % 4 != 0 {
+        result.push('_');
     }
+    Ok(result)
 }
 
 fn append_tag(data: &mut [u8; 128], bit_len: u16) {
@@ -215,56 +431,56 @@ fn append_tag(data: &mut [u8; 128], bit_len: u16) {
 }
 
 pub fn decode_hex_bitstring(s: &str) -> Result<CellBuilder> {
-    fn hex_char(c: u8) -> Result<u8> {
-        match c {
-            b'A'..=b'F' => Ok(c - b'A' + 10),
-            b'a'..=b'f' => Ok(c - b'a' + 10),
-            b'0'..=b'9' => Ok(c - b'0'),
-            _ => anyhow::bail!("Unexpected char `{c}` in hex bistring"),
-        }
-    }
-
-    anyhow::ensure!(s.is_ascii(), "Non-ascii characters in bitstring");
+    let mut buffer = [0u8; 128];
+    let mut nibbles = 0usize;
+    let mut with_tag = false;
 
-    let s = s.as_bytes();
-    let (mut s, with_tag) = match s.strip_suffix(b"_") {
-        Some(s) => (s, true),
-        None => (s, false),
-    };
-
-    let mut half_byte = None;
-    if s.len() % 2 != 0 {
-        if let Some((last, prefix)) = s.split_last() {
-            half_byte = Some(hex_char(*last)?);
-            s = prefix;
+    for (offset, c) in s.chars().enumerate() {
+        if with_tag {
+            anyhow::bail!(BitstringError::CharAfterTag { offset, ch: c });
         }
-    }
-
-    anyhow::ensure!(s.len() <= 128 * 2, "Bitstring is too long");
 
-    let mut builder = CellBuilder::new();
+        let value = match c {
+            '0'..='9' => c as u8 - b'0',
+            'a'..='f' => c as u8 - b'a' + 10,
+            'A'..='F' => c as u8 - b'A' + 10,
+            '_' => {
+                with_tag = true;
+                continue;
+            }
+            _ => anyhow::bail!(BitstringError::UnexpectedChar {
+                kind: "hex",
+                offset,
+                ch: c
+            }),
+        };
 
-    let mut bytes = hex::decode(s)?;
+        // NOTE: the buffer has room for one extra bit which is only valid
+        // when it is removed as a part of the completion tag
+        anyhow::ensure!(
+            nibbles < buffer.len() * 2,
+            BitstringError::TooLong { offset }
+        );
 
-    let mut bits = bytes.len() as u16 * 8;
-    if let Some(half_byte) = half_byte {
-        bits += 4;
-        bytes.push(half_byte << 4);
+        buffer[nibbles / 2] |= value << (4 * (1 - nibbles % 2));
+        nibbles += 1;
     }
 
+    let mut bits = nibbles * 4;
     if with_tag {
-        bits = bytes.len() as u16 * 8;
-        for byte in bytes.iter().rev() {
-            if *byte == 0 {
-                bits -= 8;
-            } else {
-                bits -= 1 + byte.trailing_zeros() as u16;
-                break;
-            }
-        }
+        // Remove trailing zeros together with the completion tag bit
+        bits = find_completion_tag(&buffer, bits);
     }
 
-    builder.store_raw(&bytes, bits)?;
+    anyhow::ensure!(
+        bits <= MAX_BIT_LEN as usize,
+        BitstringError::TooLong {
+            offset: s.chars().count()
+        }
+    );
+
+    let mut builder = CellBuilder::new();
+    builder.store_raw(&buffer, bits as u16)?;
     Ok(builder)
 }
 
@@ -272,16 +488,23 @@ pub fn decode_binary_bitstring(s: &str) -> Result<CellBuilder> {
     let mut bits = 0;
     let mut buffer = [0; 128];
 
-    for char in s.chars() {
-        let value = match char {
+    for (offset, c) in s.chars().enumerate() {
+        let value = match c {
             '0' => 0u8,
             '1' => 1,
-            c => anyhow::bail!("Unexpected char `{c}` in binary bitstring"),
+            _ => anyhow::bail!(BitstringError::UnexpectedChar {
+                kind: "binary",
+                offset,
+                ch: c
+            }),
         };
-        buffer[bits / 8] |= value << (7 - bits % 8);
+        anyhow::ensure!(
+            bits < MAX_BIT_LEN as usize,
+            BitstringError::TooLong { offset }
+        );
 
+        buffer[bits / 8] |= value << (7 - bits % 8);
         bits += 1;
-        anyhow::ensure!(bits <= MAX_BIT_LEN as usize, "Bitstring is too long");
     }
 
     let mut builder = CellBuilder::new();
@@ -289,6 +512,128 @@ pub fn decode_binary_bitstring(s: &str) -> Result<CellBuilder> {
     Ok(builder)
 }
 
+fn find_completion_tag(data: &[u8], bits: usize) -> usize {
+    let bytes = (bits + 7) / 8;
+    for (i, byte) in data[..bytes].iter().enumerate().rev() {
+        if *byte != 0 {
+            return i * 8 + 7 - byte.trailing_zeros() as usize;
+        }
+    }
+    0
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum BitstringError {
+    #[error("Unexpected char `{ch}` at offset {offset} in {kind} bitstring")]
+    UnexpectedChar {
+        kind: &'static str,
+        offset: usize,
+        ch: char,
+    },
+    #[error("Unexpected char `{ch}` at offset {offset} after the completion tag")]
+    CharAfterTag { offset: usize, ch: char },
+    #[error("Bitstring is too long: more than 1023 bits at offset {offset}")]
+    TooLong { offset: usize },
+}
+
+pub fn format_fixed(int: &BigInt, decimals: u32) -> String {
+    let mut result = int.magnitude().to_string();
+    let decimals = decimals as usize;
+    if result.len() <= decimals {
+        result.insert_str(0, &"0".repeat(decimals + 1 - result.len()));
+    }
+
+    let point = result.len() - decimals;
+    let fraction = result[point..].trim_end_matches('0').len();
+    result.truncate(point + fraction);
+    if fraction > 0 {
+        result.insert(point, '.');
+    }
+
+    if int.sign() == Sign::Minus {
+        result.insert(0, '-');
+    }
+    result
+}
+
+/// Formats bytes as a canonical hex dump (like `hexdump -C`).
+///
+/// Each row contains an offset, 16 bytes in hex and their printable ASCII chars.
+/// The last line contains the total length.
+pub fn hexdump(data: &[u8]) -> String {
+    use std::fmt::Write;
+
+    const ROW: usize = 16;
+
+    let mut result = String::with_capacity((data.len() / ROW + 2) * 80);
+    for (i, row) in data.chunks(ROW).enumerate() {
+        write!(result, "{:08x} ", i * ROW).unwrap();
+        for j in 0..ROW {
+            if j % 8 == 0 {
+                result.push(' ');
+            }
+            match row.get(j) {
+                Some(byte) => write!(result, "{byte:02x} ").unwrap(),
+                None => result.push_str("   "),
+            }
+        }
+        result.push_str(" |");
+        for &byte in row {
+            result.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        result.push_str("|\n");
+    }
+    if !data.is_empty() {
+        writeln!(result, "{:08x}", data.len()).unwrap();
+    }
+    result
+}
+
+pub fn parse_fixed(s: &str, decimals: u32) -> Result<BigInt> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (int, fraction) = s.split_once('.').unwrap_or((s, ""));
+
+    anyhow::ensure!(
+        !int.is_empty() || !fraction.is_empty(),
+        "Expected a fixed-point number"
+    );
+    anyhow::ensure!(
+        int.bytes()
+            .chain(fraction.bytes())
+            .all(|c| c.is_ascii_digit()),
+        "Invalid fixed-point number `{s}`"
+    );
+    anyhow::ensure!(
+        fraction.len() <= decimals as usize,
+        "Too many fractional digits: {} out of {decimals}",
+        fraction.len()
+    );
+
+    let mut digits = String::with_capacity(int.len() + decimals as usize);
+    digits.push_str(int);
+    digits.push_str(fraction);
+    for _ in fraction.len()..decimals as usize {
+        digits.push('0');
+    }
+
+    let mut result = if digits.is_empty() {
+        BigInt::zero()
+    } else {
+        BigInt::from_str_radix(&digits, 10)?
+    };
+    if neg {
+        result = -result;
+    }
+    Ok(result)
+}
+
 pub fn bitsize(int: &BigInt, signed: bool) -> u16 {
     fn minus_one() -> &'static BigInt {
         static MINUS_ONE: OnceLock<BigInt> = OnceLock::new();
@@ -361,3 +706,306 @@ pub fn store_int_to_builder(
 
     Ok(())
 }
+
+pub fn load_int_from_slice(
+    cs: &mut CellSlice<'_>,
+    bits: u16,
+    signed: bool,
+) -> Result<BigInt, everscale_types::error::Error> {
+    match bits {
+        0 => Ok(BigInt::zero()),
+        0..=64 if !signed => cs.load_uint(bits).map(BigInt::from),
+        0..=64 if signed => cs.load_uint(bits).map(|mut int| {
+            if bits < 64 {
+                // Clone sign bit into all high bits
+                int |= ((int >> (bits - 1)) * u64::MAX) << (bits - 1);
+            }
+            BigInt::from(int as i64)
+        }),
+        _ => {
+            let rem = bits % 8;
+            let mut buffer = [0u8; 33];
+            cs.load_raw(&mut buffer, bits).map(|buffer| {
+                let mut int = if signed {
+                    BigInt::from_signed_bytes_be(buffer)
+                } else {
+                    BigInt::from_bytes_be(Sign::Plus, buffer)
+                };
+                if bits % 8 != 0 {
+                    int >>= 8 - rem;
+                }
+                int
+            })
+        }
+    }
+}
+
+/// Computes the length of a `MsgAddress` at the beginning of the slice.
+pub fn msg_address_bit_len(cs: &CellSlice<'_>) -> Result<u16> {
+    Ok(match cs.get_small_uint(0, 2)? {
+        // addr_none
+        0b00 => 2,
+        // addr_extern
+        0b01 => 2 + 9 + cs.get_uint(2, 9)? as u16,
+        _ => {
+            let mut addr = *cs;
+            everscale_types::models::IntAddr::load_from(&mut addr)?;
+            cs.remaining_bits() - addr.remaining_bits()
+        }
+    })
+}
+
+pub const BOC_MODE_WITH_INDEX: u32 = 1;
+pub const BOC_MODE_WITH_CRC: u32 = 2;
+pub const BOC_MODE_WITH_TOP_HASH: u32 = 4;
+pub const BOC_MODE_WITH_INT_HASHES: u32 = 8;
+pub const BOC_MODE_WITH_CACHE_BITS: u32 = 16;
+
+/// Serializes cell trees into a generic BOC using the reference implementation mode bits.
+pub fn encode_boc_ext(roots: &[&DynCell], mode: u32) -> Result<Vec<u8>> {
+    use everscale_types::cell::CellDescriptor;
+
+    anyhow::ensure!(mode < 32, "Unsupported BOC serialization mode 0x{mode:x}");
+    anyhow::ensure!(!roots.is_empty(), "No root cells to serialize");
+
+    let has_index = mode & BOC_MODE_WITH_INDEX != 0;
+    let has_crc = mode & BOC_MODE_WITH_CRC != 0;
+    let has_cache_bits = has_index && mode & BOC_MODE_WITH_CACHE_BITS != 0;
+
+    // Collect cells in post order (the same as `BocHeader` does)
+    let mut post_order = Vec::<&DynCell>::new();
+    let mut post_indices = ahash::HashMap::<&HashBytes, usize>::default();
+    let mut ref_counts = ahash::HashMap::<&HashBytes, u32>::default();
+    for root in roots {
+        if post_indices.contains_key(root.repr_hash()) {
+            continue;
+        }
+
+        let mut stack = vec![root.references()];
+        while let Some(children) = stack.last_mut() {
+            if let Some(child) = children.next() {
+                *ref_counts.entry(child.repr_hash()).or_default() += 1;
+                if !post_indices.contains_key(child.repr_hash()) {
+                    stack.push(child.references());
+                }
+            } else {
+                let cell = children.cell();
+                post_indices.insert(cell.repr_hash(), post_order.len());
+                post_order.push(cell);
+                stack.pop();
+            }
+        }
+    }
+
+    let cell_count = post_order.len();
+    let index_of = |cell: &DynCell| cell_count - post_indices[cell.repr_hash()] - 1;
+    let root_indices = roots.iter().map(|&root| index_of(root)).collect::<Vec<_>>();
+
+    let bytes_to_fit = |n: u64| std::cmp::max((64 - n.leading_zeros() as usize + 7) / 8, 1);
+    let ref_size = bytes_to_fit(cell_count as u64);
+
+    // Serialize cells data
+    let mut cells_data = Vec::new();
+    let mut offsets = Vec::with_capacity(cell_count);
+    for (i, cell) in post_order.iter().rev().enumerate() {
+        let with_hashes = mode & BOC_MODE_WITH_INT_HASHES != 0
+            || mode & BOC_MODE_WITH_TOP_HASH != 0 && root_indices.contains(&i);
+
+        let mut descriptor = cell.descriptor();
+        descriptor.d1 &= !CellDescriptor::STORE_HASHES_MASK;
+        if with_hashes {
+            descriptor.d1 |= CellDescriptor::STORE_HASHES_MASK;
+        }
+
+        cells_data.extend_from_slice(&[descriptor.d1, descriptor.d2]);
+        if with_hashes {
+            let level_mask = descriptor.level_mask();
+            for level in level_mask {
+                cells_data.extend_from_slice(cell.hash(level).as_ref());
+            }
+            for level in level_mask {
+                cells_data.extend_from_slice(&cell.depth(level).to_be_bytes());
+            }
+        }
+        cells_data.extend_from_slice(cell.data());
+        for child in cell.references() {
+            let index = index_of(child) as u64;
+            cells_data.extend_from_slice(&index.to_be_bytes()[8 - ref_size..]);
+        }
+
+        let should_cache = ref_counts
+            .get(cell.repr_hash())
+            .copied()
+            .unwrap_or_default()
+            > 1;
+        offsets.push((cells_data.len() as u64, should_cache));
+    }
+
+    let total_cells_size = cells_data.len() as u64;
+    let offset_size = bytes_to_fit(total_cells_size << has_cache_bits as u8);
+
+    let mut result = Vec::with_capacity(cells_data.len() + 32);
+    result.extend_from_slice(&[0xb5, 0xee, 0x9c, 0x72]);
+    result.push(
+        ((has_index as u8) << 7)
+            | ((has_crc as u8) << 6)
+            | ((has_cache_bits as u8) << 5)
+            | ref_size as u8,
+    );
+    result.push(offset_size as u8);
+    for value in [cell_count as u64, roots.len() as u64, 0] {
+        result.extend_from_slice(&value.to_be_bytes()[8 - ref_size..]);
+    }
+    result.extend_from_slice(&total_cells_size.to_be_bytes()[8 - offset_size..]);
+    for index in root_indices {
+        result.extend_from_slice(&(index as u64).to_be_bytes()[8 - ref_size..]);
+    }
+    if has_index {
+        for (offset, should_cache) in offsets {
+            let value = if has_cache_bits {
+                offset * 2 + should_cache as u64
+            } else {
+                offset
+            };
+            result.extend_from_slice(&value.to_be_bytes()[8 - offset_size..]);
+        }
+    }
+    result.extend_from_slice(&cells_data);
+    if has_crc {
+        let crc = CRC_32_C.checksum(&result);
+        result.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    Ok(result)
+}
+
+/// Deserializes a single root BOC, errors are reported as [`InvalidBoc`].
+pub fn decode_boc(bytes: &[u8]) -> Result<Cell> {
+    Boc::decode(bytes).map_err(|e| diagnose_boc(bytes, e).into())
+}
+
+/// Deserializes all root cells of a generic BOC,
+/// errors are reported as [`InvalidBoc`].
+pub fn decode_boc_roots(bytes: &[u8]) -> Result<Vec<Cell>> {
+    use everscale_types::boc::de::{BocHeader, Options};
+
+    let header = BocHeader::decode(
+        bytes,
+        &Options {
+            min_roots: None,
+            max_roots: None,
+        },
+    )
+    .map_err(|e| diagnose_boc(bytes, e))?;
+    let cells = header
+        .finalize(&mut Cell::empty_context())
+        .map_err(|e| diagnose_boc(bytes, e))?;
+
+    let mut roots = Vec::with_capacity(header.roots().len());
+    for &index in header.roots() {
+        let Some(root) = cells.get(index) else {
+            anyhow::bail!("Root cell not found");
+        };
+        roots.push(root);
+    }
+    Ok(roots)
+}
+
+/// Finds the invalid part of the BOC which failed to deserialize.
+///
+/// Only the header, the size and the CRC are checked here,
+/// other errors are reported at the beginning of the cells data.
+fn diagnose_boc(bytes: &[u8], error: impl std::fmt::Display) -> InvalidBoc {
+    const BOC_GENERIC_MAGIC: u32 = 0xb5ee9c72;
+    const BOC_INDEXED_MAGIC: u32 = 0x68ff65f3;
+    const BOC_INDEXED_CRC32_MAGIC: u32 = 0xacc3a728;
+
+    let invalid = |offset: usize, root_count: Option<u64>, reason| InvalidBoc {
+        offset,
+        root_count: root_count.map(|count| count as usize),
+        reason,
+    };
+    let truncated = |expected: u64, root_count| {
+        let reason = InvalidBocReason::Truncated {
+            expected: expected as usize,
+            actual: bytes.len(),
+        };
+        invalid(bytes.len(), root_count, reason)
+    };
+    let read_uint = |offset: usize, size: usize| {
+        let mut value = [0u8; 8];
+        value[8 - size..].copy_from_slice(&bytes[offset..offset + size]);
+        u64::from_be_bytes(value)
+    };
+
+    if bytes.len() < 4 {
+        return truncated(4, None);
+    }
+    let magic = read_uint(0, 4) as u32;
+    if !matches!(
+        magic,
+        BOC_GENERIC_MAGIC | BOC_INDEXED_MAGIC | BOC_INDEXED_CRC32_MAGIC
+    ) {
+        return invalid(0, None, InvalidBocReason::UnknownMagic);
+    }
+    if bytes.len() < 6 {
+        return truncated(6, None);
+    }
+
+    let (has_index, has_crc) = match magic {
+        BOC_INDEXED_MAGIC => (true, false),
+        BOC_INDEXED_CRC32_MAGIC => (true, true),
+        _ => (bytes[4] & 0x80 != 0, bytes[4] & 0x40 != 0),
+    };
+    let ref_size = (bytes[4] & 0b111) as usize;
+    if !(1..=4).contains(&ref_size) {
+        let reason = InvalidBocReason::InvalidHeader { field: "ref size" };
+        return invalid(4, None, reason);
+    }
+    let offset_size = bytes[5] as usize;
+    if !(1..=8).contains(&offset_size) {
+        let reason = InvalidBocReason::InvalidHeader {
+            field: "offset size",
+        };
+        return invalid(5, None, reason);
+    }
+
+    let header_len = 6 + ref_size * 3 + offset_size;
+    if bytes.len() < header_len {
+        return truncated(header_len as u64, None);
+    }
+    let cell_count = read_uint(6, ref_size);
+    let root_count = read_uint(6 + ref_size, ref_size);
+    let total_cells_size = read_uint(6 + ref_size * 3, offset_size);
+
+    let cells_offset = (header_len as u64)
+        .saturating_add(root_count.saturating_mul(ref_size as u64))
+        .saturating_add(if has_index {
+            cell_count.saturating_mul(offset_size as u64)
+        } else {
+            0
+        });
+    let expected_len = cells_offset
+        .saturating_add(total_cells_size)
+        .saturating_add(if has_crc { 4 } else { 0 });
+
+    let root_count = Some(root_count);
+    if (bytes.len() as u64) < expected_len {
+        return truncated(expected_len, root_count);
+    }
+
+    if has_crc {
+        // NOTE: trailing bytes after the CRC are ignored
+        let crc_offset = expected_len as usize - 4;
+        let expected =
+            u32::from_le_bytes(bytes[crc_offset..expected_len as usize].try_into().unwrap());
+        let actual = CRC_32_C.checksum(&bytes[..crc_offset]);
+        if expected != actual {
+            let reason = InvalidBocReason::CrcMismatch { expected, actual };
+            return invalid(crc_offset, root_count, reason);
+        }
+    }
+
+    let reason = InvalidBocReason::InvalidCells(error.to_string());
+    invalid(cells_offset as usize, root_count, reason)
+}